@@ -0,0 +1,29 @@
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    // embedded for the `cnls/version` request, when built from a git checkout
+    let sha = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty());
+
+    if let Some(sha) = sha {
+        println!("cargo:rustc-env=CNLS_GIT_SHA={sha}");
+    }
+
+    // a path that doesn't exist reruns the script on every build, e.g. from a source tarball
+    if !Path::new(".git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=build.rs");
+        return;
+    }
+    for path in [".git/HEAD", ".git/refs", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+}
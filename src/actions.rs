@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Range, TextEdit, Url, WorkspaceEdit};
+
+use crate::analysis::UNKNOWN_CLASS_CODE;
+use crate::collect::{css_outline, escape_class_name, CssSymbol};
+use crate::find;
+use crate::text::{self, PositionEncoding};
+
+/// The class name an unknown class diagnostic published by cnls is about, taken from its data,
+/// or from the `code` it was published for when the client didn't keep the data. Variant
+/// prefixes are left out, like the css index does, e.g. `hover:` in `hover:btn-ghost`.
+pub fn unknown_class_of(
+    diagnostic: &Diagnostic,
+    code: &str,
+    encoding: PositionEncoding,
+) -> Option<String> {
+    let is_unknown_class = diagnostic.source.as_deref() == Some("cnls")
+        && diagnostic.code == Some(NumberOrString::String(UNKNOWN_CLASS_CODE.to_string()));
+    if !is_unknown_class {
+        return None;
+    }
+
+    if let Some(class_name) = diagnostic
+        .data
+        .as_ref()
+        .and_then(|data| data.get("className"))
+        .and_then(|name| name.as_str())
+    {
+        return Some(bare_class_name(class_name));
+    }
+
+    let start = text::byte_offset_of(code, diagnostic.range.start, encoding)?;
+    let end = text::byte_offset_of(code, diagnostic.range.end, encoding)?;
+    code.get(start..end).map(bare_class_name)
+}
+
+fn bare_class_name(value: &str) -> String {
+    value.rsplit(':').next().unwrap_or(value).to_string()
+}
+
+/// How many class names are suggested in place of an unknown one.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The class names among the `candidates` closest to the unknown `class_name`, by
+/// [edit_distance], e.g. `flex` for `flx`. Only those at most 2 edits away, or sharing a prefix
+/// of at least 3 characters, are suggested, the closest first.
+pub fn suggest_class_names<'a>(
+    class_name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+) -> Vec<String> {
+    let mut suggestions = candidates
+        .filter(|candidate| *candidate != class_name)
+        .filter_map(|candidate| {
+            let shared_prefix = class_name
+                .chars()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let distance = edit_distance(class_name, candidate);
+            (distance <= 2 || shared_prefix >= 3).then_some((
+                distance,
+                std::cmp::Reverse(shared_prefix),
+                candidate,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    suggestions.sort();
+    suggestions
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// The Levenshtein distance between `a` and `b`: how many characters have to be inserted,
+/// deleted or substituted to turn one into the other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Replaces the class an unknown class `diagnostic` is about with the `suggestion`, keeping its
+/// variant prefixes, e.g. `hover:flx` becomes `hover:flex`.
+pub fn replace_class_edit(
+    uri: Url,
+    diagnostic: &Diagnostic,
+    code: &str,
+    suggestion: &str,
+    encoding: PositionEncoding,
+) -> WorkspaceEdit {
+    let written = text::byte_offset_of(code, diagnostic.range.start, encoding)
+        .zip(text::byte_offset_of(code, diagnostic.range.end, encoding))
+        .and_then(|(start, end)| code.get(start..end))
+        .unwrap_or_default();
+
+    let new_text = match written.rsplit_once(':') {
+        Some((variants, _)) => format!("{variants}:{suggestion}"),
+        None => suggestion.to_string(),
+    };
+
+    let edit = TextEdit {
+        range: diagnostic.range,
+        new_text,
+    };
+
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri, vec![edit])])),
+        ..Default::default()
+    }
+}
+
+/// Deletes the class an unknown class `diagnostic` is about from its class list, along with the
+/// whitespace separating it from the next class, or from the previous one when it's the last,
+/// so that neither `"a  b"` nor `"a "` is left behind. `None` when the diagnostic no longer
+/// points to a single class in `code`.
+pub fn remove_class_edit(
+    uri: Url,
+    diagnostic: &Diagnostic,
+    code: &str,
+    encoding: PositionEncoding,
+) -> Option<WorkspaceEdit> {
+    let start = text::byte_offset_of(code, diagnostic.range.start, encoding)?;
+    let end = text::byte_offset_of(code, diagnostic.range.end, encoding)?;
+    let written = code.get(start..end)?;
+    if written.is_empty() || written.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let before = code[..start].len() - code[..start].trim_end().len();
+    let after = code[end..].len() - code[end..].trim_start().len();
+    let is_last = code[end + after..]
+        .chars()
+        .next()
+        .is_none_or(|c| matches!(c, '"' | '\'' | '`'));
+
+    let removed = if after > 0 && !is_last {
+        start..end + after
+    } else {
+        start - before..end
+    };
+
+    let edit = TextEdit {
+        range: Range::new(
+            text::position_of(code, removed.start, encoding),
+            text::position_of(code, removed.end, encoding),
+        ),
+        new_text: String::new(),
+    };
+
+    Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri, vec![edit])])),
+        ..Default::default()
+    })
+}
+
+/// Sorts the classes of the class list at `list`, a byte range into `code`, grouped by the
+/// first prefix of `order` they start with, those without any first, then alphabetically.
+/// Whitespace between classes becomes a single space, but line breaks and the whitespace
+/// around the list are kept as they are. Classes joined to a template interpolation, like
+/// `btn-${size}`, stay in place. `None` when the classes are already sorted.
+pub fn sort_classes_edit(
+    code: &str,
+    list: std::ops::Range<usize>,
+    order: &[String],
+    encoding: PositionEncoding,
+) -> Option<TextEdit> {
+    let tokens = find::class_list_tokens(code, list.clone());
+    let (first, last) = (tokens.first()?.clone(), tokens.last()?.clone());
+
+    let pinned_first = first.start == list.start && code[..list.start].ends_with('}');
+    let pinned_last = last.end == list.end && code[list.end..].starts_with("${");
+    let sorted_range = usize::from(pinned_first)..tokens.len() - usize::from(pinned_last);
+
+    let group_of = |class: &str| {
+        order
+            .iter()
+            .position(|prefix| class.starts_with(prefix.as_str()))
+            .map_or(0, |i| i + 1)
+    };
+    let mut sorted = tokens
+        .get(sorted_range.clone())
+        .unwrap_or_default()
+        .iter()
+        .map(|token| &code[token.clone()])
+        .collect::<Vec<_>>();
+    sorted.sort_by(|a, b| group_of(a).cmp(&group_of(b)).then(a.cmp(b)));
+
+    let mut classes = tokens
+        .iter()
+        .map(|token| &code[token.clone()])
+        .collect::<Vec<_>>();
+    if let Some(unsorted) = classes.get_mut(sorted_range) {
+        unsorted.copy_from_slice(&sorted);
+    }
+
+    let mut new_text = code[list.start..first.start].to_string();
+    for (i, class) in classes.iter().enumerate() {
+        new_text.push_str(class);
+        if let Some(next) = tokens.get(i + 1) {
+            let separator = &code[tokens[i].end..next.start];
+            match separator.contains(['\n', '\\']) {
+                true => new_text.push_str(separator),
+                false => new_text.push(' '),
+            }
+        }
+    }
+    new_text.push_str(&code[last.end..list.end]);
+
+    (new_text != code[list.clone()]).then(|| TextEdit {
+        range: text::range_of(code, list, encoding),
+        new_text,
+    })
+}
+
+/// The classes of each rule of the css `code`, in order, for the code lenses counting their
+/// uses: the byte range of the class selector, and whether it's the only class of its rule.
+/// A class appearing more than once in a rule, like in `.btn:hover, .btn:focus`, is listed once.
+pub fn rule_class_lenses(code: &str) -> Vec<(String, std::ops::Range<usize>, bool)> {
+    fn flatten(symbols: Vec<CssSymbol>, classes: &mut Vec<CssSymbol>) {
+        for mut symbol in symbols {
+            match symbol.is_at_rule {
+                true => flatten(std::mem::take(&mut symbol.children), classes),
+                false => classes.push(symbol),
+            }
+        }
+    }
+
+    let mut classes = vec![];
+    flatten(css_outline(code), &mut classes);
+
+    let mut lenses: Vec<(String, std::ops::Range<usize>, std::ops::Range<usize>)> = vec![];
+    for class in classes {
+        let is_listed = lenses
+            .iter()
+            .any(|(name, _, rule)| *name == class.name && *rule == class.range);
+        if !is_listed {
+            lenses.push((class.name, class.selection_range, class.range));
+        }
+    }
+
+    lenses
+        .iter()
+        .map(|(name, selector, rule)| {
+            let is_alone = lenses.iter().filter(|(_, _, r)| r == rule).count() == 1;
+            (name.clone(), selector.clone(), is_alone)
+        })
+        .collect()
+}
+
+/// Appends an empty rule for the class `class_name` to the css file at `css_uri`, holding
+/// `css_code`. The rule is separated from the last one by a blank line.
+pub fn create_rule_edit(
+    css_uri: Url,
+    css_code: &str,
+    class_name: &str,
+    encoding: PositionEncoding,
+) -> WorkspaceEdit {
+    let separator = match css_code {
+        "" => "",
+        code if code.ends_with("\n\n") => "",
+        code if code.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+
+    let end = text::position_of(css_code, css_code.len(), encoding);
+    let edit = TextEdit {
+        range: Range::new(end, end),
+        new_text: format!("{separator}.{} {{\n\n}}\n", escape_class_name(class_name)),
+    };
+
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(css_uri, vec![edit])])),
+        ..Default::default()
+    }
+}
+
+/// How many leading path components the css file and the source document have in common,
+/// ranking the css files to create a rule in, nearest first.
+pub fn shared_components(css_file: &Path, document: &Path) -> usize {
+    css_file
+        .components()
+        .zip(document.components())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range, Url};
+
+    use super::{
+        create_rule_edit, edit_distance, remove_class_edit, replace_class_edit, rule_class_lenses,
+        shared_components, sort_classes_edit, suggest_class_names, unknown_class_of,
+    };
+    use crate::text::PositionEncoding::Utf16;
+
+    fn appended(css_code: &str) -> (Range, String) {
+        let uri = Url::parse("file:///app/styles.css").unwrap();
+        let edit = create_rule_edit(uri.clone(), css_code, "btn-ghost", Utf16);
+        let edits = &edit.changes.unwrap()[&uri];
+        (edits[0].range, edits[0].new_text.clone())
+    }
+
+    #[test]
+    fn it_appends_rules_after_the_last_line() {
+        let end = |line, character| {
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            )
+        };
+
+        assert_eq!(
+            appended(".btn {}\n"),
+            (end(1, 0), "\n.btn-ghost {\n\n}\n".to_string())
+        );
+        assert_eq!(
+            appended(".btn {\n  color: red;\n}"),
+            (end(2, 1), "\n\n.btn-ghost {\n\n}\n".to_string())
+        );
+        assert_eq!(appended(""), (end(0, 0), ".btn-ghost {\n\n}\n".to_string()));
+
+        let uri = Url::parse("file:///app/styles.css").unwrap();
+        let edit = create_rule_edit(uri, ".btn {}", "hover:btn", Utf16);
+        assert!(edit.changes.unwrap().values().next().unwrap()[0]
+            .new_text
+            .contains(".hover\\:btn {"));
+    }
+
+    #[test]
+    fn it_reads_the_class_of_unknown_class_diagnostics() {
+        let code = r#"<div className="btn flx" />"#;
+        let mut diagnostic = Diagnostic {
+            range: Range::new(Position::new(0, 20), Position::new(0, 23)),
+            source: Some("cnls".to_string()),
+            code: Some(NumberOrString::String("unknown-class".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            unknown_class_of(&diagnostic, code, Utf16).as_deref(),
+            Some("flx")
+        );
+
+        diagnostic.data = Some(serde_json::json!({ "className": "hover:flx" }));
+        assert_eq!(
+            unknown_class_of(&diagnostic, code, Utf16).as_deref(),
+            Some("flx")
+        );
+
+        diagnostic.source = Some("eslint".to_string());
+        assert_eq!(unknown_class_of(&diagnostic, code, Utf16), None);
+    }
+
+    #[test]
+    fn it_ranks_css_files_by_shared_components() {
+        let document = Path::new("/app/src/components/Card.tsx");
+        assert_eq!(
+            shared_components(Path::new("/app/src/components/card.css"), document),
+            4
+        );
+        assert_eq!(shared_components(Path::new("/app/styles.css"), document), 2);
+    }
+
+    #[test]
+    fn it_measures_edit_distances() {
+        assert_eq!(edit_distance("flx", "flex"), 1);
+        assert_eq!(edit_distance("flex", "flex"), 0);
+        assert_eq!(edit_distance("", "btn"), 3);
+        assert_eq!(edit_distance("mt-4", "mx-4"), 1);
+        assert_eq!(edit_distance("bg-red-500", "bg-red-50"), 1);
+        assert_eq!(edit_distance("hover:bg-blue", "hover:bg-bleu"), 2);
+        assert_eq!(edit_distance("w-1/2", "w-1/3"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn it_suggests_the_closest_class_names() {
+        let classes = ["flex", "flx-1", "flex-col", "grid", "inline-flex", "fl"];
+        assert_eq!(
+            suggest_class_names("flx", classes.into_iter()),
+            ["fl", "flex", "flx-1"]
+        );
+
+        // long names share a prefix with their variants, too far apart otherwise
+        let classes = ["text-blue-500", "text-blue-50", "bg-red-500"];
+        assert_eq!(
+            suggest_class_names("text-bleu-500", classes.into_iter()),
+            ["text-blue-500", "text-blue-50"]
+        );
+
+        assert!(suggest_class_names("card", ["btn", "badge"].into_iter()).is_empty());
+    }
+
+    #[test]
+    fn it_replaces_the_class_keeping_its_variants() {
+        let code = r#"<div className="btn hover:flx" />"#;
+        let uri = Url::parse("file:///app/App.tsx").unwrap();
+        let diagnostic = Diagnostic {
+            range: Range::new(Position::new(0, 20), Position::new(0, 29)),
+            ..Default::default()
+        };
+
+        let edit = replace_class_edit(uri.clone(), &diagnostic, code, "flex", Utf16);
+        let edits = &edit.changes.unwrap()[&uri];
+        assert_eq!(edits[0].range, diagnostic.range);
+        assert_eq!(edits[0].new_text, "hover:flex");
+    }
+
+    /// The `code` once the class between `[` and `]` is removed.
+    fn removed(code: &str) -> String {
+        let start = code.find('[').unwrap();
+        let end = code.find(']').unwrap() - 1;
+        let code = code.replace(['[', ']'], "");
+        let uri = Url::parse("file:///app/App.tsx").unwrap();
+        let diagnostic = Diagnostic {
+            range: Range::new(
+                crate::text::position_of(&code, start, Utf16),
+                crate::text::position_of(&code, end, Utf16),
+            ),
+            ..Default::default()
+        };
+
+        let edit = remove_class_edit(uri.clone(), &diagnostic, &code, Utf16).unwrap();
+        let edit = &edit.changes.unwrap()[&uri][0];
+        let start = crate::text::byte_offset_of(&code, edit.range.start, Utf16).unwrap();
+        let end = crate::text::byte_offset_of(&code, edit.range.end, Utf16).unwrap();
+        format!("{}{}", &code[..start], &code[end..])
+    }
+
+    #[test]
+    fn it_removes_classes_with_their_separator() {
+        assert_eq!(
+            removed(r#"<div className="a [flx] b" />"#),
+            r#"<div className="a b" />"#
+        );
+        assert_eq!(
+            removed(r#"<div className="[flx]  b" />"#),
+            r#"<div className="b" />"#
+        );
+        assert_eq!(
+            removed(r#"<div className="a [flx]" />"#),
+            r#"<div className="a" />"#
+        );
+        assert_eq!(
+            removed(r#"<div className="[flx]" />"#),
+            r#"<div className="" />"#
+        );
+        assert_eq!(
+            removed("<div className={`\n  a\n  [flx]\n  b\n`} />"),
+            "<div className={`\n  a\n  b\n`} />"
+        );
+        assert_eq!(
+            removed("<div className={`\n  a\n  [flx]\n`} />"),
+            "<div className={`\n  a\n`} />"
+        );
+        assert_eq!(
+            removed(r#"<div className={`a [flx] ${size}`} />"#),
+            r#"<div className={`a ${size}`} />"#
+        );
+        assert_eq!(
+            removed(r#"<div className="a\u0020b [hover:flx] c" />"#),
+            r#"<div className="a\u0020b c" />"#
+        );
+    }
+
+    /// The `code` once the class list between `[` and `]` is sorted by `order`.
+    fn sorted(code: &str, order: &[&str]) -> String {
+        let list = code.find('[').unwrap()..code.find(']').unwrap() - 1;
+        let code = code.replace(['[', ']'], "");
+        let order = order.iter().map(|o| o.to_string()).collect::<Vec<_>>();
+
+        let Some(edit) = sort_classes_edit(&code, list.clone(), &order, Utf16) else {
+            return code;
+        };
+        assert_eq!(
+            edit.range,
+            crate::text::range_of(&code, list.clone(), Utf16)
+        );
+        format!(
+            "{}{}{}",
+            &code[..list.start],
+            edit.new_text,
+            &code[list.end..]
+        )
+    }
+
+    #[test]
+    fn it_sorts_class_lists() {
+        assert_eq!(
+            sorted(r#"<div className="[p-2  flex   btn]" />"#, &[]),
+            r#"<div className="btn flex p-2" />"#
+        );
+        assert_eq!(
+            sorted(r#"<div className="[ p-2 flex ]" />"#, &[]),
+            r#"<div className=" flex p-2 " />"#
+        );
+        assert_eq!(
+            sorted(
+                r#"<div className="[md:p-4 hover:bg-red sm:p-2 p-1 md:flex]" />"#,
+                &["sm:", "md:"]
+            ),
+            r#"<div className="hover:bg-red p-1 sm:p-2 md:flex md:p-4" />"#
+        );
+        assert_eq!(
+            sorted("<div className={`[\n  p-2 flex\n  btn\n]`} />", &[]),
+            "<div className={`\n  btn flex\n  p-2\n`} />"
+        );
+        assert_eq!(
+            sorted("const c = `[p-2 flex btn-]${size}`;", &[]),
+            "const c = `flex p-2 btn-${size}`;"
+        );
+        assert_eq!(
+            sorted("const c = `${size}[-x p-2 btn ]${y}`;", &[]),
+            "const c = `${size}-x btn p-2 ${y}`;"
+        );
+
+        let code = r#"<div className="[btn flex]" />"#;
+        assert_eq!(sorted(code, &[]), code.replace(['[', ']'], ""));
+    }
+
+    #[test]
+    fn it_lists_the_classes_of_each_rule_once() {
+        let code = ".btn { }\n.btn:hover, .btn:focus { }\n@media print {\n  .card .title { }\n}\n";
+        let lenses = rule_class_lenses(code)
+            .into_iter()
+            .map(|(name, range, is_alone)| (name, &code[range], is_alone))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lenses,
+            [
+                ("btn".to_string(), ".btn", true),
+                ("btn".to_string(), ".btn", true),
+                ("card".to_string(), ".card", false),
+                ("title".to_string(), ".title", false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,759 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::protocol::DocumentStatus;
+use crate::scope::Scope;
+use swc_common::Span;
+use swc_ecma_parser::Syntax;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DocumentLink, Location,
+    NumberOrString, Position, SemanticToken, SemanticTokenType, Url,
+};
+
+use crate::collect::ClassName;
+use crate::config::{DiagnosticLevel, DocumentLinkOptions, TokenFilter};
+use crate::find::{self, ClassNameToken};
+use crate::index::CssIndex;
+use crate::text::{self, PositionEncoding};
+
+/// A class name found in a document, along with how many times it's defined in the css files.
+#[derive(Debug, PartialEq)]
+pub struct ResolvedToken {
+    pub token: ClassNameToken,
+    pub definitions: usize,
+}
+
+/// Resolves the `tokens` taken for class names by the `filter`, leaving out the others.
+pub fn resolve_tokens(
+    tokens: Vec<ClassNameToken>,
+    css_index: &CssIndex,
+    filter: &TokenFilter,
+) -> Vec<ResolvedToken> {
+    tokens
+        .into_iter()
+        .map(|token| ResolvedToken {
+            definitions: css_index.count_definitions(&token.value),
+            token,
+        })
+        .filter(|r| filter.accepts(&r.token.value, r.definitions > 0))
+        .collect()
+}
+
+pub fn document_status(resolved: &[ResolvedToken]) -> DocumentStatus {
+    let mut status = DocumentStatus {
+        total: resolved.len(),
+        ..Default::default()
+    };
+
+    for r in resolved {
+        match r.definitions {
+            0 => {
+                status.unresolved += 1;
+                if !status.unresolved_names.contains(&r.token.value) {
+                    status.unresolved_names.push(r.token.value.clone());
+                }
+            }
+            1 => status.resolved += 1,
+            _ => {
+                status.resolved += 1;
+                status.duplicated += 1;
+            }
+        }
+    }
+
+    status
+}
+
+/// The code of the diagnostics of class names defined in no css file.
+pub const UNKNOWN_CLASS_CODE: &str = "unknown-class";
+
+/// The code of the diagnostics of classes defined by several rules.
+pub const DUPLICATE_CLASS_CODE: &str = "duplicate-class";
+
+/// The code of the diagnostics of problems found parsing css files.
+pub const CSS_PARSE_ERROR_CODE: &str = "css-parse-error";
+
+/// Reports the class names of a document defined in no css file, at the given `level`.
+pub fn unknown_class_diagnostics(
+    resolved: &[ResolvedToken],
+    code: &str,
+    level: DiagnosticLevel,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let Some(severity) = severity_of(level) else {
+        return vec![];
+    };
+
+    resolved
+        .iter()
+        .filter(|r| r.definitions == 0)
+        .map(|r| Diagnostic {
+            range: text::range_of(code, r.token.range.clone(), encoding),
+            severity: Some(severity),
+            code: Some(NumberOrString::String(UNKNOWN_CLASS_CODE.to_string())),
+            source: Some("cnls".to_string()),
+            message: format!("unknown class name {:?}", r.token.value),
+            data: Some(serde_json::json!({ "className": r.token.value })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn severity_of(level: DiagnosticLevel) -> Option<DiagnosticSeverity> {
+    match level {
+        DiagnosticLevel::Off => None,
+        DiagnosticLevel::Warn => Some(DiagnosticSeverity::WARNING),
+        DiagnosticLevel::Error => Some(DiagnosticSeverity::ERROR),
+    }
+}
+
+/// Whether two definitions of a class are duplicates: standalone selectors of different rules,
+/// unless in different at-rules of the same file, like an override in a `@media` block.
+fn are_duplicates(a: (&Path, &ClassName), b: (&Path, &ClassName)) -> bool {
+    let (a_file, a) = a;
+    let (b_file, b) = b;
+
+    if !a.is_standalone || !b.is_standalone {
+        return false;
+    }
+
+    if a_file != b_file {
+        return true;
+    }
+
+    a.span != b.span && a.at_rules == b.at_rules
+}
+
+/// Reports, in the css files of the `css_index`, each selector of a class also defined by
+/// another rule, see [are_duplicates], pointing at the other definitions.
+pub fn duplicate_class_diagnostics(
+    css_index: &CssIndex,
+    level: DiagnosticLevel,
+    encoding: PositionEncoding,
+) -> Vec<(Url, Vec<Diagnostic>)> {
+    let Some(severity) = severity_of(level) else {
+        return vec![];
+    };
+
+    let mut definitions: HashMap<&str, Vec<(&Path, &ClassName)>> = HashMap::new();
+    for (css_file, class) in css_index.class_names() {
+        if class.is_standalone {
+            definitions
+                .entry(&class.value)
+                .or_default()
+                .push((css_file, class));
+        }
+    }
+
+    let mut locator = DefinitionLocator::new(encoding);
+    let mut diagnostics: Vec<(Url, Vec<Diagnostic>)> = vec![];
+
+    let defined_twice = css_index
+        .class_names()
+        .filter(|(_, class)| definitions.get(&*class.value).is_some_and(|d| d.len() > 1));
+
+    for (css_file, class) in defined_twice {
+        let duplicates = definitions[&*class.value]
+            .iter()
+            .filter(|&&other| are_duplicates((css_file, class), other))
+            .filter_map(|(other_file, other)| locator.locate(other_file, other.selector_span))
+            .map(|location| DiagnosticRelatedInformation {
+                location,
+                message: "also defined here".to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        if duplicates.is_empty() {
+            continue;
+        }
+
+        let Some(location) = locator.locate(css_file, class.selector_span) else {
+            continue;
+        };
+
+        let diagnostic = Diagnostic {
+            range: location.range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String(DUPLICATE_CLASS_CODE.to_string())),
+            source: Some("cnls".to_string()),
+            message: format!("class {:?} is defined more than once", class.value),
+            related_information: Some(duplicates),
+            ..Default::default()
+        };
+
+        match diagnostics.iter_mut().find(|(uri, _)| *uri == location.uri) {
+            Some((_, file_diagnostics)) => file_diagnostics.push(diagnostic),
+            None => diagnostics.push((location.uri, vec![diagnostic])),
+        }
+    }
+
+    diagnostics
+}
+
+/// Reports, in the css files of the `css_index`, the problems found parsing them, for which
+/// some of their classes may be missing from the index.
+pub fn parse_problem_diagnostics(
+    css_index: &CssIndex,
+    encoding: PositionEncoding,
+) -> Vec<(Url, Vec<Diagnostic>)> {
+    let mut locator = DefinitionLocator::new(encoding);
+    let mut diagnostics: Vec<(Url, Vec<Diagnostic>)> = vec![];
+
+    for (css_file, problem) in css_index.parse_problems() {
+        let Some(location) = locator.locate_offsets(css_file, problem.range.clone()) else {
+            continue;
+        };
+
+        let diagnostic = Diagnostic {
+            range: location.range,
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String(CSS_PARSE_ERROR_CODE.to_string())),
+            source: Some("cnls".to_string()),
+            message: format!("{}, classes may be missing around here", problem.message),
+            ..Default::default()
+        };
+
+        match diagnostics.iter_mut().find(|(uri, _)| *uri == location.uri) {
+            Some((_, file_diagnostics)) => file_diagnostics.push(diagnostic),
+            None => diagnostics.push((location.uri, vec![diagnostic])),
+        }
+    }
+
+    diagnostics
+}
+
+/// The css files defining the class names of each scoped string of a source file, given the
+/// byte ranges of their texts, `lists`, and the class names found in them, `tokens`. Each is
+/// keyed by the byte offset right after the string's closing quote, the quasis of a template
+/// counting as one string. Strings none of whose class names are defined are left out.
+pub fn string_definition_files<'a>(
+    code: &str,
+    lists: &[std::ops::Range<usize>],
+    tokens: &'a [ClassNameToken],
+    css_index: &'a CssIndex,
+) -> Vec<(usize, Vec<&'a Path>)> {
+    let mut strings = vec![];
+    let mut files: Vec<&Path> = vec![];
+
+    for list in lists {
+        for token in tokens
+            .iter()
+            .filter(|token| list.start <= token.range.start && token.range.end <= list.end)
+        {
+            for (css_file, _) in css_index.definitions_of(&token.value) {
+                if !files.contains(&css_file) {
+                    files.push(css_file);
+                }
+            }
+        }
+
+        // the quasis of a template go on until one ends with the closing backtick
+        if code[list.end..].starts_with("${") {
+            continue;
+        }
+
+        let files = std::mem::take(&mut files);
+        if !files.is_empty() {
+            strings.push((list.end + 1, files));
+        }
+    }
+
+    strings
+}
+
+/// The types of the semantic tokens, by their index in the legend.
+pub const SEMANTIC_TOKEN_TYPES: [SemanticTokenType; 1] = [SemanticTokenType::CLASS];
+
+/// The semantic tokens of the `tokens` defined in the `css_index`, delta-encoded in the order
+/// they appear in the `code`, each relative to the one before.
+pub fn semantic_tokens(
+    code: &str,
+    tokens: &[ClassNameToken],
+    css_index: &CssIndex,
+    encoding: PositionEncoding,
+) -> Vec<SemanticToken> {
+    let mut ranges = tokens
+        .iter()
+        .filter(|token| css_index.count_definitions(&token.value) > 0)
+        .map(|token| text::range_of(code, token.range.clone(), encoding))
+        // class names have no line breaks, but a token may not span lines anyway
+        .filter(|range| range.start.line == range.end.line)
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| (range.start.line, range.start.character));
+    ranges.dedup();
+
+    let mut previous = Position::default();
+    ranges
+        .into_iter()
+        .map(|range| {
+            let delta_line = range.start.line - previous.line;
+            let delta_start = match delta_line {
+                0 => range.start.character - previous.character,
+                _ => range.start.character,
+            };
+            previous = range.start;
+
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: range.end.character - range.start.character,
+                token_type: 0,
+                token_modifiers_bitset: 0,
+            }
+        })
+        .collect()
+}
+
+/// Links each of the `tokens` defined in the `css_index` to the rule preferred among those
+/// defining it, with the fragment given by the `options`, and a tooltip naming its css file by
+/// `display_path`.
+pub fn document_links(
+    code: &str,
+    tokens: &[ClassNameToken],
+    css_index: &CssIndex,
+    options: &DocumentLinkOptions,
+    display_path: impl Fn(&Path) -> String,
+    encoding: PositionEncoding,
+) -> Vec<DocumentLink> {
+    let mut locator = DefinitionLocator::new(encoding);
+
+    tokens
+        .iter()
+        .filter_map(|token| {
+            let (css_file, class) = css_index.find_class_name_by_value(&token.value)?;
+            let Location { mut uri, range } = locator.locate(css_file, class.span)?;
+            uri.set_fragment(options.fragment_at(range.start).as_deref());
+
+            Some(DocumentLink {
+                range: text::range_of(code, token.range.clone(), encoding),
+                target: Some(uri),
+                tooltip: Some(display_path(css_file)),
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Finds the byte ranges of every use of the class `value` in the scopes of a source file,
+/// leaving out any variant prefix, e.g. only `card` in `hover:card`.
+pub fn class_name_references(
+    path: PathBuf,
+    syntax: Syntax,
+    code: String,
+    scopes: &[Scope],
+    value: &str,
+) -> anyhow::Result<Vec<std::ops::Range<usize>>> {
+    let references = find::collect_class_names(path, syntax, code, scopes)?
+        .into_iter()
+        .filter_map(|token| {
+            let variants_len = token.value.strip_suffix(value)?.len();
+            let is_bare_or_variant =
+                variants_len == 0 || token.value[..variants_len].ends_with(':');
+
+            is_bare_or_variant.then(|| token.range.start + variants_len..token.range.end)
+        })
+        .collect();
+
+    Ok(references)
+}
+
+/// Finds the byte ranges of every occurrence, in the scopes of a source file, of the class name
+/// under the `cursor`, that one included.
+pub fn class_name_highlights(
+    path: PathBuf,
+    syntax: Syntax,
+    code: String,
+    scopes: &[Scope],
+    cursor: usize,
+) -> anyhow::Result<Vec<std::ops::Range<usize>>> {
+    let tokens = find::collect_class_names(path, syntax, code, scopes)?;
+
+    let Some(on_cursor) = tokens
+        .iter()
+        .find(|token| token.range.start <= cursor && cursor < token.range.end)
+    else {
+        return Ok(vec![]);
+    };
+
+    let highlights = tokens
+        .iter()
+        .filter(|token| token.value == on_cursor.value)
+        .map(|token| token.range.clone())
+        .collect();
+
+    Ok(highlights)
+}
+
+/// Resolves the spans of css rules to locations, reading each css file only once.
+pub struct DefinitionLocator {
+    css_texts: HashMap<PathBuf, Option<String>>,
+    encoding: PositionEncoding,
+}
+
+impl DefinitionLocator {
+    /// Counts the characters of the locations in the `encoding` negotiated with the client.
+    pub fn new(encoding: PositionEncoding) -> Self {
+        Self {
+            css_texts: HashMap::new(),
+            encoding,
+        }
+    }
+
+    pub fn locate(&mut self, css_file: &Path, span: Span) -> Option<Location> {
+        // swc's BytePos is 1-based
+        let range = span.lo.0.checked_sub(1)? as usize..span.hi.0.checked_sub(1)? as usize;
+        self.locate_offsets(css_file, range)
+    }
+
+    /// Resolves a range of byte offsets into the `css_file` to a location.
+    pub fn locate_offsets(
+        &mut self,
+        css_file: &Path,
+        range: std::ops::Range<usize>,
+    ) -> Option<Location> {
+        let code = self
+            .css_texts
+            .entry(css_file.to_path_buf())
+            .or_insert_with(|| std::fs::read_to_string(css_file).ok())
+            .as_deref()?;
+
+        if range.end > code.len() {
+            return None;
+        }
+
+        let uri = Url::from_file_path(css_file).ok()?;
+
+        Some(Location::new(
+            uri,
+            text::range_of(code, range, self.encoding),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use tower_lsp::lsp_types::{DiagnosticSeverity, Position, Range};
+
+    use super::{
+        class_name_highlights, class_name_references, document_status, duplicate_class_diagnostics,
+        resolve_tokens, semantic_tokens, string_definition_files, unknown_class_diagnostics,
+        DefinitionLocator, ResolvedToken,
+    };
+    use crate::config::{DiagnosticLevel, TokenFilter};
+    use crate::find::ClassNameToken;
+    use crate::index::CssIndex;
+    use crate::scope::Scope;
+    use crate::text::PositionEncoding::{Utf16, Utf32, Utf8};
+
+    fn resolved(value: &str, definitions: usize) -> ResolvedToken {
+        ResolvedToken {
+            token: ClassNameToken {
+                value: value.to_string(),
+                range: 0..value.len(),
+                css_module: None,
+            },
+            definitions,
+        }
+    }
+
+    #[test]
+    fn it_counts_tokens() {
+        let status = document_status(&[
+            resolved("flex", 1),
+            resolved("btn", 2),
+            resolved("nope", 0),
+            resolved("flex", 1),
+            resolved("nope", 0),
+            resolved("missing", 0),
+        ]);
+
+        assert_eq!(status.total, 6);
+        assert_eq!(status.resolved, 3);
+        assert_eq!(status.unresolved, 3);
+        assert_eq!(status.duplicated, 1);
+        assert_eq!(status.unresolved_names, vec!["nope", "missing"]);
+    }
+
+    #[test]
+    fn it_locates_definitions() {
+        let dir = std::env::temp_dir().join(format!("cnls-analysis-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_file = dir.join("a.css");
+        std::fs::write(
+            &css_file,
+            ".flex { display: flex; }\n\n.btn {\n  padding: 0;\n}\n",
+        )
+        .unwrap();
+
+        let (css_index, _) = CssIndex::parse_files(vec![css_file.clone()]);
+        let mut locator = DefinitionLocator::new(Utf16);
+
+        let locations = css_index
+            .definitions_of("btn")
+            .filter_map(|(css_file, class)| locator.locate(css_file, class.span))
+            .collect::<Vec<_>>();
+
+        assert_eq!(locations.len(), 1);
+        assert_eq!(
+            locations[0].range,
+            Range::new(Position::new(2, 0), Position::new(4, 1))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_locates_definitions_after_multibyte_characters() {
+        let dir = std::env::temp_dir().join(format!("cnls-multibyte-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_file = dir.join("a.css");
+        std::fs::write(&css_file, "/* café ☕🎉 */ .btn { content: \"→\"; }\n").unwrap();
+
+        let (css_index, _) = CssIndex::parse_files(vec![css_file.clone()]);
+        let (_, btn) = css_index.find_class_name_by_value("btn").unwrap();
+
+        for (encoding, start, end) in [(Utf8, 20, 44), (Utf16, 15, 37), (Utf32, 14, 36)] {
+            let mut locator = DefinitionLocator::new(encoding);
+            assert_eq!(
+                locator.locate(&css_file, btn.span).unwrap().range,
+                Range::new(Position::new(0, start), Position::new(0, end)),
+                "{encoding:?}"
+            );
+            assert_eq!(
+                locator.locate(&css_file, btn.selector_span).unwrap().range,
+                Range::new(Position::new(0, start), Position::new(0, start + 4)),
+                "{encoding:?}"
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_finds_references_in_scopes() {
+        let scopes = [crate::scope::Scope::from_str("att:className").unwrap()];
+        let code = r#"const a = <div className="card-title p-2 hover:card-title sub-card-title" title="card-title">
+  <h1 className={`card-title`} />
+</div>;"#;
+
+        let syntax = crate::find::syntax_of_file(std::path::Path::new("test.tsx")).unwrap();
+        let references = class_name_references(
+            "test.tsx".into(),
+            syntax,
+            code.to_string(),
+            &scopes,
+            "card-title",
+        )
+        .unwrap();
+
+        assert_eq!(references.len(), 3);
+        assert!(references.iter().all(|r| &code[r.clone()] == "card-title"));
+        assert_eq!(references[1].start, code.find(":card-title").unwrap() + 1);
+        assert_eq!(references[2].start, code.find("`card-title").unwrap() + 1);
+    }
+
+    #[test]
+    fn it_highlights_the_class_name_on_cursor() {
+        let scopes = [crate::scope::Scope::from_str("att:className").unwrap()];
+        let code = r#"const a = <div className="flex p-2" title="flex">
+  <h1 className={`flex  flex-col`} />
+</div>;"#;
+
+        let syntax = crate::find::syntax_of_file(std::path::Path::new("test.tsx")).unwrap();
+        let highlights_at = |cursor| {
+            class_name_highlights("test.tsx".into(), syntax, code.to_string(), &scopes, cursor)
+                .unwrap()
+        };
+
+        let highlights = highlights_at(code.find("flex").unwrap() + 2);
+        assert_eq!(highlights.len(), 2);
+        assert!(highlights.iter().all(|r| &code[r.clone()] == "flex"));
+        assert_eq!(highlights[1].start, code.find("`flex").unwrap() + 1);
+
+        assert!(highlights_at(code.find("flex  ").unwrap() + 5).is_empty());
+        assert!(highlights_at(code.find("title").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn it_updates_unknown_class_diagnostics_after_css_edits() {
+        let dir = std::env::temp_dir().join(format!("cnls-diagnostics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_file = dir.join("button.css");
+
+        let scopes = [crate::scope::Scope::from_str("att:className").unwrap()];
+        let code = r#"const a = <div className="card card-footer" />;"#;
+        let syntax = crate::find::syntax_of_file(std::path::Path::new("test.tsx")).unwrap();
+        let tokens =
+            crate::find::collect_class_names("test.tsx".into(), syntax, code.to_string(), &scopes)
+                .unwrap();
+
+        let diagnose = |css: &str| {
+            std::fs::write(&css_file, css).unwrap();
+            let (css_index, _) = CssIndex::parse_files(vec![css_file.clone()]);
+            let resolved = resolve_tokens(tokens.clone(), &css_index, &TokenFilter::default());
+            unknown_class_diagnostics(&resolved, code, DiagnosticLevel::Warn, Utf16)
+                .into_iter()
+                .map(|d| (d.range.start.character, d.message))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            diagnose(".card {}"),
+            vec![(31, r#"unknown class name "card-footer""#.to_string())]
+        );
+        assert!(diagnose(".card {} .card-footer {}").is_empty());
+        assert_eq!(
+            diagnose(".card-footer {}"),
+            vec![(26, r#"unknown class name "card""#.to_string())]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_reports_classes_defined_more_than_once() {
+        let dir = std::env::temp_dir().join(format!("cnls-duplicates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let legacy = dir.join("legacy.css");
+        let components = dir.join("components.css");
+        std::fs::write(&legacy, ".btn { color: red; }\n").unwrap();
+        std::fs::write(
+            &components,
+            ".btn, .btn-primary { color: blue; }
+.card .btn, .btn:hover { color: green; }
+@media (min-width: 768px) {
+  .btn { color: black; }
+}
+",
+        )
+        .unwrap();
+
+        let (css_index, _) = CssIndex::parse_files(vec![legacy.clone(), components.clone()]);
+        let diagnostics = duplicate_class_diagnostics(&css_index, DiagnosticLevel::Warn, Utf16);
+
+        let reported = diagnostics
+            .iter()
+            .map(|(uri, diagnostics)| {
+                let file = uri.path().rsplit('/').next().unwrap().to_string();
+                let lines = diagnostics
+                    .iter()
+                    .map(|d| {
+                        let related = d.related_information.as_ref().unwrap();
+                        let related_lines = related
+                            .iter()
+                            .map(|r| r.location.range.start.line)
+                            .collect::<Vec<_>>();
+                        (d.range.start.line, related_lines)
+                    })
+                    .collect::<Vec<_>>();
+                (file, lines)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            reported,
+            vec![
+                ("legacy.css".to_string(), vec![(0, vec![0, 3])]),
+                (
+                    "components.css".to_string(),
+                    vec![(0, vec![0]), (3, vec![0])]
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].1[1].range,
+            Range::new(Position::new(3, 2), Position::new(3, 6))
+        );
+        assert_eq!(
+            diagnostics[0].1[0].message,
+            r#"class "btn" is defined more than once"#
+        );
+
+        assert!(duplicate_class_diagnostics(&css_index, DiagnosticLevel::Off, Utf16).is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_reports_unknown_classes_at_the_configured_level() {
+        let code = "nope";
+        let resolved = [resolved("nope", 0), resolved("flex", 1)];
+
+        let errors = unknown_class_diagnostics(&resolved, code, DiagnosticLevel::Error, Utf16);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            errors[0].range,
+            Range::new(Position::new(0, 0), Position::new(0, 4))
+        );
+
+        assert!(unknown_class_diagnostics(&resolved, code, DiagnosticLevel::Off, Utf16).is_empty());
+    }
+
+    #[test]
+    fn it_finds_the_css_files_of_each_string() {
+        let dir = std::env::temp_dir().join(format!("cnls-string-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (buttons, cards) = (dir.join("buttons.css"), dir.join("cards.css"));
+        std::fs::write(&buttons, ".btn {} .btn-ghost {}").unwrap();
+        std::fs::write(&cards, ".card {}").unwrap();
+        let (css_index, _) = CssIndex::parse_files(vec![buttons.clone(), cards.clone()]);
+
+        let code = r#"<div className="btn btn-ghost nope" />;
+<div className="btn card" />;
+<div className="nope" />;
+cn(`btn ${x} card`);"#;
+        let scopes = [
+            Scope::from_str("att:className").unwrap(),
+            Scope::from_str("fn:cn").unwrap(),
+        ];
+        let syntax = crate::find::syntax_of_file(std::path::Path::new("a.tsx")).unwrap();
+        let lists =
+            crate::find::scoped_class_lists("a.tsx".into(), syntax, code.to_string(), &scopes)
+                .unwrap();
+        let tokens =
+            crate::find::collect_class_names("a.tsx".into(), syntax, code.to_string(), &scopes)
+                .unwrap();
+
+        let strings = string_definition_files(code, &lists, &tokens, &css_index)
+            .into_iter()
+            .map(|(end, files)| (&code[..end], files))
+            .collect::<Vec<_>>();
+
+        assert_eq!(strings.len(), 3);
+        assert!(strings[0].0.ends_with(r#"btn-ghost nope""#));
+        assert_eq!(strings[0].1, [buttons.as_path()]);
+        assert_eq!(strings[1].1, [buttons.as_path(), cards.as_path()]);
+        assert!(strings[2].0.ends_with("card`"));
+        assert_eq!(strings[2].1, [buttons.as_path(), cards.as_path()]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_delta_encodes_the_semantic_tokens_of_known_classes() {
+        let dir = std::env::temp_dir().join(format!("cnls-semantic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_file = dir.join("styles.css");
+        std::fs::write(&css_file, ".flex {} .rounded-lg {} .p-2 {}").unwrap();
+        let (css_index, _) = CssIndex::parse_files(vec![css_file]);
+
+        let code = "<div className={`flex nope\n    rounded-lg\n  p-2 flex`} />;";
+        let scopes = [Scope::from_str("att:className").unwrap()];
+        let syntax = crate::find::syntax_of_file(std::path::Path::new("a.tsx")).unwrap();
+        let tokens =
+            crate::find::collect_class_names("a.tsx".into(), syntax, code.to_string(), &scopes)
+                .unwrap();
+
+        let encoded = semantic_tokens(code, &tokens, &css_index, Utf16)
+            .into_iter()
+            .map(|t| (t.delta_line, t.delta_start, t.length))
+            .collect::<Vec<_>>();
+
+        assert_eq!(encoded, [(0, 17, 4), (1, 4, 10), (1, 2, 3), (0, 4, 4)]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use crate::config::Config;
+use crate::index::{self, CssIndex};
+use crate::text::{self, PositionEncoding};
+
+/// Renders what the `css_index` holds, with the problems found parsing its css files as
+/// `path:line:column: message`, paths relative to the `root`.
+pub fn render_check(root: &Path, css_index: &CssIndex) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "indexed {} class names from {} css files",
+        css_index.class_names().count(),
+        css_index.css_files().count()
+    );
+
+    let mut texts: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut problems = css_index.parse_problems().peekable();
+    if problems.peek().is_none() {
+        return out;
+    }
+
+    let _ = writeln!(out, "\nParse problems:");
+    for (css_file, problem) in problems {
+        let code = texts
+            .entry(css_file.to_path_buf())
+            .or_insert_with(|| std::fs::read_to_string(css_file).ok());
+        let position = code
+            .as_deref()
+            .map(|code| text::position_of(code, problem.range.start, PositionEncoding::Utf16))
+            .unwrap_or_default();
+        let display_path = css_file.strip_prefix(root).unwrap_or(css_file);
+
+        let _ = writeln!(
+            out,
+            "  {}:{}:{}: {}",
+            display_path.display(),
+            position.line + 1,
+            position.character + 1,
+            problem.message
+        );
+    }
+
+    out
+}
+
+/// Runs the check over the workspace at `root` from the command line, printing what was indexed
+/// to stdout. Fails when a css file couldn't be parsed entirely.
+pub fn run_cli(root: &Path, config: &Config) -> anyhow::Result<()> {
+    crate::fs::check_workspace_root(root)?;
+
+    let mut css_files = vec![];
+    crate::fs::find_all_css_files_in_dir(root, &config.css, &mut css_files)?;
+    let prioritized = index::prioritize_css_files(
+        root,
+        css_files,
+        config.index_minified,
+        config.css_file_limits,
+    );
+    let (css_index, _, errors) = CssIndex::parse_files_with(
+        index::rank_css_files(vec![prioritized]),
+        config.index_minified,
+        config.css_file_limits,
+        &|_, _| {},
+        &AtomicBool::new(false),
+    );
+    for err in errors {
+        eprintln!("{err:#}");
+    }
+
+    write!(
+        std::io::stdout().lock(),
+        "{}",
+        render_check(root, &css_index)
+    )?;
+
+    let problems = css_index.parse_problems().count();
+    if problems > 0 {
+        anyhow::bail!("found {problems} problems parsing css files");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_check;
+    use crate::index::CssIndex;
+
+    #[test]
+    fn it_lists_parse_problems_by_position() {
+        let dir = std::env::temp_dir().join(format!("cnls-check-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let (ok, broken) = (dir.join("src/ok.css"), dir.join("src/broken.css"));
+        std::fs::write(&ok, ".btn {}\n").unwrap();
+        std::fs::write(&broken, ".card {}\n.1bad {}\n").unwrap();
+
+        let (css_index, _) = CssIndex::parse_files(vec![ok.clone()]);
+        assert_eq!(
+            render_check(&dir, &css_index),
+            "indexed 1 class names from 1 css files\n"
+        );
+
+        let (css_index, _) = CssIndex::parse_files(vec![ok, broken]);
+        assert_eq!(
+            render_check(&dir, &css_index),
+            "indexed 2 class names from 2 css files\n\nParse problems:\n  src/broken.css:2:1: Invalid selector\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use tower_lsp::lsp_types::{ClientCapabilities, MarkupKind};
+
+use crate::hover;
+use crate::text::PositionEncoding;
+
+/// What the client declared it supports on initialize, for the server to only advertise and
+/// use that.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientCaps {
+    pub pull_configuration: bool,
+    pub workspace_folders: bool,
+    /// Whether the client pulls the diagnostics of documents rather than have them pushed.
+    pub pull_diagnostics: bool,
+    pub refresh_diagnostics: bool,
+    pub semantic_tokens: bool,
+    pub inlay_hints: bool,
+    pub register_inlay_hints: bool,
+    pub register_file_watchers: bool,
+    pub work_done_progress: bool,
+    /// The markup hover contents are rendered in, the one the client prefers.
+    pub hover_markup_kind: MarkupKind,
+    /// What the characters of positions are counted in, negotiated out of those the client
+    /// offers.
+    pub position_encoding: PositionEncoding,
+}
+
+impl ClientCaps {
+    /// Those of a client that declared nothing, or that isn't initialized yet.
+    pub const NONE: ClientCaps = ClientCaps {
+        pull_configuration: false,
+        workspace_folders: false,
+        pull_diagnostics: false,
+        refresh_diagnostics: false,
+        semantic_tokens: false,
+        inlay_hints: false,
+        register_inlay_hints: false,
+        register_file_watchers: false,
+        work_done_progress: false,
+        hover_markup_kind: MarkupKind::Markdown,
+        position_encoding: PositionEncoding::Utf16,
+    };
+
+    pub fn new(capabilities: &ClientCapabilities) -> Self {
+        let workspace = capabilities.workspace.as_ref();
+        let text_document = capabilities.text_document.as_ref();
+
+        let hover_content_format = text_document
+            .and_then(|t| t.hover.as_ref())
+            .and_then(|h| h.content_format.as_deref());
+
+        Self {
+            pull_configuration: workspace.and_then(|w| w.configuration).unwrap_or(false),
+            workspace_folders: workspace.and_then(|w| w.workspace_folders).unwrap_or(false),
+            pull_diagnostics: text_document.is_some_and(|t| t.diagnostic.is_some()),
+            refresh_diagnostics: workspace
+                .and_then(|w| w.diagnostic.as_ref())
+                .and_then(|d| d.refresh_support)
+                .unwrap_or(false),
+            semantic_tokens: text_document.is_some_and(|t| t.semantic_tokens.is_some()),
+            inlay_hints: text_document.is_some_and(|t| t.inlay_hint.is_some()),
+            register_inlay_hints: text_document
+                .and_then(|t| t.inlay_hint.as_ref())
+                .and_then(|h| h.dynamic_registration)
+                .unwrap_or(false),
+            register_file_watchers: workspace
+                .and_then(|w| w.did_change_watched_files.as_ref())
+                .and_then(|w| w.dynamic_registration)
+                .unwrap_or(false),
+            work_done_progress: capabilities
+                .window
+                .as_ref()
+                .and_then(|w| w.work_done_progress)
+                .unwrap_or(false),
+            hover_markup_kind: hover::negotiate_markup_kind(hover_content_format),
+            position_encoding: PositionEncoding::negotiate(
+                capabilities
+                    .general
+                    .as_ref()
+                    .and_then(|g| g.position_encodings.as_deref())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tower_lsp::lsp_types::{ClientCapabilities, MarkupKind};
+
+    use super::ClientCaps;
+    use crate::text::PositionEncoding;
+
+    fn caps(capabilities: serde_json::Value) -> ClientCaps {
+        ClientCaps::new(&serde_json::from_value::<ClientCapabilities>(capabilities).unwrap())
+    }
+
+    #[test]
+    fn it_records_what_the_client_supports() {
+        let minimal = caps(json!({}));
+        assert_eq!(minimal, ClientCaps::NONE);
+
+        let full = caps(json!({
+            "general": { "positionEncodings": ["utf-8", "utf-16"] },
+            "workspace": {
+                "configuration": true,
+                "didChangeWatchedFiles": { "dynamicRegistration": true }
+            },
+            "textDocument": {
+                "hover": { "contentFormat": ["plaintext", "markdown"] },
+                "inlayHint": { "dynamicRegistration": false },
+                "semanticTokens": {
+                    "requests": { "full": true },
+                    "tokenTypes": [],
+                    "tokenModifiers": [],
+                    "formats": ["relative"]
+                }
+            },
+            "window": { "workDoneProgress": true }
+        }));
+        assert!(full.pull_configuration && full.register_file_watchers);
+        assert!(full.semantic_tokens && full.inlay_hints && full.work_done_progress);
+        assert!(!full.register_inlay_hints && !full.pull_diagnostics);
+        assert_eq!(full.hover_markup_kind, MarkupKind::PlainText);
+        assert_eq!(full.position_encoding, PositionEncoding::Utf8);
+    }
+}
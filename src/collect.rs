@@ -1,90 +1,981 @@
 use std::path::PathBuf;
 
+use anyhow::Context;
+
+use crate::css::ClassDefinition;
+use crate::dialect::Dialect;
+
 use swc_common::errors::{ColorConfig, Handler};
 use swc_common::sync::Lrc;
-use swc_common::{FileName, SourceFile, SourceMap, Span};
+use swc_common::{BytePos, FileName, SourceFile, SourceMap, Span, Spanned};
 use swc_css::visit::{Visit, VisitWith};
 
 use swc_css::{ast::Rule, parser::parse_file};
 
+#[derive(Clone)]
 pub struct ClassName {
-    pub value: cnls::Str,
+    pub value: crate::Str,
+    pub span: Span,
+    /// The other subclass selectors (classes, pseudo-classes, attribute selectors...) of the
+    /// compound selector this class is in, e.g. `.primary` and `:hover` for `btn` in
+    /// `.btn.primary:hover`.
+    pub siblings: Vec<crate::Str>,
+    pub definition: ClassDefinition,
+    /// The class selector itself, dot included.
+    pub selector_span: Span,
+    /// Whether the class is a whole selector of its rule, like `.btn` in `.btn, .card .title`.
+    pub is_standalone: bool,
+    /// The preludes of the at-rules the rule is in, outermost first, e.g.
+    /// `@media (min-width: 768px)`.
+    pub at_rules: Vec<crate::Str>,
+    /// The preludes of the rules and at-rules the rule is nested in, outermost first, e.g.
+    /// `.card` and `@media (min-width: 768px)` for `&-wide` in
+    /// `.card { @media (min-width: 768px) { &-wide {} } }`.
+    pub ancestors: Vec<crate::Str>,
+}
+
+/// A problem found parsing a css file, for which part of it may have been skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseProblem {
+    pub message: String,
+    /// Byte offsets into the css file.
+    pub range: std::ops::Range<usize>,
+}
+
+/// A custom property declared in a css file, e.g. `--brand: #3b82f6` in `:root`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomProperty {
+    /// With its leading dashes, e.g. `--brand`.
+    pub name: crate::Str,
+    /// As written, whitespace collapsed.
+    pub value: crate::Str,
     pub span: Span,
 }
 
+/// A class a selector of an enclosing rule ends with, which the `&-suffix` selectors of the
+/// rules nested in it append to, e.g. `card` for `.card { &-title {} }`.
+#[derive(Clone)]
+pub(crate) struct ParentClass {
+    pub value: crate::Str,
+    /// Whether the selector is made of the class alone.
+    pub is_alone: bool,
+}
+
 pub struct ClassNamesCollector {
     class_names: Vec<ClassName>,
+    custom_properties: Vec<CustomProperty>,
+    problems: Vec<ParseProblem>,
+    imports: Vec<crate::Str>,
     last_rule_span: Option<Span>,
+    /// For each rule being visited, outermost first, the classes its selectors end with.
+    parents: Vec<Vec<ParentClass>>,
+    at_rules: Vec<crate::Str>,
+    ancestors: Vec<crate::Str>,
+    /// Whether the complex selector being visited is a single compound selector, i.e. has no
+    /// combinator.
+    is_single_compound: bool,
+    /// Text of the file being visited, the position it starts at, and its hash.
+    source: Option<(BytePos, Box<str>, u64)>,
 }
 
 impl ClassNamesCollector {
     pub fn new() -> Self {
         ClassNamesCollector {
             last_rule_span: None,
+            parents: vec![],
+            at_rules: vec![],
+            ancestors: vec![],
+            is_single_compound: false,
             class_names: vec![],
+            custom_properties: vec![],
+            problems: vec![],
+            imports: vec![],
+            source: None,
+        }
+    }
+
+    fn source_text(&self, span: Span) -> Option<&str> {
+        let (start_pos, src, _) = self.source.as_ref()?;
+        let start = span.lo.0.checked_sub(start_pos.0)? as usize;
+        let end = span.hi.0.checked_sub(start_pos.0)? as usize;
+        src.get(start..end)
+    }
+
+    fn definition_of(&self, rule_span: Span) -> ClassDefinition {
+        match &self.source {
+            Some((start_pos, src, hash)) => ClassDefinition {
+                rule: rule_span.lo.0.saturating_sub(start_pos.0) as usize
+                    ..rule_span.hi.0.saturating_sub(start_pos.0) as usize,
+                source_len: src.len(),
+                source_hash: *hash,
+            },
+            None => ClassDefinition::new(0..0, ""),
         }
     }
 
+    /// Visits the `rules` parsed from the `source` file, whose text as written is `code`.
+    fn visit_source(&mut self, rules: &Vec<Rule>, source: &SourceFile, code: &str) {
+        self.source = Some((source.start_pos, code.into(), crate::css::hash_source(code)));
+        rules.visit_with(self);
+        self.source = None;
+    }
+
+    pub fn class_names(&self) -> &[ClassName] {
+        &self.class_names
+    }
+
+    pub fn custom_properties(&self) -> &[CustomProperty] {
+        &self.custom_properties
+    }
+
     pub fn find_class_name_by_value(&self, value: &str) -> Option<&ClassName> {
         self.class_names.iter().find(|c| &c.value == value)
     }
 
+    /// The problems found parsing the css file, classes in the parts skipped over for them
+    /// being left out.
+    pub fn problems(&self) -> &[ParseProblem] {
+        &self.problems
+    }
+
+    /// The urls of the stylesheets `@import`ed, as written, e.g. `./buttons.css`.
+    pub fn imports(&self) -> &[crate::Str] {
+        &self.imports
+    }
+
+    /// Collects the classes of the Sass `code`, in the indented syntax, see [crate::sass].
+    fn from_sass(code: &str) -> Self {
+        let mut ccns = ClassNamesCollector::new();
+        ccns.class_names = crate::sass::class_names(code);
+        ccns
+    }
+
     pub fn parse(css_file: PathBuf) -> anyhow::Result<Self> {
-        let options = swc_css::parser::parser::ParserConfig::default();
+        let code = std::fs::read_to_string(&css_file)
+            .with_context(|| format!("failed to read {}", css_file.display()))?;
+
+        Ok(Self::parse_code(css_file, &code))
+    }
+
+    /// Like [ClassNamesCollector::parse], for the `code` of `css_file` already read.
+    pub fn parse_code(css_file: PathBuf, code: &str) -> Self {
+        let dialect = Dialect::of(&css_file).unwrap_or(Dialect::Css);
+        let options = dialect.parser_config();
 
-        let (cssfile, cm) = css_source_file_from(css_file)?;
+        if dialect == Dialect::Sass {
+            return Self::from_sass(code);
+        }
+
+        let cm: Lrc<SourceMap> = Default::default();
+        let cssfile = cm.new_source_file(
+            FileName::Real(css_file),
+            dialect.preprocess(code).into_owned(),
+        );
         let handler = Handler::with_tty_emitter(ColorConfig::Auto, true, false, Some(cm.clone()));
 
         let mut errors = vec![];
-        let c = parse_file::<Vec<Rule>>(&cssfile, None, options, &mut errors).unwrap();
+        let rules = match parse_file::<Vec<Rule>>(&cssfile, None, options, &mut errors) {
+            Ok(rules) => rules,
+            Err(err) => {
+                errors.push(err);
+                vec![]
+            }
+        };
+
+        let mut ccns = ClassNamesCollector::new();
+
+        ccns.visit_source(&rules, &cssfile, code);
 
         for e in errors {
             e.to_diagnostics(&handler).emit();
-        }
 
-        let mut ccns = ClassNamesCollector::new();
-
-        c.visit_with(&mut ccns);
+            let message = e.message().to_string();
+            let (span, _) = *e.into_inner();
+            let start_pos = cssfile.start_pos.0;
+            ccns.problems.push(ParseProblem {
+                message,
+                range: span.lo.0.saturating_sub(start_pos) as usize
+                    ..span.hi.0.saturating_sub(start_pos) as usize,
+            });
+        }
 
-        Ok(ccns)
+        ccns
     }
 }
 
 impl Visit for ClassNamesCollector {
+    fn visit_at_rule(&mut self, n: &swc_css::ast::AtRule) {
+        let prelude_end = n.block.as_ref().map(|b| b.span.lo).unwrap_or(n.span.hi);
+        let prelude = self
+            .source_text(Span::new(n.span.lo, prelude_end, Default::default()))
+            .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+
+        let is_import = matches!(
+            &n.name,
+            swc_css::ast::AtRuleName::Ident(name) if name.value.eq_ignore_ascii_case("import")
+        );
+        if is_import {
+            self.imports
+                .extend(import_urls(&prelude).into_iter().map(crate::Str::from));
+        }
+
+        self.at_rules.push(prelude.as_str().into());
+        self.ancestors.push(prelude.as_str().into());
+        // the selectors of a prelude, e.g. in `@supports selector(.a:has(.b))`, are conditions
+        // rather than definitions
+        n.block.visit_with(self);
+        self.ancestors.pop();
+        self.at_rules.pop();
+    }
+
+    fn visit_declaration(&mut self, n: &swc_css::ast::Declaration) {
+        if let swc_css::ast::DeclarationName::DashedIdent(_) = n.name {
+            let declaration = self
+                .source_text(n.span)
+                .and_then(|text| text.split_once(':'))
+                .map(|(name, value)| {
+                    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                    (name.trim().into(), value.as_str().into())
+                });
+
+            if let Some((name, value)) = declaration {
+                self.custom_properties.push(CustomProperty {
+                    name,
+                    value,
+                    span: n.span,
+                });
+            }
+        }
+
+        n.visit_children_with(self)
+    }
+
     fn visit_qualified_rule(&mut self, n: &swc_css::ast::QualifiedRule) {
-        self.last_rule_span = Some(n.span);
+        let outer_rule_span = self.last_rule_span.replace(n.span);
+        n.prelude.visit_with(self);
+
+        let prelude = self
+            .source_text(n.prelude.span())
+            .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        let parents = self.classes_ending(&n.prelude);
+        self.parents.push(parents);
+        self.ancestors.push(prelude.as_str().into());
+        n.block.visit_with(self);
+        self.ancestors.pop();
+        self.parents.pop();
+
+        self.last_rule_span = outer_rule_span;
+    }
+
+    fn visit_complex_selector(&mut self, n: &swc_css::ast::ComplexSelector) {
+        self.is_single_compound = n.children.len() == 1;
         n.visit_children_with(self)
     }
 
     fn visit_compound_selector(&mut self, n: &swc_css::ast::CompoundSelector) {
+        // classes are only defined by the selectors of a rule
+        let Some(span) = self.last_rule_span else {
+            return;
+        };
         let selectors = &n.subclass_selectors;
 
-        selectors
+        if let Some((suffix, selector_span)) = nesting_suffix(n) {
+            let siblings = selectors
+                .iter()
+                .filter_map(|sibling| self.source_text(sibling.span()))
+                .map(crate::Str::from)
+                .collect::<Vec<_>>();
+
+            for parent in self.parents.last().cloned().unwrap_or_default() {
+                self.class_names.push(ClassName {
+                    value: format!("{}{suffix}", &*parent.value).as_str().into(),
+                    span,
+                    siblings: siblings.clone(),
+                    definition: self.definition_of(span),
+                    selector_span,
+                    is_standalone: self.is_single_compound
+                        && parent.is_alone
+                        && selectors.is_empty(),
+                    at_rules: self.at_rules.clone(),
+                    ancestors: self.ancestors.clone(),
+                });
+            }
+        }
+
+        // `&.active` in `.card` is `.card.active`
+        let composed_with = match self.parents.last().map(Vec::as_slice) {
+            Some([parent]) if n.nesting_selector.is_some() && nesting_suffix(n).is_none() => Some(
+                crate::Str::from(format!(".{}", escape_class_name(&parent.value)).as_str()),
+            ),
+            _ => None,
+        };
+
+        for (i, s) in selectors.iter().enumerate() {
+            let swc_css::ast::SubclassSelector::Class(s) = s else {
+                continue;
+            };
+
+            let siblings = composed_with
+                .iter()
+                .cloned()
+                .chain(
+                    selectors
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .filter_map(|(_, sibling)| self.source_text(sibling.span()))
+                        .map(crate::Str::from),
+                )
+                .collect();
+
+            let value = bare_class_name(&s.text.value);
+
+            let is_standalone = self.is_single_compound
+                && n.nesting_selector.is_none()
+                && n.type_selector.is_none()
+                && selectors.len() == 1;
+
+            self.class_names.push(ClassName {
+                value: value.into(),
+                span,
+                siblings,
+                definition: self.definition_of(span),
+                selector_span: s.span,
+                is_standalone,
+                at_rules: self.at_rules.clone(),
+                ancestors: self.ancestors.clone(),
+            });
+        }
+    }
+}
+
+impl ClassNamesCollector {
+    /// The classes the selectors of a rule with the `prelude` end with, for the rules nested in
+    /// it to append to, e.g. `card` for `.card`, `btn-icon` for `&-icon` in `.btn`, but none for
+    /// `.card:hover`.
+    fn classes_ending(&self, prelude: &swc_css::ast::QualifiedRulePrelude) -> Vec<ParentClass> {
+        use swc_css::ast::{ComplexSelectorChildren, QualifiedRulePrelude, SubclassSelector};
+
+        let complex_selectors = match prelude {
+            QualifiedRulePrelude::SelectorList(list) => list.children.iter().collect(),
+            QualifiedRulePrelude::RelativeSelectorList(list) => list
+                .children
                 .iter()
-                .filter_map(|s| match s {
-                    swc_css::ast::SubclassSelector::Class(selector) => Some(selector),
-                    _ => None,
-                })
-                .for_each(|s| {
-                    if s.text.value.contains(':') {
-                        let cn = s.text.value.split(':').last().expect("should have at least one value after split, since empty selectors aren't allowed");
-
-                        self.class_names.push(ClassName {
-                            value: cn.into(),
-                            span: self.last_rule_span.unwrap_or_default()
+                .filter(|relative| relative.combinator.is_none())
+                .map(|relative| &relative.selector)
+                .collect(),
+            QualifiedRulePrelude::ListOfComponentValues(_) => vec![],
+        };
+
+        let mut classes = vec![];
+        for complex in complex_selectors {
+            let Some(ComplexSelectorChildren::CompoundSelector(last)) = complex.children.last()
+            else {
+                continue;
+            };
+            let is_single_compound = complex.children.len() == 1;
+
+            match last.subclass_selectors.last() {
+                Some(SubclassSelector::Class(class)) => classes.push(ParentClass {
+                    value: bare_class_name(&class.text.value).into(),
+                    is_alone: is_single_compound
+                        && last.nesting_selector.is_none()
+                        && last.type_selector.is_none()
+                        && last.subclass_selectors.len() == 1,
+                }),
+                Some(_) => {}
+                None => {
+                    let Some((suffix, _)) = nesting_suffix(last) else {
+                        continue;
+                    };
+                    for parent in self.parents.last().into_iter().flatten() {
+                        classes.push(ParentClass {
+                            value: format!("{}{suffix}", &*parent.value).as_str().into(),
+                            is_alone: is_single_compound && parent.is_alone,
                         });
-                    } else {
-                        self.class_names.push(ClassName { value: s.text.value.as_str().into(), span: self.last_rule_span.unwrap_or_default()});
                     }
-                });
+                }
+            }
+        }
+
+        classes
+    }
+}
+
+/// The text a compound selector appends to the selector of the rule it's nested in, along with
+/// the span of the nesting selector and that text, e.g. `-title` for `&-title`.
+fn nesting_suffix(n: &swc_css::ast::CompoundSelector) -> Option<(&str, Span)> {
+    let nesting = n.nesting_selector.as_ref()?;
+    let swc_css::ast::TypeSelector::TagName(tag) = n.type_selector.as_deref()? else {
+        return None;
+    };
+
+    let is_appended = tag.name.prefix.is_none() && tag.span.lo == nesting.span.hi;
+    is_appended.then(|| {
+        (
+            &*tag.name.value.value,
+            Span::new(nesting.span.lo, tag.span.hi, Default::default()),
+        )
+    })
+}
+
+/// The urls of the stylesheets an `@import` at-rule with the `prelude` imports, e.g.
+/// `./buttons.css` for `@import url("./buttons.css") layer(components);`, or each of a list in
+/// scss or less.
+fn import_urls(prelude: &str) -> Vec<&str> {
+    let mut urls = vec![];
+    let mut rest = prelude["@import".len().min(prelude.len())..].trim_start();
+    // the options of less, e.g. `(reference)`
+    if let Some(options) = rest.strip_prefix('(') {
+        rest = options
+            .split_once(')')
+            .map_or("", |(_, after)| after.trim_start());
+    }
+
+    loop {
+        let (url, after) = if let Some(quoted) = rest.strip_prefix(['"', '\'']) {
+            let quote = rest.as_bytes()[0] as char;
+            match quoted.split_once(quote) {
+                Some(split) => split,
+                None => break,
+            }
+        } else if let Some(inner) = rest.strip_prefix("url(") {
+            match inner.split_once(')') {
+                Some((url, after)) => (url.trim().trim_matches(['"', '\'']), after),
+                None => break,
+            }
+        } else {
+            break;
+        };
+
+        if !url.is_empty() {
+            urls.push(url);
+        }
+        match after.trim_start().strip_prefix(',') {
+            Some(next) => rest = next.trim_start(),
+            None => break,
+        }
+    }
+
+    urls
+}
+
+/// Strips the variant prefixes of an escaped Tailwind class name, e.g. `hover:` in
+/// `.hover\:bg-red`.
+pub(crate) fn bare_class_name(value: &str) -> &str {
+    value
+        .split(':')
+        .next_back()
+        .expect("should have at least one value after split, since empty selectors aren't allowed")
+}
+
+#[derive(Default)]
+struct ClassSelectorFinder {
+    selectors: Vec<(String, Span)>,
+}
+
+impl Visit for ClassSelectorFinder {
+    fn visit_class_selector(&mut self, n: &swc_css::ast::ClassSelector) {
+        self.selectors
+            .push((bare_class_name(&n.text.value).to_string(), n.span));
+    }
+}
+
+/// Finds every class selector in the css `code`, along with its byte range, dot included.
+fn class_selectors(code: &str) -> Vec<(String, std::ops::Range<usize>)> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon, code.to_string());
+
+    let Ok(rules) = parse_file::<Vec<Rule>>(&fm, None, Default::default(), &mut vec![]) else {
+        return vec![];
+    };
+
+    let mut finder = ClassSelectorFinder::default();
+    rules.visit_with(&mut finder);
+
+    let start_pos = fm.start_pos.0;
+    finder
+        .selectors
+        .into_iter()
+        .map(|(value, span)| {
+            let range = (span.lo.0 - start_pos) as usize..(span.hi.0 - start_pos) as usize;
+            (value, range)
+        })
+        .collect()
+}
+
+/// An entry of the outline of a css file: a class and the rule defining it, or an at-rule
+/// holding such rules, e.g. `@media (min-width: 640px)`.
+#[derive(Debug, PartialEq)]
+pub struct CssSymbol {
+    pub name: String,
+    pub is_at_rule: bool,
+    /// Byte offsets of the whole rule.
+    pub range: std::ops::Range<usize>,
+    /// Byte offsets of the class selector, or of the at-rule prelude.
+    pub selection_range: std::ops::Range<usize>,
+    pub children: Vec<CssSymbol>,
+}
+
+#[derive(Default)]
+struct CssOutliner {
+    start_pos: u32,
+    /// The at-rules being visited, outermost first, each with the symbols found in it so far.
+    at_rules: Vec<CssSymbol>,
+    symbols: Vec<CssSymbol>,
+    last_rule_span: Option<Span>,
+}
+
+impl CssOutliner {
+    fn range_of(&self, span: Span) -> std::ops::Range<usize> {
+        (span.lo.0 - self.start_pos) as usize..(span.hi.0 - self.start_pos) as usize
+    }
+
+    fn push(&mut self, symbol: CssSymbol) {
+        match self.at_rules.last_mut() {
+            Some(at_rule) => at_rule.children.push(symbol),
+            None => self.symbols.push(symbol),
+        }
+    }
+}
+
+impl Visit for CssOutliner {
+    fn visit_at_rule(&mut self, n: &swc_css::ast::AtRule) {
+        let prelude_end = n.block.as_ref().map(|b| b.span.lo).unwrap_or(n.span.hi);
+        let selection_range = self.range_of(Span::new(n.span.lo, prelude_end, Default::default()));
+
+        self.at_rules.push(CssSymbol {
+            name: String::new(),
+            is_at_rule: true,
+            range: self.range_of(n.span),
+            selection_range,
+            children: vec![],
+        });
+
+        n.block.visit_with(self);
+
+        let at_rule = self
+            .at_rules
+            .pop()
+            .expect("at-rule should have been pushed");
+        // only at-rules holding classes are worth showing
+        if !at_rule.children.is_empty() {
+            self.push(at_rule);
+        }
+    }
+
+    fn visit_qualified_rule(&mut self, n: &swc_css::ast::QualifiedRule) {
+        let outer_rule_span = self.last_rule_span.replace(n.span);
+        n.visit_children_with(self);
+        self.last_rule_span = outer_rule_span;
+    }
+
+    fn visit_class_selector(&mut self, n: &swc_css::ast::ClassSelector) {
+        let Some(rule_span) = self.last_rule_span else {
+            return;
+        };
+
+        self.push(CssSymbol {
+            name: bare_class_name(&n.text.value).to_string(),
+            is_at_rule: false,
+            range: self.range_of(rule_span),
+            selection_range: self.range_of(n.span),
+            children: vec![],
+        });
     }
 }
 
-pub fn css_source_file_from(
-    css_file: PathBuf,
-) -> anyhow::Result<(Lrc<SourceFile>, Lrc<SourceMap>)> {
-    let code = std::fs::read_to_string(&css_file)?;
+/// Outlines the css `code`: the classes defined in it, in order, nested in the at-rules
+/// they're in.
+pub fn css_outline(code: &str) -> Vec<CssSymbol> {
     let cm: Lrc<SourceMap> = Default::default();
-    let filename = FileName::Real(css_file);
-    Ok((cm.new_source_file(filename, code), cm))
+    let fm = cm.new_source_file(FileName::Anon, code.to_string());
+
+    let Ok(rules) = parse_file::<Vec<Rule>>(&fm, None, Default::default(), &mut vec![]) else {
+        return vec![];
+    };
+
+    let mut outliner = CssOutliner {
+        start_pos: fm.start_pos.0,
+        ..Default::default()
+    };
+    rules.visit_with(&mut outliner);
+
+    let mut symbols = outliner.symbols;
+    name_at_rules(&mut symbols, code);
+    symbols
+}
+
+/// Names the at-rules after their text up to the block, e.g. `@media (min-width: 640px)`,
+/// leaving the whitespace before the block out of their selection range.
+fn name_at_rules(symbols: &mut [CssSymbol], code: &str) {
+    for symbol in symbols.iter_mut().filter(|symbol| symbol.is_at_rule) {
+        let prelude = code[symbol.selection_range.clone()].trim_end();
+        symbol.selection_range.end = symbol.selection_range.start + prelude.len();
+        symbol.name = prelude.split_whitespace().collect::<Vec<_>>().join(" ");
+        name_at_rules(&mut symbol.children, code);
+    }
+}
+
+/// Finds the class selector under `cursor` (a byte offset into the css `code`).
+pub fn class_selector_at(code: &str, cursor: usize) -> Option<String> {
+    class_selectors(code)
+        .into_iter()
+        .find(|(_, range)| range.start <= cursor && cursor <= range.end)
+        .map(|(value, _)| value)
+}
+
+/// Finds the byte ranges of the class `value` in every selector of the css `code`, leaving out
+/// the dot and any variant prefix, e.g. only `card` in `.hover\:card`.
+pub fn class_selector_ranges(code: &str, value: &str) -> Vec<std::ops::Range<usize>> {
+    class_selectors(code)
+        .into_iter()
+        .filter(|(selector, _)| selector == value)
+        .map(|(_, range)| {
+            let name_start = range.start + 1;
+            let start = code[name_start..range.end]
+                .rfind(':')
+                .map(|i| name_start + i + 1)
+                .unwrap_or(name_start);
+            start..range.end
+        })
+        .collect()
+}
+
+/// Escapes the characters of a class name that can't be written as is in a css selector.
+pub fn escape_class_name(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if !(c.is_alphanumeric() || c == '-' || c == '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use swc_common::sync::Lrc;
+    use swc_common::{FileName, SourceMap};
+    use swc_css::ast::Rule;
+
+    use super::{
+        class_selector_at, class_selector_ranges, css_outline, escape_class_name, import_urls,
+        ClassNamesCollector,
+    };
+    use crate::dialect::Dialect;
+
+    fn collect(code: &str) -> ClassNamesCollector {
+        collect_in(code, Dialect::Css)
+    }
+
+    fn collect_in(code: &str, dialect: Dialect) -> ClassNamesCollector {
+        if dialect == Dialect::Sass {
+            return ClassNamesCollector::from_sass(code);
+        }
+
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Anon, dialect.preprocess(code).into_owned());
+        let rules = swc_css::parser::parse_file::<Vec<Rule>>(
+            &fm,
+            None,
+            dialect.parser_config(),
+            &mut vec![],
+        )
+        .unwrap();
+
+        let mut collector = ClassNamesCollector::new();
+        collector.visit_source(&rules, &fm, code);
+        collector
+    }
+
+    fn strs(values: &[&str]) -> Vec<crate::Str> {
+        values.iter().map(|v| crate::Str::from(*v)).collect()
+    }
+
+    #[test]
+    fn it_retains_compound_siblings() {
+        let collector = collect(".btn.primary:hover { color: red } .solo { color: blue }");
+
+        let btn = collector.find_class_name_by_value("btn").unwrap();
+        assert_eq!(btn.siblings, strs(&[".primary", ":hover"]));
+
+        let primary = collector.find_class_name_by_value("primary").unwrap();
+        assert_eq!(primary.siblings, strs(&[".btn", ":hover"]));
+
+        let solo = collector.find_class_name_by_value("solo").unwrap();
+        assert!(solo.siblings.is_empty());
+    }
+
+    #[test]
+    fn it_renders_attribute_siblings() {
+        let collector = collect(".card .input[disabled] { opacity: 0.5 }");
+
+        let card = collector.find_class_name_by_value("card").unwrap();
+        assert!(card.siblings.is_empty());
+
+        let input = collector.find_class_name_by_value("input").unwrap();
+        assert_eq!(input.siblings, strs(&["[disabled]"]));
+    }
+
+    #[test]
+    fn it_collects_custom_properties() {
+        let code = ":root {\n  --brand: #3b82f6;\n  --shadow:  0 1px\n    2px black;\n  color: red;\n}\n.dark { --brand: navy }";
+        let collector = collect(code);
+
+        let found = collector
+            .custom_properties()
+            .iter()
+            .map(|p| (p.name.to_string(), p.value.to_string()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            [
+                ("--brand", "#3b82f6"),
+                ("--shadow", "0 1px 2px black"),
+                ("--brand", "navy"),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn it_records_selectors_and_at_rules() {
+        let code = ".btn, .card .title { color: red }
+@media (min-width:  768px) { @supports (display: grid) { .btn:hover { color: blue } } }";
+        let collector = collect(code);
+        let text = |span: swc_common::Span| &code[span.lo.0 as usize - 1..span.hi.0 as usize - 1];
+
+        let found = collector
+            .class_names()
+            .iter()
+            .map(|c| {
+                let at_rules = c.at_rules.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                (text(c.selector_span), c.is_standalone, at_rules)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            vec![
+                (".btn", true, vec![]),
+                (".card", false, vec![]),
+                (".title", false, vec![]),
+                (
+                    ".btn",
+                    false,
+                    vec![
+                        "@media (min-width: 768px)".to_string(),
+                        "@supports (display: grid)".to_string()
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_records_imports() {
+        let code = "@import \"./buttons.css\";\n@import url(forms.css) layer(forms);\n@import url('https://x.io/a.css') screen;\n.a { color: red }";
+        let collector = collect(code);
+        let imports = collector
+            .imports()
+            .iter()
+            .map(|url| &**url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            imports,
+            vec!["./buttons.css", "forms.css", "https://x.io/a.css"]
+        );
+
+        assert_eq!(import_urls("@import \"a\", 'b' screen;"), vec!["a", "b"]);
+        assert_eq!(
+            import_urls("@import (reference) \"mixins\";"),
+            vec!["mixins"]
+        );
+    }
+
+    #[test]
+    fn it_records_parse_problems() {
+        let dir = std::env::temp_dir().join(format!("cnls-collect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_file = dir.join("broken.css");
+        let code =
+            ".ok { color: red; }\n.broken { color: red;; ] }\n.after { margin: 0; }\n.1bad { }\n";
+        std::fs::write(&css_file, code).unwrap();
+
+        let collector = ClassNamesCollector::parse(css_file).unwrap();
+        let problems = collector
+            .problems()
+            .iter()
+            .map(|p| (p.message.clone(), &code[p.range.clone()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                ("Unexpected end of file, but expected '{'".to_string(), "] "),
+                ("Unexpected token".to_string(), "]"),
+                ("Invalid selector".to_string(), ".1bad"),
+            ]
+        );
+
+        let classes = collector
+            .class_names()
+            .iter()
+            .map(|c| c.value.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(classes, vec!["ok", "broken", "after"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_finds_class_selector_at_cursor() {
+        let code = ".card .btn-primary:hover, .hover\\:bg-red { color: red }";
+
+        let cursor = code.find("primary").unwrap();
+        assert_eq!(
+            class_selector_at(code, cursor),
+            Some("btn-primary".to_owned())
+        );
+
+        let cursor = code.find("bg-red").unwrap();
+        assert_eq!(class_selector_at(code, cursor), Some("bg-red".to_owned()));
+
+        let cursor = code.find("color").unwrap();
+        assert_eq!(class_selector_at(code, cursor), None);
+    }
+
+    #[test]
+    fn it_finds_class_selector_ranges() {
+        let code =
+            ".old-card, .hover\\:old-card:hover { color: red }\n.old-card-title .old-card {}";
+
+        let ranges = class_selector_ranges(code, "old-card");
+        assert_eq!(ranges.len(), 3);
+        assert!(ranges.iter().all(|r| &code[r.clone()] == "old-card"));
+        assert_eq!(ranges[1].start, code.find("\\:").unwrap() + 2);
+
+        assert_eq!(escape_class_name("w-1/2"), "w-1\\/2");
+        assert_eq!(escape_class_name("card_title-2"), "card_title-2");
+    }
+
+    #[test]
+    fn it_outlines_classes_in_at_rules() {
+        let code = ".btn, .card { padding: 0 }
+@media (min-width: 640px) {
+  .sm\\:btn { padding: 1px }
+}
+@font-face { font-family: x }
+";
+        let outline = css_outline(code);
+
+        let names = outline
+            .iter()
+            .map(|s| (s.name.as_str(), s.children.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![("btn", 0), ("card", 0), ("@media (min-width: 640px)", 1)]
+        );
+
+        assert_eq!(&code[outline[1].selection_range.clone()], ".card");
+        assert_eq!(
+            &code[outline[1].range.clone()],
+            ".btn, .card { padding: 0 }"
+        );
+
+        let nested = &outline[2].children[0];
+        assert_eq!(nested.name, "btn");
+        assert_eq!(&code[nested.selection_range.clone()], ".sm\\:btn");
+        assert!(code[outline[2].range.clone()].ends_with("}\n}"));
+        assert_eq!(
+            &code[outline[2].selection_range.clone()],
+            "@media (min-width: 640px)"
+        );
+    }
+
+    /// Renders what's collected from the css `code`, class by class, with the text of
+    /// the selector and rule (whitespace collapsed) it was found in.
+    fn render_snapshot(code: &str, dialect: Dialect) -> String {
+        let collapse = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text_of =
+            |span: swc_common::Span| &code[span.lo.0 as usize - 1..span.hi.0 as usize - 1];
+
+        let mut snapshot = String::new();
+        for class in collect_in(code, dialect).class_names() {
+            snapshot += &format!("{}\n", &*class.value);
+            snapshot += &format!("  selector: {}\n", text_of(class.selector_span));
+            snapshot += &format!(
+                "  rule: {}\n",
+                collapse(&code[class.definition.rule.clone()])
+            );
+            if !class.at_rules.is_empty() {
+                let at_rules = class.at_rules.iter().map(|a| &**a).collect::<Vec<_>>();
+                snapshot += &format!("  at-rules: {}\n", at_rules.join(" > "));
+            }
+            if class.ancestors != class.at_rules {
+                let ancestors = class.ancestors.iter().map(|a| &**a).collect::<Vec<_>>();
+                snapshot += &format!("  nested in: {}\n", ancestors.join(" > "));
+            }
+            if !class.siblings.is_empty() {
+                let siblings = class.siblings.iter().map(|s| &**s).collect::<Vec<_>>();
+                snapshot += &format!("  siblings: {}\n", siblings.join(" "));
+            }
+            if class.is_standalone {
+                snapshot += "  standalone\n";
+            }
+        }
+
+        snapshot
+    }
+
+    /// Compares what's collected from each stylesheet in `tests/fixtures/collect` with the
+    /// `.snap` file next to it. Run with `CNLS_BLESS=1` to write the snapshots anew, e.g. after
+    /// adding a fixture, then review their diff.
+    #[test]
+    fn it_matches_fixture_snapshots() {
+        let fixtures =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/collect");
+        let bless = std::env::var_os("CNLS_BLESS").is_some();
+
+        let mut css_files = std::fs::read_dir(&fixtures)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| Dialect::of(path).is_some())
+            .collect::<Vec<_>>();
+        css_files.sort();
+        assert!(!css_files.is_empty());
+
+        let mut mismatches = vec![];
+        for css_file in css_files {
+            let dialect = Dialect::of(&css_file).unwrap();
+            let actual = render_snapshot(&std::fs::read_to_string(&css_file).unwrap(), dialect);
+            let snap_file = css_file.with_extension("snap");
+
+            if bless {
+                std::fs::write(&snap_file, &actual).unwrap();
+                continue;
+            }
+
+            let expected = std::fs::read_to_string(&snap_file).unwrap_or_default();
+            if expected != actual {
+                mismatches.push(format!(
+                    "{}:\n--- expected\n{expected}--- actual\n{actual}",
+                    snap_file.display()
+                ));
+            }
+        }
+
+        assert!(
+            mismatches.is_empty(),
+            "snapshots changed, rerun with CNLS_BLESS=1 to accept them:\n\n{}",
+            mismatches.join("\n")
+        );
+    }
 }
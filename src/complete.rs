@@ -0,0 +1,486 @@
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::css::{self, ClassDefinition, RuleTextError, SourceText};
+use crate::scope::Scope;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use swc_common::sync::Lrc;
+use swc_common::{FileName, SourceMap, Span, Spanned};
+use swc_css::ast::{AtRule, AtRuleName, ComponentValue, Declaration, DeclarationName, Stylesheet};
+use swc_css::visit::{Visit, VisitWith};
+use swc_ecma_parser::Syntax;
+use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, CompletionTextEdit, TextEdit};
+
+use crate::collect::ClassNamesCollector;
+use crate::find::{self, class_name_substrings, raw_class_name_substrings};
+use crate::index::{CssIndex, PathPriority};
+use crate::workspace::WorkspaceContext;
+
+/// A class list, of an `@apply` at-rule, a `composes` declaration or a scoped string, as found
+/// around the cursor.
+#[derive(Debug, PartialEq)]
+pub struct ClassListContext {
+    /// Byte range of the class name being typed, empty when the cursor is on whitespace.
+    pub typed: Range<usize>,
+    /// The other class names already in the list.
+    pub listed: Vec<String>,
+}
+
+impl ClassListContext {
+    /// Finds the class name being typed at `cursor` in the class list at `list` (byte ranges
+    /// into `code`).
+    fn in_list(code: &str, list: Range<usize>, cursor: usize, raw: bool) -> Self {
+        let substrings = if raw {
+            raw_class_name_substrings(&code[list.clone()])
+        } else {
+            class_name_substrings(&code[list.clone()])
+        };
+
+        let mut typed = cursor..cursor;
+        let mut listed = vec![];
+
+        for (s, e) in substrings {
+            let (s, e) = (list.start + s, list.start + e + 1);
+            if s <= cursor && cursor <= e {
+                typed = s..e;
+            } else {
+                listed.push(code[s..e].to_string());
+            }
+        }
+
+        Self { typed, listed }
+    }
+
+    /// The part of the typed class name to complete, after any variant prefix, e.g. `bg-` in
+    /// `hover:bg-`, since the index has the bare class names.
+    pub fn completed_range(&self, code: &str) -> Range<usize> {
+        let start = code[self.typed.clone()]
+            .rfind(':')
+            .map(|i| self.typed.start + i + 1)
+            .unwrap_or(self.typed.start);
+
+        start..self.typed.end
+    }
+}
+
+struct ApplyRuleFinder {
+    cursor: u32,
+    found: Option<(u32, u32)>,
+}
+
+impl Visit for ApplyRuleFinder {
+    fn visit_at_rule(&mut self, n: &AtRule) {
+        if let AtRuleName::Ident(name) = &n.name {
+            let prelude_end = n
+                .prelude
+                .as_ref()
+                .map(|p| p.span().hi)
+                .unwrap_or(name.span.hi);
+
+            if &*name.value == "apply"
+                && name.span.hi.0 <= self.cursor
+                && self.cursor <= prelude_end.0
+            {
+                self.found = Some((name.span.hi.0, prelude_end.0));
+                return;
+            }
+        }
+
+        n.visit_children_with(self)
+    }
+
+    fn visit_declaration(&mut self, n: &Declaration) {
+        let DeclarationName::Ident(name) = &n.name else {
+            return;
+        };
+        if &*name.value != "composes" {
+            return;
+        }
+
+        // the classes of another css module follow them, e.g. `composes: a b from "./c.css"`
+        let classes_end = n
+            .value
+            .iter()
+            .find(|value| matches!(value, ComponentValue::Ident(ident) if &*ident.value == "from"))
+            .map(|from| from.span().lo)
+            .unwrap_or(n.span.hi);
+        let classes_start = n
+            .value
+            .first()
+            .map(|value| value.span().lo)
+            .unwrap_or(classes_end);
+
+        if classes_start.0 <= self.cursor && self.cursor <= classes_end.0 {
+            self.found = Some((classes_start.0, classes_end.0));
+        }
+    }
+}
+
+/// Finds the `@apply` or `composes` class list containing `cursor` (a byte offset into the css
+/// `code`).
+pub fn apply_context_at(code: &str, cursor: usize) -> Option<ClassListContext> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon, code.to_string());
+
+    let mut errors = vec![];
+    let stylesheet =
+        swc_css::parser::parse_file::<Stylesheet>(&fm, None, Default::default(), &mut errors)
+            .ok()?;
+
+    let mut finder = ApplyRuleFinder {
+        cursor: fm.start_pos.0 + cursor as u32,
+        found: None,
+    };
+    stylesheet.visit_with(&mut finder);
+
+    let (start, end) = finder.found?;
+    let start = (start - fm.start_pos.0) as usize;
+    let end = (end - fm.start_pos.0) as usize;
+
+    Some(ClassListContext::in_list(code, start..end, cursor, false))
+}
+
+/// Finds the scoped string containing `cursor` (a byte offset into the source `code`).
+pub fn class_string_context_at(
+    path: PathBuf,
+    syntax: Syntax,
+    code: &str,
+    scopes: &[Scope],
+    cursor: usize,
+) -> anyhow::Result<Option<ClassListContext>> {
+    let context = find::scoped_class_lists(path, syntax, code.to_string(), scopes)?
+        .into_iter()
+        .find(|list| list.start <= cursor && cursor <= list.end)
+        .map(|list| ClassListContext::in_list(code, list, cursor, true));
+
+    Ok(context)
+}
+
+/// Where a completed class is defined, kept in the completion item to load its rule when the
+/// item is resolved.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionData {
+    pub css_file: PathBuf,
+    pub span: Span,
+    pub definition: ClassDefinition,
+}
+
+/// Reads the rule defining the class `label`, re-parsing the css file to find the rule again
+/// if the file changed since it was indexed.
+pub fn rule_text(label: &str, data: &CompletionData) -> anyhow::Result<String> {
+    let source = SourceText::File(&data.css_file);
+
+    match css::extract_rule_text(&data.definition, &source) {
+        Err(RuleTextError::Stale) => {}
+        result => return Ok(result?),
+    }
+
+    let collector = ClassNamesCollector::parse(data.css_file.clone())?;
+    let class = collector.find_class_name_by_value(label).ok_or_else(|| {
+        anyhow!(
+            "class {label:?} is no longer defined in {}",
+            data.css_file.display()
+        )
+    })?;
+
+    Ok(css::extract_rule_text(&class.definition, &source)?)
+}
+
+/// How a completion item ranks among the others, compared field by field, then by label.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompletionRank {
+    /// Lower ranks first, e.g. the [PathPriority] of the css file defining the class.
+    pub priority: u8,
+    /// Higher ranks first, how well the item matches what was typed.
+    pub score: u32,
+    /// Higher ranks first, how often the item is used, when known.
+    pub frequency: u32,
+}
+
+impl CompletionRank {
+    /// The `sort_text` of an item labelled `label`. Its fields are zero-padded to a fixed width,
+    /// so that sorting the texts as strings ranks the items as intended, without ever leaving
+    /// two items equal for the client to order by its locale.
+    pub fn sort_text(&self, label: &str) -> String {
+        format!(
+            "{:03}.{:010}.{:010}.{label}",
+            self.priority,
+            u32::MAX - self.score,
+            u32::MAX - self.frequency,
+        )
+    }
+}
+
+/// Builds completion items for every indexed class name starting with `typed`, except those in
+/// `exclude`, each replacing the text in `range`. Those defined in first party css files rank
+/// first, then the shorter names.
+pub fn class_name_completions(
+    css_index: &CssIndex,
+    range: tower_lsp::lsp_types::Range,
+    typed: &str,
+    exclude: &[String],
+    workspace: &WorkspaceContext,
+) -> Vec<CompletionItem> {
+    let mut seen = std::collections::HashSet::new();
+
+    css_index
+        .class_names()
+        .filter(|(_, class)| class.value.starts_with(typed))
+        .filter(|(_, class)| !exclude.iter().any(|e| *e == *class.value))
+        .filter(|(_, class)| seen.insert(class.value.to_string()))
+        .map(|(css_file, class)| CompletionItem {
+            label: class.value.to_string(),
+            kind: Some(CompletionItemKind::CLASS),
+            detail: Some(workspace.display_path(css_file)),
+            sort_text: Some(
+                CompletionRank {
+                    priority: PathPriority::of(
+                        workspace.root_of(css_file).unwrap_or(css_file),
+                        css_file,
+                    ) as u8,
+                    score: u32::MAX - (class.value.len() - typed.len()) as u32,
+                    frequency: 0,
+                }
+                .sort_text(&class.value),
+            ),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: class.value.to_string(),
+            })),
+            data: serde_json::to_value(CompletionData {
+                css_file: css_file.to_path_buf(),
+                span: class.span,
+                definition: class.definition.clone(),
+            })
+            .ok(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Range;
+
+    use std::str::FromStr;
+
+    use crate::scope::Scope;
+
+    use super::{
+        apply_context_at, class_name_completions, class_string_context_at, rule_text,
+        ClassListContext, CompletionRank,
+    };
+    use crate::index::CssIndex;
+    use crate::workspace::WorkspaceContext;
+
+    fn context_at_marker(code: &str) -> Option<ClassListContext> {
+        let cursor = code.find('|').unwrap();
+        apply_context_at(&code.replace('|', ""), cursor)
+    }
+
+    #[test]
+    fn it_finds_typed_class_in_apply() {
+        let ctx = context_at_marker(".btn {\n  @apply px-4 py|;\n}\n").unwrap();
+        assert_eq!(ctx.typed, 21..23);
+        assert_eq!(ctx.listed, vec!["px-4"]);
+
+        let ctx = context_at_marker(".btn {\n  @apply px-4 p|\n}\n").unwrap();
+        assert_eq!(ctx.typed, 21..22);
+        assert_eq!(ctx.listed, vec!["px-4"]);
+
+        let ctx = context_at_marker(".btn {\n  @apply p|x-4 rounded;\n}\n").unwrap();
+        assert_eq!(ctx.typed, 16..20);
+        assert_eq!(ctx.listed, vec!["rounded"]);
+    }
+
+    #[test]
+    fn it_finds_empty_slot_in_apply() {
+        let ctx = context_at_marker(".btn {\n  @apply |\n}\n").unwrap();
+        assert_eq!(ctx.typed, 16..16);
+        assert!(ctx.listed.is_empty());
+
+        let ctx = context_at_marker(".btn { @apply px-4 | rounded; }").unwrap();
+        assert_eq!(ctx.typed, 19..19);
+        assert_eq!(ctx.listed, vec!["px-4", "rounded"]);
+    }
+
+    #[test]
+    fn it_finds_nothing_outside_apply() {
+        assert_eq!(context_at_marker(".btn {\n  color: re|d;\n}\n"), None);
+        assert_eq!(context_at_marker(".bt|n {\n  @apply px-4;\n}\n"), None);
+        assert_eq!(
+            context_at_marker("@media (min-width: 1|0px) { .a { @apply px-4; } }"),
+            None
+        );
+        assert_eq!(context_at_marker(".btn { @ap|ply px-4; }"), None);
+    }
+
+    #[test]
+    fn it_finds_apply_nested_in_at_rules() {
+        let ctx = context_at_marker("@media (min-width: 10px) { .a { @apply px-4 m|; } }").unwrap();
+        assert_eq!(ctx.listed, vec!["px-4"]);
+    }
+
+    #[test]
+    fn it_finds_typed_class_in_composes() {
+        let ctx = context_at_marker(".a {\n  composes: btn ro|unded;\n}\n").unwrap();
+        assert_eq!(ctx.typed, 21..28);
+        assert_eq!(ctx.listed, vec!["btn"]);
+
+        let ctx = context_at_marker(".a { composes: b|tn from \"./b.css\"; }").unwrap();
+        assert_eq!(ctx.typed, 15..18);
+        assert!(ctx.listed.is_empty());
+
+        assert_eq!(
+            context_at_marker(".a { composes: btn from \"./b|.css\"; }"),
+            None
+        );
+        assert_eq!(context_at_marker(".a { color: re|d; }"), None);
+    }
+
+    #[test]
+    fn it_resolves_rule_text_of_completions() {
+        let dir = std::env::temp_dir().join(format!("cnls-complete-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_file = dir.join("a.css");
+        std::fs::write(&css_file, ".flex { display: flex; }\n.btn { padding: 0; }").unwrap();
+
+        let (css_index, _) = CssIndex::parse_files(vec![css_file.clone()]);
+        let items = class_name_completions(
+            &css_index,
+            Range::default(),
+            "",
+            &[],
+            &WorkspaceContext::default(),
+        );
+        let btn = items.iter().find(|item| item.label == "btn").unwrap();
+        let data = serde_json::from_value(btn.data.clone().unwrap()).unwrap();
+
+        assert_eq!(rule_text("btn", &data).unwrap(), ".btn { padding: 0; }");
+
+        std::fs::write(&css_file, ".btn {\n  padding: 1px;\n}").unwrap();
+
+        assert_eq!(
+            rule_text("btn", &data).unwrap(),
+            ".btn {\n  padding: 1px;\n}"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_finds_typed_class_in_scoped_strings() {
+        let scopes = [Scope::from_str("att:className").unwrap()];
+        let context_at_marker = |code: &str| {
+            let cursor = code.find('|').unwrap();
+            let code = code.replace('|', "");
+            let syntax = crate::find::syntax_of_file(std::path::Path::new("test.tsx")).unwrap();
+            let ctx =
+                class_string_context_at("test.tsx".into(), syntax, &code, &scopes, cursor).unwrap();
+            ctx.map(|ctx| (code[ctx.completed_range(&code)].to_string(), ctx.listed))
+        };
+
+        assert_eq!(
+            context_at_marker(r#"<div className="flex hover:bg-|" />"#),
+            Some(("bg-".to_owned(), vec!["flex".to_owned()]))
+        );
+        assert_eq!(
+            context_at_marker(r#"<div className="md:hover:te|xt-sm p-2" />"#),
+            Some(("text-sm".to_owned(), vec!["p-2".to_owned()]))
+        );
+        assert_eq!(
+            context_at_marker(r#"<div className={`p-2 |`} />"#),
+            Some(("".to_owned(), vec!["p-2".to_owned()]))
+        );
+        assert_eq!(
+            context_at_marker(r#"<div className="|" />"#),
+            Some(("".to_owned(), vec![]))
+        );
+        assert_eq!(context_at_marker(r#"<div title="fl|ex" />"#), None);
+    }
+
+    /// A few thousand ranks, spread over the whole range of each field, the same every run.
+    fn sample_ranks() -> Vec<(CompletionRank, String)> {
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let labels = [
+            "flex",
+            "flex-col",
+            "p-2",
+            "btn",
+            "btn-ghost",
+            "hover:bg",
+            "",
+        ];
+        (0..4000)
+            .map(|_| {
+                let pick = |n: u64, max: u32| match n % 4 {
+                    0 => 0,
+                    1 => max,
+                    _ => (n >> 8) as u32 % 64,
+                };
+                let rank = CompletionRank {
+                    priority: (next() % 3) as u8,
+                    score: pick(next(), u32::MAX),
+                    frequency: pick(next(), u32::MAX),
+                };
+                (rank, labels[next() as usize % labels.len()].to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_sorts_texts_by_rank_then_label() {
+        let ranks = sample_ranks();
+        assert_eq!(ranks, sample_ranks());
+
+        let mut by_text = ranks.clone();
+        by_text.sort_by_key(|(rank, label)| rank.sort_text(label));
+
+        let mut by_rank = ranks.clone();
+        by_rank.sort_by_key(|(rank, label)| {
+            (
+                rank.priority,
+                std::cmp::Reverse(rank.score),
+                std::cmp::Reverse(rank.frequency),
+                label.clone(),
+            )
+        });
+
+        assert_eq!(by_text, by_rank);
+
+        let width = |label: &str| CompletionRank::default().sort_text(label).len() - label.len();
+        assert_eq!(width(""), width("btn-ghost"));
+    }
+
+    #[test]
+    fn it_ranks_first_party_then_shorter_completions_first() {
+        let dir = std::env::temp_dir().join(format!("cnls-sort-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("node_modules/ui")).unwrap();
+        let (ours, theirs) = (dir.join("a.css"), dir.join("node_modules/ui/b.css"));
+        std::fs::write(&ours, ".flex-col {} .flex-row {}").unwrap();
+        std::fs::write(&theirs, ".flex {} .flex-wrap {}").unwrap();
+
+        let (css_index, _) = CssIndex::parse_files(vec![theirs, ours]);
+        let workspace = WorkspaceContext::new(vec![dir.clone()]);
+        let labels = || {
+            let mut items =
+                class_name_completions(&css_index, Range::default(), "fl", &[], &workspace);
+            items.sort_by_key(|item| item.sort_text.clone());
+            items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+        };
+
+        assert_eq!(labels(), ["flex-col", "flex-row", "flex", "flex-wrap"]);
+        assert_eq!(labels(), labels());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
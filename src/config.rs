@@ -0,0 +1,1184 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config_file;
+use crate::fs::{is_gitignored, CssFileGlobs, CssFileLimits};
+use crate::protocol::EffectiveConfiguration;
+use crate::scope::Scope;
+use anyhow::anyhow;
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use tower_lsp::lsp_types::Position;
+
+use crate::fallback::FallbackPatterns;
+
+/// What to do with stylesheets that look minified or are too large, see [CssFileLimits].
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexMinified {
+    #[default]
+    Skip,
+    Index,
+    /// Index them, but only after every other file, so their definitions rank last.
+    LowPriority,
+}
+
+impl FromStr for IndexMinified {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "index" => Ok(Self::Index),
+            "lowPriority" => Ok(Self::LowPriority),
+            _ => Err(anyhow!(
+                "unrecognized value: {s:?}, expected one of skip | index | lowPriority"
+            )),
+        }
+    }
+}
+
+/// How the definitions of a class found in different css files are ordered, see
+/// `cnls.resolution.order`.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResolutionOrder {
+    /// The css files closest to the document first, see [crate::index::compare_proximity].
+    #[default]
+    Proximity,
+    /// As indexed, by workspace-relative path, see [crate::index::compare_definitions].
+    Alphabetical,
+}
+
+impl FromStr for ResolutionOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proximity" => Ok(Self::Proximity),
+            "alphabetical" => Ok(Self::Alphabetical),
+            _ => Err(anyhow!(
+                "unrecognized value: {s:?}, expected one of proximity | alphabetical"
+            )),
+        }
+    }
+}
+
+/// How diagnostics of a kind are reported, if at all.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticLevel {
+    Off,
+    #[default]
+    Warn,
+    Error,
+}
+
+impl FromStr for DiagnosticLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => Err(anyhow!(
+                "unrecognized value: {s:?}, expected one of off | warn | error"
+            )),
+        }
+    }
+}
+
+/// Words made of the characters of class names, Tailwind arbitrary values included (e.g.
+/// `bg-[url('/a.png')]`, `[&>*]:p-2`, `!-mt-1/2`), not ending like a word of a sentence does.
+pub const DEFAULT_TOKEN_PATTERN: &str =
+    r#"^[a-zA-Z0-9_\[\]()&>*:/%#.,'"=+@~!-]{0,127}[a-zA-Z0-9_\])%*-]$"#;
+
+/// Which tokens of the scoped strings are taken for class names when `cnls.strictTokens` is
+/// on: those matching the pattern, or defined as is in the css files.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenFilter {
+    #[serde(rename = "strictTokens")]
+    pub strict: bool,
+    #[serde(rename = "tokenPattern", serialize_with = "serialize_regex")]
+    pub pattern: Regex,
+}
+
+impl Default for TokenFilter {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            pattern: Regex::new(DEFAULT_TOKEN_PATTERN).expect("default regex should be valid"),
+        }
+    }
+}
+
+impl TokenFilter {
+    pub fn accepts(&self, token: &str, is_defined: bool) -> bool {
+        !self.strict || is_defined || self.pattern.is_match(token)
+    }
+}
+
+/// How each kind of diagnostics is reported, see `cnls.diagnostics`.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticLevels {
+    /// How class names defined in no css file are reported.
+    pub unknown_class: DiagnosticLevel,
+    /// How classes defined by several rules are reported, in the css files defining them.
+    pub duplicate_class: DiagnosticLevel,
+    /// Whether source documents are diagnosed once saved rather than as they're typed.
+    pub on_save_only: bool,
+}
+
+/// How class lists are sorted, see `cnls.sort`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SortOptions {
+    /// The prefixes grouping classes when sorting a class list, e.g. `md:` for the classes of
+    /// a tailwind variant, in order. The classes without any come first.
+    pub order: Vec<String>,
+}
+
+/// Inlay hints naming the css files defining the classes of each scoped string, see
+/// `cnls.inlayHints`.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct InlayHintOptions {
+    pub enabled: bool,
+}
+
+/// What hovering a class name shows, see `cnls.hover`.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HoverOptions {
+    /// How many of the rules defining the class are shown, the others being only counted.
+    pub max_rules: usize,
+}
+
+impl Default for HoverOptions {
+    fn default() -> Self {
+        Self { max_rules: 5 }
+    }
+}
+
+/// Where going to the definition of a class, and hovering it, look for its rules, see
+/// `cnls.definition`.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefinitionOptions {
+    /// Whether only the css files a document imports are looked in, rather than every other
+    /// one when none of them defines the class.
+    pub imported_only: bool,
+    /// Whether only the css files of the package a document is in, under its nearest
+    /// `package.json`, are looked in, rather than only first.
+    pub package_scoped: bool,
+}
+
+/// Which stylesheets are looked for, by globs of their paths relative to the workspace
+/// folder, see `cnls.css`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CssOptions {
+    /// The stylesheets looked for, every one when empty.
+    pub include: Vec<String>,
+    /// The stylesheets and folders left out, besides [EXCLUDED_DIRS](crate::fs::EXCLUDED_DIRS),
+    /// those starting with `!` being looked in after all, e.g. `!node_modules`.
+    pub exclude: Vec<String>,
+    /// Both, compiled.
+    #[serde(skip)]
+    pub globs: CssFileGlobs,
+    /// Whether the stylesheets and folders `.gitignore` files ignore are left out too.
+    pub respect_gitignore: bool,
+    /// Whether the folders links lead to are walked, those leading back to a folder being
+    /// walked aside.
+    pub follow_symlinks: bool,
+}
+
+impl CssOptions {
+    /// Whether the stylesheet at `path` is looked for walking `root`, as when the index is
+    /// built.
+    pub fn includes(&self, root: &Path, path: &Path) -> bool {
+        self.globs.includes(root, path) && !(self.respect_gitignore && is_gitignored(root, path))
+    }
+}
+
+impl Default for CssOptions {
+    fn default() -> Self {
+        Self {
+            include: vec![],
+            exclude: vec![],
+            globs: CssFileGlobs::default(),
+            respect_gitignore: true,
+            follow_symlinks: true,
+        }
+    }
+}
+
+/// How the definitions of a class are ranked, see `cnls.resolution`.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct ResolutionOptions {
+    pub order: ResolutionOrder,
+}
+
+/// The links from class names to the rules defining them, see `cnls.documentLinks`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentLinkOptions {
+    /// The fragment of the link targets, with `{line}` and `{column}` replaced by those of the
+    /// rule, counted from 1. Empty for links to the css file alone, for clients that don't
+    /// support fragments.
+    pub fragment: String,
+}
+
+impl Default for DocumentLinkOptions {
+    fn default() -> Self {
+        Self {
+            fragment: "L{line}".to_string(),
+        }
+    }
+}
+
+impl DocumentLinkOptions {
+    /// The fragment of a link to the rule at `position`, if any.
+    pub fn fragment_at(&self, position: Position) -> Option<String> {
+        if self.fragment.is_empty() {
+            return None;
+        }
+
+        let fragment = self
+            .fragment
+            .replace("{line}", &(position.line + 1).to_string())
+            .replace("{column}", &(position.character + 1).to_string());
+
+        Some(fragment)
+    }
+}
+
+/// Where the value of a setting comes from.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConfigSource {
+    #[default]
+    Default,
+    /// An environment variable, e.g. `CNLS_SCOPES`.
+    Environment,
+    /// The `cnls.toml` file at the root of the workspace.
+    File,
+    /// The `initializationOptions` of the client, given once when it starts the server.
+    InitializationOptions,
+    /// The settings of the client, given by `workspace/didChangeConfiguration` or pulled.
+    Client,
+}
+
+pub const SCOPES_ENV_VAR: &str = "CNLS_SCOPES";
+
+/// The settings of cnls, serialized as the `cnls` settings section that would give them.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    #[serde(serialize_with = "serialize_scopes")]
+    pub scopes: Vec<Scope>,
+    pub index_minified: IndexMinified,
+    #[serde(flatten)]
+    pub css_file_limits: CssFileLimits,
+    pub fallback_patterns: FallbackPatterns,
+    /// Requests taking longer are logged with a breakdown of where the time went.
+    #[serde(serialize_with = "serialize_millis")]
+    pub slow_request_threshold: Duration,
+    #[serde(flatten)]
+    pub token_filter: TokenFilter,
+    /// How long hover and go to definition wait for the css index, before looking up the
+    /// stylesheets near the document instead.
+    #[serde(serialize_with = "serialize_millis")]
+    pub index_wait_budget: Duration,
+    /// How often the indexed css files are checked for changes on disk, when the client
+    /// doesn't watch them.
+    #[serde(serialize_with = "serialize_millis")]
+    pub css_check_interval: Duration,
+    pub diagnostics: DiagnosticLevels,
+    /// Whether the problems found parsing css files are published against them.
+    pub report_css_parse_errors: bool,
+    /// How many `@import`s away from the indexed css files stylesheets are indexed too, `0`
+    /// following none.
+    pub follow_imports: usize,
+    pub css: CssOptions,
+    pub sort: SortOptions,
+    pub inlay_hints: InlayHintOptions,
+    pub document_links: DocumentLinkOptions,
+    pub hover: HoverOptions,
+    pub definition: DefinitionOptions,
+    pub resolution: ResolutionOptions,
+    /// Where each top level setting comes from, those missing being defaults.
+    #[serde(skip)]
+    pub provenance: BTreeMap<String, ConfigSource>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let default_scopes =
+            ["att:className,class", "fn:createElement"].map(|s| Scope::from_str(s).unwrap());
+        Self {
+            scopes: default_scopes.to_vec(),
+            index_minified: IndexMinified::default(),
+            css_file_limits: CssFileLimits::default(),
+            fallback_patterns: FallbackPatterns::default(),
+            slow_request_threshold: Duration::from_millis(500),
+            token_filter: TokenFilter::default(),
+            index_wait_budget: Duration::from_secs(2),
+            css_check_interval: Duration::from_secs(2),
+            diagnostics: DiagnosticLevels::default(),
+            report_css_parse_errors: false,
+            follow_imports: 5,
+            css: CssOptions::default(),
+            sort: SortOptions::default(),
+            inlay_hints: InlayHintOptions::default(),
+            document_links: DocumentLinkOptions::default(),
+            hover: HoverOptions::default(),
+            definition: DefinitionOptions::default(),
+            resolution: ResolutionOptions::default(),
+            provenance: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// The globs of the files the css index is built from, for the client to watch.
+    pub fn css_watch_globs(&self) -> Vec<String> {
+        vec![format!("**/*.{{{}}}", crate::dialect::EXTENSIONS.join(","))]
+    }
+
+    /// Applies the settings found in a `cnls` settings section of the client, leaving untouched
+    /// whatever the section doesn't mention. Invalid entries are skipped and reported back to
+    /// the caller.
+    pub fn update_from_json(&mut self, section: &Value) -> Vec<anyhow::Error> {
+        self.update_from_section(section, ConfigSource::Client)
+    }
+
+    /// Like [Config::update_from_json], for the `initializationOptions` of the client, which
+    /// its settings override later on.
+    pub fn update_from_initialization_options(&mut self, options: &Value) -> Vec<anyhow::Error> {
+        match settings_section(options) {
+            Some(section) => self.update_from_section(section, ConfigSource::InitializationOptions),
+            None => vec![],
+        }
+    }
+
+    /// Like [Config::update_from_json], for a `cnls.toml` file whose keys are those of the
+    /// settings section. Errors point at the file, and at the line when it can't be parsed.
+    pub fn update_from_file(&mut self, path: &Path) -> Vec<anyhow::Error> {
+        let section = match std::fs::read_to_string(path) {
+            Ok(text) => config_file::parse(&text),
+            Err(err) => return vec![anyhow!("failed to read {}: {err}", path.display())],
+        };
+
+        match section {
+            Ok(section) => self
+                .update_from_section(&section, ConfigSource::File)
+                .into_iter()
+                .map(|err| err.context(path.display().to_string()))
+                .collect(),
+            Err(err) => vec![anyhow!("{}:{}: {}", path.display(), err.line, err.message)],
+        }
+    }
+
+    fn update_from_section(&mut self, section: &Value, source: ConfigSource) -> Vec<anyhow::Error> {
+        let before = self.to_json();
+        let errors = self.apply_section(section);
+        let after = self.to_json();
+
+        // a setting comes from the section if it changed, or if it was given as is
+        for key in after.keys() {
+            let is_given = section.get(key).is_some()
+                && !errors
+                    .iter()
+                    .any(|err| format!("{err:#}").contains(&format!("cnls.{key}")));
+            if is_given || before.get(key) != after.get(key) {
+                self.provenance.insert(key.clone(), source);
+            }
+        }
+
+        errors
+    }
+
+    /// The settings as a `cnls` settings section.
+    fn to_json(&self) -> serde_json::Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(map)) => map,
+            _ => unreachable!("the config serializes to an object"),
+        }
+    }
+
+    /// The top level settings that differ in `other`, e.g. to log what a reload changed.
+    pub fn changed_settings(&self, other: &Config) -> Vec<String> {
+        let (before, after) = (self.to_json(), other.to_json());
+        after
+            .into_iter()
+            .filter(|(key, value)| before.get(key) != Some(value))
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// The settings in effect, along with where each comes from.
+    pub fn effective(&self) -> EffectiveConfiguration {
+        let config = self.to_json();
+        let provenance = config
+            .keys()
+            .map(|key| {
+                let source = self.provenance.get(key).copied().unwrap_or_default();
+                (key.clone(), source)
+            })
+            .collect();
+
+        EffectiveConfiguration {
+            config: Value::Object(config),
+            provenance,
+        }
+    }
+
+    fn apply_section(&mut self, section: &Value) -> Vec<anyhow::Error> {
+        let mut errors = vec![];
+
+        match section.get("scopes") {
+            None => {}
+            Some(Value::Array(arr)) => {
+                let scopes = arr
+                    .iter()
+                    .filter_map(|v| {
+                        let s = v.as_str();
+                        if s.is_none() {
+                            errors.push(anyhow!("cnls.scopes: expected a string, got {v}"));
+                        }
+                        s
+                    })
+                    .collect::<Vec<_>>();
+
+                errors.extend(self.set_scopes(scopes, "cnls.scopes"));
+            }
+            Some(_) => errors.push(anyhow!("cnls.scopes should be an array of strings")),
+        }
+
+        match section.get("indexMinified") {
+            None => {}
+            Some(Value::String(s)) => match IndexMinified::from_str(s) {
+                Ok(v) => self.index_minified = v,
+                Err(err) => errors.push(err.context("cnls.indexMinified")),
+            },
+            Some(_) => errors.push(anyhow!("cnls.indexMinified should be a string")),
+        }
+
+        match section.get("maxFileSize") {
+            None => {}
+            Some(v) => match v.as_u64() {
+                Some(n) => self.css_file_limits.max_file_size = n,
+                None => errors.push(anyhow!("cnls.maxFileSize should be a positive integer")),
+            },
+        }
+
+        match section.get("maxLineLength") {
+            None => {}
+            Some(v) => match v.as_u64() {
+                Some(n) => self.css_file_limits.max_line_length = n as usize,
+                None => errors.push(anyhow!("cnls.maxLineLength should be a positive integer")),
+            },
+        }
+
+        match section.get("slowRequestThreshold") {
+            None => {}
+            Some(v) => match v.as_u64() {
+                Some(ms) => self.slow_request_threshold = Duration::from_millis(ms),
+                None => errors.push(anyhow!(
+                    "cnls.slowRequestThreshold should be a positive integer, in milliseconds"
+                )),
+            },
+        }
+
+        match section.get("indexWaitBudget") {
+            None => {}
+            Some(v) => match v.as_u64() {
+                Some(ms) => self.index_wait_budget = Duration::from_millis(ms),
+                None => errors.push(anyhow!(
+                    "cnls.indexWaitBudget should be a positive integer, in milliseconds"
+                )),
+            },
+        }
+
+        match section.get("cssCheckInterval") {
+            None => {}
+            Some(v) => match v.as_u64() {
+                Some(ms) => self.css_check_interval = Duration::from_millis(ms),
+                None => errors.push(anyhow!(
+                    "cnls.cssCheckInterval should be a positive integer, in milliseconds"
+                )),
+            },
+        }
+
+        match section.get("reportCssParseErrors") {
+            None => {}
+            Some(Value::Bool(report)) => self.report_css_parse_errors = *report,
+            Some(_) => errors.push(anyhow!("cnls.reportCssParseErrors should be a boolean")),
+        }
+
+        match section.get("followImports") {
+            None => {}
+            Some(v) => match v.as_u64() {
+                Some(depth) => self.follow_imports = depth as usize,
+                None => errors.push(anyhow!("cnls.followImports should be a positive integer")),
+            },
+        }
+
+        let mut css_globs = [
+            ("include", self.css.include.clone()),
+            ("exclude", self.css.exclude.clone()),
+        ];
+        let mut css_globs_changed = false;
+        for (key, globs) in &mut css_globs {
+            match section.get("css").and_then(|css| css.get(*key)) {
+                None => {}
+                Some(Value::Array(arr)) => match arr
+                    .iter()
+                    .map(|v| v.as_str().map(String::from))
+                    .collect::<Option<Vec<_>>>()
+                {
+                    Some(value) => {
+                        *globs = value;
+                        css_globs_changed = true;
+                    }
+                    None => errors.push(anyhow!("cnls.css.{key} should be an array of strings")),
+                },
+                Some(_) => errors.push(anyhow!("cnls.css.{key} should be an array of strings")),
+            }
+        }
+        if css_globs_changed {
+            let [(_, include), (_, exclude)] = css_globs;
+            match CssFileGlobs::new(&include, &exclude) {
+                Ok(globs) => {
+                    self.css.include = include;
+                    self.css.exclude = exclude;
+                    self.css.globs = globs;
+                }
+                Err(err) => errors.push(err.context("cnls.css")),
+            }
+        }
+
+        match section
+            .get("css")
+            .and_then(|css| css.get("respectGitignore"))
+        {
+            None => {}
+            Some(Value::Bool(respect)) => self.css.respect_gitignore = *respect,
+            Some(_) => errors.push(anyhow!("cnls.css.respectGitignore should be a boolean")),
+        }
+
+        match section.get("css").and_then(|css| css.get("followSymlinks")) {
+            None => {}
+            Some(Value::Bool(follow)) => self.css.follow_symlinks = *follow,
+            Some(_) => errors.push(anyhow!("cnls.css.followSymlinks should be a boolean")),
+        }
+
+        match section.get("sort").and_then(|sort| sort.get("order")) {
+            None => {}
+            Some(Value::Array(arr)) => match arr
+                .iter()
+                .map(|v| v.as_str().map(String::from))
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(order) => self.sort.order = order,
+                None => errors.push(anyhow!("cnls.sort.order should be an array of strings")),
+            },
+            Some(_) => errors.push(anyhow!("cnls.sort.order should be an array of strings")),
+        }
+
+        match section
+            .get("inlayHints")
+            .and_then(|hints| hints.get("enabled"))
+        {
+            None => {}
+            Some(Value::Bool(enabled)) => self.inlay_hints.enabled = *enabled,
+            Some(_) => errors.push(anyhow!("cnls.inlayHints.enabled should be a boolean")),
+        }
+
+        match section
+            .get("documentLinks")
+            .and_then(|links| links.get("fragment"))
+        {
+            None => {}
+            Some(Value::String(fragment)) => self.document_links.fragment = fragment.clone(),
+            Some(_) => errors.push(anyhow!("cnls.documentLinks.fragment should be a string")),
+        }
+
+        match section.get("hover").and_then(|hover| hover.get("maxRules")) {
+            None => {}
+            Some(value) => match value.as_u64().filter(|max| *max > 0) {
+                Some(max) => self.hover.max_rules = max as usize,
+                None => errors.push(anyhow!("cnls.hover.maxRules should be a positive integer")),
+            },
+        }
+
+        match section
+            .get("definition")
+            .and_then(|definition| definition.get("importedOnly"))
+        {
+            None => {}
+            Some(Value::Bool(imported_only)) => self.definition.imported_only = *imported_only,
+            Some(_) => errors.push(anyhow!("cnls.definition.importedOnly should be a boolean")),
+        }
+
+        match section
+            .get("definition")
+            .and_then(|definition| definition.get("packageScoped"))
+        {
+            None => {}
+            Some(Value::Bool(package_scoped)) => self.definition.package_scoped = *package_scoped,
+            Some(_) => errors.push(anyhow!("cnls.definition.packageScoped should be a boolean")),
+        }
+
+        match section
+            .get("resolution")
+            .and_then(|resolution| resolution.get("order"))
+        {
+            None => {}
+            Some(Value::String(s)) => match ResolutionOrder::from_str(s) {
+                Ok(order) => self.resolution.order = order,
+                Err(err) => errors.push(err.context("cnls.resolution.order")),
+            },
+            Some(_) => errors.push(anyhow!("cnls.resolution.order should be a string")),
+        }
+
+        match section.get("strictTokens") {
+            None => {}
+            Some(Value::Bool(strict)) => self.token_filter.strict = *strict,
+            Some(_) => errors.push(anyhow!("cnls.strictTokens should be a boolean")),
+        }
+
+        match section.get("tokenPattern") {
+            None => {}
+            Some(Value::String(s)) => match Regex::new(s) {
+                Ok(pattern) => self.token_filter.pattern = pattern,
+                Err(err) => errors.push(anyhow!(err).context("cnls.tokenPattern")),
+            },
+            Some(_) => errors.push(anyhow!("cnls.tokenPattern should be a regex string")),
+        }
+
+        let diagnostic_levels = [
+            ("unknownClass", &mut self.diagnostics.unknown_class),
+            ("duplicateClass", &mut self.diagnostics.duplicate_class),
+        ];
+        for (key, level) in diagnostic_levels {
+            match section.get("diagnostics").and_then(|d| d.get(key)) {
+                None => {}
+                Some(Value::String(s)) => match DiagnosticLevel::from_str(s) {
+                    Ok(v) => *level = v,
+                    Err(err) => errors.push(err.context(format!("cnls.diagnostics.{key}"))),
+                },
+                Some(_) => errors.push(anyhow!("cnls.diagnostics.{key} should be a string")),
+            }
+        }
+
+        match section.get("diagnostics").and_then(|d| d.get("onSaveOnly")) {
+            None => {}
+            Some(Value::Bool(on_save_only)) => self.diagnostics.on_save_only = *on_save_only,
+            Some(_) => errors.push(anyhow!("cnls.diagnostics.onSaveOnly should be a boolean")),
+        }
+
+        if let Some(v) = section.get("fallbackPatterns") {
+            match FallbackPatterns::from_json(v) {
+                Ok(patterns) => self.fallback_patterns = patterns,
+                Err(err) => errors.push(err.context("cnls.fallbackPatterns")),
+            }
+        }
+
+        errors
+    }
+
+    /// Replaces the scopes with the valid ones among `scopes`, returning an error for each
+    /// invalid entry, attributed to `source`.
+    pub fn set_scopes<'a>(
+        &mut self,
+        scopes: impl IntoIterator<Item = &'a str>,
+        source: &'static str,
+    ) -> Vec<anyhow::Error> {
+        let mut errors = vec![];
+
+        self.scopes.clear();
+
+        for s in scopes {
+            match Scope::from_str(s) {
+                Ok(scope) => self.scopes.push(scope),
+                Err(err) => errors.push(err.context(source)),
+            }
+        }
+
+        errors
+    }
+
+    /// Applies the `CNLS_SCOPES` environment variable, if it's set.
+    pub fn update_from_env(&mut self) -> Vec<anyhow::Error> {
+        match std::env::var(SCOPES_ENV_VAR) {
+            Ok(value) => {
+                self.provenance
+                    .insert("scopes".to_string(), ConfigSource::Environment);
+                self.set_scopes(
+                    split_scope_list(&value).iter().map(|s| s.as_str()),
+                    SCOPES_ENV_VAR,
+                )
+            }
+            Err(_) => vec![],
+        }
+    }
+}
+
+fn serialize_scopes<S: Serializer>(scopes: &[Scope], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(scopes.iter().map(|scope| scope.to_string()))
+}
+
+fn serialize_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+fn serialize_regex<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+/// Splits a list of scopes separated by semicolons or commas, e.g.
+/// `att:className,class;fn:cn,clsx` or `att:className,class,fn:cn,clsx`.
+///
+/// Since commas also separate the values of a scope, a comma only starts a new scope when the
+/// part following it names a variant, i.e. contains a `:`.
+pub fn split_scope_list(s: &str) -> Vec<String> {
+    let mut scopes: Vec<String> = vec![];
+
+    for group in s.split(';') {
+        let mut current: Option<String> = None;
+
+        for part in group.split(',').map(str::trim) {
+            match &mut current {
+                Some(scope) if !part.contains(':') => {
+                    scope.push(',');
+                    scope.push_str(part);
+                }
+                _ => {
+                    if let Some(scope) = current.take() {
+                        scopes.push(scope);
+                    }
+                    if !part.is_empty() {
+                        current = Some(part.to_string());
+                    }
+                }
+            }
+        }
+
+        scopes.extend(current);
+    }
+
+    scopes
+}
+
+/// Finds the `cnls` section in a `workspace/didChangeConfiguration` payload.
+///
+/// Clients disagree on the shape: some send the whole settings tree (`{"cnls": {...}}`), others
+/// send the section contents directly (`{"scopes": [...]}`), and some send `null` expecting the
+/// server to pull. `None` means there is nothing to apply and the settings should be pulled.
+pub fn settings_section(settings: &Value) -> Option<&Value> {
+    match settings {
+        Value::Object(map) if map.is_empty() => None,
+        Value::Object(map) => match map.get("cnls") {
+            Some(Value::Null) => None,
+            Some(section) => Some(section),
+            None => Some(settings),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        settings_section, split_scope_list, Config, ConfigSource, DiagnosticLevel, IndexMinified,
+        ResolutionOrder,
+    };
+    use crate::scope::Scope;
+    use serde_json::json;
+    use std::path::Path;
+    use std::str::FromStr;
+    use tower_lsp::lsp_types::Position;
+
+    fn apply(settings: serde_json::Value) -> (Config, usize) {
+        let mut config = Config::default();
+        let errors = settings_section(&settings)
+            .map(|section| config.update_from_json(section))
+            .unwrap_or_default();
+        (config, errors.len())
+    }
+
+    #[test]
+    fn it_reads_nested_section() {
+        let (config, errors) = apply(json!({ "cnls": { "scopes": ["fn:cva"] } }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.scopes, vec![Scope::from_str("fn:cva").unwrap()]);
+    }
+
+    #[test]
+    fn it_reads_flat_section() {
+        let (config, errors) = apply(json!({ "scopes": ["fn:cva", "att:class"] }));
+        assert_eq!(errors, 0);
+        assert_eq!(
+            config.scopes,
+            vec![
+                Scope::from_str("fn:cva").unwrap(),
+                Scope::from_str("att:class").unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn it_finds_nothing_in_empty_settings() {
+        assert!(settings_section(&json!(null)).is_none());
+        assert!(settings_section(&json!({})).is_none());
+        assert!(settings_section(&json!({ "cnls": null })).is_none());
+
+        let (config, errors) = apply(json!(null));
+        assert_eq!(errors, 0);
+        assert_eq!(config.scopes, Config::default().scopes);
+    }
+
+    #[test]
+    fn it_reports_invalid_entries() {
+        let (config, errors) = apply(json!({ "cnls": { "scopes": ["fn:cva", "nope", 4] } }));
+        assert_eq!(errors, 2);
+        assert_eq!(config.scopes, vec![Scope::from_str("fn:cva").unwrap()]);
+
+        let (config, errors) = apply(json!({ "scopes": "fn:cva" }));
+        assert_eq!(errors, 1);
+        assert_eq!(config.scopes, Config::default().scopes);
+    }
+
+    #[test]
+    fn it_reads_minified_settings() {
+        let (config, errors) = apply(json!({
+            "cnls": { "indexMinified": "lowPriority", "maxFileSize": 2048, "maxLineLength": 500 }
+        }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.index_minified, IndexMinified::LowPriority);
+        assert_eq!(config.css_file_limits.max_file_size, 2048);
+        assert_eq!(config.css_file_limits.max_line_length, 500);
+
+        let (config, errors) = apply(json!({ "indexMinified": "sometimes", "maxFileSize": -1 }));
+        assert_eq!(errors, 2);
+        assert_eq!(config.index_minified, IndexMinified::Skip);
+    }
+
+    #[test]
+    fn it_reads_slow_request_threshold() {
+        let (config, errors) = apply(json!({ "slowRequestThreshold": 200 }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.slow_request_threshold.as_millis(), 200);
+
+        let (config, errors) = apply(json!({ "slowRequestThreshold": "fast" }));
+        assert_eq!(errors, 1);
+        assert_eq!(config.slow_request_threshold.as_millis(), 500);
+
+        let (config, errors) = apply(json!({ "indexWaitBudget": 100 }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.index_wait_budget.as_millis(), 100);
+
+        let (config, errors) = apply(json!({ "cssCheckInterval": 0 }));
+        assert_eq!(errors, 0);
+        assert!(config.css_check_interval.is_zero());
+    }
+
+    #[test]
+    fn it_reads_diagnostic_levels() {
+        let (config, errors) =
+            apply(json!({ "cnls": { "diagnostics": { "unknownClass": "error" } } }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.diagnostics.unknown_class, DiagnosticLevel::Error);
+
+        let (config, errors) = apply(json!({
+            "diagnostics": { "unknownClass": "loud", "duplicateClass": "off" }
+        }));
+        assert_eq!(errors, 1);
+        assert_eq!(config.diagnostics.unknown_class, DiagnosticLevel::Warn);
+        assert_eq!(config.diagnostics.duplicate_class, DiagnosticLevel::Off);
+        assert!(!config.diagnostics.on_save_only);
+        assert!(!config.report_css_parse_errors);
+
+        let (config, errors) = apply(json!({ "diagnostics": { "onSaveOnly": true } }));
+        assert_eq!(errors, 0);
+        assert!(config.diagnostics.on_save_only);
+
+        let (config, errors) = apply(json!({ "reportCssParseErrors": true }));
+        assert_eq!(errors, 0);
+        assert!(config.report_css_parse_errors);
+
+        let (config, errors) = apply(json!({ "followImports": 0 }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.follow_imports, 0);
+
+        let (config, errors) = apply(json!({ "followImports": "all" }));
+        assert_eq!(errors, 1);
+        assert_eq!(config.follow_imports, 5);
+
+        let (config, errors) = apply(json!({ "inlayHints": { "enabled": true } }));
+        assert_eq!(errors, 0);
+        assert!(config.inlay_hints.enabled);
+    }
+
+    #[test]
+    fn it_reads_the_max_rules_of_hovers() {
+        let (config, errors) = apply(json!({ "hover": { "maxRules": 2 } }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.hover.max_rules, 2);
+
+        let (config, errors) = apply(json!({ "hover": { "maxRules": 0 } }));
+        assert_eq!(errors, 1);
+        assert_eq!(config.hover.max_rules, 5);
+    }
+
+    #[test]
+    fn it_reads_where_definitions_are_looked_for() {
+        let (config, errors) = apply(json!({}));
+        assert_eq!(errors, 0);
+        assert!(!config.definition.imported_only);
+
+        let (config, errors) = apply(json!({ "definition": { "importedOnly": true } }));
+        assert_eq!(errors, 0);
+        assert!(config.definition.imported_only);
+
+        let (config, errors) = apply(json!({ "definition": { "importedOnly": "yes" } }));
+        assert_eq!(errors, 1);
+        assert!(!config.definition.imported_only);
+
+        let (config, errors) = apply(json!({ "definition": { "packageScoped": true } }));
+        assert_eq!(errors, 0);
+        assert!(config.definition.package_scoped);
+        assert!(!config.definition.imported_only);
+
+        let (config, errors) = apply(json!({ "resolution": { "order": "alphabetical" } }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.resolution.order, ResolutionOrder::Alphabetical);
+
+        let (config, errors) = apply(json!({ "resolution": { "order": "nearest" } }));
+        assert_eq!(errors, 1);
+        assert_eq!(config.resolution.order, ResolutionOrder::Proximity);
+    }
+
+    #[test]
+    fn it_formats_document_link_fragments() {
+        let position = Position::new(4, 2);
+
+        let (config, _) = apply(json!({}));
+        assert_eq!(
+            config.document_links.fragment_at(position).as_deref(),
+            Some("L5")
+        );
+
+        let (config, errors) = apply(json!({ "documentLinks": { "fragment": "{line}:{column}" } }));
+        assert_eq!(errors, 0);
+        assert_eq!(
+            config.document_links.fragment_at(position).as_deref(),
+            Some("5:3")
+        );
+
+        let (config, _) = apply(json!({ "documentLinks": { "fragment": "" } }));
+        assert_eq!(config.document_links.fragment_at(position), None);
+    }
+
+    #[test]
+    fn it_reads_which_css_files_are_looked_for() {
+        let root = Path::new("/app");
+        let (config, errors) = apply(json!({}));
+        assert_eq!(errors, 0);
+        assert!(!config
+            .css
+            .globs
+            .includes(root, Path::new("/app/node_modules/ui/ui.css")));
+
+        let (config, errors) = apply(json!({
+            "css": { "include": ["src/**"], "exclude": ["vendor", "!node_modules"] }
+        }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.css.include, ["src/**"]);
+        let globs = &config.css.globs;
+        assert!(globs.includes(root, Path::new("/app/src/node_modules/ui.css")));
+        assert!(!globs.includes(root, Path::new("/app/src/vendor/a.css")));
+        assert!(!globs.includes(root, Path::new("/app/src/dist/a.css")));
+        assert!(!globs.includes(root, Path::new("/app/global.css")));
+
+        let (config, errors) = apply(json!({ "css": { "exclude": "vendor" } }));
+        assert_eq!(errors, 1);
+        assert!(config.css.exclude.is_empty());
+
+        let (config, errors) = apply(json!({ "css": { "include": ["src/["], "exclude": ["x"] } }));
+        assert_eq!(errors, 1);
+        assert!(config.css.include.is_empty());
+        assert!(config.css.exclude.is_empty());
+
+        assert!(config.css.respect_gitignore);
+        let (config, errors) = apply(json!({ "css": { "respectGitignore": false } }));
+        assert_eq!(errors, 0);
+        assert!(!config.css.respect_gitignore);
+        assert!(config.css.follow_symlinks);
+        let (config, errors) = apply(json!({ "css": { "followSymlinks": "no" } }));
+        assert_eq!(errors, 1);
+        assert!(config.css.follow_symlinks);
+    }
+
+    #[test]
+    fn it_reads_the_sort_order() {
+        let (config, errors) = apply(json!({ "sort": { "order": ["sm:", "md:"] } }));
+        assert_eq!(errors, 0);
+        assert_eq!(config.sort.order, ["sm:", "md:"]);
+
+        let (config, errors) = apply(json!({ "sort": { "order": ["sm:", 2] } }));
+        assert_eq!(errors, 1);
+        assert!(config.sort.order.is_empty());
+    }
+
+    #[test]
+    fn it_filters_tokens_when_strict() {
+        let (config, errors) = apply(json!({ "strictTokens": true }));
+        assert_eq!(errors, 0);
+        let filter = &config.token_filter;
+
+        for token in [
+            "flex",
+            "-mx-2",
+            "!mt-0",
+            "w-1/2",
+            "hover:bg-red-500",
+            "bg-[url('/img/hero.png')]",
+            "grid-cols-[1fr_auto]",
+            "w-[calc(100%-2rem)]",
+            "[&>*]:p-2",
+            "text-[#fff]",
+            "x",
+        ] {
+            assert!(filter.accepts(token, false), "{token} should be accepted");
+        }
+
+        for token in ["failed.", "really?", "wait;", "connect,", &"a".repeat(200)] {
+            assert!(!filter.accepts(token, false), "{token} should be rejected");
+            assert!(filter.accepts(token, true));
+        }
+
+        assert!(Config::default().token_filter.accepts("failed.", false));
+
+        let (config, errors) = apply(json!({ "strictTokens": true, "tokenPattern": "^[a-z]+$" }));
+        assert_eq!(errors, 0);
+        assert!(!config.token_filter.accepts("p-2", false));
+
+        let (_, errors) = apply(json!({ "strictTokens": "yes", "tokenPattern": "[" }));
+        assert_eq!(errors, 2);
+    }
+
+    #[test]
+    fn it_splits_scope_lists() {
+        assert_eq!(
+            split_scope_list("att:className,class;fn:cn,clsx"),
+            vec!["att:className,class", "fn:cn,clsx"]
+        );
+        assert_eq!(
+            split_scope_list("att:className,class,fn:cn,clsx"),
+            vec!["att:className,class", "fn:cn,clsx"]
+        );
+        assert_eq!(
+            split_scope_list(" fn:cva ; ;prop:*ClassName,"),
+            vec!["fn:cva", "prop:*ClassName,"]
+        );
+        assert!(split_scope_list("").is_empty());
+    }
+
+    #[test]
+    fn it_reports_invalid_scope_list_entries() {
+        let mut config = Config::default();
+        let errors = config.set_scopes(
+            split_scope_list("fn:cn;nope:cn;att:")
+                .iter()
+                .map(|s| s.as_str()),
+            "CNLS_SCOPES",
+        );
+        assert_eq!(errors.len(), 2);
+        assert_eq!(config.scopes, vec![Scope::from_str("fn:cn").unwrap()]);
+    }
+
+    #[test]
+    fn it_lists_the_changed_settings() {
+        let config = Config::default();
+        let mut other = config.clone();
+        assert!(config.changed_settings(&other).is_empty());
+
+        let errors = other.update_from_json(&json!({
+            "scopes": ["att:className", "fn:cva"],
+            "diagnostics": { "unknownClass": "error" },
+            "strictTokens": false
+        }));
+        assert!(errors.is_empty());
+        assert_eq!(config.changed_settings(&other), ["diagnostics", "scopes"]);
+    }
+
+    #[test]
+    fn it_serializes_as_the_settings_section_giving_it() {
+        let (config, errors) = apply(json!({
+            "scopes": ["att:className,*Class", "fn:cn"],
+            "indexMinified": "lowPriority",
+            "maxLineLength": 200,
+            "diagnostics": { "duplicateClass": "off" },
+            "sort": { "order": ["md:"] },
+            "strictTokens": true,
+        }));
+        assert_eq!(errors, 0);
+
+        let effective = config.effective();
+        let mut reapplied = Config::default();
+        assert!(reapplied.update_from_json(&effective.config).is_empty());
+        assert_eq!(reapplied.effective().config, effective.config);
+
+        assert_eq!(
+            effective.config["scopes"],
+            json!(["att:className,*Class", "fn:cn"])
+        );
+        assert_eq!(effective.config["slowRequestThreshold"], json!(500));
+        assert_eq!(effective.config["diagnostics"]["duplicateClass"], "off");
+
+        assert_eq!(effective.provenance["maxLineLength"], ConfigSource::Client);
+        assert_eq!(effective.provenance["diagnostics"], ConfigSource::Client);
+        assert_eq!(effective.provenance["maxFileSize"], ConfigSource::Default);
+        assert_eq!(effective.provenance["tokenPattern"], ConfigSource::Default);
+    }
+
+    #[test]
+    fn it_leaves_invalid_settings_to_their_previous_source() {
+        let (config, errors) = apply(json!({ "indexMinified": "sometimes", "maxFileSize": 1 }));
+        assert_eq!(errors, 1);
+
+        let effective = config.effective();
+        assert_eq!(effective.provenance["indexMinified"], ConfigSource::Default);
+        assert_eq!(effective.provenance["maxFileSize"], ConfigSource::Client);
+    }
+
+    #[test]
+    fn it_reads_initialization_options() {
+        let mut config = Config::default();
+        let errors = config.update_from_initialization_options(
+            &json!({ "scopes": ["att:className", "fn:cva", "nope"] }),
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(config.scopes.len(), 2);
+        assert_eq!(
+            config.effective().provenance["scopes"],
+            ConfigSource::InitializationOptions
+        );
+
+        config.update_from_json(&json!({ "scopes": ["att:className"] }));
+        assert_eq!(config.scopes.len(), 1);
+        assert_eq!(
+            config.effective().provenance["scopes"],
+            ConfigSource::Client
+        );
+
+        assert!(config
+            .update_from_initialization_options(&json!(null))
+            .is_empty());
+    }
+}
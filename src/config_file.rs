@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+/// The names of the configuration file looked for at the root of a workspace, in order.
+pub const CONFIG_FILE_NAMES: [&str; 2] = ["cnls.toml", ".cnls.toml"];
+
+/// The configuration file at the `root` of a workspace, if there's one.
+pub fn find_in(root: &Path) -> Option<PathBuf> {
+    CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| root.join(name))
+        .find(|path| path.is_file())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// Counted from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a TOML file into the `cnls` settings section it stands for, e.g. with dates as the
+/// strings they are written as.
+pub fn parse(text: &str) -> Result<Value, ParseError> {
+    match toml::from_str::<toml::Table>(text) {
+        Ok(table) => Ok(to_json(toml::Value::Table(table))),
+        Err(err) => Err(ParseError {
+            line: err
+                .span()
+                .and_then(|span| text.get(..span.start))
+                .map_or(1, |before| before.matches('\n').count() + 1),
+            message: err.message().trim().replace('\n', ", "),
+        }),
+    }
+}
+
+fn to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(n) => Value::from(n),
+        toml::Value::Float(n) => Value::from(n),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(values) => Value::Array(values.into_iter().map(to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, to_json(value)))
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse;
+
+    #[test]
+    fn it_parses_settings() {
+        let text = r#"# shared by the whole team
+scopes = [
+  "att:className,class",
+  'fn:cva', # variants
+]
+strictTokens = true
+maxFileSize = 1_048_576
+hover.maxRules = 2
+
+[diagnostics]
+unknownClass = "error" # loud
+"duplicateClass" = "off"
+
+[documentLinks]
+fragment = "L{line}C{column}"
+"#;
+
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "scopes": ["att:className,class", "fn:cva"],
+                "strictTokens": true,
+                "maxFileSize": 1048576,
+                "hover": { "maxRules": 2 },
+                "diagnostics": { "unknownClass": "error", "duplicateClass": "off" },
+                "documentLinks": { "fragment": "L{line}C{column}" }
+            })
+        );
+
+        assert_eq!(
+            parse("diagnostics = { unknownClass = \"off\", onSaveOnly = true }\r\n").unwrap(),
+            json!({ "diagnostics": { "unknownClass": "off", "onSaveOnly": true } })
+        );
+        assert_eq!(parse("").unwrap(), json!({}));
+        assert_eq!(
+            parse("[[themes]]\nname = \"dark\"\nratio = 1.5\n").unwrap(),
+            json!({ "themes": [{ "name": "dark", "ratio": 1.5 }] })
+        );
+    }
+
+    #[test]
+    fn it_reports_the_line_of_errors() {
+        let error = |text: &str| parse(text).unwrap_err();
+
+        let error_at_yes = error("scopes = [\"att:className\"]\n\nstrictTokens = yes\n");
+        assert_eq!(error_at_yes.line, 3);
+        assert!(!error_at_yes.message.is_empty());
+        assert_eq!(error("scopes = [\n  \"a\"\n  \"b\"\n]").line, 3);
+        assert_eq!(error("a = 1\na = 2").line, 2);
+        assert_eq!(error("a = 1\n[a]").line, 2);
+        assert_eq!(error("a = \"open\nb = 1").line, 1);
+        assert_eq!(error("a = 1 b = 2").line, 1);
+    }
+}
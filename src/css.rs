@@ -0,0 +1,626 @@
+//! Reading back the css rules class names are defined in.
+
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dialect::Dialect;
+
+use swc_common::sync::Lrc;
+use swc_common::{FileName, SourceMap, Span, Spanned};
+use swc_css::ast::{AtRuleName, ComponentValue, DeclarationName, QualifiedRule, Rule, Stylesheet};
+use swc_css::codegen::writer::basic::{BasicCssWriter, BasicCssWriterConfig};
+use swc_css::codegen::{CodeGenerator, CodegenConfig, Emit};
+
+/// Where a class is defined: the byte range of its rule in the css file, along with a
+/// fingerprint of the file as it was when parsed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassDefinition {
+    /// Byte offsets into the css file, starting at 0.
+    pub rule: Range<usize>,
+    pub source_len: usize,
+    pub source_hash: u64,
+}
+
+impl ClassDefinition {
+    /// `rule` is a byte range into the `source` text it was parsed from.
+    pub fn new(rule: Range<usize>, source: &str) -> Self {
+        Self {
+            rule,
+            source_len: source.len(),
+            source_hash: hash_source(source),
+        }
+    }
+
+    fn matches(&self, source: &str) -> bool {
+        self.source_len == source.len() && self.source_hash == hash_source(source)
+    }
+}
+
+pub fn hash_source(source: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The text to read a rule from.
+pub enum SourceText<'a> {
+    /// The text the definition was parsed from, e.g. kept around by the caller.
+    Retained(&'a str),
+    /// A css file, read afresh, which may have changed since the definition was parsed.
+    File(&'a Path),
+}
+
+#[derive(Debug)]
+pub enum RuleTextError {
+    Io(std::io::Error),
+    /// The source text isn't the one the definition was parsed from.
+    Stale,
+    OutOfBounds {
+        rule: Range<usize>,
+        source_len: usize,
+    },
+}
+
+impl Display for RuleTextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleTextError::Io(err) => write!(f, "failed to read css source file: {err}"),
+            RuleTextError::Stale => write!(f, "css source changed since it was indexed"),
+            RuleTextError::OutOfBounds { rule, source_len } => write!(
+                f,
+                "rule at {rule:?} is out of the css source of {source_len} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RuleTextError {}
+
+/// Reads the text of the rule `def` points to, refusing to if the `source` no longer matches
+/// the one it was parsed from.
+pub fn extract_rule_text(
+    def: &ClassDefinition,
+    source: &SourceText,
+) -> Result<String, RuleTextError> {
+    let read;
+    let text = match source {
+        SourceText::Retained(text) => *text,
+        SourceText::File(path) => {
+            read = std::fs::read_to_string(path).map_err(RuleTextError::Io)?;
+            read.as_str()
+        }
+    };
+
+    if !def.matches(text) {
+        return Err(RuleTextError::Stale);
+    }
+
+    text.get(def.rule.clone())
+        .map(str::to_string)
+        .ok_or_else(|| RuleTextError::OutOfBounds {
+            rule: def.rule.clone(),
+            source_len: text.len(),
+        })
+}
+
+/// The text of the comments right above the rule starting at byte `rule_start` of the css
+/// `source`, without their markers, e.g. documenting the class. The comments must start their
+/// lines and follow each other, and at most one blank line may separate them from the rule, so
+/// that those of a previous rule aren't taken for this one's.
+pub fn preceding_comment(source: &str, rule_start: usize) -> Option<String> {
+    let mut before = source.get(..rule_start)?;
+    let mut comments = vec![];
+    let mut max_line_breaks = 2;
+
+    loop {
+        let trimmed = before.trim_end();
+        let line_breaks = before[trimmed.len()..].matches('\n').count();
+        if line_breaks > max_line_breaks || (line_breaks == 0 && !trimmed.is_empty()) {
+            break;
+        }
+
+        let Some(rest) = trimmed.strip_suffix("*/") else {
+            break;
+        };
+        let Some(start) = rest.rfind("/*") else {
+            break;
+        };
+
+        let starts_line = rest[..start]
+            .rsplit('\n')
+            .next()
+            .is_some_and(|line| line.trim().is_empty());
+        if !starts_line {
+            break;
+        }
+
+        comments.push(&rest[start + 2..]);
+        before = &rest[..start];
+        max_line_breaks = 1;
+    }
+
+    let text = comments
+        .iter()
+        .rev()
+        .flat_map(|comment| comment.lines())
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.trim()
+        })
+        .skip_while(|line| line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let text = text.trim_end();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The custom properties the `rule` refers to through `var()`, once each, in the order they first
+/// appear, e.g. `--brand` in `color: var(--brand, blue)`.
+pub fn referenced_custom_properties(rule: &str) -> Vec<&str> {
+    let mut names = vec![];
+
+    for (i, _) in rule.match_indices("var(") {
+        let args = rule[i + "var(".len()..].trim_start();
+        if !args.starts_with("--") {
+            continue;
+        }
+
+        let end = args
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()))
+            .unwrap_or(args.len());
+        let name = &args[..end];
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    names
+}
+
+/// The classes the `rule` applies the declarations of through `@apply`, in order, e.g. `px-4` and
+/// `rounded` in `.btn { @apply px-4 rounded; }`, leaving out those of the rules nested in it.
+pub fn applied_class_names(rule: &str, dialect: Dialect) -> Vec<String> {
+    let Some(parsed) = parse_rule(rule, dialect) else {
+        return vec![];
+    };
+
+    let mut names = vec![];
+    for value in &parsed.block.value {
+        let ComponentValue::AtRule(at_rule) = value else {
+            continue;
+        };
+        let is_apply = matches!(
+            &at_rule.name,
+            AtRuleName::Ident(name) if name.value.eq_ignore_ascii_case("apply")
+        );
+        let Some(prelude) = at_rule.prelude.as_ref().filter(|_| is_apply) else {
+            continue;
+        };
+
+        let applied = text_of(rule, prelude.span())
+            .split_whitespace()
+            .filter(|name| *name != "!important");
+        for name in applied {
+            if !names.iter().any(|applied| applied == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    names
+}
+
+/// A `composes` declaration of a css module rule, e.g. `composes: base large from "./shared.css"`.
+#[derive(Debug, PartialEq)]
+pub struct Composes {
+    pub classes: Vec<String>,
+    /// The stylesheet the classes are in, as written, `global` for global classes, or `None`
+    /// for the one of the rule.
+    pub from: Option<String>,
+}
+
+/// The `composes` declarations of the `rule` itself, in order.
+pub fn composes(rule: &str, dialect: Dialect) -> Vec<Composes> {
+    let Some(parsed) = parse_rule(rule, dialect) else {
+        return vec![];
+    };
+
+    let mut composes = vec![];
+    for value in &parsed.block.value {
+        let ComponentValue::Declaration(declaration) = value else {
+            continue;
+        };
+        let is_composes = matches!(
+            &declaration.name,
+            DeclarationName::Ident(name) if name.value.eq_ignore_ascii_case("composes")
+        );
+        if !is_composes {
+            continue;
+        }
+
+        let mut values = declaration.value.iter();
+        let mut classes = vec![];
+        let mut from = None;
+        while let Some(value) = values.next() {
+            match value {
+                ComponentValue::Ident(ident) if &*ident.value == "from" => {
+                    from = match values.next() {
+                        Some(ComponentValue::Str(url)) => Some(url.value.to_string()),
+                        Some(ComponentValue::Ident(ident)) => Some(ident.value.to_string()),
+                        _ => None,
+                    };
+                    break;
+                }
+                ComponentValue::Ident(ident) => classes.push(ident.value.to_string()),
+                _ => {}
+            }
+        }
+
+        composes.push(Composes { classes, from });
+    }
+
+    composes
+}
+
+/// The declarations of the `rule` itself, as written, e.g. `padding: 0` in
+/// `.btn { padding: 0; &:hover { color: red; } }`.
+pub fn declarations(rule: &str, dialect: Dialect) -> Vec<String> {
+    let Some(parsed) = parse_rule(rule, dialect) else {
+        return vec![];
+    };
+
+    parsed
+        .block
+        .value
+        .iter()
+        .filter_map(|value| match value {
+            ComponentValue::Declaration(declaration) => Some(declaration.span),
+            _ => None,
+        })
+        .map(|span| {
+            text_of(rule, span)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+/// The first rule of the `rule` text, unless it's in the indented syntax of Sass, which swc
+/// doesn't parse.
+fn parse_rule(rule: &str, dialect: Dialect) -> Option<QualifiedRule> {
+    if dialect == Dialect::Sass {
+        return None;
+    }
+
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon, dialect.preprocess(rule).into_owned());
+
+    let mut errors = vec![];
+    let rules =
+        swc_css::parser::parse_file::<Vec<Rule>>(&fm, None, dialect.parser_config(), &mut errors)
+            .ok()?;
+    rules.into_iter().find_map(|rule| match rule {
+        Rule::QualifiedRule(rule) => Some(*rule),
+        _ => None,
+    })
+}
+
+/// The text of the `rule` a span of its only source file covers, which starts at 1.
+fn text_of(rule: &str, span: Span) -> &str {
+    let start = span.lo.0.saturating_sub(1) as usize;
+    let end = span.hi.0.saturating_sub(1) as usize;
+    rule.get(start..end).unwrap_or_default()
+}
+
+/// Re-emits the text of a rule from its syntax tree, one selector and one declaration per line,
+/// the latter indented by two spaces, whatever its layout in the css file, e.g. minified. Falls
+/// back to [dedent]ing the text as is when it can't be parsed.
+pub fn pretty_print(rule: &str) -> String {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon, rule.to_string());
+
+    let mut errors = vec![];
+    let stylesheet =
+        match swc_css::parser::parse_file::<Stylesheet>(&fm, None, Default::default(), &mut errors)
+        {
+            Ok(stylesheet) if errors.is_empty() => stylesheet,
+            _ => return dedent(rule),
+        };
+
+    let mut out = String::new();
+    let writer = BasicCssWriter::new(&mut out, None, BasicCssWriterConfig::default());
+    let mut generator = CodeGenerator::new(writer, CodegenConfig { minify: false });
+    if generator.emit(&stylesheet).is_err() {
+        return dedent(rule);
+    }
+
+    // selector lists are split after their commas
+    out.lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Nests the text of a `rule` in the blocks it's in, given by the preludes of their rules and
+/// at-rules, outermost first, e.g. `.card` and `@media (min-width: 768px)`, indenting it by two
+/// spaces per block.
+pub fn wrap_in_blocks(rule: &str, preludes: &[crate::Str]) -> String {
+    nest_in_blocks(rule, preludes, true)
+}
+
+/// Like [wrap_in_blocks], for the indented syntax of Sass, in which blocks have no braces.
+pub fn indent_in_blocks(rule: &str, preludes: &[crate::Str]) -> String {
+    nest_in_blocks(rule, preludes, false)
+}
+
+fn nest_in_blocks(rule: &str, preludes: &[crate::Str], braces: bool) -> String {
+    let mut lines = vec![];
+    for (depth, prelude) in preludes.iter().enumerate() {
+        match braces {
+            true => lines.push(format!("{}{} {{", "  ".repeat(depth), &**prelude)),
+            false => lines.push(format!("{}{}", "  ".repeat(depth), &**prelude)),
+        }
+    }
+
+    let indent = "  ".repeat(preludes.len());
+    for line in rule.lines() {
+        match line.is_empty() {
+            true => lines.push(String::new()),
+            false => lines.push(format!("{indent}{line}")),
+        }
+    }
+
+    if braces {
+        for depth in (0..preludes.len()).rev() {
+            lines.push(format!("{}}}", "  ".repeat(depth)));
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Removes the indentation shared by the lines of a rule after the first one, which start where
+/// the rule starts, e.g. for a rule nested in a media query.
+pub fn dedent(rule: &str) -> String {
+    let mut lines = rule.lines();
+    let Some(first) = lines.next() else {
+        return String::new();
+    };
+
+    let rest = lines.collect::<Vec<_>>();
+    let indent = rest
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    std::iter::once(first)
+        .chain(
+            rest.iter()
+                .map(|line| line.get(indent..).unwrap_or(line.trim_start())),
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        applied_class_names, composes, declarations, dedent, extract_rule_text, indent_in_blocks,
+        preceding_comment, pretty_print, referenced_custom_properties, wrap_in_blocks,
+        ClassDefinition, Composes, RuleTextError, SourceText,
+    };
+
+    use crate::dialect::Dialect;
+
+    const CSS: &str = ".flex { display: flex; }\n.btn { padding: 0; }\n";
+
+    #[test]
+    fn it_extracts_rule_text() {
+        let def = ClassDefinition::new(25..45, CSS);
+
+        assert_eq!(
+            extract_rule_text(&def, &SourceText::Retained(CSS)).unwrap(),
+            ".btn { padding: 0; }"
+        );
+
+        let dir = std::env::temp_dir().join(format!("cnls-css-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_file = dir.join("a.css");
+        std::fs::write(&css_file, CSS).unwrap();
+
+        assert_eq!(
+            extract_rule_text(&def, &SourceText::File(&css_file)).unwrap(),
+            ".btn { padding: 0; }"
+        );
+
+        std::fs::write(&css_file, CSS.replace("0;", "1px;")).unwrap();
+        assert!(matches!(
+            extract_rule_text(&def, &SourceText::File(&css_file)),
+            Err(RuleTextError::Stale)
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_refuses_stale_or_out_of_bounds_rules() {
+        let def = ClassDefinition::new(25..45, CSS);
+        assert!(matches!(
+            extract_rule_text(&def, &SourceText::Retained(".btn { padding: 0; }")),
+            Err(RuleTextError::Stale)
+        ));
+
+        let def = ClassDefinition::new(25..80, CSS);
+        assert!(matches!(
+            extract_rule_text(&def, &SourceText::Retained(CSS)),
+            Err(RuleTextError::OutOfBounds { source_len: 46, .. })
+        ));
+    }
+
+    #[test]
+    fn it_dedents_nested_rules() {
+        assert_eq!(
+            dedent(".btn {\n    padding: 0;\n  }"),
+            ".btn {\n  padding: 0;\n}"
+        );
+        assert_eq!(dedent(".btn { padding: 0; }"), ".btn { padding: 0; }");
+    }
+
+    #[test]
+    fn it_pretty_prints_rules() {
+        assert_eq!(
+            pretty_print(".btn,.btn:hover{padding:0;color:red!important}"),
+            ".btn,\n.btn:hover {\n  padding: 0;\n  color: red !important;\n}"
+        );
+        assert_eq!(
+            pretty_print(".hover\\:card {\n      margin: 0 auto;\n    }"),
+            ".hover\\:card {\n  margin: 0 auto;\n}"
+        );
+    }
+
+    #[test]
+    fn it_pretty_prints_rules_from_a_single_long_line() {
+        let declarations = (0..500)
+            .map(|i| format!("--v{i}:{i}px"))
+            .collect::<Vec<_>>();
+        let rule = format!(".btn{{{}}}", declarations.join(";"));
+        assert!(rule.len() > 5000);
+
+        let pretty = pretty_print(&rule);
+        let lines = pretty.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 502);
+        assert_eq!(lines[0], ".btn {");
+        assert_eq!(lines[1], "  --v0: 0px;");
+        assert_eq!(lines[500], "  --v499: 499px;");
+        assert_eq!(lines[501], "}");
+    }
+
+    #[test]
+    fn it_falls_back_to_dedenting_what_cant_be_parsed() {
+        assert_eq!(
+            pretty_print(".btn {\n    padding: 0\n  "),
+            dedent(".btn {\n    padding: 0\n  ")
+        );
+    }
+
+    #[test]
+    fn it_wraps_rules_in_their_blocks() {
+        let rule = ".btn {\n  padding: 0;\n}";
+        assert_eq!(wrap_in_blocks(rule, &[]), rule);
+        assert_eq!(
+            wrap_in_blocks(
+                rule,
+                &["@media (min-width: 768px)".into(), "@layer components".into()]
+            ),
+            "@media (min-width: 768px) {\n  @layer components {\n    .btn {\n      padding: 0;\n    }\n  }\n}"
+        );
+        assert_eq!(
+            indent_in_blocks("&-title\n  font-weight: bold", &[".card".into()]),
+            ".card\n  &-title\n    font-weight: bold"
+        );
+    }
+
+    #[test]
+    fn it_finds_the_comment_right_above_a_rule() {
+        let comment_of = |css: &str| preceding_comment(css, css.find(".btn").unwrap());
+
+        assert_eq!(
+            comment_of("/**\n * A button.\n *\n * Pairs with `.icon`.\n */\n.btn {}").as_deref(),
+            Some("A button.\n\nPairs with `.icon`.")
+        );
+        assert_eq!(
+            comment_of("/* Buttons */\n\n/* primary */\n/* solid */\n\n.btn {}").as_deref(),
+            Some("primary\nsolid")
+        );
+        assert_eq!(
+            comment_of("  /* nested */\n  .btn {}").as_deref(),
+            Some("nested")
+        );
+    }
+
+    #[test]
+    fn it_leaves_out_the_comments_of_previous_rules() {
+        let comment_of = |css: &str| preceding_comment(css, css.find(".btn").unwrap());
+
+        assert_eq!(comment_of("/* Cards */\n\n\n.btn {}"), None);
+        assert_eq!(comment_of("/* Cards */\n.card {}\n.btn {}"), None);
+        assert_eq!(comment_of(".card {} /* card */\n.btn {}"), None);
+        assert_eq!(comment_of(".card {}\n.btn {}"), None);
+        assert_eq!(comment_of("/* */\n.btn {}"), None);
+    }
+
+    #[test]
+    fn it_finds_the_custom_properties_a_rule_refers_to() {
+        assert_eq!(
+            referenced_custom_properties(
+                ".btn {\n  color: var(--brand);\n  margin: var( --gap, var(--gap-ä) ) var(--brand);\n}"
+            ),
+            ["--brand", "--gap", "--gap-ä"]
+        );
+        assert!(referenced_custom_properties(".btn { --var(x): 0; }").is_empty());
+    }
+
+    #[test]
+    fn it_finds_the_classes_a_rule_applies() {
+        let rule = ".btn {\n  @apply px-4 py-2 rounded !important;\n  color: red;\n  &:hover {\n    @apply bg-blue-600;\n  }\n  @apply   px-4\n    shadow;\n}";
+        assert_eq!(
+            applied_class_names(rule, Dialect::Scss),
+            ["px-4", "py-2", "rounded", "shadow"]
+        );
+        assert_eq!(
+            applied_class_names(".btn { @apply px-4; }", Dialect::Css),
+            ["px-4"]
+        );
+        assert!(applied_class_names(".btn\n  @apply px-4", Dialect::Sass).is_empty());
+    }
+
+    #[test]
+    fn it_finds_what_a_rule_composes() {
+        let rule = ".btn {\n  composes: base large;\n  composes: shadow from \"./shared.css\";\n  composes: reset from global;\n  color: red;\n}";
+        assert_eq!(
+            composes(rule, Dialect::Css),
+            [
+                Composes {
+                    classes: vec!["base".into(), "large".into()],
+                    from: None
+                },
+                Composes {
+                    classes: vec!["shadow".into()],
+                    from: Some("./shared.css".into())
+                },
+                Composes {
+                    classes: vec!["reset".into()],
+                    from: Some("global".into())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_lists_the_declarations_of_a_rule_itself() {
+        assert_eq!(
+            declarations(
+                ".px-4 {\n  padding-left: 1rem;\n  padding-right:\n    1rem !important\n}",
+                Dialect::Css
+            ),
+            ["padding-left: 1rem", "padding-right: 1rem !important"]
+        );
+        assert_eq!(
+            declarations(
+                ".a { $gap: 1px; margin: $gap; .b { color: red; } }",
+                Dialect::Scss
+            ),
+            ["margin: $gap"]
+        );
+    }
+}
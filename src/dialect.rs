@@ -0,0 +1,397 @@
+//! The languages stylesheets are written in, and how to have swc parse those that aren't css.
+//! The indented syntax of Sass isn't parsed by swc, see [crate::sass].
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use swc_css::parser::parser::ParserConfig;
+
+/// The extensions of the stylesheets classes are looked for in.
+pub const EXTENSIONS: [&str; 4] = ["css", "scss", "sass", "less"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Css,
+    Scss,
+    /// The indented syntax of Sass.
+    Sass,
+    Less,
+}
+
+impl Dialect {
+    /// The dialect of the stylesheet at `path`, told by its extension. `None` for other files.
+    pub fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "css" => Some(Self::Css),
+            "scss" => Some(Self::Scss),
+            "sass" => Some(Self::Sass),
+            "less" => Some(Self::Less),
+            _ => None,
+        }
+    }
+
+    /// The dialect of a document the client opened with the `language_id`.
+    pub fn of_language_id(language_id: &str) -> Option<Self> {
+        match language_id {
+            "css" | "postcss" | "tailwindcss" => Some(Self::Css),
+            "scss" => Some(Self::Scss),
+            "sass" => Some(Self::Sass),
+            "less" => Some(Self::Less),
+            _ => None,
+        }
+    }
+
+    /// The language id of the dialect, e.g. for the code blocks of hover.
+    pub fn language_id(self) -> &'static str {
+        match self {
+            Self::Css => "css",
+            Self::Scss => "scss",
+            Self::Sass => "sass",
+            Self::Less => "less",
+        }
+    }
+
+    pub fn parser_config(self) -> ParserConfig {
+        match self {
+            Self::Css | Self::Sass => ParserConfig::default(),
+            Self::Scss | Self::Less => ParserConfig {
+                allow_wrong_line_comments: true,
+                legacy_nesting: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The `code` as swc can parse it: with the syntax of the dialect that isn't css blanked
+    /// out, e.g. the variable declarations of scss or the mixin calls of less, and variables
+    /// made into identifiers. Every
+    /// byte stays at its offset, so that spans point into the `code` as written.
+    pub fn preprocess(self, code: &str) -> Cow<'_, str> {
+        match self {
+            Self::Css | Self::Sass => Cow::Borrowed(code),
+            Self::Scss => Cow::Owned(preprocess_scss(code)),
+            Self::Less => Cow::Owned(preprocess_less(code)),
+        }
+    }
+}
+
+fn preprocess_scss(code: &str) -> String {
+    let bytes = code.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut at_statement_start = true;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => {
+                i = string_end(bytes, i);
+                at_statement_start = false;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = block_comment_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => i = line_end(bytes, i),
+            b'{' | b'}' | b';' => {
+                i += 1;
+                at_statement_start = true;
+            }
+            b'$' if at_statement_start && is_variable_declaration(bytes, i) => {
+                let end = statement_end(bytes, i);
+                blank(&mut out, i..end);
+                i = end;
+            }
+            b'$' => {
+                // `$gap` reads as the identifier `-gap`
+                out[i] = b'-';
+                i += 1;
+                at_statement_start = false;
+            }
+            b'u' if bytes[i..].starts_with(b"url(") && !is_ident_byte(bytes, i.wrapping_sub(1)) => {
+                i = url_end(bytes, i);
+                at_statement_start = false;
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            _ => {
+                i += 1;
+                at_statement_start = false;
+            }
+        }
+    }
+
+    String::from_utf8(out).expect("only whole characters are replaced, by ascii ones")
+}
+
+fn preprocess_less(code: &str) -> String {
+    let bytes = code.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut at_statement_start = true;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => {
+                i = string_end(bytes, i);
+                at_statement_start = false;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = block_comment_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => i = line_end(bytes, i),
+            b'{' | b'}' | b';' => {
+                i += 1;
+                at_statement_start = true;
+            }
+            b'@' if at_statement_start && is_variable_declaration(bytes, i) => {
+                let end = statement_end(bytes, i);
+                blank(&mut out, i..end);
+                i = end;
+            }
+            b'@' if !at_statement_start && is_ident_byte(bytes, i + 1) => {
+                // `@gap` reads as the identifier `-gap`
+                out[i] = b'-';
+                i += 1;
+            }
+            b'.' | b'#' | b'&' if at_statement_start => {
+                let end = prelude_end(bytes, i);
+                let name_end = (i + 1..bytes.len())
+                    .find(|&j| !is_ident_byte(bytes, j))
+                    .unwrap_or(bytes.len());
+                let has_parameters = bytes[i] != b'&' && bytes.get(name_end) == Some(&b'(');
+
+                match bytes.get(end) {
+                    // a mixin definition, e.g. `.bordered(@width) { ... }`, which outputs nothing
+                    Some(b'{') if has_parameters => {
+                        let end = block_end(bytes, end);
+                        blank(&mut out, i..end);
+                        i = end;
+                    }
+                    Some(b'{') => {
+                        // `.dark when (@mode = dark)` is kept as `.dark`
+                        i = match guard_start(bytes, i, end) {
+                            Some(guard) => {
+                                blank(&mut out, guard..end);
+                                end
+                            }
+                            None => i + 1,
+                        };
+                        at_statement_start = false;
+                    }
+                    // a mixin call, e.g. `.bordered();`, or `&:extend(.btn);`
+                    Some(b';') => {
+                        blank(&mut out, i..end + 1);
+                        i = end + 1;
+                    }
+                    _ => {
+                        blank(&mut out, i..end);
+                        i = end;
+                    }
+                }
+            }
+            b'u' if bytes[i..].starts_with(b"url(") && !is_ident_byte(bytes, i.wrapping_sub(1)) => {
+                i = url_end(bytes, i);
+                at_statement_start = false;
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            _ => {
+                i += 1;
+                at_statement_start = false;
+            }
+        }
+    }
+
+    String::from_utf8(out).expect("only whole characters are replaced, by ascii ones")
+}
+
+/// Whether the `$` (or the `@` of less) at `start` starts the declaration of a variable, e.g.
+/// `$gap: 4px;`.
+fn is_variable_declaration(bytes: &[u8], start: usize) -> bool {
+    let name_end = (start + 1..bytes.len())
+        .find(|&i| !is_ident_byte(bytes, i))
+        .unwrap_or(bytes.len());
+    let after = bytes[name_end..].iter().find(|c| !c.is_ascii_whitespace());
+
+    name_end > start + 1 && after == Some(&b':')
+}
+
+fn is_ident_byte(bytes: &[u8], i: usize) -> bool {
+    bytes
+        .get(i)
+        .is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c >= 0x80)
+}
+
+/// Where the prelude of the rule starting at `start` ends: at its `{`, or at the `;` or `}`
+/// ending the statement it turns out to be instead.
+fn prelude_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => i = string_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = block_comment_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => i = line_end(bytes, i),
+            b'(' | b'[' => {
+                depth += 1;
+                i += 1;
+            }
+            b')' | b']' => {
+                depth = depth.saturating_sub(1);
+                i += 1;
+            }
+            b'{' | b'}' | b';' if depth == 0 => return i,
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+/// The end of the block opened at `start`, past its closing `}`.
+fn block_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => i = string_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = block_comment_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => i = line_end(bytes, i),
+            b'{' => {
+                depth += 1;
+                i += 1;
+            }
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                i += 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+/// Where the guard of the selector in `start..end` starts, at its `when` keyword.
+fn guard_start(bytes: &[u8], start: usize, end: usize) -> Option<usize> {
+    (start + 1..end).find(|&i| {
+        bytes[i..end].starts_with(b"when")
+            && bytes[i - 1].is_ascii_whitespace()
+            && bytes
+                .get(i + 4)
+                .is_some_and(|&c| c.is_ascii_whitespace() || c == b'(')
+    })
+}
+
+/// The end of the statement starting at `start`: past its `;`, or before the `}` closing the
+/// block it's in.
+fn statement_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => i = string_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i = block_comment_end(bytes, i),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => i = line_end(bytes, i),
+            b'(' | b'[' | b'{' => {
+                depth += 1;
+                i += 1;
+            }
+            b')' | b']' | b'}' if depth > 0 => {
+                depth -= 1;
+                i += 1;
+            }
+            b'}' => return i,
+            b';' if depth == 0 => return i + 1,
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut i = start + 1;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' => return i,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+fn block_comment_end(bytes: &[u8], start: usize) -> usize {
+    (start + 2..bytes.len())
+        .find(|&i| bytes[i..].starts_with(b"*/"))
+        .map_or(bytes.len(), |i| i + 2)
+}
+
+fn line_end(bytes: &[u8], start: usize) -> usize {
+    (start..bytes.len())
+        .find(|&i| bytes[i] == b'\n')
+        .unwrap_or(bytes.len())
+}
+
+/// The end of an unquoted `url(...)`, in which `//` doesn't start a comment.
+fn url_end(bytes: &[u8], start: usize) -> usize {
+    (start..bytes.len())
+        .find(|&i| bytes[i] == b')')
+        .map_or(bytes.len(), |i| i + 1)
+}
+
+/// Replaces the characters in `range` with spaces, keeping line breaks.
+fn blank(bytes: &mut [u8], range: std::ops::Range<usize>) {
+    for byte in &mut bytes[range] {
+        if !matches!(byte, b'\n' | b'\r') {
+            *byte = b' ';
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Dialect;
+
+    #[test]
+    fn it_tells_dialects_apart() {
+        assert_eq!(Dialect::of(Path::new("a/b.css")), Some(Dialect::Css));
+        assert_eq!(Dialect::of(Path::new("a/_b.scss")), Some(Dialect::Scss));
+        assert_eq!(Dialect::of(Path::new("a/b.sass")), Some(Dialect::Sass));
+        assert_eq!(Dialect::of(Path::new("a/b.less")), Some(Dialect::Less));
+        assert_eq!(Dialect::of(Path::new("a/b.tsx")), None);
+        assert_eq!(Dialect::of_language_id("scss"), Some(Dialect::Scss));
+        assert_eq!(Dialect::of_language_id("tailwindcss"), Some(Dialect::Css));
+    }
+
+    #[test]
+    fn it_blanks_out_scss_variables() {
+        let code = "$gap: 4px !default;\n$map: (a: 1, b: 2);\n// $not: here;\n.card { $inner: 1; padding: $gap; content: \"$x: y;\"; }\n.b { background: url(http://x.io/$a.png); }";
+        let preprocessed = Dialect::Scss.preprocess(code);
+
+        assert_eq!(preprocessed.len(), code.len());
+        assert_eq!(
+            preprocessed,
+            "                   \n                   \n// $not: here;\n.card {            padding: -gap; content: \"$x: y;\"; }\n.b { background: url(http://x.io/$a.png); }"
+        );
+        assert_eq!(Dialect::Css.preprocess(code), code);
+    }
+
+    #[test]
+    fn it_blanks_out_less_variables_and_mixins() {
+        let code = "@gap: 4px;\n.bordered(@w: 1px) { border: @w solid; }\n.card { .bordered(); margin: @gap; &:extend(.base); }\n.dark when (@mode = dark) { color: white; }";
+        let preprocessed = Dialect::Less.preprocess(code);
+
+        assert_eq!(preprocessed.len(), code.len());
+        assert_eq!(
+            preprocessed,
+            "          \n                                        \n.card {              margin: -gap;                  }\n.dark                     { color: white; }"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use dashmap::mapref::multiple::RefMulti;
+use dashmap::mapref::one::{Ref, RefMut};
+use dashmap::DashMap;
+use tower_lsp::lsp_types::Url;
+
+/// Whether paths differing only by case name the same file, as they do by default on macOS and
+/// Windows.
+const CASE_INSENSITIVE_PATHS: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// The open documents, keyed by [document_key] so that the same file is found whichever way
+/// the client spells its uri.
+pub struct DocumentStore<T> {
+    documents: DashMap<Url, T>,
+}
+
+impl<T> Default for DocumentStore<T> {
+    fn default() -> Self {
+        Self {
+            documents: DashMap::new(),
+        }
+    }
+}
+
+impl<T> DocumentStore<T> {
+    pub fn get(&self, uri: &Url) -> Option<Ref<'_, Url, T>> {
+        self.documents
+            .get(&document_key(uri, CASE_INSENSITIVE_PATHS))
+    }
+
+    pub fn get_mut(&self, uri: &Url) -> Option<RefMut<'_, Url, T>> {
+        self.documents
+            .get_mut(&document_key(uri, CASE_INSENSITIVE_PATHS))
+    }
+
+    /// Stores the `document`, returning the one it replaces, if it was already open.
+    pub fn insert(&self, uri: &Url, document: T) -> Option<T> {
+        self.documents
+            .insert(document_key(uri, CASE_INSENSITIVE_PATHS), document)
+    }
+
+    pub fn remove(&self, uri: &Url) -> Option<T> {
+        self.documents
+            .remove(&document_key(uri, CASE_INSENSITIVE_PATHS))
+            .map(|(_, document)| document)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = RefMulti<'_, Url, T>> {
+        self.documents.iter()
+    }
+}
+
+/// The key of the document at `uri`: file uris are re-encoded from their path, so that
+/// `%2D` and `-` or `%3A` and `:` are the same, and lowercased when `fold_case`, for the file
+/// systems ignoring case. Other uris, e.g. `untitled:`, are kept as they are.
+pub fn document_key(uri: &Url, fold_case: bool) -> Url {
+    if uri.scheme() != "file" {
+        return uri.clone();
+    }
+
+    let Ok(path) = uri.to_file_path() else {
+        return uri.clone();
+    };
+
+    let path = match fold_case {
+        true => path.to_string_lossy().to_lowercase().into(),
+        false => path,
+    };
+
+    Url::from_file_path(path).unwrap_or_else(|_| uri.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Url;
+
+    use super::document_key;
+
+    #[test]
+    fn it_keys_documents_by_their_decoded_path() {
+        let key = |uri: &str, fold_case| document_key(&Url::parse(uri).unwrap(), fold_case);
+
+        assert_eq!(
+            key("file:///app/src/my%2Dcard.tsx", false),
+            key("file:///app/src/my-card.tsx", false)
+        );
+        assert_eq!(
+            key("file:///app/src/My%20Card.tsx", false).as_str(),
+            "file:///app/src/My%20Card.tsx"
+        );
+        assert_ne!(
+            key("file:///app/src/Card.tsx", false),
+            key("file:///app/src/card.tsx", false)
+        );
+        assert_eq!(
+            key("file:///App/src/Card.tsx", true),
+            key("file:///app/src/card.tsx", true)
+        );
+        assert_eq!(
+            key("untitled:Untitled-1", true).as_str(),
+            "untitled:Untitled-1"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use tracing::info;
+
+use crate::find::{class_name_substrings, ClassNameToken};
+
+/// Name of the capture group holding the class list in a fallback pattern.
+pub const CLASSES_GROUP: &str = "classes";
+
+/// Patterns for finding class lists in file types cnls can't parse (Twig, ERB, Blade, PHP...),
+/// keyed by the glob of the files they apply to.
+#[derive(Debug, Clone)]
+pub struct FallbackPatterns(Vec<(GlobMatcher, Regex)>);
+
+impl Default for FallbackPatterns {
+    fn default() -> Self {
+        Self(vec![(
+            Glob::new("**/*.{html,php,erb,twig}")
+                .expect("default glob should be valid")
+                .compile_matcher(),
+            Regex::new(r#"class="(?<classes>[^"]*)""#).expect("default regex should be valid"),
+        )])
+    }
+}
+
+impl Serialize for FallbackPatterns {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            self.0
+                .iter()
+                .map(|(glob, regex)| (glob.glob().glob(), regex.as_str())),
+        )
+    }
+}
+
+impl FallbackPatterns {
+    /// Parses a `cnls.fallbackPatterns` object, mapping globs to regexes.
+    pub fn from_json(value: &Value) -> anyhow::Result<Self> {
+        let Value::Object(map) = value else {
+            return Err(anyhow!("should be an object mapping file globs to regexes"));
+        };
+
+        let patterns = map
+            .iter()
+            .map(|(glob, regex)| {
+                let glob = Glob::new(glob)
+                    .with_context(|| format!("invalid glob: {glob:?}"))?
+                    .compile_matcher();
+
+                let regex = regex
+                    .as_str()
+                    .ok_or_else(|| anyhow!("expected a regex string, got {regex}"))?;
+                let regex =
+                    Regex::new(regex).with_context(|| format!("invalid regex: {regex:?}"))?;
+
+                if !regex.capture_names().any(|n| n == Some(CLASSES_GROUP)) {
+                    return Err(anyhow!(
+                        "regex {:?} should have a named capture group '{CLASSES_GROUP}'",
+                        regex.as_str()
+                    ));
+                }
+
+                Ok((glob, regex))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self(patterns))
+    }
+
+    pub fn pattern_for(&self, path: &Path) -> Option<&Regex> {
+        self.0
+            .iter()
+            .find(|(glob, _)| glob.is_match(path))
+            .map(|(_, regex)| regex)
+    }
+}
+
+/// Finds the class name under `cursor` (a byte offset into `code`), looking only inside the
+/// class lists captured by `pattern`.
+pub fn find_class_name_at(code: &str, pattern: &Regex, cursor: usize) -> Option<ClassNameToken> {
+    let classes = pattern
+        .captures_iter(code)
+        .filter_map(|c| c.name(CLASSES_GROUP))
+        .find(|m| m.start() <= cursor && cursor <= m.end())?;
+
+    info!(
+        "found class_name list around current cursor: {:?} at byte {}",
+        classes.as_str(),
+        classes.start()
+    );
+
+    class_name_substrings(classes.as_str())
+        .into_iter()
+        .map(|(start, end)| (classes.start() + start, classes.start() + end))
+        .find(|(start, end)| *start <= cursor && cursor <= *end)
+        .map(|(start, end)| ClassNameToken {
+            value: code[start..=end].to_string(),
+            range: start..end + 1,
+            css_module: None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+    use tower_lsp::lsp_types::Position;
+
+    use super::{find_class_name_at, FallbackPatterns};
+    use crate::text::{byte_offset_of, PositionEncoding::Utf16};
+
+    #[test]
+    fn it_matches_default_globs() {
+        let patterns = FallbackPatterns::default();
+        assert!(patterns
+            .pattern_for(Path::new("/app/views/index.html.erb"))
+            .is_some());
+        assert!(patterns
+            .pattern_for(Path::new("/app/views/home.blade.php"))
+            .is_some());
+        assert!(patterns
+            .pattern_for(Path::new("/app/src/App.tsx"))
+            .is_none());
+    }
+
+    #[test]
+    fn it_parses_patterns() {
+        let patterns = FallbackPatterns::from_json(&json!({
+            "**/*.twig": r#"klass='(?<classes>[^']*)'"#
+        }))
+        .unwrap();
+        assert!(patterns.pattern_for(Path::new("a/b.twig")).is_some());
+        assert!(patterns.pattern_for(Path::new("a/b.html")).is_none());
+
+        FallbackPatterns::from_json(&json!({ "**/*.twig": "class='([^']*)'" })).unwrap_err();
+        FallbackPatterns::from_json(&json!({ "**/*.twig": "class='(?<classes>" })).unwrap_err();
+        FallbackPatterns::from_json(&json!(["**/*.twig"])).unwrap_err();
+    }
+
+    #[test]
+    fn it_finds_name_in_captured_group() {
+        let patterns = FallbackPatterns::default();
+        let pattern = patterns.pattern_for(Path::new("index.html")).unwrap();
+        let code = "<div class=\"p-2 flex\">\n  <span class=\"text-sm\">flex</span>\n</div>";
+
+        let cursor = byte_offset_of(code, Position::new(0, 17), Utf16).unwrap();
+        let token = find_class_name_at(code, pattern, cursor).unwrap();
+        assert_eq!(token.value, "flex");
+        assert_eq!(&code[token.range], "flex");
+
+        let cursor = byte_offset_of(code, Position::new(1, 16), Utf16).unwrap();
+        let token = find_class_name_at(code, pattern, cursor).unwrap();
+        assert_eq!(token.value, "text-sm");
+        assert_eq!(&code[token.range], "text-sm");
+
+        let cursor = byte_offset_of(code, Position::new(1, 26), Utf16).unwrap();
+        assert_eq!(find_class_name_at(code, pattern, cursor), None);
+
+        let cursor = byte_offset_of(code, Position::new(0, 15), Utf16).unwrap();
+        assert_eq!(find_class_name_at(code, pattern, cursor), None);
+    }
+}
@@ -1,31 +1,139 @@
+use crate::dialect::Dialect;
+use crate::scope::{Scope, ScopeVariant};
+use crate::text::{self, PositionEncoding};
 use anyhow::anyhow;
-use cnls::scope::{Scope, ScopeVariant};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use swc_common::sync::Lrc;
 use swc_common::{
     errors::{ColorConfig, Handler},
     SourceMap,
 };
-use swc_common::{BytePos, FileName, SourceFile};
-use swc_ecma_ast::{Callee, EsVersion, Expr, Ident, JSXAttrName, PropName};
+use swc_common::{BytePos, FileName, SourceFile, Span, Spanned, SyntaxContext};
+use swc_ecma_ast::{
+    Callee, EsVersion, Expr, Ident, ImportSpecifier, JSXAttrName, Lit, MemberProp, Module,
+    ModuleDecl, ModuleItem, Pat, PropName,
+};
 use swc_ecma_parser::{parse_file_as_module, Syntax};
 use swc_ecma_visit::{Visit, VisitWith};
 use tracing::{debug, info};
 
+/// A class name found in a scoped string, or accessed on a css module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassNameToken {
+    pub value: String,
+    /// Byte offsets into the source code.
+    pub range: std::ops::Range<usize>,
+    /// The stylesheet of the css module the class is accessed on, e.g. `./Button.module.css`
+    /// for `styles.primary` after `import styles from "./Button.module.css"`, to which the
+    /// class belongs rather than to any css file.
+    pub css_module: Option<std::path::PathBuf>,
+}
+
+/// What a scope starts at, e.g. the `<div>` of a `className` attribute, or a `cn()` call.
+#[derive(Debug, Clone, PartialEq)]
+struct ScopeOwner {
+    name: String,
+    span: Span,
+}
+
 struct StringsWithClassNamesFinder<'scopes> {
-    cursor_position: BytePos,
+    /// When set, only the class name on the cursor is looked for, otherwise every class name
+    /// in scope is collected.
+    cursor_position: Option<BytePos>,
     scopes: &'scopes [Scope],
     is_in_scope: bool,
-    found_classname_on_cursor: Option<String>,
+    found: Vec<(String, Span)>,
+    /// Text of the scoped strings and template quasis, without quotes, along with the owner of
+    /// their scope.
+    scoped_texts: Vec<(Span, Option<ScopeOwner>)>,
+    owner: Option<ScopeOwner>,
+    /// The jsx element whose attributes are being visited.
+    jsx_element: Option<ScopeOwner>,
+    /// Variables used in a scoped position, e.g. `cls` in `className={cls}`.
+    scoped_idents: Vec<String>,
+    /// The variable on the cursor, when it's used in a scoped position.
+    ident_on_cursor: Option<String>,
+    /// Where template quasis meet an interpolation, e.g. after `btn-` in `btn-${size}`.
+    interpolation_edges: HashSet<BytePos>,
+    /// The stylesheets imported as css modules, by the variable they're imported as.
+    css_modules: HashMap<String, String>,
+    /// The stylesheet of the css module whose class is on the cursor, as imported.
+    css_module_on_cursor: Option<String>,
 }
 
 impl<'scopes> StringsWithClassNamesFinder<'scopes> {
-    fn new(scopes: &'scopes [Scope], cursor_position: BytePos) -> Self {
+    fn new(scopes: &'scopes [Scope], cursor_position: Option<BytePos>) -> Self {
         Self {
             cursor_position,
             scopes,
             is_in_scope: false,
-            found_classname_on_cursor: None,
+            found: vec![],
+            scoped_texts: vec![],
+            owner: None,
+            jsx_element: None,
+            scoped_idents: vec![],
+            ident_on_cursor: None,
+            interpolation_edges: HashSet::new(),
+            css_modules: HashMap::new(),
+            css_module_on_cursor: None,
+        }
+    }
+
+    /// Visits the `module`, then the initializers of the local variables used in scoped
+    /// positions, as if they were written in place of the variables.
+    fn find_in_module(&mut self, module: &Module) {
+        let mut bindings = LocalBindings::default();
+        module.visit_with(&mut bindings);
+        self.css_modules = css_module_imports(module);
+
+        self.visit_module(module);
+
+        let mut resolved = HashSet::new();
+        while let Some(ident) = self.scoped_idents.pop() {
+            if self.is_done() {
+                break;
+            }
+
+            let Some(init) = bindings.initializer_of(&ident) else {
+                continue;
+            };
+
+            if resolved.insert(ident.clone()) {
+                let was_in_scope = std::mem::replace(&mut self.is_in_scope, true);
+                let owner = self.owner.replace(ScopeOwner {
+                    name: ident,
+                    span: init.span(),
+                });
+                init.visit_with(self);
+                self.owner = owner;
+                self.is_in_scope = was_in_scope;
+            }
+        }
+
+        if self.found.is_empty() {
+            if let Some(init) = self
+                .ident_on_cursor
+                .as_ref()
+                .and_then(|ident| bindings.initializer_of(ident))
+            {
+                let mut finder = StringsWithClassNamesFinder::new(self.scopes, None);
+                finder.is_in_scope = true;
+                init.visit_with(&mut finder);
+                self.found.extend(finder.found);
+            }
+        }
+
+        // an initializer can be both in a scope of its own and used in a scoped position
+        let mut seen = HashSet::new();
+        self.found.retain(|(_, span)| seen.insert(*span));
+
+        // only part of a class name is written next to an interpolation, so it's left out of
+        // the collected names rather than taken for another class
+        if self.cursor_position.is_none() {
+            let edges = &self.interpolation_edges;
+            self.found
+                .retain(|(_, span)| !edges.contains(&span.lo) && !edges.contains(&span.hi));
         }
     }
 
@@ -35,19 +143,50 @@ impl<'scopes> StringsWithClassNamesFinder<'scopes> {
             .iter()
             .any(|scope| scope.matches(ident, variant))
     }
+
+    fn is_done(&self) -> bool {
+        self.cursor_position.is_some() && !self.found.is_empty()
+    }
+
+    /// Visits `n` in the scope started at `owner`.
+    fn visit_in_scope<N: VisitWith<Self>>(&mut self, n: &N, owner: ScopeOwner) {
+        let was_in_scope = std::mem::replace(&mut self.is_in_scope, true);
+        let outer_owner = self.owner.replace(owner);
+        n.visit_with(self);
+        self.owner = outer_owner;
+        self.is_in_scope = was_in_scope;
+    }
 }
 
 impl<'scopes> Visit for StringsWithClassNamesFinder<'scopes> {
+    fn visit_jsx_opening_element(&mut self, n: &swc_ecma_ast::JSXOpeningElement) {
+        let name = match &n.name {
+            swc_ecma_ast::JSXElementName::Ident(ident) => ident.sym.to_string(),
+            swc_ecma_ast::JSXElementName::JSXMemberExpr(expr) => expr.prop.sym.to_string(),
+            swc_ecma_ast::JSXElementName::JSXNamespacedName(name) => name.name.sym.to_string(),
+        };
+
+        let outer_element = self.jsx_element.replace(ScopeOwner {
+            name: format!("<{name}>"),
+            span: n.span,
+        });
+        n.visit_children_with(self);
+        self.jsx_element = outer_element;
+    }
+
     fn visit_jsx_attr(&mut self, n: &swc_ecma_ast::JSXAttr) {
         if let JSXAttrName::Ident(name) = &n.name {
             if self.starts_a_valid_scope(name, ScopeVariant::AttrNames) {
-                self.is_in_scope = true;
-                n.value.visit_with(self);
-                self.is_in_scope = false;
+                let owner = self.jsx_element.clone().unwrap_or_else(|| ScopeOwner {
+                    name: name.sym.to_string(),
+                    span: n.span,
+                });
+                self.visit_in_scope(&n.value, owner);
+                return;
             }
         }
 
-        if self.found_classname_on_cursor.is_none() {
+        if !self.is_done() {
             n.visit_children_with(self);
         }
     }
@@ -56,14 +195,17 @@ impl<'scopes> Visit for StringsWithClassNamesFinder<'scopes> {
         if let Callee::Expr(expr) = &n.callee {
             if let Expr::Ident(name) = expr.as_ref() {
                 if self.starts_a_valid_scope(name, ScopeVariant::FnCall) {
-                    self.is_in_scope = true;
-                    n.args.visit_with(self);
-                    self.is_in_scope = false;
+                    let owner = ScopeOwner {
+                        name: format!("{}()", name.sym),
+                        span: n.span,
+                    };
+                    self.visit_in_scope(&n.args, owner);
+                    return;
                 }
             }
         }
 
-        if self.found_classname_on_cursor.is_none() {
+        if !self.is_done() {
             n.visit_children_with(self);
         }
     }
@@ -71,30 +213,208 @@ impl<'scopes> Visit for StringsWithClassNamesFinder<'scopes> {
     fn visit_key_value_prop(&mut self, n: &swc_ecma_ast::KeyValueProp) {
         if let PropName::Ident(ident) = &n.key {
             if self.starts_a_valid_scope(ident, ScopeVariant::RecordEntries) {
-                self.is_in_scope = true;
-                n.value.visit_with(self);
-                self.is_in_scope = false;
+                let owner = ScopeOwner {
+                    name: format!("{}:", ident.sym),
+                    span: n.span(),
+                };
+                self.visit_in_scope(&n.value, owner);
+                return;
             }
         }
 
-        if self.found_classname_on_cursor.is_none() {
+        if !self.is_done() {
+            n.visit_children_with(self);
+        }
+    }
+
+    fn visit_expr(&mut self, n: &Expr) {
+        if let Expr::Ident(ident) = n {
+            if self.is_in_scope {
+                let on_cursor = self
+                    .cursor_position
+                    .is_some_and(|c| ident.span.lo <= c && c <= ident.span.hi);
+                if on_cursor {
+                    self.ident_on_cursor = Some(ident.sym.to_string());
+                }
+
+                self.scoped_idents.push(ident.sym.to_string());
+            }
+            return;
+        }
+
+        if !self.is_done() {
+            n.visit_children_with(self);
+        }
+    }
+
+    /// A class accessed on a css module, e.g. `primary` in `styles.primary` or
+    /// `styles["primary-dark"]`, is found only on the cursor.
+    fn visit_member_expr(&mut self, n: &swc_ecma_ast::MemberExpr) {
+        if let (Some(cursor_position), Expr::Ident(object)) = (self.cursor_position, &*n.obj) {
+            let key = match &n.prop {
+                MemberProp::Ident(prop) => Some((prop.sym.to_string(), prop.span)),
+                MemberProp::Computed(prop) => match prop.expr.as_ref() {
+                    Expr::Lit(Lit::Str(key)) if key.span.hi.0 - key.span.lo.0 >= 2 => {
+                        let text = Span::new(
+                            key.span.lo + BytePos(1),
+                            key.span.hi - BytePos(1),
+                            SyntaxContext::empty(),
+                        );
+                        Some((key.value.to_string(), text))
+                    }
+                    _ => None,
+                },
+                MemberProp::PrivateName(_) => None,
+            };
+
+            let stylesheet = self.css_modules.get(object.sym.as_str());
+            if let (Some(stylesheet), Some((key, span))) = (stylesheet, key) {
+                if span.lo <= cursor_position && cursor_position <= span.hi {
+                    info!("found css module class on cursor: {key:?} of {stylesheet:?}");
+                    self.css_module_on_cursor = Some(stylesheet.clone());
+                    self.found.push((key, span));
+                    return;
+                }
+            }
+        }
+
+        if !self.is_done() {
             n.visit_children_with(self);
         }
     }
 
     fn visit_str(&mut self, n: &swc_ecma_ast::Str) {
-        if !self.is_in_scope {
+        if !self.is_in_scope || self.is_done() {
             return;
         }
 
-        if self.found_classname_on_cursor.is_none() {
-            self.found_classname_on_cursor = find_class_name_in_str(n, self.cursor_position)
+        if n.span.hi.0 - n.span.lo.0 >= 2 {
+            let text = Span::new(
+                n.span.lo + BytePos(1),
+                n.span.hi - BytePos(1),
+                SyntaxContext::empty(),
+            );
+            self.scoped_texts.push((text, self.owner.clone()));
+        }
+
+        match self.cursor_position {
+            Some(cursor_position) => self
+                .found
+                .extend(find_class_name_in_str(n, cursor_position)),
+            None => self.found.extend(class_names_in_str(n)),
+        }
+    }
+
+    fn visit_tpl(&mut self, n: &swc_ecma_ast::Tpl) {
+        if self.is_in_scope {
+            for (i, quasi) in n.quasis.iter().enumerate() {
+                if i > 0 {
+                    self.interpolation_edges.insert(quasi.span.lo);
+                }
+                if i < n.exprs.len() {
+                    self.interpolation_edges.insert(quasi.span.hi);
+                }
+            }
+        }
+
+        n.visit_children_with(self)
+    }
+
+    fn visit_tpl_element(&mut self, n: &swc_ecma_ast::TplElement) {
+        if !self.is_in_scope || self.is_done() {
+            return;
+        }
+
+        self.scoped_texts.push((n.span, self.owner.clone()));
+
+        match self.cursor_position {
+            Some(cursor_position) => self
+                .found
+                .extend(find_class_name_in_tpl_element(n, cursor_position)),
+            None => self.found.extend(class_names_in_tpl_element(n)),
+        }
+    }
+}
+
+/// The stylesheets a module imports as css modules, by the variable they're imported as, e.g.
+/// `styles` in `import styles from "./card.module.css"` or `import * as styles from ...`.
+fn css_module_imports(module: &Module) -> HashMap<String, String> {
+    let mut css_modules = HashMap::new();
+
+    for item in &module.body {
+        let ModuleItem::ModuleDecl(ModuleDecl::Import(import)) = item else {
+            continue;
+        };
+        if Dialect::of(Path::new(&*import.src.value)).is_none() {
+            continue;
+        }
+
+        for specifier in &import.specifiers {
+            let local = match specifier {
+                ImportSpecifier::Default(default) => &default.local,
+                ImportSpecifier::Namespace(namespace) => &namespace.local,
+                ImportSpecifier::Named(_) => continue,
+            };
+            css_modules.insert(local.sym.to_string(), import.src.value.to_string());
+        }
+    }
+
+    css_modules
+}
+
+/// Variables bound exactly once in a module, to an initializer that can hold class names.
+/// Variables bound more than once, by shadowing or reassignment, are left out since which
+/// value they hold at a given use isn't tracked.
+#[derive(Default)]
+struct LocalBindings {
+    bindings: HashMap<String, usize>,
+    initializers: HashMap<String, Box<Expr>>,
+}
+
+impl LocalBindings {
+    fn initializer_of(&self, ident: &str) -> Option<&Expr> {
+        if self.bindings.get(ident) != Some(&1) {
+            return None;
+        }
+
+        self.initializers.get(ident).map(|init| init.as_ref())
+    }
+}
+
+impl Visit for LocalBindings {
+    fn visit_var_declarator(&mut self, n: &swc_ecma_ast::VarDeclarator) {
+        if let (Pat::Ident(name), Some(init)) = (&n.name, &n.init) {
+            let can_hold_class_names = matches!(
+                init.as_ref(),
+                Expr::Lit(Lit::Str(_)) | Expr::Tpl(_) | Expr::Call(_) | Expr::Ident(_)
+            );
+
+            if can_hold_class_names {
+                self.initializers
+                    .insert(name.id.sym.to_string(), init.clone());
+            }
+        }
+
+        n.visit_children_with(self)
+    }
+
+    /// Declarations, parameters and assignment targets are all binding identifiers.
+    fn visit_binding_ident(&mut self, n: &swc_ecma_ast::BindingIdent) {
+        *self.bindings.entry(n.id.sym.to_string()).or_default() += 1;
+    }
+
+    fn visit_update_expr(&mut self, n: &swc_ecma_ast::UpdateExpr) {
+        if let Expr::Ident(ident) = n.arg.as_ref() {
+            *self.bindings.entry(ident.sym.to_string()).or_default() += 1;
         }
+
+        n.visit_children_with(self)
     }
 }
 
 pub struct SrcCodeMeta {
     path: std::path::PathBuf,
+    syntax: Syntax,
     cursor_byte_position: BytePos,
     file: Lrc<SourceFile>,
     source_map: Lrc<SourceMap>,
@@ -103,79 +423,390 @@ pub struct SrcCodeMeta {
 impl SrcCodeMeta {
     pub fn build(
         path: std::path::PathBuf,
+        syntax: Syntax,
         code: String,
         curr_cursor_position: tower_lsp::lsp_types::Position,
+        encoding: PositionEncoding,
     ) -> anyhow::Result<Self> {
+        let offset = text::line_clamped_byte_offset_of(&code, curr_cursor_position, encoding)
+            .ok_or_else(|| {
+                anyhow!(
+                    "line {} is past the end of the document",
+                    curr_cursor_position.line
+                )
+            })?;
+
         let cm: Lrc<SourceMap> = Default::default();
         let fm = cm.new_source_file(FileName::Real(path.clone()), code);
 
-        let (start_pos, _) = fm.line_bounds(curr_cursor_position.line as usize);
-
         info!(
-            "current line {} found to start on byte {}",
-            curr_cursor_position.line, start_pos.0
+            "cursor on line {} found at byte {offset}",
+            curr_cursor_position.line
         );
 
-        let cursor_position = BytePos(start_pos.0 + curr_cursor_position.character);
+        let cursor_position = BytePos(fm.start_pos.0 + offset as u32);
 
         debug!("resolved cursor byte pos to {}", cursor_position.0);
 
         Ok(Self {
             path,
+            syntax,
             cursor_byte_position: cursor_position,
             file: fm,
             source_map: cm,
         })
     }
 
-    pub fn get_classname_on_cursor(self, scopes: &[Scope]) -> anyhow::Result<Option<String>> {
-        let path = self.path;
-        let error_handler =
-            Handler::with_tty_emitter(ColorConfig::Auto, true, false, Some(self.source_map));
+    /// Finds the class name on the cursor, along with its byte range in the source code. A
+    /// variable on the cursor resolves to the first class name it holds, ranging where it's
+    /// written.
+    pub fn get_classname_on_cursor(
+        self,
+        scopes: &[Scope],
+    ) -> anyhow::Result<Option<ClassNameToken>> {
+        Ok(self.get_classnames_on_cursor(scopes)?.into_iter().next())
+    }
+
+    /// Finds the class names on the cursor, along with their byte ranges in the source code:
+    /// the one written there, or every one held by a variable on the cursor, ranging where
+    /// they're written.
+    pub fn get_classnames_on_cursor(self, scopes: &[Scope]) -> anyhow::Result<Vec<ClassNameToken>> {
+        let start_pos = self.file.start_pos.0;
 
-        let mut errors = vec![];
+        let path = self.path.clone();
+        let (found, css_module) = self.find_on_cursor(scopes)?;
+        let css_module = css_module.and_then(|src| crate::fs::resolve_import(&path, &src));
+        let tokens = found
+            .into_iter()
+            .map(|(value, span)| ClassNameToken {
+                value,
+                range: (span.lo.0 - start_pos) as usize..(span.hi.0 - start_pos) as usize,
+                css_module: css_module.clone(),
+            })
+            .collect();
 
-        let module = parse_file_as_module(
-            &self.file,
-            get_syntax_of_file(&path)?,
-            EsVersion::latest(),
-            None,
-            &mut errors,
-        )
-        .map_err(|e| e.into_diagnostic(&error_handler).emit())
-        .expect("failed to parser module");
+        Ok(tokens)
+    }
+
+    /// Finds the class name written under the cursor, along with its byte range in the source
+    /// code. Unlike [SrcCodeMeta::get_classname_on_cursor], a variable on the cursor doesn't
+    /// resolve to the class names it holds.
+    pub fn get_class_name_token_on_cursor(
+        self,
+        scopes: &[Scope],
+    ) -> anyhow::Result<Option<ClassNameToken>> {
+        let cursor = (self.cursor_byte_position.0 - self.file.start_pos.0) as usize;
+
+        let token = self
+            .get_classname_on_cursor(scopes)?
+            .filter(|token| token.range.contains(&cursor));
+
+        Ok(token)
+    }
+
+    /// The class names on the cursor, with the stylesheet of the css module they're accessed
+    /// on, as imported.
+    fn find_on_cursor(
+        self,
+        scopes: &[Scope],
+    ) -> anyhow::Result<(Vec<(String, Span)>, Option<String>)> {
+        let module = parse_module(&self.path, self.syntax, &self.file, self.source_map)?;
 
         info!("parsed source code");
 
-        let mut finder = StringsWithClassNamesFinder::new(scopes, self.cursor_byte_position);
+        let mut finder = StringsWithClassNamesFinder::new(scopes, Some(self.cursor_byte_position));
 
-        finder.visit_module(&module);
+        finder.find_in_module(&module);
 
-        Ok(finder.found_classname_on_cursor)
+        Ok((finder.found, finder.css_module_on_cursor))
     }
 }
 
-fn find_class_name_in_str(s: &swc_ecma_ast::Str, cursor_position: BytePos) -> Option<String> {
-    if s.is_empty() {
-        return None;
+/// Finds every class name in the configured scopes of a source file.
+pub fn collect_class_names(
+    path: std::path::PathBuf,
+    syntax: Syntax,
+    code: String,
+    scopes: &[Scope],
+) -> anyhow::Result<Vec<ClassNameToken>> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Real(path.clone()), code);
+
+    let module = parse_module(&path, syntax, &fm, cm)?;
+
+    let mut finder = StringsWithClassNamesFinder::new(scopes, None);
+
+    finder.find_in_module(&module);
+
+    let start_pos = fm.start_pos.0;
+    let tokens = finder
+        .found
+        .into_iter()
+        .map(|(value, span)| ClassNameToken {
+            value,
+            range: (span.lo.0 - start_pos) as usize..(span.hi.0 - start_pos) as usize,
+            css_module: None,
+        })
+        .collect();
+
+    Ok(tokens)
+}
+
+/// Finds the byte ranges of the text of every scoped string and template quasi in a source
+/// file, i.e. the class lists.
+pub fn scoped_class_lists(
+    path: std::path::PathBuf,
+    syntax: Syntax,
+    code: String,
+    scopes: &[Scope],
+) -> anyhow::Result<Vec<std::ops::Range<usize>>> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Real(path.clone()), code);
+
+    let module = parse_module(&path, syntax, &fm, cm)?;
+
+    let mut finder = StringsWithClassNamesFinder::new(scopes, None);
+
+    finder.find_in_module(&module);
+
+    let start_pos = fm.start_pos.0;
+    let mut lists = finder
+        .scoped_texts
+        .into_iter()
+        .map(|(span, _)| (span.lo.0 - start_pos) as usize..(span.hi.0 - start_pos) as usize)
+        .collect::<Vec<_>>();
+    lists.sort_by_key(|list| list.start);
+    lists.dedup();
+
+    Ok(lists)
+}
+
+/// The class names of the class list at `list`, as found by [scoped_class_lists], as byte ranges
+/// into `code`.
+pub fn class_list_tokens(code: &str, list: std::ops::Range<usize>) -> Vec<std::ops::Range<usize>> {
+    raw_class_name_substrings(&code[list.clone()])
+        .into_iter()
+        .map(|(start, end)| list.start + start..list.start + end + 1)
+        .collect()
+}
+
+/// The scoped strings and template quasis owned by the same jsx element, function call, object
+/// key or local variable, e.g. `<div>` for the `className` and `class` attributes of a div.
+#[derive(Debug, PartialEq)]
+pub struct ClassStringGroup {
+    pub owner: String,
+    /// Byte offsets of the owner, e.g. the opening tag of the jsx element.
+    pub range: std::ops::Range<usize>,
+    /// Byte offsets of the text of each string, without quotes.
+    pub strings: Vec<std::ops::Range<usize>>,
+}
+
+/// Finds the scoped strings and template quasis of a source file, grouped by what their scope
+/// starts at, in order of appearance.
+pub fn class_string_groups(
+    path: std::path::PathBuf,
+    syntax: Syntax,
+    code: String,
+    scopes: &[Scope],
+) -> anyhow::Result<Vec<ClassStringGroup>> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Real(path.clone()), code);
+
+    let module = parse_module(&path, syntax, &fm, cm)?;
+
+    let mut finder = StringsWithClassNamesFinder::new(scopes, None);
+
+    finder.find_in_module(&module);
+
+    let start_pos = fm.start_pos.0;
+    let range_of = |span: Span| (span.lo.0 - start_pos) as usize..(span.hi.0 - start_pos) as usize;
+
+    let mut groups: Vec<ClassStringGroup> = vec![];
+    for (span, owner) in finder.scoped_texts {
+        if span.lo == span.hi {
+            continue;
+        }
+
+        let owner = owner.unwrap_or_else(|| ScopeOwner {
+            name: String::new(),
+            span,
+        });
+
+        match groups
+            .iter_mut()
+            .find(|group| group.range == range_of(owner.span))
+        {
+            Some(group) => group.strings.push(range_of(span)),
+            None => groups.push(ClassStringGroup {
+                owner: owner.name,
+                range: range_of(owner.span),
+                strings: vec![range_of(span)],
+            }),
+        }
     }
 
-    let start_of_str = s.span.lo.0 + 1; // not counting the quote;
+    for group in &mut groups {
+        group.strings.sort_by_key(|string| string.start);
+        group.strings.dedup();
+    }
+    groups.sort_by_key(|group| group.range.start);
+
+    Ok(groups)
+}
+
+/// The stylesheets a source file imports, as written, e.g. `./card.css` in
+/// `import "./card.css"`, `import styles from "./card.css"` or `require("./card.css")`.
+pub fn imported_stylesheets(
+    path: std::path::PathBuf,
+    syntax: Syntax,
+    code: String,
+) -> anyhow::Result<Vec<String>> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Real(path.clone()), code);
+
+    let module = parse_module(&path, syntax, &fm, cm)?;
+
+    let mut required = RequiredModules::default();
+    module.visit_with(&mut required);
+
+    let stylesheets = module
+        .body
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => {
+                Some(import.src.value.to_string())
+            }
+            _ => None,
+        })
+        .chain(required.0)
+        .filter(|src| Dialect::of(Path::new(src)).is_some())
+        .collect();
+
+    Ok(stylesheets)
+}
+
+/// The modules a source file `require`s, as written.
+#[derive(Default)]
+struct RequiredModules(Vec<String>);
+
+impl Visit for RequiredModules {
+    fn visit_call_expr(&mut self, n: &swc_ecma_ast::CallExpr) {
+        if let Callee::Expr(callee) = &n.callee {
+            if let (Expr::Ident(name), [arg]) = (callee.as_ref(), n.args.as_slice()) {
+                if let (true, Expr::Lit(Lit::Str(src))) = (&*name.sym == "require", &*arg.expr) {
+                    self.0.push(src.value.to_string());
+                }
+            }
+        }
+
+        n.visit_children_with(self)
+    }
+}
+
+fn parse_module(
+    path: &Path,
+    syntax: Syntax,
+    file: &Lrc<SourceFile>,
+    source_map: Lrc<SourceMap>,
+) -> anyhow::Result<Module> {
+    let error_handler = Handler::with_tty_emitter(ColorConfig::Auto, true, false, Some(source_map));
+
+    let mut errors = vec![];
+
+    parse_file_as_module(file, syntax, EsVersion::latest(), None, &mut errors).map_err(|e| {
+        e.into_diagnostic(&error_handler).emit();
+        anyhow!("failed to parse module: {}", path.display())
+    })
+}
+
+fn find_class_name_in_str(
+    s: &swc_ecma_ast::Str,
+    cursor_position: BytePos,
+) -> Option<(String, Span)> {
     let contains_cursor = s.span.lo < cursor_position && cursor_position < s.span.hi;
-    if !contains_cursor {
+    if s.is_empty() || !contains_cursor {
         return None;
     }
 
     info!(
         "found class_name strings around current cursor: {:?} at bytepos {}",
-        s.value, start_of_str
+        s.value,
+        s.span.lo.0 + 1
+    );
+
+    find_class_name_on_cursor(class_names_in_str(s), cursor_position)
+}
+
+/// Template literals are searched quasi by quasi, so the class lists can span many lines, but
+/// class names right next to an interpolation (e.g. `btn-${size}`) are only partially found.
+fn find_class_name_in_tpl_element(
+    el: &swc_ecma_ast::TplElement,
+    cursor_position: BytePos,
+) -> Option<(String, Span)> {
+    let contains_cursor = el.span.lo <= cursor_position && cursor_position <= el.span.hi;
+    if el.raw.is_empty() || !contains_cursor {
+        return None;
+    }
+
+    info!(
+        "found class_name template around current cursor: {:?} at bytepos {}",
+        el.raw, el.span.lo.0
     );
 
-    let mut substrings = vec![]; // inclusive incluse ranges for slices of the ast::Str that are
-                                 // substrings
+    find_class_name_on_cursor(class_names_in_tpl_element(el), cursor_position)
+}
+
+fn find_class_name_on_cursor(
+    class_names: Vec<(String, Span)>,
+    cursor_position: BytePos,
+) -> Option<(String, Span)> {
+    let class_name = class_names
+        .into_iter()
+        .find(|(_, span)| span.lo <= cursor_position && cursor_position < span.hi);
+
+    if let Some((value, _)) = &class_name {
+        info!("resolved substring on current cursor: {:?}", value);
+    }
+
+    class_name
+}
+
+fn class_names_in_str(s: &swc_ecma_ast::Str) -> Vec<(String, Span)> {
+    let start_of_str = s.span.lo.0 + 1; // not counting the quote;
+
+    // the raw text maps one to one onto the source bytes, unlike the value, where escapes
+    // and line continuations are already resolved
+    match &s.raw {
+        Some(raw) if raw.len() >= 2 => class_names_in_text(&raw[1..raw.len() - 1], start_of_str),
+        _ => class_names_in_text(&s.value, start_of_str),
+    }
+}
+
+fn class_names_in_tpl_element(el: &swc_ecma_ast::TplElement) -> Vec<(String, Span)> {
+    class_names_in_text(&el.raw, el.span.lo.0)
+}
+
+/// `start_of_text` is the byte position of the first byte of `text` in the source file.
+fn class_names_in_text(text: &str, start_of_text: u32) -> Vec<(String, Span)> {
+    raw_class_name_substrings(text)
+        .into_iter()
+        .map(|(start, end)| {
+            let span = Span::new(
+                BytePos(start_of_text + start as u32),
+                BytePos(start_of_text + end as u32 + 1),
+                SyntaxContext::empty(),
+            );
+            (text[start..=end].to_string(), span)
+        })
+        .collect()
+}
+
+/// Splits `value` on whitespace, returning the inclusive byte ranges of each substring.
+pub fn class_name_substrings(value: &str) -> Vec<(usize, usize)> {
+    let mut substrings = vec![];
     let mut start = None;
 
-    for (offset, b) in s.value.as_bytes().iter().enumerate() {
+    for (offset, b) in value.as_bytes().iter().enumerate() {
         if b.is_ascii_whitespace() {
             if start.is_some() && offset > 0 {
                 substrings.push((start.unwrap(), offset - 1)); // -1 to keep end inclusive
@@ -187,58 +818,117 @@ fn find_class_name_in_str(s: &swc_ecma_ast::Str, cursor_position: BytePos) -> Op
     }
 
     if let Some(start) = start {
-        substrings.push((start, s.value.len() - 1));
+        substrings.push((start, value.len() - 1));
     }
 
-    let class_name = substrings.into_iter().find_map(|(start, end)| {
-        let b_byte_start_pos = start_of_str + start as u32;
-        let b_byte_end_pos = start_of_str + end as u32;
+    substrings
+}
+
+/// Like [class_name_substrings], for the raw source text of a js string, where escaped
+/// whitespace (`\n`, `\t`, line continuations...) also separates class names.
+pub fn raw_class_name_substrings(raw: &str) -> Vec<(usize, usize)> {
+    let mut masked = raw.as_bytes().to_vec();
+    let mut i = 0;
+
+    while i + 1 < masked.len() {
+        if masked[i] != b'\\' {
+            i += 1;
+            continue;
+        }
 
-        if b_byte_start_pos <= cursor_position.0 && cursor_position.0 <= b_byte_end_pos {
-            let value = &s.value[start..=end];
-            info!("resolved substring on current cursor: {:?}", value);
-            return Some(value.to_string());
+        if matches!(
+            masked[i + 1],
+            b'n' | b't' | b'r' | b'f' | b'v' | b'\n' | b'\r'
+        ) {
+            masked[i] = b' ';
+            masked[i + 1] = b' ';
         }
 
-        None
-    });
+        i += 2;
+    }
+
+    let masked = String::from_utf8(masked).expect("only ascii bytes were replaced");
 
-    return class_name;
+    class_name_substrings(&masked)
 }
 
-fn get_syntax_of_file(source_file: &Path) -> anyhow::Result<Syntax> {
-    let syntax = match source_file.extension().and_then(|e| e.to_str()) {
-        Some("js") | Some("jsx") => Syntax::Es(swc_ecma_parser::EsConfig {
+/// Picks the syntax to parse a source file with, by its extension.
+pub fn syntax_of_file(source_file: &Path) -> anyhow::Result<Syntax> {
+    match source_file.extension().and_then(|e| e.to_str()) {
+        Some(ext @ ("js" | "jsx" | "ts" | "tsx")) => {
+            Ok(syntax_of_language(ext).expect("extension should be a known language"))
+        }
+        None => Err(anyhow!(
+            "unknown filetype, missing extension: {}",
+            source_file.display()
+        )),
+        ext => Err(anyhow!("unknown filetype: {ext:?}")),
+    }
+}
+
+/// Picks the syntax to parse an open document with, preferring its `languageId` over the
+/// extension of its `path`, since editors may open files as another language than their
+/// extension implies. None when cnls can't parse the document as js or ts.
+pub fn syntax_of_document(path: &Path, language_id: Option<&str>) -> Option<Syntax> {
+    match language_id {
+        Some(language_id) => syntax_of_language(language_id),
+        None => syntax_of_file(path).ok(),
+    }
+}
+
+/// Maps the standard LSP language identifiers, or file extensions, to a syntax.
+fn syntax_of_language(language: &str) -> Option<Syntax> {
+    let syntax = match language {
+        "javascript" | "javascriptreact" | "js" | "jsx" => Syntax::Es(swc_ecma_parser::EsConfig {
             jsx: true,
             ..Default::default()
         }),
-        Some("ts") => Syntax::Typescript(Default::default()),
-        Some("tsx") => Syntax::Typescript(swc_ecma_parser::TsConfig {
+        "typescript" | "ts" => Syntax::Typescript(Default::default()),
+        "typescriptreact" | "tsx" => Syntax::Typescript(swc_ecma_parser::TsConfig {
             tsx: true,
             ..Default::default()
         }),
-        None => {
-            return Err(anyhow!(
-                "unknown filetype, missing extension: {}",
-                source_file.display()
-            ))
-        }
-        ext => return Err(anyhow!("unknown filetype: {ext:?}")),
+        _ => return None,
     };
 
-    return Ok(syntax);
+    Some(syntax)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::find_class_name_in_str;
+    use super::SrcCodeMeta;
+    use crate::scope::Scope;
+    use crate::text::PositionEncoding::Utf16;
+    use std::str::FromStr;
     use swc_common::{BytePos, SyntaxContext};
     use swc_ecma_ast::Str;
+    use tower_lsp::lsp_types::Position;
+
+    fn tsx() -> swc_ecma_parser::Syntax {
+        super::syntax_of_file(std::path::Path::new("test.tsx")).unwrap()
+    }
+
+    fn classname_at(code: &str, position: Position) -> Option<String> {
+        let scopes = [
+            Scope::from_str("att:className").unwrap(),
+            Scope::from_str("fn:cn").unwrap(),
+        ];
+        SrcCodeMeta::build("test.tsx".into(), tsx(), code.to_string(), position, Utf16)
+            .unwrap()
+            .get_classname_on_cursor(&scopes)
+            .unwrap()
+            .map(|token| token.value)
+    }
+
+    fn find_class_name_in_str(s: &Str, cursor_position: BytePos) -> Option<String> {
+        super::find_class_name_in_str(s, cursor_position).map(|(value, _)| value)
+    }
 
     /// `offset` marks the byte position of the opening quote of the string `value`.
+    /// The raw text is the value as is between quotes, like in a jsx attribute.
     fn mock_str(value: &str, offset: u32) -> Str {
-        let raw = format!("{value:?}");
-        return Str {
+        let raw = format!("\"{value}\"");
+        Str {
             span: swc_common::Span {
                 lo: BytePos(offset),
                 hi: BytePos(raw.len() as u32 + offset),
@@ -246,7 +936,7 @@ mod tests {
             },
             value: value.into(),
             raw: Some(raw.into()),
-        };
+        }
     }
 
     #[test]
@@ -316,4 +1006,419 @@ test"#,
         let s = mock_str("     ", 1);
         assert_eq!(find_class_name_in_str(&s, BytePos(2)), None);
     }
+
+    #[test]
+    fn it_finds_names_in_multiline_templates() {
+        let code = r#"export const Card = () => (
+  <div
+    className={`
+      flex flex-col
+      rounded-lg
+      ${cn("shadow", "p-4 m-2")}
+      bg-white
+    `}
+  />
+);"#;
+
+        assert_eq!(
+            classname_at(code, Position::new(3, 12)),
+            Some("flex-col".to_owned())
+        );
+        assert_eq!(
+            classname_at(code, Position::new(4, 6)),
+            Some("rounded-lg".to_owned())
+        );
+        assert_eq!(
+            classname_at(code, Position::new(5, 27)),
+            Some("m-2".to_owned())
+        );
+        assert_eq!(
+            classname_at(code, Position::new(6, 13)),
+            Some("bg-white".to_owned())
+        );
+        assert_eq!(classname_at(code, Position::new(4, 3)), None);
+    }
+
+    #[test]
+    fn it_finds_names_in_multiline_strings() {
+        let code = "const a = cn(\"h-10\\\n  w-10\", \"p-2\\\n\\\n  m-2\");";
+
+        assert_eq!(
+            classname_at(code, Position::new(1, 4)),
+            Some("w-10".to_owned())
+        );
+        assert_eq!(
+            classname_at(code, Position::new(3, 3)),
+            Some("m-2".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_collects_every_scoped_name() {
+        let scopes = [
+            Scope::from_str("att:className").unwrap(),
+            Scope::from_str("fn:cn").unwrap(),
+        ];
+        let code =
+            r#"const a = <div className={cn("p-2 flex", cond && `m-1`)} title="not classes" />;"#;
+
+        let tokens =
+            super::collect_class_names("test.tsx".into(), tsx(), code.to_string(), &scopes)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.value, &code[t.range]))
+                .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                ("p-2".to_owned(), "p-2"),
+                ("flex".to_owned(), "flex"),
+                ("m-1".to_owned(), "m-1")
+            ]
+        );
+    }
+
+    #[test]
+    fn it_leaves_out_names_next_to_interpolations() {
+        let scopes = [
+            Scope::from_str("att:className").unwrap(),
+            Scope::from_str("fn:cn").unwrap(),
+        ];
+        let code = r#"const a = <div className={`btn-${size} card ${tone}-text ${x} p-2`} title={cn("", ``)} />;"#;
+
+        let tokens =
+            super::collect_class_names("test.tsx".into(), tsx(), code.to_string(), &scopes)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.value, &code[t.range]))
+                .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![("card".to_owned(), "card"), ("p-2".to_owned(), "p-2")]
+        );
+    }
+
+    #[test]
+    fn it_lists_imported_stylesheets() {
+        let code = r#"import "./card.css";
+import styles from "../theme.css";
+import { cn } from "./utils";
+const legacy = require("./legacy.scss");
+const utils = require("./utils");
+"#;
+
+        let stylesheets =
+            super::imported_stylesheets("test.tsx".into(), tsx(), code.to_string()).unwrap();
+        assert_eq!(
+            stylesheets,
+            vec!["./card.css", "../theme.css", "./legacy.scss"]
+        );
+    }
+
+    #[test]
+    fn it_resolves_local_variables_in_scope() {
+        let code = r#"export const Card = () => {
+  const cls = "card elevated";
+  const other = cn("p-2");
+  return <div className={cls}><span className={other} /></div>;
+};"#;
+
+        assert_eq!(
+            classname_at(code, Position::new(1, 22)),
+            Some("elevated".to_owned())
+        );
+        assert_eq!(
+            classname_at(code, Position::new(3, 26)),
+            Some("card".to_owned())
+        );
+        assert_eq!(
+            classname_at(code, Position::new(2, 20)),
+            Some("p-2".to_owned())
+        );
+
+        let scopes = [
+            Scope::from_str("att:className").unwrap(),
+            Scope::from_str("fn:cn").unwrap(),
+        ];
+        let tokens =
+            super::collect_class_names("test.tsx".into(), tsx(), code.to_string(), &scopes)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.value)
+                .collect::<Vec<_>>();
+
+        assert_eq!(tokens, vec!["p-2", "card", "elevated"]);
+    }
+
+    #[test]
+    fn it_resolves_every_class_name_of_a_variable_on_cursor() {
+        let code = r#"const cls = cn("card elevated", `p-2 ${size}`);
+const a = <div className={cls} />;"#;
+
+        let tokens = SrcCodeMeta::build(
+            "test.tsx".into(),
+            tsx(),
+            code.to_string(),
+            Position::new(1, 27),
+            Utf16,
+        )
+        .unwrap()
+        .get_classnames_on_cursor(&[Scope::from_str("att:className").unwrap()])
+        .unwrap();
+
+        let names = tokens
+            .iter()
+            .map(|token| (token.value.as_str(), &code[token.range.clone()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![("card", "card"), ("elevated", "elevated"), ("p-2", "p-2")]
+        );
+
+        // on one of them, only that one
+        let tokens = SrcCodeMeta::build(
+            "test.tsx".into(),
+            tsx(),
+            code.to_string(),
+            Position::new(0, 22),
+            Utf16,
+        )
+        .unwrap()
+        .get_classnames_on_cursor(&[Scope::from_str("fn:cn").unwrap()])
+        .unwrap();
+        assert_eq!(
+            tokens.iter().map(|t| t.value.as_str()).collect::<Vec<_>>(),
+            vec!["elevated"]
+        );
+    }
+
+    #[test]
+    fn it_finds_the_range_of_the_name_on_cursor() {
+        let code = r#"const cls = "card";
+const a = <div className="flex  hover:btn" title={cls} />;
+const b = <div className={cls} />;"#;
+
+        let token_at = |position| {
+            SrcCodeMeta::build("test.tsx".into(), tsx(), code.to_string(), position, Utf16)
+                .unwrap()
+                .get_class_name_token_on_cursor(&[Scope::from_str("att:className").unwrap()])
+                .unwrap()
+        };
+
+        let token = token_at(Position::new(1, 36)).unwrap();
+        assert_eq!(token.value, "hover:btn");
+        assert_eq!(&code[token.range], "hover:btn");
+
+        assert_eq!(token_at(Position::new(1, 31)), None);
+        assert_eq!(token_at(Position::new(2, 27)), None);
+    }
+
+    #[test]
+    fn it_finds_the_classes_accessed_on_css_modules() {
+        let dir = std::env::temp_dir().join(format!("cnls-find-modules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Button.module.css"), ".primary {}").unwrap();
+        let code = r#"import styles from "./Button.module.css";
+import * as theme from "./Button.module.css";
+const a = <div className={styles.primary} />;
+const b = <div className={styles["primary-dark"]} />;
+const c = theme.primaryDark;
+const d = other.primary;"#;
+
+        let token_at = |position| {
+            SrcCodeMeta::build(
+                dir.join("App.tsx"),
+                tsx(),
+                code.to_string(),
+                position,
+                Utf16,
+            )
+            .unwrap()
+            .get_classname_on_cursor(&[Scope::from_str("att:className").unwrap()])
+            .unwrap()
+        };
+        let css_module = Some(dir.join("Button.module.css"));
+
+        let token = token_at(Position::new(2, 35)).unwrap();
+        assert_eq!(token.value, "primary");
+        assert_eq!(&code[token.range], "primary");
+        assert_eq!(token.css_module, css_module);
+
+        let token = token_at(Position::new(3, 37)).unwrap();
+        assert_eq!(token.value, "primary-dark");
+        assert_eq!(&code[token.range], "primary-dark");
+        assert_eq!(token.css_module, css_module);
+
+        let token = token_at(Position::new(4, 18)).unwrap();
+        assert_eq!(token.value, "primaryDark");
+        assert_eq!(token.css_module, css_module);
+
+        assert_eq!(token_at(Position::new(5, 16)), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_clamps_positions_past_the_end_of_a_line() {
+        let code = "const a = <div className={`p-2 flex\n  hover:btn`} />;";
+        let build = |position| {
+            SrcCodeMeta::build("test.tsx".into(), tsx(), code.to_string(), position, Utf16)
+        };
+
+        // at the end of the line, right after the class
+        assert_eq!(classname_at(code, Position::new(0, 1000)), None);
+        assert_eq!(
+            classname_at(code, Position::new(1, 1000)),
+            classname_at(code, Position::new(1, 13))
+        );
+        assert!(build(Position::new(2, 0)).is_err());
+        assert!(build(Position::new(u32::MAX, u32::MAX)).is_err());
+    }
+
+    #[test]
+    fn it_finds_the_range_of_the_name_on_cursor_past_line_breaks_and_escapes() {
+        let code = "const a = <div className={`flex\n    hover:btn`} />;\nconst b = cn(\"p-2\\tcard w-4\", cls);";
+        let at = |position| {
+            SrcCodeMeta::build("test.tsx".into(), tsx(), code.to_string(), position, Utf16)
+                .unwrap()
+                .get_classname_on_cursor(&[
+                    Scope::from_str("att:className").unwrap(),
+                    Scope::from_str("fn:cn").unwrap(),
+                ])
+                .unwrap()
+                .unwrap()
+        };
+
+        let token = at(Position::new(1, 8));
+        assert_eq!(token.value, "hover:btn");
+        assert_eq!(&code[token.range], "hover:btn");
+
+        let token = at(Position::new(2, 20));
+        assert_eq!(token.value, "card");
+        assert_eq!(&code[token.range], "card");
+
+        let token = at(Position::new(2, 25));
+        assert_eq!(token.value, "w-4");
+        assert_eq!(&code[token.range], "w-4");
+    }
+
+    #[test]
+    fn it_finds_names_next_to_multibyte_characters() {
+        let code = "const label = \"héllo ✨\"; const a = <div className=\"p-2 flex\" />;
+const b = <div className=\"café ✨ flex\" />;
+const c = <div className=\"p-2 flex\" title=\"✨ 新\" />;";
+
+        // the LSP column, in UTF-16 code units, of the `nth` occurrence of `needle` on `line`
+        let position = |line: u32, needle: &str, nth: usize| {
+            let text = code.lines().nth(line as usize).unwrap();
+            let index = text.match_indices(needle).nth(nth).unwrap().0;
+            Position::new(line, text[..index].encode_utf16().count() as u32 + 1)
+        };
+        let token_at = |position| {
+            SrcCodeMeta::build("test.tsx".into(), tsx(), code.to_string(), position, Utf16)
+                .unwrap()
+                .get_class_name_token_on_cursor(&[Scope::from_str("att:className").unwrap()])
+                .unwrap()
+                .unwrap()
+        };
+
+        // before the class string
+        let token = token_at(position(0, "flex", 0));
+        assert_eq!(token.value, "flex");
+        assert_eq!(&code[token.range], "flex");
+        assert_eq!(token_at(position(0, "p-2", 0)).value, "p-2");
+
+        // inside it
+        let token = token_at(position(1, "caf", 0));
+        assert_eq!(token.value, "café");
+        assert_eq!(&code[token.range], "café");
+        assert_eq!(token_at(position(1, "flex", 0)).value, "flex");
+        assert_eq!(classname_at(code, position(1, "✨", 0)), None);
+
+        // after it
+        assert_eq!(token_at(position(2, "flex", 0)).value, "flex");
+        assert_eq!(classname_at(code, position(2, "新", 0)), None);
+    }
+
+    #[test]
+    fn it_groups_class_strings_by_owner() {
+        let code = r#"const cls = "card";
+const a = <div className="flex p-2" class={`m-1 ${x}`} title="no">
+  <span className={cn("a", isOn && "b")} />
+  <p className={cls} />
+</div>;"#;
+
+        let scopes = [
+            Scope::from_str("att:className,class").unwrap(),
+            Scope::from_str("fn:cn").unwrap(),
+        ];
+        let groups =
+            super::class_string_groups("test.tsx".into(), tsx(), code.to_string(), &scopes)
+                .unwrap()
+                .into_iter()
+                .map(|group| {
+                    let strings = group
+                        .strings
+                        .into_iter()
+                        .map(|string| &code[string])
+                        .collect::<Vec<_>>();
+                    (group.owner, strings)
+                })
+                .collect::<Vec<_>>();
+
+        assert_eq!(
+            groups,
+            vec![
+                ("cls".to_owned(), vec!["card"]),
+                ("<div>".to_owned(), vec!["flex p-2", "m-1 "]),
+                ("cn()".to_owned(), vec!["a", "b"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_ignores_reassigned_or_unscoped_variables() {
+        let code = r#"import { imported } from "./styles";
+let cls = "card";
+cls = "other";
+const unused = "not classes";
+const a = <div className={cls} title={unused} />;
+const b = <div className={imported} />;"#;
+
+        assert_eq!(classname_at(code, Position::new(1, 12)), None);
+        assert_eq!(classname_at(code, Position::new(4, 27)), None);
+        assert_eq!(classname_at(code, Position::new(3, 17)), None);
+        assert_eq!(classname_at(code, Position::new(5, 29)), None);
+    }
+
+    #[test]
+    fn it_prefers_language_id_over_extension() {
+        use super::syntax_of_document;
+        use std::path::Path;
+        use swc_ecma_parser::Syntax;
+
+        let syntax = syntax_of_document(Path::new("index.html"), Some("javascriptreact"));
+        assert!(matches!(syntax, Some(Syntax::Es(config)) if config.jsx));
+
+        let syntax = syntax_of_document(Path::new("gen.ts"), Some("plaintext"));
+        assert!(syntax.is_none());
+
+        let syntax = syntax_of_document(Path::new("app.tsx"), None);
+        assert!(matches!(syntax, Some(Syntax::Typescript(config)) if config.tsx));
+
+        assert!(syntax_of_document(Path::new("index.html"), None).is_none());
+
+        let code = r#"<div className="flex" />"#;
+        let syntax = syntax_of_document(Path::new("index.html"), Some("javascriptreact"));
+        let tokens = super::collect_class_names(
+            "index.html".into(),
+            syntax.unwrap(),
+            code.to_string(),
+            &[Scope::from_str("att:className").unwrap()],
+        )
+        .unwrap();
+        assert_eq!(tokens[0].value, "flex");
+    }
 }
@@ -0,0 +1,171 @@
+use tower_lsp::lsp_types::{MarkupContent, MarkupKind};
+
+/// A part of the hover content, rendered according to the markup the client supports.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Section {
+    /// Starts a new group of sections, e.g. for each rule shown.
+    Header(String),
+    Code {
+        language: String,
+        text: String,
+    },
+    Note(String),
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HoverDoc {
+    sections: Vec<Section>,
+}
+
+impl HoverDoc {
+    pub fn header(mut self, text: impl Into<String>) -> Self {
+        self.sections.push(Section::Header(text.into()));
+        self
+    }
+
+    pub fn code(mut self, language: impl Into<String>, text: impl Into<String>) -> Self {
+        self.sections.push(Section::Code {
+            language: language.into(),
+            text: text.into(),
+        });
+        self
+    }
+
+    pub fn note(mut self, text: impl Into<String>) -> Self {
+        self.sections.push(Section::Note(text.into()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    pub fn render(&self, kind: MarkupKind) -> MarkupContent {
+        let value = match kind {
+            MarkupKind::Markdown => self.render_with(render_markdown_section),
+            MarkupKind::PlainText => self.render_with(render_plaintext_section),
+        };
+
+        MarkupContent { kind, value }
+    }
+
+    /// Sections are separated by blank lines, and groups by a `---` line, which markdown
+    /// renders as a horizontal rule.
+    fn render_with(&self, render_section: fn(&Section) -> String) -> String {
+        let mut out = String::new();
+
+        for (i, section) in self.sections.iter().enumerate() {
+            if i > 0 {
+                out.push_str(match section {
+                    Section::Header(_) => "\n\n---\n\n",
+                    _ => "\n\n",
+                });
+            }
+            out.push_str(&render_section(section));
+        }
+
+        out
+    }
+}
+
+fn render_markdown_section(section: &Section) -> String {
+    match section {
+        Section::Header(text) => format!("**{text}**"),
+        Section::Code { language, text } => format!("```{language}\n{}\n```", text.trim_end()),
+        Section::Note(text) => text.clone(),
+    }
+}
+
+fn render_plaintext_section(section: &Section) -> String {
+    match section {
+        Section::Header(text) | Section::Note(text) => text.clone(),
+        Section::Code { text, .. } => text
+            .trim_end()
+            .lines()
+            .map(|line| format!("    {line}"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Picks the first markup kind in the client's order of preference, defaulting to markdown
+/// when the client doesn't say.
+pub fn negotiate_markup_kind(content_format: Option<&[MarkupKind]>) -> MarkupKind {
+    content_format
+        .and_then(|formats| formats.first())
+        .cloned()
+        .unwrap_or(MarkupKind::Markdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::MarkupKind;
+
+    use super::{negotiate_markup_kind, HoverDoc};
+
+    fn doc() -> HoverDoc {
+        HoverDoc::default()
+            .header("styles.css")
+            .code("css", ".btn.primary {\n  color: red;\n}\n")
+            .note("also requires: .primary")
+            .header("theme.css")
+            .code("css", ".btn {\n  padding: 0;\n}")
+    }
+
+    #[test]
+    fn it_renders_markdown() {
+        assert_eq!(
+            doc().render(MarkupKind::Markdown).value,
+            "**styles.css**
+
+```css
+.btn.primary {
+  color: red;
+}
+```
+
+also requires: .primary
+
+---
+
+**theme.css**
+
+```css
+.btn {
+  padding: 0;
+}
+```"
+        );
+    }
+
+    #[test]
+    fn it_renders_plaintext() {
+        assert_eq!(
+            doc().render(MarkupKind::PlainText).value,
+            "styles.css
+
+    .btn.primary {
+      color: red;
+    }
+
+also requires: .primary
+
+---
+
+theme.css
+
+    .btn {
+      padding: 0;
+    }"
+        );
+    }
+
+    #[test]
+    fn it_negotiates_markup_kind() {
+        assert_eq!(negotiate_markup_kind(None), MarkupKind::Markdown);
+        assert_eq!(
+            negotiate_markup_kind(Some(&[MarkupKind::PlainText, MarkupKind::Markdown])),
+            MarkupKind::PlainText
+        );
+    }
+}
@@ -0,0 +1,979 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+
+use crate::fs::CssFileLimits;
+use anyhow::Context;
+use tokio::sync::watch;
+
+use crate::collect::{ClassName, ClassNamesCollector, CustomProperty, ParseProblem};
+use crate::config::IndexMinified;
+
+/// Class names collected from every css file in the workspace, in the order their definitions
+/// should be preferred. Cloning it shares the collected class names of its files.
+#[derive(Default, Clone)]
+pub struct CssIndex {
+    files: Vec<IndexedFile>,
+}
+
+#[derive(Clone)]
+struct IndexedFile {
+    path: PathBuf,
+    rank: Rank,
+    collector: Arc<ClassNamesCollector>,
+}
+
+/// Where the definitions of an indexed css file rank before their paths are compared: those of
+/// the files found in the workspace first, the minified ones ranked low per `cnls.indexMinified`
+/// after the others, and those of the files indexed for being imported last.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Rank {
+    pub imported: bool,
+    pub low_priority: bool,
+}
+
+impl CssIndex {
+    /// Parses the `css_files` in order, returning the errors of those that failed alongside.
+    pub fn parse_files(css_files: Vec<PathBuf>) -> (Self, Vec<anyhow::Error>) {
+        let css_files = css_files
+            .into_iter()
+            .map(|css_file| (css_file, Rank::default()))
+            .collect();
+        let (css_index, _, errors) = Self::parse_files_with(
+            css_files,
+            IndexMinified::Index,
+            CssFileLimits::default(),
+            &|_, _| {},
+            &AtomicBool::new(false),
+        );
+        (css_index, errors)
+    }
+
+    /// Like [CssIndex::parse_files], for css files ranked each, see [parse_css_file], calling
+    /// `progress` with the number of files done out of the total after each one. Also returns
+    /// the files left out for looking minified once read. Stops early, with the files parsed so
+    /// far, once `cancelled` is set.
+    pub fn parse_files_with(
+        css_files: Vec<(PathBuf, Rank)>,
+        index_minified: IndexMinified,
+        limits: CssFileLimits,
+        progress: &dyn Fn(usize, usize),
+        cancelled: &AtomicBool,
+    ) -> (Self, Vec<PathBuf>, Vec<anyhow::Error>) {
+        let mut css_index = CssIndex::default();
+        let mut skipped = vec![];
+        let mut errors = vec![];
+        let total = css_files.len();
+
+        for (done, (css_file, rank)) in css_files.into_iter().enumerate() {
+            if cancelled.load(AtomicOrdering::Relaxed) {
+                break;
+            }
+
+            match parse_css_file(css_file.clone(), rank, index_minified, limits) {
+                Ok(Some((rank, collector))) => css_index.push(css_file, rank, collector),
+                Ok(None) => skipped.push(css_file),
+                Err(err) => errors.push(err),
+            }
+
+            progress(done + 1, total);
+        }
+
+        // the files ranked low once read after all the others, in order otherwise
+        css_index.files.sort_by_key(|file| file.rank);
+
+        (css_index, skipped, errors)
+    }
+
+    /// Indexes the stylesheets the indexed ones `@import`, and those these import in turn, up to
+    /// `depth` imports away, each once, after the others. Returns the errors of those that
+    /// failed to parse.
+    pub fn follow_imports(&mut self, depth: usize, cancelled: &AtomicBool) -> Vec<anyhow::Error> {
+        let mut visited = self
+            .files
+            .iter()
+            .map(|file| file.path.clone())
+            .collect::<HashSet<_>>();
+        let mut importers = 0..self.files.len();
+        let mut errors = vec![];
+
+        for _ in 0..depth {
+            let imported = self.files[importers.clone()]
+                .iter()
+                .flat_map(|file| {
+                    file.collector
+                        .imports()
+                        .iter()
+                        .filter_map(|url| crate::fs::resolve_import(&file.path, url))
+                })
+                .filter(|css_file| visited.insert(css_file.clone()))
+                .collect::<Vec<_>>();
+
+            let first_imported = self.files.len();
+            for css_file in imported {
+                if cancelled.load(AtomicOrdering::Relaxed) {
+                    return errors;
+                }
+
+                let rank = Rank {
+                    imported: true,
+                    ..Rank::default()
+                };
+                match ClassNamesCollector::parse(css_file.clone()) {
+                    Ok(collector) => self.push(css_file, rank, collector),
+                    Err(err) => errors.push(err),
+                }
+            }
+
+            importers = first_imported..self.files.len();
+            if importers.is_empty() {
+                break;
+            }
+        }
+
+        errors
+    }
+
+    /// The `css_files` along with those they `@import`, directly or not, as far as they're
+    /// indexed.
+    pub fn import_closure(&self, css_files: Vec<PathBuf>) -> HashSet<PathBuf> {
+        let mut closure = css_files.iter().cloned().collect::<HashSet<_>>();
+        let mut importers = css_files;
+
+        while let Some(importer) = importers.pop() {
+            let Some(file) = self.files.iter().find(|file| file.path == importer) else {
+                continue;
+            };
+            for url in file.collector.imports() {
+                let Some(imported) = crate::fs::resolve_import(&importer, url) else {
+                    continue;
+                };
+                if closure.insert(imported.clone()) {
+                    importers.push(imported);
+                }
+            }
+        }
+
+        closure
+    }
+
+    pub fn push(&mut self, css_file: PathBuf, rank: Rank, collector: ClassNamesCollector) {
+        self.files.push(IndexedFile {
+            path: css_file,
+            rank,
+            collector: Arc::new(collector),
+        });
+    }
+
+    /// Indexes the `collector` of `css_file` in place of the one it had, or where it belongs
+    /// when it wasn't indexed yet or ranks otherwise, e.g. once it's created: by its `rank`, then
+    /// among the files of the first of the workspace folders at `roots` listing it, as when the
+    /// index was built, per [compare_definitions].
+    pub fn update(
+        &mut self,
+        roots: &[PathBuf],
+        css_file: PathBuf,
+        rank: Rank,
+        collector: ClassNamesCollector,
+    ) {
+        let collector = Arc::new(collector);
+
+        if let Some(file) = self.files.iter_mut().find(|file| file.path == css_file) {
+            if file.rank == rank {
+                file.collector = collector;
+                return;
+            }
+            self.remove(&css_file);
+        }
+
+        let root_of = |css_file: &Path| {
+            roots
+                .iter()
+                .position(|root| css_file.starts_with(root))
+                .unwrap_or(roots.len())
+        };
+        let root = root_of(&css_file);
+        let root_path = roots.get(root).map_or(Path::new(""), PathBuf::as_path);
+
+        let at = self.files.partition_point(|file| {
+            file.rank
+                .cmp(&rank)
+                .then_with(|| root_of(&file.path).cmp(&root))
+                .then_with(|| compare_definitions(root_path, (&file.path, 0), (&css_file, 0)))
+                == Ordering::Less
+        });
+        self.files.insert(
+            at,
+            IndexedFile {
+                path: css_file,
+                rank,
+                collector,
+            },
+        );
+    }
+
+    /// Drops the class names of `css_file`, e.g. once it's deleted, returning whether it was
+    /// indexed.
+    pub fn remove(&mut self, css_file: &Path) -> bool {
+        let len = self.files.len();
+        self.files.retain(|file| file.path != css_file);
+        self.files.len() != len
+    }
+
+    pub fn css_files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    /// Where `css_file` ranks among the others, `None` when it isn't indexed.
+    pub fn rank_of(&self, css_file: &Path) -> Option<Rank> {
+        self.files
+            .iter()
+            .find(|file| file.path == css_file)
+            .map(|file| file.rank)
+    }
+
+    pub fn class_names(&self) -> impl Iterator<Item = (&Path, &ClassName)> {
+        self.files.iter().flat_map(|file| {
+            file.collector
+                .class_names()
+                .iter()
+                .map(|class| (file.path.as_path(), class))
+        })
+    }
+
+    /// Every class name defined, once each, in the order they are first defined.
+    pub fn class_name_values(&self) -> impl Iterator<Item = &str> {
+        let mut seen = HashSet::new();
+        self.class_names()
+            .map(|(_, class)| &*class.value)
+            .filter(move |value| seen.insert(*value))
+    }
+
+    /// The problems found parsing each css file, see [ClassNamesCollector::problems].
+    pub fn parse_problems(&self) -> impl Iterator<Item = (&Path, &ParseProblem)> {
+        self.files.iter().flat_map(|file| {
+            file.collector
+                .problems()
+                .iter()
+                .map(|problem| (file.path.as_path(), problem))
+        })
+    }
+
+    /// The first declaration of the custom property `name`, dashes included, in the order
+    /// definitions are preferred.
+    pub fn find_custom_property(&self, name: &str) -> Option<(&Path, &CustomProperty)> {
+        self.files.iter().find_map(|file| {
+            file.collector
+                .custom_properties()
+                .iter()
+                .find(|property| &*property.name == name)
+                .map(|property| (file.path.as_path(), property))
+        })
+    }
+
+    pub fn count_definitions(&self, value: &str) -> usize {
+        self.definitions_of(value).count()
+    }
+
+    /// Every rule defining the class `value`, in the order of [compare_definitions].
+    pub fn definitions_of<'a>(
+        &'a self,
+        value: &'a str,
+    ) -> impl Iterator<Item = (&'a Path, &'a ClassName)> + 'a {
+        self.class_names()
+            .filter(move |(_, class)| &class.value == value)
+    }
+
+    /// Every definition of the class names matching the `query`, see [fuzzy_matches].
+    pub fn search<'a>(
+        &'a self,
+        query: &'a str,
+    ) -> impl Iterator<Item = (&'a Path, &'a ClassName)> + 'a {
+        self.class_names()
+            .filter(move |(_, class)| fuzzy_matches(query, &class.value))
+    }
+
+    pub fn find_class_name_by_value(&self, value: &str) -> Option<(&Path, &ClassName)> {
+        self.files.iter().find_map(|file| {
+            file.collector
+                .find_class_name_by_value(value)
+                .map(|class| (file.path.as_path(), class))
+        })
+    }
+}
+
+/// Whether the characters of the `query` appear in order in the `value`, ignoring case, e.g.
+/// `bpr` in `btn-primary`.
+pub fn fuzzy_matches(query: &str, value: &str) -> bool {
+    let mut chars = value.chars().flat_map(char::to_lowercase);
+
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| chars.any(|c| c == q))
+}
+
+/// How much the definitions in a css file are preferred, by where it is in the workspace.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum PathPriority {
+    FirstParty,
+    /// Build output, e.g. in `dist/`.
+    Dist,
+    NodeModules,
+}
+
+impl PathPriority {
+    pub fn of(root: &Path, css_file: &Path) -> Self {
+        let mut priority = PathPriority::FirstParty;
+
+        for component in css_file.strip_prefix(root).unwrap_or(css_file) {
+            match component.to_str() {
+                Some("node_modules") => return PathPriority::NodeModules,
+                Some("dist" | "build" | "out") => priority = PathPriority::Dist,
+                _ => {}
+            }
+        }
+
+        priority
+    }
+}
+
+/// The order in which definitions, given by their css file and byte offset, are listed wherever
+/// more than one is found: by [PathPriority], then by workspace-relative path, then by offset.
+/// It doesn't depend on the order the files were found in, which differs across machines.
+pub fn compare_definitions(root: &Path, a: (&Path, u32), b: (&Path, u32)) -> Ordering {
+    let relative = |path: &'_ Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+
+    PathPriority::of(root, a.0)
+        .cmp(&PathPriority::of(root, b.0))
+        .then_with(|| relative(a.0).cmp(&relative(b.0)))
+        .then(a.1.cmp(&b.1))
+}
+
+/// The order in which the css files defining a class are listed for a `document`, closest
+/// first: by the number of folders they share with it, then by depth, then by path.
+pub fn compare_proximity(document: &Path, a: &Path, b: &Path) -> Ordering {
+    let shared = |css_file: &Path| {
+        css_file
+            .components()
+            .zip(document.components())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+
+    shared(b)
+        .cmp(&shared(a))
+        .then_with(|| a.components().count().cmp(&b.components().count()))
+        .then_with(|| a.cmp(b))
+}
+
+/// The css files of a workspace folder, sorted out per the `cnls.indexMinified` setting, see
+/// [prioritize_css_files].
+#[derive(Debug, Default, PartialEq)]
+pub struct PrioritizedCssFiles {
+    pub css_files: Vec<PathBuf>,
+    /// Minified or oversized, indexed after the css files of every workspace folder.
+    pub low_priority: Vec<PathBuf>,
+    /// Minified or oversized, not indexed.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// The css files to index of every workspace folder, `prioritized` in order, each ranked: those
+/// of low priority after all the others.
+pub fn rank_css_files(prioritized: Vec<PrioritizedCssFiles>) -> Vec<(PathBuf, Rank)> {
+    let mut ranked = vec![];
+    let mut low_priority = vec![];
+    for prioritized in prioritized {
+        ranked.extend(
+            prioritized
+                .css_files
+                .into_iter()
+                .map(|css_file| (css_file, Rank::default())),
+        );
+        low_priority.extend(prioritized.low_priority.into_iter().map(|css_file| {
+            let rank = Rank {
+                low_priority: true,
+                ..Rank::default()
+            };
+            (css_file, rank)
+        }));
+    }
+
+    ranked.extend(low_priority);
+    ranked
+}
+
+/// Orders the `css_files` of the workspace folder at `root` per [compare_definitions], setting
+/// aside the oversized ones as the `index_minified` setting says. Those are told apart by their
+/// size alone, the minified ones only once read, see [parse_css_file].
+pub fn prioritize_css_files(
+    root: &Path,
+    mut css_files: Vec<PathBuf>,
+    index_minified: IndexMinified,
+    limits: CssFileLimits,
+) -> PrioritizedCssFiles {
+    css_files.sort_by(|a, b| compare_definitions(root, (a, 0), (b, 0)));
+
+    if index_minified == IndexMinified::Index {
+        return PrioritizedCssFiles {
+            css_files,
+            ..PrioritizedCssFiles::default()
+        };
+    }
+
+    let (oversized, css_files): (Vec<_>, Vec<_>) = css_files
+        .into_iter()
+        .partition(|file| limits.is_exceeded_by(file).unwrap_or(false));
+
+    match index_minified {
+        IndexMinified::LowPriority => PrioritizedCssFiles {
+            css_files,
+            low_priority: oversized,
+            skipped: vec![],
+        },
+        _ => PrioritizedCssFiles {
+            css_files,
+            low_priority: vec![],
+            skipped: oversized,
+        },
+    }
+}
+
+/// Parses the `css_file` ranked `rank` by its size, see [prioritize_css_files], checking the lines
+/// of its code as read for those of minified files. Unless already ranked low or imported, such
+/// a file is ranked low or left out, for `None`, as the `index_minified` setting says.
+pub fn parse_css_file(
+    css_file: PathBuf,
+    mut rank: Rank,
+    index_minified: IndexMinified,
+    limits: CssFileLimits,
+) -> anyhow::Result<Option<(Rank, ClassNamesCollector)>> {
+    let code = std::fs::read_to_string(&css_file)
+        .with_context(|| format!("failed to read {}", css_file.display()))?;
+
+    if rank == Rank::default() && limits.is_exceeded_by_lines_of(&code) {
+        match index_minified {
+            IndexMinified::Index => {}
+            IndexMinified::LowPriority => rank.low_priority = true,
+            IndexMinified::Skip => return Ok(None),
+        }
+    }
+
+    Ok(Some((
+        rank,
+        ClassNamesCollector::parse_code(css_file, &code),
+    )))
+}
+
+struct Flight<T> {
+    generation: u64,
+    /// The result of the latest run, once it's done, and whether it's to stop.
+    run: Option<(watch::Receiver<Option<T>>, Arc<AtomicBool>)>,
+}
+
+/// Coalesces concurrent runs of the same work: callers arriving while a run is in flight await
+/// its result instead of starting another one. Once a run completes, the next caller starts a
+/// fresh one. Runs go on on a task of their own, whether their callers still wait or not,
+/// until they're invalidated.
+pub struct SingleFlight<T> {
+    flight: Mutex<Flight<T>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self {
+            flight: Mutex::new(Flight {
+                generation: 0,
+                run: None,
+            }),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> SingleFlight<T> {
+    /// Awaits the result of the run in flight, or of the `work` spawned otherwise, given the
+    /// flag set once it's invalidated to stop early. Callers that stop waiting, e.g. once they
+    /// time out or are cancelled, leave it running for the others and the next ones.
+    pub async fn run<F, Fut>(&self, work: F) -> T
+    where
+        F: FnOnce(Arc<AtomicBool>) -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        let mut run = {
+            let mut flight = self.flight.lock().expect("single flight lock was poisoned");
+            let in_flight = flight
+                .run
+                .as_ref()
+                .filter(|(run, _)| run.borrow().is_none() && run.has_changed().is_ok());
+
+            match in_flight {
+                Some((run, _)) => run.clone(),
+                None => {
+                    let (done, run) = watch::channel(None);
+                    let cancelled = Arc::new(AtomicBool::new(false));
+                    let work = work(cancelled.clone());
+                    tokio::spawn(async move {
+                        let _ = done.send(Some(work.await));
+                    });
+                    flight.run = Some((run.clone(), cancelled));
+                    run
+                }
+            }
+        };
+
+        let value = run
+            .wait_for(Option::is_some)
+            .await
+            .expect("single flight work shouldn't panic")
+            .clone();
+        value.expect("waited for the result")
+    }
+
+    /// Counts the invalidations, e.g. to tell whether results computed since are outdated.
+    pub fn generation(&self) -> u64 {
+        self.flight
+            .lock()
+            .expect("single flight lock was poisoned")
+            .generation
+    }
+
+    /// Stops any run in flight, so that the next caller starts fresh work rather than awaiting
+    /// a result computed from outdated inputs.
+    pub fn invalidate(&self) {
+        let mut flight = self.flight.lock().expect("single flight lock was poisoned");
+        flight.generation += 1;
+        if let Some((_, cancelled)) = flight.run.take() {
+            cancelled.store(true, AtomicOrdering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use std::path::{Path, PathBuf};
+
+    use crate::fs::CssFileLimits;
+
+    use super::{
+        compare_definitions, compare_proximity, fuzzy_matches, parse_css_file,
+        prioritize_css_files, rank_css_files, CssIndex, PathPriority, Rank, SingleFlight,
+    };
+    use crate::collect::ClassNamesCollector;
+    use crate::config::IndexMinified;
+
+    async fn scan(runs: Arc<AtomicUsize>) -> usize {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        runs.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    #[tokio::test]
+    async fn it_runs_once_for_concurrent_callers() {
+        let flight = Arc::new(SingleFlight::default());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let handles = (0..8)
+            .map(|_| {
+                let flight = flight.clone();
+                let runs = runs.clone();
+                tokio::spawn(async move { flight.run(|_| scan(runs)).await })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), 1);
+        }
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        assert_eq!(flight.run(|_| scan(runs.clone())).await, 2);
+    }
+
+    #[tokio::test]
+    async fn it_keeps_running_once_callers_stop_waiting() {
+        let flight = SingleFlight::default();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let waited = tokio::time::timeout(
+            Duration::from_millis(10),
+            flight.run(|_| scan(runs.clone())),
+        )
+        .await;
+        assert!(waited.is_err());
+
+        assert_eq!(flight.run(|_| scan(runs.clone())).await, 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn it_stops_runs_once_invalidated() {
+        let flight = SingleFlight::default();
+
+        let run = flight.run(|cancelled| async move {
+            // until it's invalidated, for a second at most
+            for _ in 0..100 {
+                if cancelled.load(Ordering::SeqCst) {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            false
+        });
+        let invalidate = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            flight.invalidate();
+        };
+
+        let (stopped, ()) = tokio::join!(run, invalidate);
+        assert!(stopped);
+    }
+
+    #[tokio::test]
+    async fn it_starts_fresh_after_invalidation() {
+        let flight = Arc::new(SingleFlight::default());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let first = {
+            let flight = flight.clone();
+            let runs = runs.clone();
+            tokio::spawn(async move { flight.run(|_| scan(runs)).await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        flight.invalidate();
+
+        let second = flight.run(|_| scan(runs.clone())).await;
+
+        assert_eq!(first.await.unwrap() + second, 3);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn it_orders_css_files_regardless_of_input_order() {
+        let root = Path::new("/app");
+        let expected = [
+            "/app/src/a.css",
+            "/app/src/components/b.css",
+            "/app/styles.css",
+            "/app/dist/index.css",
+            "/app/node_modules/lib/dist/lib.css",
+            "/app/node_modules/lib/lib.css",
+        ]
+        .map(PathBuf::from)
+        .to_vec();
+
+        let mut css_files = expected.clone();
+        for i in 0..css_files.len() {
+            css_files.rotate_left(i);
+            if i % 2 == 0 {
+                css_files.reverse();
+            }
+
+            let prioritized = prioritize_css_files(
+                root,
+                css_files.clone(),
+                IndexMinified::Index,
+                CssFileLimits::default(),
+            );
+            assert_eq!(prioritized.css_files, expected);
+            assert!(prioritized.skipped.is_empty());
+        }
+    }
+
+    #[test]
+    fn it_classifies_path_priority_within_the_workspace() {
+        let root = Path::new("/home/build/app");
+        assert_eq!(
+            PathPriority::of(root, Path::new("/home/build/app/src/a.css")),
+            PathPriority::FirstParty
+        );
+        assert_eq!(
+            PathPriority::of(root, Path::new("/home/build/app/out/a.css")),
+            PathPriority::Dist
+        );
+        assert_eq!(
+            compare_definitions(
+                root,
+                (Path::new("/home/build/app/a.css"), 20),
+                (Path::new("/home/build/app/a.css"), 3)
+            ),
+            std::cmp::Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn it_orders_css_files_by_proximity_to_a_document() {
+        let document = Path::new("/ws/src/components/card/Card.tsx");
+        let mut css_files = vec![
+            Path::new("/ws/a.css"),
+            Path::new("/ws/src/components/card/styles/card.css"),
+            Path::new("/ws/src/b.css"),
+            Path::new("/ws/src/components/card/card.css"),
+            Path::new("/ws/src/a.css"),
+            Path::new("/ws/lib/components/card/card.css"),
+        ];
+        css_files.sort_by(|a, b| compare_proximity(document, a, b));
+
+        assert_eq!(
+            css_files,
+            [
+                Path::new("/ws/src/components/card/card.css"),
+                Path::new("/ws/src/components/card/styles/card.css"),
+                Path::new("/ws/src/a.css"),
+                Path::new("/ws/src/b.css"),
+                Path::new("/ws/a.css"),
+                Path::new("/ws/lib/components/card/card.css"),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_matches_queries_fuzzily() {
+        assert!(fuzzy_matches("btn", "btn-primary"));
+        assert!(fuzzy_matches("bpr", "btn-primary"));
+        assert!(fuzzy_matches("BTN", "btn"));
+        assert!(fuzzy_matches("", "btn"));
+        assert!(!fuzzy_matches("nbt", "btn"));
+        assert!(!fuzzy_matches("btnx", "btn"));
+    }
+
+    #[test]
+    fn it_updates_files_in_place_and_in_order() {
+        let dir = std::env::temp_dir().join(format!("cnls-index-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("dist")).unwrap();
+        let write = |name: &str, code: &str| {
+            let css_file = dir.join(name);
+            std::fs::write(&css_file, code).unwrap();
+            css_file
+        };
+
+        let a = write("a.css", ".btn {}");
+        let dist = write("dist/index.css", ".btn {}");
+        let (mut css_index, _) = CssIndex::parse_files(vec![a.clone(), dist.clone()]);
+
+        let b = write("b.css", ".btn {}");
+        css_index.update(
+            &[dir.clone()],
+            b.clone(),
+            Rank::default(),
+            ClassNamesCollector::parse(b.clone()).unwrap(),
+        );
+        assert_eq!(
+            css_index.css_files().collect::<Vec<_>>(),
+            [a.as_path(), b.as_path(), dist.as_path()]
+        );
+
+        write("a.css", ".card {}");
+        css_index.update(
+            &[dir.clone()],
+            a.clone(),
+            Rank::default(),
+            ClassNamesCollector::parse(a.clone()).unwrap(),
+        );
+        assert_eq!(css_index.css_files().count(), 3);
+        assert_eq!(css_index.count_definitions("btn"), 2);
+        assert_eq!(css_index.count_definitions("card"), 1);
+
+        assert!(css_index.remove(&b));
+        assert!(!css_index.remove(&b));
+        assert_eq!(css_index.count_definitions("btn"), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_ranks_minified_css_files_after_those_of_every_folder() {
+        let dir = std::env::temp_dir().join(format!("cnls-index-minified-{}", std::process::id()));
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        std::fs::create_dir_all(&a).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        let write = |css_file: PathBuf, code: &str| {
+            std::fs::write(&css_file, code).unwrap();
+            css_file
+        };
+        let minified = ".x{color:red}.y{color:blue}";
+
+        let a_app = write(a.join("app.css"), ".a {}\n");
+        let a_bundle = write(a.join("bundle.css"), minified);
+        let b_app = write(b.join("app.css"), ".b {}\n");
+        let limits = CssFileLimits {
+            max_file_size: 1024,
+            max_line_length: 16,
+        };
+        let prioritized = [
+            (&a, vec![a_bundle.clone(), a_app.clone()]),
+            (&b, vec![b_app.clone()]),
+        ]
+        .map(|(root, css_files)| {
+            prioritize_css_files(root, css_files, IndexMinified::LowPriority, limits)
+        })
+        .to_vec();
+
+        // small enough to be found minified only once read
+        let ranked = rank_css_files(prioritized);
+        assert!(ranked.iter().all(|(_, rank)| !rank.low_priority));
+
+        let not_cancelled = std::sync::atomic::AtomicBool::new(false);
+        let (mut css_index, skipped, _) = CssIndex::parse_files_with(
+            ranked,
+            IndexMinified::LowPriority,
+            limits,
+            &|_, _| {},
+            &not_cancelled,
+        );
+        assert!(skipped.is_empty());
+        assert_eq!(
+            css_index.css_files().collect::<Vec<_>>(),
+            [&a_app, &b_app, &a_bundle].map(|css_file| css_file.as_path())
+        );
+        assert_eq!(
+            css_index.rank_of(&a_bundle).map(|rank| rank.low_priority),
+            Some(true)
+        );
+        let b_min = write(b.join("another.min.css"), minified);
+        let b_new = write(b.join("new.css"), ".c {}\n");
+        for css_file in [&b_min, &b_new] {
+            let parsed = parse_css_file(
+                css_file.clone(),
+                Rank::default(),
+                IndexMinified::LowPriority,
+                limits,
+            );
+            let (rank, collector) = parsed.unwrap().unwrap();
+            css_index.update(&[a.clone(), b.clone()], css_file.clone(), rank, collector);
+        }
+        assert_eq!(
+            css_index.css_files().collect::<Vec<_>>(),
+            [&a_app, &b_app, &b_new, &a_bundle, &b_min].map(|css_file| css_file.as_path())
+        );
+
+        let skipped = parse_css_file(b_min, Rank::default(), IndexMinified::Skip, limits);
+        assert!(skipped.unwrap().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_updates_files_among_those_of_their_folder() {
+        let dir = std::env::temp_dir().join(format!("cnls-index-roots-{}", std::process::id()));
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        std::fs::create_dir_all(a.join("node_modules/ui")).unwrap();
+        std::fs::create_dir_all(&b).unwrap();
+        let write = |css_file: PathBuf| {
+            std::fs::write(&css_file, ".btn {}\n").unwrap();
+            css_file
+        };
+
+        // as found per folder, the vendored css of `a` listed after its own
+        let mut css_files = vec![
+            write(a.join("app.css")),
+            write(a.join("node_modules/ui/ui.css")),
+            write(b.join("app.css")),
+        ];
+        let (mut css_index, _) = CssIndex::parse_files(css_files.clone());
+
+        let created = write(b.join("new.css"));
+        let collector = ClassNamesCollector::parse(created.clone()).unwrap();
+        css_index.update(&[a, b], created.clone(), Rank::default(), collector);
+        css_files.push(created);
+        assert_eq!(
+            css_index.css_files().collect::<Vec<_>>(),
+            css_files.iter().map(PathBuf::as_path).collect::<Vec<_>>()
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_follows_imports_once_up_to_the_depth() {
+        let dir = std::env::temp_dir().join(format!("cnls-index-imports-{}", std::process::id()));
+        let files = [
+            ("app/index.css", "@import \"../lib/buttons.css\";\n.app {}"),
+            (
+                "lib/buttons.css",
+                "@import \"./base.css\";\n@import \"../app/index.css\";\n.btn {}",
+            ),
+            ("lib/base.css", "@import url(deep.css);\n.base {}"),
+            ("lib/deep.css", ".deep {}"),
+        ];
+        for (file, content) in files {
+            std::fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        let not_cancelled = std::sync::atomic::AtomicBool::new(false);
+
+        let index = vec![dir.join("app/index.css")];
+        let (mut css_index, _) = CssIndex::parse_files(index.clone());
+        assert!(css_index.follow_imports(2, &not_cancelled).is_empty());
+        assert_eq!(
+            css_index.css_files().collect::<Vec<_>>(),
+            [
+                dir.join("app/index.css"),
+                dir.join("lib/buttons.css"),
+                dir.join("lib/base.css")
+            ]
+        );
+
+        let (mut css_index, _) = CssIndex::parse_files(index);
+        css_index.follow_imports(1, &not_cancelled);
+        assert_eq!(css_index.css_files().count(), 2);
+        assert_eq!(css_index.count_definitions("deep"), 0);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_reports_the_css_files_that_cant_be_read_alone() {
+        let dir = std::env::temp_dir().join(format!("cnls-index-unread-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.css"), ".btn {}").unwrap();
+
+        let (css_index, errors) =
+            CssIndex::parse_files(vec![dir.join("gone.css"), dir.join("a.css")]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().ends_with("gone.css"));
+        assert_eq!(css_index.count_definitions("btn"), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn it_stops_parsing_once_cancelled() {
+        let dir = std::env::temp_dir().join(format!("cnls-index-cancel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let css_files = ["a.css", "b.css", "c.css"]
+            .map(|name| {
+                let css_file = dir.join(name);
+                std::fs::write(&css_file, ".btn {}").unwrap();
+                (css_file, Rank::default())
+            })
+            .to_vec();
+
+        let cancelled = std::sync::atomic::AtomicBool::new(false);
+        let reported = std::sync::Mutex::new(vec![]);
+        let (css_index, _, errors) = CssIndex::parse_files_with(
+            css_files,
+            IndexMinified::Index,
+            CssFileLimits::default(),
+            &|done, total| {
+                reported.lock().unwrap().push((done, total));
+                if done == 2 {
+                    cancelled.store(true, Ordering::SeqCst);
+                }
+            },
+            &cancelled,
+        );
+
+        assert!(errors.is_empty());
+        assert_eq!(css_index.css_files().count(), 2);
+        assert_eq!(reported.into_inner().unwrap(), [(1, 3), (2, 3)]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
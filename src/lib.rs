@@ -1,6 +1,28 @@
 use std::fmt::Debug;
 
+mod actions;
+mod analysis;
+pub mod check;
+mod client_caps;
+mod collect;
+mod complete;
+pub mod config;
+pub mod config_file;
+pub mod css;
+mod dialect;
+mod documents;
+mod fallback;
+mod find;
+mod hover;
+mod index;
+pub mod protocol;
+mod sass;
 pub mod scope;
+pub mod server;
+mod text;
+mod timing;
+pub mod usage;
+mod workspace;
 
 pub type Array<T> = Box<[T]>;
 
@@ -30,40 +52,842 @@ impl From<&str> for Str {
 
 impl PartialEq<str> for Str {
     fn eq(&self, other: &str) -> bool {
-        return &*self.0 == other;
+        &*self.0 == other
     }
 }
 
 pub mod fs {
     use std::{
+        collections::HashSet,
         ffi::OsStr,
-        path::{Path, PathBuf},
+        path::{Component, Path, PathBuf},
     };
 
-    use tracing::error;
+    use crate::config::CssOptions;
+    use crate::dialect::{Dialect, EXTENSIONS};
 
+    use anyhow::Context;
+    use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+    use ignore::gitignore::Gitignore;
+    use tracing::{debug, error};
+
+    /// The directories not descended into looking for stylesheets by default, holding
+    /// dependencies, build output and the like, see `cnls.css.exclude`.
+    pub const EXCLUDED_DIRS: [&str; 7] = [
+        "node_modules",
+        ".git",
+        "dist",
+        "build",
+        "coverage",
+        ".next",
+        ".nuxt",
+    ];
+
+    /// Which stylesheets are looked for, by globs of their paths relative to the folder walked,
+    /// see `cnls.css`. What's excluded is left out even when it's included too.
+    #[derive(Debug, Clone)]
+    pub struct CssFileGlobs {
+        include: Option<GlobSet>,
+        /// The folders the include globs start with, e.g. `src` for `src/**/*.css`, the others
+        /// holding no file they match.
+        include_bases: Vec<PathBuf>,
+        exclude: GlobSet,
+    }
+
+    impl Default for CssFileGlobs {
+        fn default() -> Self {
+            Self::new(&[], &[]).expect("default globs should be valid")
+        }
+    }
+
+    impl CssFileGlobs {
+        /// Compiles the `include` globs, every stylesheet being included when there are none,
+        /// and the `exclude` ones along with [EXCLUDED_DIRS], but for those named with a `!`,
+        /// e.g. `!node_modules`. Globs without a `/` match at any depth, like `*.min.css`.
+        pub fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+            let mut excluded = EXCLUDED_DIRS.map(String::from).to_vec();
+            for entry in exclude {
+                match entry.strip_prefix('!') {
+                    Some(included) => excluded.retain(|glob| glob != included),
+                    None if !excluded.contains(entry) => excluded.push(entry.clone()),
+                    None => {}
+                }
+            }
+
+            let include_bases = include
+                .iter()
+                .map(|glob| {
+                    Path::new(&anchored(glob))
+                        .components()
+                        .take_while(|c| !c.as_os_str().to_string_lossy().contains(GLOB_CHARS))
+                        .collect()
+                })
+                .collect();
+
+            Ok(Self {
+                include: (!include.is_empty())
+                    .then(|| glob_set(include))
+                    .transpose()?,
+                include_bases,
+                exclude: glob_set(&excluded)?,
+            })
+        }
+
+        /// Whether the folder at `dir`, relative to the one walked, is descended into.
+        pub fn includes_dir(&self, dir: &Path) -> bool {
+            let may_hold_included = self.include.is_none()
+                || self
+                    .include_bases
+                    .iter()
+                    .any(|base| base.starts_with(dir) || dir.starts_with(base));
+            may_hold_included && !self.exclude.is_match(dir)
+        }
+
+        /// Whether the stylesheet at `file`, relative to the folder walked, is looked for.
+        pub fn includes_file(&self, file: &Path) -> bool {
+            self.include
+                .as_ref()
+                .is_none_or(|include| include.is_match(file))
+                && !self.exclude.is_match(file)
+        }
+
+        /// Whether the stylesheet at `path` is looked for walking `root`, its folders included.
+        pub fn includes(&self, root: &Path, path: &Path) -> bool {
+            let Ok(relative) = path.strip_prefix(root) else {
+                return false;
+            };
+
+            relative
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| !dir.as_os_str().is_empty())
+                .all(|dir| self.includes_dir(dir))
+                && self.includes_file(relative)
+        }
+    }
+
+    const GLOB_CHARS: [char; 4] = ['*', '?', '[', '{'];
+
+    /// The `glob` relative to the folder walked, those without a `/` matching at any depth, as
+    /// in a `.gitignore`.
+    fn anchored(glob: &str) -> String {
+        match glob.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if glob.contains('/') => glob.to_string(),
+            None => format!("**/{glob}"),
+        }
+    }
+
+    fn glob_set(globs: &[String]) -> anyhow::Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            let glob = GlobBuilder::new(&anchored(glob))
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("invalid glob: {glob:?}"))?;
+            builder.add(glob);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// What walking a folder found, along with the entries that couldn't be read on the way,
+    /// e.g. for lack of permissions, the files found being all there is otherwise.
+    #[derive(Debug, Default)]
+    pub struct WalkSummary {
+        pub files: Vec<PathBuf>,
+        pub errors: Vec<(PathBuf, std::io::Error)>,
+    }
+
+    /// Finds the stylesheets, css or any other [Dialect](crate::dialect::Dialect), that the
+    /// globs of `css` include, without descending into the folders they leave out, nor into
+    /// those the `.gitignore` files along the way ignore unless told otherwise. Each is listed
+    /// once by the path of the file itself, however many links lead to it.
+    pub fn walk_css_files_in_dir(dir: impl AsRef<Path>, css: &CssOptions) -> WalkSummary {
+        let root = dir.as_ref().to_path_buf();
+        let dir_globs = css.globs.clone();
+        // pruned as they're reached, for none of what's in them to be read
+        let walk = ignore::WalkBuilder::new(&root)
+            .git_ignore(css.respect_gitignore)
+            .git_exclude(css.respect_gitignore)
+            .git_global(css.respect_gitignore)
+            // outside of a git repository too
+            .require_git(false)
+            // a link back to a folder being walked is reported as a loop, and not descended into
+            .follow_links(css.follow_symlinks)
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                entry.depth() == 0
+                    || !is_dir
+                    || entry
+                        .path()
+                        .strip_prefix(&root)
+                        .is_ok_and(|relative| dir_globs.includes_dir(relative))
+            })
+            .build();
+
+        let found = collect_files_with_extensions(dir.as_ref(), walk, &EXTENSIONS, |path| {
+            path.strip_prefix(dir.as_ref())
+                .is_ok_and(|relative| css.globs.includes_file(relative))
+        });
+
+        let root = dir.as_ref();
+        let canonical_root = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let mut seen = HashSet::new();
+        let files = found
+            .files
+            .into_iter()
+            .map(|css_file| link_target(root, &canonical_root, &css_file))
+            .filter(|css_file| seen.insert(css_file.clone()))
+            .collect();
+
+        WalkSummary {
+            files,
+            errors: found.errors,
+        }
+    }
+
+    /// Like [walk_css_files_in_dir], adding the files found to `css_files` and logging the
+    /// entries that couldn't be read.
     pub fn find_all_css_files_in_dir(
         dir: impl AsRef<Path>,
+        css: &CssOptions,
         css_files: &mut Vec<PathBuf>,
     ) -> anyhow::Result<()> {
-        for d in ignore::Walk::new(dir) {
+        let summary = walk_css_files_in_dir(dir, css);
+        log_walk_errors(&summary.errors);
+        css_files.extend(summary.files);
+
+        Ok(())
+    }
+
+    /// The file a `path` found walking `root` stands for, resolving the links along the way,
+    /// but kept below `root` as it's spelled when it's there, e.g. for a `root` reached through
+    /// a link itself.
+    fn link_target(root: &Path, canonical_root: &Path, path: &Path) -> PathBuf {
+        let Ok(target) = std::fs::canonicalize(path) else {
+            return path.to_path_buf();
+        };
+
+        match target.strip_prefix(canonical_root) {
+            Ok(relative) => root.join(relative),
+            Err(_) => target,
+        }
+    }
+
+    /// Whether one of the `.gitignore` files from the folder of `path` up to `root` ignores it.
+    pub fn is_gitignored(root: &Path, path: &Path) -> bool {
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(root))
+            .any(|dir| {
+                let (gitignore, _) = Gitignore::new(dir.join(".gitignore"));
+                gitignore
+                    .matched_path_or_any_parents(path, false)
+                    .is_ignore()
+            })
+    }
+
+    /// Resolves the `url` of an `@import` in `css_file` to the stylesheet it names: relative to
+    /// the file, or else, for a bare specifier like `pkg/buttons.css`, in the `node_modules` of
+    /// its directory or of one of its ancestors, a package alone standing for the stylesheet of
+    /// its `style` or `main` field. `None` for remote urls and missing files.
+    pub fn resolve_import(css_file: &Path, url: &str) -> Option<PathBuf> {
+        if url.contains("://") || url.starts_with("//") || url.starts_with("data:") {
+            return None;
+        }
+        let dir = css_file.parent()?;
+
+        // `~pkg` is how webpack's loaders spell bare specifiers
+        if let Some(specifier) = url.strip_prefix('~') {
+            return find_in_node_modules(dir, specifier);
+        }
+
+        let relative = stylesheet_at(&normalize(&dir.join(url)));
+        if relative.is_some() || url.starts_with('.') || url.starts_with('/') {
+            return relative;
+        }
+
+        find_in_node_modules(dir, url)
+    }
+
+    fn find_in_node_modules(dir: &Path, specifier: &str) -> Option<PathBuf> {
+        dir.ancestors().find_map(|ancestor| {
+            let path = normalize(&ancestor.join("node_modules").join(specifier));
+            stylesheet_at(&path).or_else(|| package_stylesheet(&path))
+        })
+    }
+
+    /// The stylesheet at `path`, or when there's none, at `path` with the extension of a
+    /// stylesheet added, as a scss partial (`_buttons.scss`) or not.
+    fn stylesheet_at(path: &Path) -> Option<PathBuf> {
+        if path.is_file() && Dialect::of(path).is_some() {
+            return Some(path.to_path_buf());
+        }
+
+        let name = path.file_name()?.to_str()?;
+        EXTENSIONS
+            .iter()
+            .flat_map(|ext| [format!("{name}.{ext}"), format!("_{name}.{ext}")])
+            .map(|candidate| path.with_file_name(candidate))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// The stylesheet the `package` directory stands for, given by the `style` or `main` field
+    /// of its `package.json`.
+    fn package_stylesheet(package: &Path) -> Option<PathBuf> {
+        let manifest = std::fs::read_to_string(package.join("package.json")).ok()?;
+        let manifest = serde_json::from_str::<serde_json::Value>(&manifest).ok()?;
+
+        ["style", "main"]
+            .into_iter()
+            .filter_map(|field| manifest.get(field)?.as_str())
+            .find_map(|entry| stylesheet_at(&normalize(&package.join(entry))))
+    }
+
+    /// Resolves the `.` and `..` components of a `path` without touching the file system, for a
+    /// file reached through different relative paths to be found once.
+    pub fn normalize(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir if normalized.file_name().is_some() => {
+                    normalized.pop();
+                }
+                component => normalized.push(component),
+            }
+        }
+        normalized
+    }
+
+    /// The folder of the nearest `package.json` above a `file`, e.g. the package of a monorepo
+    /// it's in.
+    pub fn package_root(file: &Path) -> Option<&Path> {
+        file.ancestors()
+            .skip(1)
+            .find(|dir| dir.join("package.json").is_file())
+    }
+
+    /// Checks that a workspace `root` is a directory that can be read, before walking it.
+    pub fn check_workspace_root(root: &Path) -> anyhow::Result<()> {
+        let metadata = std::fs::metadata(root).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => anyhow::anyhow!("{} does not exist", root.display()),
+            _ => anyhow::anyhow!("{} can't be accessed: {err}", root.display()),
+        })?;
+
+        if !metadata.is_dir() {
+            anyhow::bail!("{} is not a directory", root.display());
+        }
+
+        std::fs::read_dir(root)
+            .map_err(|err| anyhow::anyhow!("{} can't be read: {err}", root.display()))?;
+
+        Ok(())
+    }
+
+    /// Finds the css files right in the directory of `path` and in each of its ancestors up to
+    /// the `root`, without walking their subdirectories. The closest ones come first.
+    pub fn find_css_files_near(path: &Path, root: Option<&Path>) -> Vec<PathBuf> {
+        let mut css_files = vec![];
+
+        for dir in path.ancestors().skip(1) {
+            if root.is_some_and(|root| !dir.starts_with(root)) {
+                break;
+            }
+
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+
+            let mut found = entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.is_file() && Dialect::of(path).is_some())
+                .collect::<Vec<_>>();
+            found.sort();
+            css_files.extend(found);
+
+            if root.is_none() {
+                break;
+            }
+        }
+
+        css_files
+    }
+
+    pub const SOURCE_FILE_EXTENSIONS: [&str; 4] = ["js", "jsx", "ts", "tsx"];
+
+    /// Finds the js, jsx, ts and tsx files, where class names are used.
+    pub fn find_all_source_files_in_dir(
+        dir: impl AsRef<Path>,
+        source_files: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<()> {
+        let summary = collect_files_with_extensions(
+            dir.as_ref(),
+            ignore::Walk::new(dir.as_ref()),
+            &SOURCE_FILE_EXTENSIONS,
+            |_| true,
+        );
+        log_walk_errors(&summary.errors);
+        source_files.extend(summary.files);
+
+        Ok(())
+    }
+
+    /// Like [find_all_source_files_in_dir], over every workspace folder in `dirs`, finding the
+    /// files of nested folders once.
+    pub fn find_all_source_files_in_dirs(
+        dirs: &[PathBuf],
+        source_files: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<()> {
+        let mut seen = std::collections::HashSet::new();
+
+        for dir in dirs {
+            let mut found = vec![];
+            find_all_source_files_in_dir(dir, &mut found)?;
+            source_files.extend(found.into_iter().filter(|file| seen.insert(file.clone())));
+        }
+
+        Ok(())
+    }
+
+    fn collect_files_with_extensions(
+        dir: &Path,
+        walk: ignore::Walk,
+        extensions: &[&str],
+        is_included: impl Fn(&Path) -> bool,
+    ) -> WalkSummary {
+        let mut summary = WalkSummary::default();
+
+        for d in walk {
             match d {
                 Ok(entry) => {
                     let path = entry.path();
-                    let is_css_file = path.is_file()
-                        && path
-                            .extension()
-                            .map(|e| e == OsStr::new("css"))
-                            .unwrap_or(false);
-
-                    if is_css_file {
-                        css_files.push(path.to_path_buf());
+                    let has_extension = path
+                        .extension()
+                        .map(|e| extensions.iter().any(|ext| e == OsStr::new(ext)))
+                        .unwrap_or(false);
+
+                    if !has_extension || !is_included(path) {
+                        continue;
                     }
+
+                    // e.g. a broken link, a named pipe, or a file deleted since it was listed
+                    match std::fs::metadata(path) {
+                        Ok(metadata) if metadata.is_file() => {
+                            summary.files.push(path.to_path_buf())
+                        }
+                        Ok(_) => debug!("skipped {}, not a regular file", path.display()),
+                        Err(err) => debug!("skipped {}: {err}", path.display()),
+                    }
+                }
+                Err(err) => {
+                    let path = path_of(&err).unwrap_or(dir).to_path_buf();
+                    let message = err.to_string();
+                    let err = err
+                        .into_io_error()
+                        .unwrap_or_else(|| std::io::Error::other(message));
+                    summary.errors.push((path, err));
                 }
-                Err(err) => error!(" failed to read a directory entry: {err}"),
             }
         }
 
-        Ok(())
+        summary
+    }
+
+    /// The path of the entry a walk failed to read, if it's known.
+    fn path_of(err: &ignore::Error) -> Option<&Path> {
+        match err {
+            ignore::Error::WithPath { path, .. } => Some(path),
+            ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+                path_of(err)
+            }
+            ignore::Error::Loop { child, .. } => Some(child),
+            _ => None,
+        }
+    }
+
+    fn log_walk_errors(errors: &[(PathBuf, std::io::Error)]) {
+        for (path, err) in errors {
+            error!(" failed to read {}: {err}", path.display());
+        }
+    }
+
+    /// Thresholds past which a stylesheet is considered a build artifact (a minified or bundled
+    /// file) rather than source CSS.
+    #[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct CssFileLimits {
+        pub max_file_size: u64,
+        pub max_line_length: usize,
+    }
+
+    impl Default for CssFileLimits {
+        fn default() -> Self {
+            Self {
+                max_file_size: 1024 * 1024,
+                max_line_length: 10_000,
+            }
+        }
+    }
+
+    impl CssFileLimits {
+        /// Whether the file at `path` is larger than the limit, going by its metadata alone.
+        pub fn is_exceeded_by(&self, path: &Path) -> std::io::Result<bool> {
+            Ok(std::fs::metadata(path)?.len() > self.max_file_size)
+        }
+
+        /// Whether a line of the `code` of a css file, as read to parse it, is longer than the
+        /// limit.
+        pub fn is_exceeded_by_lines_of(&self, code: &str) -> bool {
+            code.lines().any(|line| line.len() > self.max_line_length)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::path::Path;
+
+        use super::{
+            check_workspace_root, find_all_css_files_in_dir, find_all_source_files_in_dirs,
+            find_css_files_near, is_gitignored, normalize, package_root, resolve_import,
+            walk_css_files_in_dir, CssFileGlobs, CssFileLimits,
+        };
+        use crate::config::CssOptions;
+
+        #[test]
+        fn it_rejects_bad_workspace_roots() {
+            let dir = std::env::temp_dir().join(format!("cnls-roots-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let file = dir.join("a.css");
+            std::fs::write(&file, "").unwrap();
+
+            assert!(check_workspace_root(&dir).is_ok());
+
+            let err = check_workspace_root(&file).unwrap_err();
+            assert!(err.to_string().ends_with("a.css is not a directory"));
+
+            let err = check_workspace_root(&dir.join("deleted")).unwrap_err();
+            assert!(err.to_string().ends_with("deleted does not exist"));
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn it_finds_css_files_near_a_document() {
+            let root = std::env::temp_dir().join(format!("cnls-near-{}", std::process::id()));
+            let dir = root.join("src/components");
+            std::fs::create_dir_all(root.join("src/other")).unwrap();
+            std::fs::create_dir_all(&dir).unwrap();
+            for css_file in ["global.css", "src/components/card.css", "src/other/x.css"] {
+                std::fs::write(root.join(css_file), "").unwrap();
+            }
+
+            let near = find_css_files_near(&dir.join("Card.tsx"), Some(&root));
+            assert_eq!(near, vec![dir.join("card.css"), root.join("global.css")]);
+
+            let near = find_css_files_near(&dir.join("Card.tsx"), None);
+            assert_eq!(near, vec![dir.join("card.css")]);
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[test]
+        fn it_finds_the_package_of_a_file() {
+            let root = std::env::temp_dir().join(format!("cnls-packages-{}", std::process::id()));
+            std::fs::create_dir_all(root.join("packages/app/src")).unwrap();
+            std::fs::write(root.join("package.json"), "{}").unwrap();
+            std::fs::write(root.join("packages/app/package.json"), "{}").unwrap();
+
+            let app = root.join("packages/app");
+            assert_eq!(package_root(&app.join("src/App.tsx")), Some(app.as_path()));
+            assert_eq!(package_root(&app.join("package.json")), Some(app.as_path()));
+            assert_eq!(
+                package_root(&root.join("packages/other/App.tsx")),
+                Some(root.as_path())
+            );
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[test]
+        fn it_resolves_imports() {
+            let root = std::env::temp_dir().join(format!("cnls-imports-{}", std::process::id()));
+            let files = [
+                ("src/index.css", ""),
+                ("src/_mixins.scss", ""),
+                ("shared/buttons.css", ""),
+                (
+                    "node_modules/ui/package.json",
+                    r#"{ "main": "index.js", "style": "dist/ui.css" }"#,
+                ),
+                ("node_modules/ui/dist/ui.css", ""),
+                ("node_modules/ui/forms.css", ""),
+            ];
+            for (file, content) in files {
+                let path = root.join(file);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, content).unwrap();
+            }
+            let index = root.join("src/index.css");
+
+            assert_eq!(
+                resolve_import(&index, "../shared/buttons.css"),
+                Some(root.join("shared/buttons.css"))
+            );
+            assert_eq!(
+                resolve_import(&index, "mixins"),
+                Some(root.join("src/_mixins.scss"))
+            );
+            assert_eq!(
+                resolve_import(&index, "ui"),
+                Some(root.join("node_modules/ui/dist/ui.css"))
+            );
+            assert_eq!(
+                resolve_import(&index, "~ui/forms.css"),
+                Some(root.join("node_modules/ui/forms.css"))
+            );
+            assert_eq!(resolve_import(&index, "./missing.css"), None);
+            assert_eq!(resolve_import(&index, "https://x.io/a.css"), None);
+
+            assert_eq!(
+                normalize(std::path::Path::new("/a/./b/../c.css")),
+                std::path::PathBuf::from("/a/c.css")
+            );
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[test]
+        fn it_finds_the_files_of_nested_folders_once() {
+            let root = std::env::temp_dir().join(format!("cnls-nested-{}", std::process::id()));
+            let nested = root.join("packages/ui");
+            std::fs::create_dir_all(&nested).unwrap();
+            for file in ["App.tsx", "packages/ui/Button.tsx"] {
+                std::fs::write(root.join(file), "").unwrap();
+            }
+
+            let mut source_files = vec![];
+            find_all_source_files_in_dirs(&[nested.clone(), root.clone()], &mut source_files)
+                .unwrap();
+            source_files.sort();
+            assert_eq!(
+                source_files,
+                vec![root.join("App.tsx"), nested.join("Button.tsx")]
+            );
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[test]
+        fn it_skips_the_excluded_dirs() {
+            let root = std::env::temp_dir().join(format!("cnls-excluded-{}", std::process::id()));
+            let build = root.join("build");
+            std::fs::create_dir_all(root.join("node_modules/ui")).unwrap();
+            std::fs::create_dir_all(root.join("src/dist")).unwrap();
+            std::fs::create_dir_all(&build).unwrap();
+            for css_file in [
+                "app.css",
+                "node_modules/ui/ui.css",
+                "src/dist/bundle.css",
+                "build/out.css",
+            ] {
+                std::fs::write(root.join(css_file), "").unwrap();
+            }
+            let css = CssOptions::default();
+
+            let mut css_files = vec![];
+            find_all_css_files_in_dir(&root, &css, &mut css_files).unwrap();
+            assert_eq!(css_files, vec![root.join("app.css")]);
+
+            // the folder walked itself isn't skipped, whatever its name
+            let mut css_files = vec![];
+            find_all_css_files_in_dir(&build, &css, &mut css_files).unwrap();
+            assert_eq!(css_files, vec![build.join("out.css")]);
+
+            assert!(!css.globs.includes(&root, &root.join("src/dist/bundle.css")));
+            assert!(css.globs.includes(&build, &build.join("out.css")));
+
+            let css = CssOptions {
+                globs: CssFileGlobs::new(&[], &["!node_modules".to_string()]).unwrap(),
+                ..CssOptions::default()
+            };
+            let mut css_files = vec![];
+            find_all_css_files_in_dir(&root, &css, &mut css_files).unwrap();
+            css_files.sort();
+            assert_eq!(
+                css_files,
+                vec![root.join("app.css"), root.join("node_modules/ui/ui.css")]
+            );
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[test]
+        fn it_skips_what_gitignore_files_ignore() {
+            let root = std::env::temp_dir().join(format!("cnls-gitignore-{}", std::process::id()));
+            std::fs::create_dir_all(root.join("storybook-static")).unwrap();
+            std::fs::create_dir_all(root.join("src")).unwrap();
+            for (file, content) in [
+                (".gitignore", "storybook-static/\n*.generated.css\n"),
+                ("src/.gitignore", "local.css\n"),
+                ("storybook-static/main.css", ""),
+                ("src/theme.generated.css", ""),
+                ("src/local.css", ""),
+                ("src/app.css", ""),
+            ] {
+                std::fs::write(root.join(file), content).unwrap();
+            }
+            let css = CssOptions::default();
+
+            let mut css_files = vec![];
+            find_all_css_files_in_dir(&root, &css, &mut css_files).unwrap();
+            assert_eq!(css_files, vec![root.join("src/app.css")]);
+            assert!(is_gitignored(&root, &root.join("src/local.css")));
+            assert!(is_gitignored(
+                &root,
+                &root.join("storybook-static/main.css")
+            ));
+            assert!(!is_gitignored(&root, &root.join("src/app.css")));
+
+            let css = CssOptions {
+                respect_gitignore: false,
+                ..CssOptions::default()
+            };
+            let mut css_files = vec![];
+            find_all_css_files_in_dir(&root, &css, &mut css_files).unwrap();
+            assert_eq!(css_files.len(), 4);
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn it_lists_the_css_files_links_lead_to_once() {
+            let root = std::env::temp_dir().join(format!("cnls-links-{}", std::process::id()));
+            let styles = root.join("styles");
+            std::fs::create_dir_all(&styles).unwrap();
+            std::fs::write(styles.join("app.css"), "").unwrap();
+            // back to the folder holding it, endlessly
+            std::os::unix::fs::symlink(&root, styles.join("loop")).unwrap();
+            std::os::unix::fs::symlink(styles.join("app.css"), root.join("link.css")).unwrap();
+            std::os::unix::fs::symlink(&styles, root.join("theme")).unwrap();
+
+            for follow_symlinks in [true, false] {
+                let css = CssOptions {
+                    follow_symlinks,
+                    ..CssOptions::default()
+                };
+                let mut css_files = vec![];
+                find_all_css_files_in_dir(&root, &css, &mut css_files).unwrap();
+                assert_eq!(css_files, vec![styles.join("app.css")]);
+            }
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn it_skips_what_isnt_a_regular_file() {
+            let root = std::env::temp_dir().join(format!("cnls-irregular-{}", std::process::id()));
+            std::fs::create_dir_all(root.join("theme.css")).unwrap();
+            std::fs::write(root.join("app.css"), "").unwrap();
+            std::os::unix::fs::symlink(root.join("deleted.css"), root.join("broken.css")).unwrap();
+
+            let mut css_files = vec![];
+            find_all_css_files_in_dir(&root, &CssOptions::default(), &mut css_files).unwrap();
+            assert_eq!(css_files, vec![root.join("app.css")]);
+
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn it_collects_the_entries_that_cant_be_read() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let root = std::env::temp_dir().join(format!("cnls-unreadable-{}", std::process::id()));
+            let locked = root.join("locked");
+            std::fs::create_dir_all(&locked).unwrap();
+            std::fs::write(locked.join("a.css"), "").unwrap();
+            std::fs::write(root.join("app.css"), "").unwrap();
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+            // unless permissions aren't enforced, e.g. for root
+            if std::fs::read_dir(&locked).is_err() {
+                let summary = walk_css_files_in_dir(&root, &CssOptions::default());
+                assert_eq!(summary.files, vec![root.join("app.css")]);
+                assert_eq!(summary.errors.len(), 1);
+                let (path, err) = &summary.errors[0];
+                assert_eq!(path, &locked);
+                assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+            }
+
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+            std::fs::remove_dir_all(root).unwrap();
+        }
+
+        #[test]
+        fn it_matches_css_files_by_globs() {
+            let globs = |include: &[&str], exclude: &[&str]| {
+                let strings =
+                    |globs: &[&str]| globs.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+                CssFileGlobs::new(&strings(include), &strings(exclude)).unwrap()
+            };
+            let root = Path::new("/app");
+
+            let all = globs(&[], &["*.min.css"]);
+            assert!(all.includes(root, Path::new("/app/src/card.css")));
+            assert!(!all.includes(root, Path::new("/app/src/vendor.min.css")));
+            assert!(!all.includes(root, Path::new("/elsewhere/card.css")));
+
+            let some = globs(
+                &["src/**/*.css", "packages/ui/styles/**"],
+                &["**/*.min.css"],
+            );
+            assert!(some.includes(root, Path::new("/app/src/card.css")));
+            assert!(some.includes(root, Path::new("/app/src/deep/card.css")));
+            assert!(some.includes(root, Path::new("/app/packages/ui/styles/a.scss")));
+            assert!(!some.includes(root, Path::new("/app/packages/ui/a.css")));
+            assert!(!some.includes(root, Path::new("/app/global.css")));
+            // excluded wins over included
+            assert!(!some.includes(root, Path::new("/app/src/bootstrap.min.css")));
+            assert!(!some.includes(root, Path::new("/app/src/dist/card.css")));
+
+            assert!(some.includes_dir(Path::new("packages")));
+            assert!(some.includes_dir(Path::new("packages/ui/styles/themes")));
+            assert!(!some.includes_dir(Path::new("packages/api")));
+            assert!(!some.includes_dir(Path::new("src/node_modules")));
+
+            let invalid = CssFileGlobs::new(&["src/[".to_string()], &[]);
+            assert!(invalid.is_err());
+        }
+
+        #[test]
+        fn it_flags_minified_and_large_files() {
+            let dir = std::env::temp_dir().join(format!("cnls-limits-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            let limits = CssFileLimits {
+                max_file_size: 64,
+                max_line_length: 16,
+            };
+
+            let source = dir.join("source.css");
+            std::fs::write(&source, ".a {\n  color: red;\n}\n").unwrap();
+            assert!(!limits.is_exceeded_by(&source).unwrap());
+
+            let minified = dir.join("index-8f3a2b.css");
+            std::fs::write(&minified, ".a{color:red}.b{color:blue}").unwrap();
+            // found minified only once read
+            assert!(!limits.is_exceeded_by(&minified).unwrap());
+            assert!(!limits.is_exceeded_by_lines_of(".a {\n  color: red;\n}\n"));
+            assert!(limits.is_exceeded_by_lines_of(".a{color:red}.b{color:blue}"));
+
+            let large = dir.join("large.css");
+            std::fs::write(&large, ".a {\n  color: red;\n}\n".repeat(8)).unwrap();
+            assert!(limits.is_exceeded_by(&large).unwrap());
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
     }
 }
@@ -1,9 +1,8 @@
-use clap::Parser;
-use server::start;
+use std::path::{Path, PathBuf};
 
-mod collect;
-mod find;
-mod server;
+use clap::{Parser, Subcommand};
+use cnls::server::start;
+use cnls::{check, config, config_file, usage};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -12,6 +11,50 @@ struct Cli {
     /// Set log level, one of trace, debug, info, warn, error
     #[arg(short, long, default_value = "info", global = true)]
     level: tracing::Level,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print how often each class name is used across the source files of a workspace
+    UsageReport {
+        /// Root of the workspace to scan
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Print the report as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the configuration cnls starts with in the current directory, along with where each
+    /// setting comes from
+    CheckConfig,
+    /// Print what the css index of a workspace holds, with the problems found parsing its css
+    /// files. Exits with an error when there are any
+    Check {
+        /// Root of the workspace to check
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+/// The configuration the server would start with in the workspace at `dir`, from its
+/// `cnls.toml` file then the environment.
+fn load_config(dir: &Path) -> config::Config {
+    let mut config = config::Config::default();
+
+    if let Some(path) = config_file::find_in(dir) {
+        for err in config.update_from_file(&path) {
+            tracing::error!("{err:#}");
+        }
+    }
+    for err in config.update_from_env() {
+        tracing::error!("{err:#}");
+    }
+
+    config
 }
 
 #[tokio::main]
@@ -24,5 +67,34 @@ async fn main() {
         .with_writer(std::io::stderr)
         .init();
 
-    start().await
+    match cli.command {
+        Some(Command::UsageReport { dir, json }) => {
+            let config = load_config(&dir);
+
+            if let Err(err) = usage::run_cli(&dir, &config, json) {
+                tracing::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::CheckConfig) => {
+            let config = load_config(Path::new("."));
+
+            match serde_json::to_string_pretty(&config.effective()) {
+                Ok(json) => println!("{json}"),
+                Err(err) => {
+                    tracing::error!("{err:#}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Command::Check { dir }) => {
+            let config = load_config(&dir);
+
+            if let Err(err) = check::run_cli(&dir, &config) {
+                tracing::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        None => start().await,
+    }
 }
@@ -0,0 +1,135 @@
+//! Types of the custom requests cnls serves, besides the standard LSP ones.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Location, Range, TextDocumentIdentifier};
+
+pub const DOCUMENT_STATUS_METHOD: &str = "cnls/documentStatus";
+
+pub const RESOLVE_RANGE_METHOD: &str = "cnls/resolveRange";
+
+pub const CONFIGURATION_METHOD: &str = "cnls/configuration";
+
+pub const VERSION_METHOD: &str = "cnls/version";
+
+pub const USAGE_REPORT_COMMAND: &str = "cnls.usageReport";
+
+pub const SHOW_INDEX_STATS_COMMAND: &str = "cnls.showIndexStats";
+
+pub const REINDEX_COMMAND: &str = "cnls.reindex";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStatusParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Counts of the class names found in the configured scopes of a document.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStatus {
+    /// Every class name found, repeats included.
+    pub total: usize,
+    /// Class names defined in at least one css file.
+    pub resolved: usize,
+    /// Class names defined in no css file.
+    pub unresolved: usize,
+    /// Class names defined more than once across the css files.
+    pub duplicated: usize,
+    /// Distinct unresolved class names, in order of first appearance.
+    pub unresolved_names: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveRangeParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+/// A class name found in the configured scopes of a document, within the requested range.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedClassToken {
+    pub text: String,
+    pub range: Range,
+    /// Rules defining the class name, empty when it's unresolved.
+    pub definitions: Vec<Location>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UsageStatus {
+    Used,
+    UsedButUndefined,
+    DefinedButUnused,
+}
+
+/// How often a class is used across the source files of a workspace.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassUsage {
+    pub class: String,
+    pub count: usize,
+    /// Css files defining the class.
+    pub defined_in: Vec<String>,
+    pub status: UsageStatus,
+}
+
+/// Response of the `cnls.usageReport` command, sorted from most to least used.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub classes: Vec<ClassUsage>,
+}
+
+/// How many requests of one method were served since startup, and the percentiles of the
+/// durations of the latest ones.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestTimings {
+    pub count: usize,
+    pub p50_ms: u128,
+    pub p95_ms: u128,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    pub css_files: usize,
+    pub class_names: usize,
+    /// Problems found parsing the css files, some of their classes may be missing.
+    pub css_parse_problems: usize,
+    /// By request method.
+    pub requests: BTreeMap<String, RequestTimings>,
+}
+
+/// The settings cnls ended up with, as a `cnls` settings section, along with where each top
+/// level setting comes from.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectiveConfiguration {
+    pub config: serde_json::Value,
+    pub provenance: BTreeMap<String, crate::config::ConfigSource>,
+}
+
+/// What the running server is, as answered to `cnls/version` requests.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub name: String,
+    pub version: String,
+    /// The commit the server was built from, unless it wasn't built from a git checkout.
+    pub git_sha: Option<String>,
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_sha: option_env!("CNLS_GIT_SHA").map(str::to_string),
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! The classes of stylesheets in the indented syntax of Sass, which swc doesn't parse. Rules are
+//! told apart by their indentation rather than braces, so they're found line by line: a rule
+//! goes from its selector through the last line indented more than it.
+
+use std::ops::Range;
+
+use swc_common::{BytePos, Span};
+
+use crate::collect::{bare_class_name, ClassName, ParentClass};
+use crate::css::ClassDefinition;
+
+/// A line along with the lines indented more than it, which make its block.
+struct Block {
+    indent: usize,
+    /// Byte offset of the line, past its indentation.
+    start: usize,
+    /// Byte offset where the last line of the block ends.
+    end: usize,
+    kind: BlockKind,
+}
+
+enum BlockKind {
+    /// A rule, with the classes its selectors end with and the indices of the classes found in
+    /// them. Its prelude is on the stack of ancestors while in its block.
+    Rule {
+        parents: Vec<ParentClass>,
+        classes: Range<usize>,
+    },
+    /// An at-rule, whose prelude is on the stacks of at-rules and ancestors while in its block.
+    AtRule,
+    /// Anything else, e.g. a declaration, a variable or a mixin.
+    Other,
+}
+
+/// Finds the classes defined in the Sass `code`, with `&`-suffixes (e.g. `&-title` in `.card`)
+/// appended to the classes of the enclosing rule like in scss. Rules whose selector has an
+/// interpolation (`#{...}`) are skipped.
+pub fn class_names(code: &str) -> Vec<ClassName> {
+    let mut lines = vec![];
+    let mut offset = 0;
+    for line in code.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        lines.push(offset..offset + text.len());
+        offset += line.len();
+    }
+
+    let hash = crate::css::hash_source(code);
+    let mut class_names = vec![];
+    let mut at_rules: Vec<crate::Str> = vec![];
+    let mut ancestors: Vec<crate::Str> = vec![];
+    let mut blocks: Vec<Block> = vec![];
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = &code[lines[i].clone()];
+        let content = line.trim_start();
+        let indent = line.len() - content.len();
+        let start = lines[i].start + indent;
+        i += 1;
+
+        if content.trim_end().is_empty() {
+            continue;
+        }
+
+        if content.starts_with("//") || content.starts_with("/*") {
+            // comments go on over the lines indented more
+            while i < lines.len() && is_nested(&code[lines[i].clone()], indent) {
+                i += 1;
+            }
+            continue;
+        }
+
+        while blocks.last().is_some_and(|block| block.indent >= indent) {
+            let block = blocks.pop().expect("there is a last block");
+            close(
+                block,
+                &mut class_names,
+                &mut at_rules,
+                &mut ancestors,
+                hash,
+                code.len(),
+            );
+        }
+
+        let kind = if content.starts_with('@') {
+            let prelude = content.split_whitespace().collect::<Vec<_>>().join(" ");
+            at_rules.push(prelude.as_str().into());
+            ancestors.push(prelude.as_str().into());
+            BlockKind::AtRule
+        } else if content.starts_with(['$', '=', '+']) || is_declaration(content) {
+            BlockKind::Other
+        } else {
+            // selectors ending with a comma go on on the next line
+            let mut selector_lines = vec![start..lines[i - 1].end];
+            while code[selector_lines.last().expect("has a line").clone()]
+                .trim_end()
+                .ends_with(',')
+                && i < lines.len()
+            {
+                let next = &code[lines[i].clone()];
+                let next_start = lines[i].start + next.len() - next.trim_start().len();
+                selector_lines.push(next_start..lines[i].end);
+                i += 1;
+            }
+
+            let enclosing = blocks
+                .iter()
+                .rev()
+                .find_map(|block| match &block.kind {
+                    BlockKind::Rule { parents, .. } => Some(parents.as_slice()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+
+            let first_class = class_names.len();
+            let mut parents = vec![];
+            let has_interpolation = selector_lines
+                .iter()
+                .any(|line| code[line.clone()].contains("#{"));
+
+            if !has_interpolation {
+                for line in &selector_lines {
+                    for complex in split_top_level(code, line.clone(), |c| c == b',') {
+                        let compounds = split_top_level(code, complex, |c| {
+                            c.is_ascii_whitespace() || matches!(c, b'>' | b'+' | b'~')
+                        });
+                        let is_single_compound = compounds.len() == 1;
+
+                        for (j, compound) in compounds.iter().enumerate() {
+                            let compound = Compound::parse(code, compound.clone());
+                            compound.collect(code, enclosing, is_single_compound, &mut class_names);
+
+                            if j == compounds.len() - 1 {
+                                parents.extend(compound.parents(
+                                    code,
+                                    enclosing,
+                                    is_single_compound,
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+
+            for class in &mut class_names[first_class..] {
+                class.at_rules = at_rules.clone();
+                class.ancestors = ancestors.clone();
+            }
+
+            let prelude = selector_lines
+                .iter()
+                .flat_map(|line| code[line.clone()].split_whitespace())
+                .collect::<Vec<_>>()
+                .join(" ");
+            ancestors.push(prelude.as_str().into());
+
+            BlockKind::Rule {
+                parents,
+                classes: first_class..class_names.len(),
+            }
+        };
+
+        let end = lines[i - 1].end;
+        for block in &mut blocks {
+            block.end = end;
+        }
+        blocks.push(Block {
+            indent,
+            start,
+            end,
+            kind,
+        });
+    }
+
+    while let Some(block) = blocks.pop() {
+        close(
+            block,
+            &mut class_names,
+            &mut at_rules,
+            &mut ancestors,
+            hash,
+            code.len(),
+        );
+    }
+
+    class_names
+}
+
+/// Ends a `block`, giving the classes of a rule the span of the whole of it.
+fn close(
+    block: Block,
+    class_names: &mut [ClassName],
+    at_rules: &mut Vec<crate::Str>,
+    ancestors: &mut Vec<crate::Str>,
+    source_hash: u64,
+    source_len: usize,
+) {
+    match block.kind {
+        BlockKind::Rule { classes, .. } => {
+            let span = span_of(block.start..block.end);
+            for class in &mut class_names[classes] {
+                class.span = span;
+                class.definition = ClassDefinition {
+                    rule: block.start..block.end,
+                    source_len,
+                    source_hash,
+                };
+            }
+            ancestors.pop();
+        }
+        BlockKind::AtRule => {
+            at_rules.pop();
+            ancestors.pop();
+        }
+        BlockKind::Other => {}
+    }
+}
+
+/// Whether a `line` is blank or indented more than `indent`.
+fn is_nested(line: &str, indent: usize) -> bool {
+    let content = line.trim_start();
+    content.trim_end().is_empty() || line.len() - content.len() > indent
+}
+
+/// Whether a line is a declaration, e.g. `color: red`, or `font:` for nested properties, as
+/// opposed to a selector like `a:hover`.
+fn is_declaration(content: &str) -> bool {
+    let name_end = content
+        .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(content.len());
+
+    name_end > 0
+        && content[name_end..]
+            .strip_prefix(':')
+            .is_some_and(|value| value.is_empty() || value.starts_with(char::is_whitespace))
+}
+
+/// Splits the `range` of `code` at the bytes for which `is_separator` holds, outside of
+/// parentheses and brackets, leaving out empty parts.
+fn split_top_level(
+    code: &str,
+    range: Range<usize>,
+    is_separator: impl Fn(u8) -> bool,
+) -> Vec<Range<usize>> {
+    let bytes = code.as_bytes();
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut part_start = range.start;
+    let mut i = range.start;
+
+    while i < range.end {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            c if depth == 0 && is_separator(c) => {
+                if part_start < i {
+                    parts.push(part_start..i);
+                }
+                part_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if part_start < range.end {
+        parts.push(part_start..range.end);
+    }
+    parts
+}
+
+/// A compound selector, e.g. `&-title.is-open:hover`.
+struct Compound {
+    /// The `&` and the text appended to the enclosing selector, e.g. `&-title`.
+    nesting_suffix: Option<Range<usize>>,
+    /// Whether it starts with a type selector or `&`.
+    has_prefix: bool,
+    /// The classes, ids, attribute selectors and pseudo-classes, e.g. `.is-open` and `:hover`.
+    subclasses: Vec<Range<usize>>,
+}
+
+impl Compound {
+    fn parse(code: &str, range: Range<usize>) -> Self {
+        let bytes = code.as_bytes();
+        let mut compound = Compound {
+            nesting_suffix: None,
+            has_prefix: false,
+            subclasses: vec![],
+        };
+
+        let mut i = range.start;
+        if bytes[i] == b'&' {
+            compound.has_prefix = true;
+            let end = ident_end(bytes, i + 1, range.end);
+            if end > i + 1 {
+                compound.nesting_suffix = Some(i..end);
+            }
+            i = end;
+        } else if bytes[i] == b'*' {
+            compound.has_prefix = true;
+            i += 1;
+        } else if !matches!(bytes[i], b'.' | b'#' | b'[' | b':' | b'%') {
+            compound.has_prefix = true;
+            i = ident_end(bytes, i, range.end).max(i + 1);
+        }
+
+        while i < range.end {
+            let end = match bytes[i] {
+                b'.' | b'#' | b'%' => ident_end(bytes, i + 1, range.end),
+                b'[' => closing(bytes, i, range.end, b'[', b']'),
+                b':' => {
+                    let name_start = if bytes.get(i + 1) == Some(&b':') {
+                        i + 2
+                    } else {
+                        i + 1
+                    };
+                    let name_end = ident_end(bytes, name_start, range.end);
+                    match bytes.get(name_end) {
+                        Some(b'(') => closing(bytes, name_end, range.end, b'(', b')'),
+                        _ => name_end,
+                    }
+                }
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            compound.subclasses.push(i..end.max(i + 1));
+            i = end.max(i + 1);
+        }
+
+        compound
+    }
+
+    /// Adds the classes of the compound selector, `&`-suffixes appended to the `enclosing`
+    /// rule's classes.
+    fn collect(
+        &self,
+        code: &str,
+        enclosing: &[ParentClass],
+        is_single_compound: bool,
+        class_names: &mut Vec<ClassName>,
+    ) {
+        let mut push = |value: &str, selector: Range<usize>, siblings, is_standalone| {
+            class_names.push(ClassName {
+                value: value.into(),
+                // set once the end of the rule is known
+                span: Span::default(),
+                siblings,
+                definition: ClassDefinition::new(0..0, ""),
+                selector_span: span_of(selector),
+                is_standalone,
+                // set by the caller, along with the ancestors
+                at_rules: vec![],
+                ancestors: vec![],
+            })
+        };
+
+        if let Some(nesting) = &self.nesting_suffix {
+            let suffix = &code[nesting.start + 1..nesting.end];
+            for parent in enclosing {
+                push(
+                    &format!("{}{suffix}", &*parent.value),
+                    nesting.clone(),
+                    self.siblings(code, None),
+                    is_single_compound && parent.is_alone && self.subclasses.is_empty(),
+                );
+            }
+        }
+
+        for (i, subclass) in self.subclasses.iter().enumerate() {
+            if code.as_bytes()[subclass.start] != b'.' {
+                continue;
+            }
+
+            push(
+                bare_class_name(&unescape(&code[subclass.start + 1..subclass.end])),
+                subclass.clone(),
+                self.siblings(code, Some(i)),
+                is_single_compound && !self.has_prefix && self.subclasses.len() == 1,
+            );
+        }
+    }
+
+    /// The texts of the subclass selectors, but the one at index `except`.
+    fn siblings(&self, code: &str, except: Option<usize>) -> Vec<crate::Str> {
+        self.subclasses
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != except)
+            .map(|(_, subclass)| code[subclass.clone()].into())
+            .collect()
+    }
+
+    /// The classes a selector ending with this compound ends with, for the rules nested in it
+    /// to append to.
+    fn parents(
+        &self,
+        code: &str,
+        enclosing: &[ParentClass],
+        is_single_compound: bool,
+    ) -> Vec<ParentClass> {
+        match self.subclasses.last() {
+            Some(last) if code.as_bytes()[last.start] == b'.' => vec![ParentClass {
+                value: bare_class_name(&unescape(&code[last.start + 1..last.end])).into(),
+                is_alone: is_single_compound && !self.has_prefix && self.subclasses.len() == 1,
+            }],
+            Some(_) => vec![],
+            None => match &self.nesting_suffix {
+                Some(nesting) => enclosing
+                    .iter()
+                    .map(|parent| ParentClass {
+                        value: format!(
+                            "{}{}",
+                            &*parent.value,
+                            &code[nesting.start + 1..nesting.end]
+                        )
+                        .as_str()
+                        .into(),
+                        is_alone: is_single_compound && parent.is_alone,
+                    })
+                    .collect(),
+                None => vec![],
+            },
+        }
+    }
+}
+
+/// The end of the identifier starting at `start`, escaped characters included.
+fn ident_end(bytes: &[u8], start: usize, end: usize) -> usize {
+    let mut i = start;
+    while i < end {
+        match bytes[i] {
+            b'\\' => i += 2,
+            c if c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c >= 0x80 => i += 1,
+            _ => break,
+        }
+    }
+    i.min(end)
+}
+
+/// The end of the `open`ing bracket at `start`, past its `close`ing one.
+fn closing(bytes: &[u8], start: usize, end: usize, open: u8, close: u8) -> usize {
+    let mut depth = 0usize;
+    for (i, &c) in bytes.iter().enumerate().take(end).skip(start) {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+    }
+    end
+}
+
+/// Removes the backslashes escaping characters of an identifier, e.g. in `hover\:bg-red`.
+fn unescape(ident: &str) -> String {
+    let mut unescaped = String::with_capacity(ident.len());
+    let mut chars = ident.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// The span of a byte `range` of the file, as swc would have it.
+fn span_of(range: Range<usize>) -> Span {
+    Span::new(
+        BytePos(range.start as u32 + 1),
+        BytePos(range.end as u32 + 1),
+        Default::default(),
+    )
+}
@@ -50,6 +50,33 @@ impl Scope {
     }
 }
 
+impl std::fmt::Display for Scope {
+    /// Writes the scope as it's parsed, e.g. `att:className,*Class`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let variant = match self.variant {
+            ScopeVariant::AttrNames => "att",
+            ScopeVariant::RecordEntries => "prop",
+            ScopeVariant::FnCall => "fn",
+        };
+        write!(f, "{variant}:")?;
+
+        for (i, ScopeValue(match_type, value)) in self.values.iter().enumerate() {
+            let value = &**value;
+            match match_type {
+                MatchType::Is => write!(f, "{value}"),
+                MatchType::Contains => write!(f, "*{value}*"),
+                MatchType::StartsWith => write!(f, "{value}*"),
+                MatchType::EndWith => write!(f, "*{value}"),
+            }?;
+            if i + 1 < self.values.len() {
+                write!(f, ",")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl FromStr for Scope {
     type Err = anyhow::Error;
 
@@ -60,7 +87,7 @@ impl FromStr for Scope {
         let [variant, values] = group.as_slice() else {
             return Err(anyhow!("incorrect number of parts: {:?}", group))
                 .with_context(|| {
-                    return format!("\n{}\n\tvariants are one of {}\n\ta value can be a string starting or ending with an '*'", "should be two parts, a variant and values: <variant>:<...values>".yellow(), "att | prop | fn".green())
+                    format!("\n{}\n\tvariants are one of {}\n\ta value can be a string starting or ending with an '*'", "should be two parts, a variant and values: <variant>:<...values>".yellow(), "att | prop | fn".green())
                 });
         };
 
@@ -274,4 +301,11 @@ mod tests {
         assert!(scope.matches("bodyClassName", ScopeVariant::AttrNames));
         assert!(scope.matches("buttonClassName", ScopeVariant::AttrNames));
     }
+
+    #[test]
+    fn it_displays_as_parsed() {
+        for s in ["att:className,class", "fn:*cn*,clsx", "prop:*Class,class*"] {
+            assert_eq!(Scope::from_str(s).unwrap().to_string(), s);
+        }
+    }
 }
@@ -1,354 +1,3606 @@
-use std::mem;
-use std::ops::Deref;
-use std::str::FromStr;
-
-use crate::collect::ClassNamesCollector;
-use crate::find::SrcCodeMeta;
-use anyhow::{anyhow, Context};
-use cnls::fs;
-use cnls::scope::Scope;
-use dashmap::DashMap;
-use tower_lsp::jsonrpc::Result;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Instant, SystemTime};
+
+use crate::actions;
+use crate::analysis;
+use crate::client_caps::ClientCaps;
+use crate::collect::{self, ClassName};
+use crate::complete;
+use crate::config::{self, Config};
+use crate::config_file;
+use crate::css::{extract_rule_text, RuleTextError, SourceText};
+use crate::dialect::Dialect;
+use crate::documents::DocumentStore;
+use crate::fallback;
+use crate::find::{self, ClassNameToken, SrcCodeMeta};
+use crate::fs;
+use crate::hover::HoverDoc;
+use crate::index::{self, CssIndex, SingleFlight};
+use crate::protocol::{
+    self, DocumentStatus, DocumentStatusParams, EffectiveConfiguration, IndexStats,
+    ResolveRangeParams, ResolvedClassToken, UsageReport,
+};
+use crate::text::{self, PositionEncoding};
+use crate::timing::{RequestStats, RequestTimer};
+use crate::usage;
+use crate::workspace::{self, WorkspaceContext};
+use dashmap::DashSet;
+use swc_ecma_parser::Syntax;
+use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
-use tower_lsp::{Client, LanguageServer, LspService, Server};
-use tracing::{debug, error};
+use tower_lsp::{Client, ClientSocket, LanguageServer, LspService, Server};
+use tracing::{debug, error, info, warn};
 
-#[derive(Debug)]
-struct Config {
-    scopes: Vec<Scope>,
+#[derive(Clone)]
+struct TextDocument {
+    /// As last given by the client, which may spell it differently than the key of the
+    /// document, see [DocumentStore].
+    uri: Url,
+    text: String,
+    /// As given by the client when opening the document.
+    language_id: Option<String>,
+    version: i32,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        let default_scopes =
-            ["att:className,class", "fn:createElement"].map(|s| Scope::from_str(s).unwrap());
-        Self {
-            scopes: default_scopes.to_vec(),
+impl TextDocument {
+    fn path(uri: &Url) -> std::path::PathBuf {
+        std::path::PathBuf::from(uri.path())
+    }
+
+    /// The syntax to parse the document with, if it's js or ts.
+    fn syntax(&self, uri: &Url) -> Option<Syntax> {
+        find::syntax_of_document(&Self::path(uri), self.language_id.as_deref())
+    }
+
+    /// Whether the document is a stylesheet, css or any other [Dialect].
+    fn is_css(&self, uri: &Url) -> bool {
+        match self.language_id.as_deref() {
+            Some(language_id) => Dialect::of_language_id(language_id).is_some(),
+            None => Dialect::of(&Self::path(uri)).is_some(),
         }
     }
 }
 
-#[derive(Debug)]
-struct Backend {
+const CSS_FILE_WATCHERS_ID: &str = "cnls-css-file-watchers";
+const CONFIG_FILE_WATCHERS_ID: &str = "cnls-config-file-watchers";
+
+const NO_ROOTS_MESSAGE: &str = "cnls has no folder to search for css files: the client gave no \
+    workspace folder nor root, and no document was opened";
+
+/// The sources of the settings layered over the ones the server started with, kept to layer
+/// them anew as the `cnls.toml` file changes.
+#[derive(Default)]
+struct ConfigSources {
+    /// The workspace folder the `cnls.toml` file is looked for in.
+    root: Option<std::path::PathBuf>,
+    initialization_options: Option<serde_json::Value>,
+    /// The latest `cnls` settings section of the client.
+    client: Option<serde_json::Value>,
+    /// When the `cnls.toml` file was last modified, as of reading it.
+    file_modified: Option<SystemTime>,
+    /// Whether the client watches the `cnls.toml` file, otherwise it's checked for changes
+    /// along with the css files.
+    file_watched: bool,
+    file_checked_at: Option<Instant>,
+}
+
+struct BuiltCssIndex {
+    /// The workspace folders the css files were found in.
+    roots: Vec<std::path::PathBuf>,
+    /// The directory of the document they were looked for around, when there's no folder.
+    document_dir: Option<std::path::PathBuf>,
+    css_index: Arc<CssIndex>,
+    /// When each indexed css file was last modified, as of parsing it.
+    modified: HashMap<std::path::PathBuf, SystemTime>,
+    /// The css files indexed only because the others `@import` them.
+    imported: HashSet<std::path::PathBuf>,
+}
+
+/// The language server, cheap to clone onto the tasks of work that outlives the requests
+/// starting it, e.g. indexing the css files.
+#[derive(Clone)]
+pub struct Backend(Arc<BackendState>);
+
+impl std::ops::Deref for Backend {
+    type Target = BackendState;
+
+    fn deref(&self) -> &BackendState {
+        &self.0
+    }
+}
+
+/// What the clones of a [Backend] share.
+pub struct BackendState {
     client: Client,
     config: tokio::sync::RwLock<Config>,
-    documents: DashMap<Url, String>,
+    /// The settings the server started with, from the defaults and the environment.
+    base_config: Config,
+    config_sources: std::sync::Mutex<ConfigSources>,
+    documents: DocumentStore<TextDocument>,
+    /// What the client supports, as of initialize.
+    client_caps: OnceLock<ClientCaps>,
+    /// The root the client gave on initialize, as `rootUri` or the deprecated `rootPath`, for
+    /// clients that don't support workspace folders.
+    initialize_root: OnceLock<std::path::PathBuf>,
+    next_progress_token: AtomicU32,
+    /// The progress of indexing the css files being reported, and whether the client cancelled
+    /// it.
+    indexing_progress: std::sync::Mutex<Option<(ProgressToken, Arc<AtomicBool>)>>,
+    /// The globs the client was asked to watch, if it was.
+    watched_css_globs: tokio::sync::Mutex<Option<Vec<String>>>,
+    /// When the css files of the index were last checked for changes, see
+    /// [Config::css_check_interval].
+    css_files_checked_at: std::sync::Mutex<Option<Instant>>,
+    /// Whether the client was told inlay hints are served, which only happens once enabled.
+    serves_inlay_hints: AtomicBool,
+    /// The invalid entries of the `initializationOptions`, until they're shown.
+    initialization_errors: std::sync::Mutex<Vec<anyhow::Error>>,
+    reported_skipped_css_files: DashSet<std::path::PathBuf>,
+    reported_bad_roots: DashSet<std::path::PathBuf>,
+    /// Whether the client answered with an empty list of workspace folders, once logged.
+    reported_empty_folders: AtomicBool,
+    /// Whether there was no folder to search, once shown.
+    reported_no_roots: AtomicBool,
+    /// The css files duplicate class or parse problem diagnostics were last published for.
+    css_files_with_diagnostics: DashSet<Url>,
+    workspace: std::sync::RwLock<WorkspaceContext>,
+    css_index: SingleFlight<Option<Arc<CssIndex>>>,
+    /// The css index as last built, kept across requests until it's invalidated, and updated
+    /// as css files change on disk.
+    built_css_index: tokio::sync::RwLock<Option<BuiltCssIndex>>,
+    /// Where the class names are used in the source files of the workspace, as saved.
+    source_usages: SingleFlight<Option<Arc<usage::UsageLocations>>>,
+    request_stats: RequestStats,
 }
 
 impl Backend {
+    /// Starts timing a request about `context`, e.g. the document and position it targets.
+    async fn timer(&self, method: &'static str, context: String) -> RequestTimer<'_> {
+        let threshold = self.config.read().await.slow_request_threshold;
+        RequestTimer::start(method, context, &self.request_stats, threshold)
+    }
+
+    fn client_caps(&self) -> &ClientCaps {
+        static NONE: ClientCaps = ClientCaps::NONE;
+        self.client_caps.get().unwrap_or(&NONE)
+    }
+
     async fn workspace_uris(&self) -> Result<Option<Vec<Url>>> {
-        let paths = self
-            .client
-            .workspace_folders()
+        if !self.client_caps().workspace_folders {
+            return Ok(None);
+        }
+
+        let client = self.client.clone();
+        let paths = detached(async move { client.workspace_folders().await })
             .await?
             .map(|folders| folders.into_iter().map(|f| f.uri).collect::<Vec<_>>());
 
         Ok(paths)
     }
 
-    async fn find_class_name_on_cursor_at(
-        &self,
-        uri: &Url,
-        position: tower_lsp::lsp_types::Position,
-    ) -> Result<Option<(std::path::PathBuf, swc_common::Span)>> {
-        let current_doc = self
-            .documents
-            .get(uri)
-            .expect("failed to get document by uri");
-        let code = current_doc.deref();
-        let scopes = &self.config.read().await.scopes;
-        let path = std::path::PathBuf::from(uri.path());
-
-        let src = match SrcCodeMeta::build(path, code.to_owned(), position) {
-            Ok(s) => s,
+    /// The workspace folders that can be walked, showing a message once for each one that
+    /// can't, e.g. deleted or unmounted since it was opened. Falls back on the root given on
+    /// initialize, then on the directory of the `document` a request is about, see
+    /// [workspace::search_roots]. `None` when there's none of them.
+    async fn workspace_roots(&self, document: Option<&Url>) -> Option<Vec<std::path::PathBuf>> {
+        let folders = match self.workspace_uris().await {
+            Ok(uris) => uris.map(|uris| uris.iter().map(TextDocument::path).collect::<Vec<_>>()),
             Err(err) => {
-                error!("{err:#}");
-                return Ok(None);
+                debug!("failed to get the workspace folders: {err}");
+                None
             }
         };
+        if folders.as_ref().is_some_and(|folders| folders.is_empty())
+            && !self.reported_empty_folders.swap(true, Ordering::Relaxed)
+        {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    "the client gave no workspace folders, falling back on the root it gave on initialize or the directory of the document",
+                )
+                .await;
+        }
 
-        let classname_on_cursor = match src.get_classname_on_cursor(scopes) {
-            Ok(Some(strs)) => strs,
-            Ok(None) => return Ok(None),
-            Err(err) => {
+        let Some(all_roots) = workspace::search_roots(
+            folders,
+            self.initialize_root.get().map(|root| root.as_path()),
+            document
+                .map(TextDocument::path)
+                .as_deref()
+                .and_then(std::path::Path::parent),
+        ) else {
+            if !self.reported_no_roots.swap(true, Ordering::Relaxed) {
                 self.client
-                    .log_message(MessageType::ERROR, format!("{err:#}"))
+                    .show_message(MessageType::ERROR, NO_ROOTS_MESSAGE)
                     .await;
-                return Ok(None);
             }
+            return None;
         };
+        *self.workspace.write().expect("workspace lock was poisoned") =
+            WorkspaceContext::new(all_roots.clone());
 
-        let Ok(Some(uris)) = self.workspace_uris().await else {
-            self.client
-                .log_message(MessageType::ERROR, "must define the root_path for cnls")
-                .await;
+        let mut roots = vec![];
+        for root in all_roots {
+            match fs::check_workspace_root(&root) {
+                Ok(()) => roots.push(root),
+                Err(err) => {
+                    if self.reported_bad_roots.insert(root) {
+                        self.client
+                            .show_message(
+                                MessageType::WARNING,
+                                format!("cnls won't search for css files in the workspace folder {err:#}"),
+                            )
+                            .await;
+                    }
+                }
+            }
+        }
 
-            return Ok(None);
+        Some(roots)
+    }
+
+    /// Renders the `path` for the user, relative to the workspace root containing it.
+    fn display_path(&self, path: &std::path::Path) -> String {
+        self.workspace
+            .read()
+            .expect("workspace lock was poisoned")
+            .display_path(path)
+    }
+
+    async fn pull_configuration(&self) -> Option<serde_json::Value> {
+        if !self.client_caps().pull_configuration {
+            return None;
+        }
+
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some("cnls".to_string()),
+        };
+
+        match self.client.configuration(vec![item]).await {
+            Ok(mut values) if !values.is_empty() => Some(values.swap_remove(0)),
+            Ok(_) => None,
+            Err(err) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("failed to pull cnls configuration: {err}"),
+                    )
+                    .await;
+                None
+            }
+        }
+    }
+
+    /// Pulls the settings of the client and applies them, if it has any, returning whether they
+    /// could be pulled.
+    async fn pull_and_apply_configuration(&self) -> bool {
+        let Some(settings) = self.pull_configuration().await else {
+            return false;
         };
 
-        let mut css_files = vec![];
+        if let Some(section) = config::settings_section(&settings) {
+            self.apply_configuration(section).await;
+        }
+        true
+    }
 
-        let root = uris[0].path();
-        if let Err(err) = fs::find_all_css_files_in_dir(root, &mut css_files) {
+    async fn apply_configuration(&self, section: &serde_json::Value) {
+        self.config_sources().client = Some(section.clone());
+        let errors = self.config.write().await.update_from_json(section);
+
+        for err in errors {
             self.client
                 .log_message(MessageType::ERROR, format!("{err:#}"))
                 .await
-        };
+        }
 
-        let parsed = css_files
-            .into_iter()
-            .map(|file| (file.clone(), ClassNamesCollector::parse(file)))
-            .collect::<Vec<_>>();
+        self.configuration_changed().await;
+    }
 
-        for p in parsed {
-            let (css_file, p) = p;
+    fn config_sources(&self) -> std::sync::MutexGuard<'_, ConfigSources> {
+        self.config_sources
+            .lock()
+            .expect("config sources lock was poisoned")
+    }
 
-            match p {
-                Err(err) => {
-                    self.client
-                        .log_message(MessageType::ERROR, format!("{err:#}"))
-                        .await
-                }
-                Ok(collector) => {
-                    if let Some(class) = collector.find_class_name_by_value(&classname_on_cursor) {
-                        self.client
-                            .log_message(
-                                MessageType::INFO,
-                                format!(
-                                    "found class rule {classname_on_cursor:?} in css file {}",
-                                    css_file.display()
-                                ),
-                            )
-                            .await;
+    /// Layers the settings anew after the `cnls.toml` file changed, in increasing precedence:
+    /// those the server started with, the file, the environment, the `initializationOptions`
+    /// and the latest client settings. An invalid file is reported, keeping the settings as
+    /// they were.
+    async fn reload_config_file(&self) {
+        let (root, options, client) = {
+            let sources = self.config_sources();
+            let Some(root) = sources.root.clone() else {
+                return;
+            };
+            (
+                root,
+                sources.initialization_options.clone(),
+                sources.client.clone(),
+            )
+        };
 
-                        return Ok(Some((css_file, class.span)));
-                    };
+        let mut config = self.base_config.clone();
+        let path = config_file::find_in(&root);
+        if let Some(path) = &path {
+            let errors = config.update_from_file(path);
+            if !errors.is_empty() {
+                for err in errors {
+                    self.client
+                        .show_message(
+                            MessageType::ERROR,
+                            format!("invalid cnls settings, keeping the previous ones: {err:#}"),
+                        )
+                        .await;
                 }
+                return;
             }
         }
+        // their errors were reported when they were first applied
+        config.update_from_env();
+        if let Some(options) = &options {
+            config.update_from_initialization_options(options);
+        }
+        if let Some(section) = &client {
+            config.update_from_json(section);
+        }
+
+        let changed = {
+            let mut current = self.config.write().await;
+            let changed = current.changed_settings(&config);
+            *current = config;
+            changed
+        };
+        if changed.is_empty() {
+            debug!("reloaded the cnls.toml file, without any change");
+            return;
+        }
+
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!(
+                    "reloaded {}, changing: {}",
+                    path.as_deref()
+                        .unwrap_or_else(|| std::path::Path::new("the deleted cnls.toml file"))
+                        .display(),
+                    changed.join(", ")
+                ),
+            )
+            .await;
 
-        Ok(None)
+        self.configuration_changed().await;
     }
-}
 
-#[tower_lsp::async_trait]
-impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
-        Ok(InitializeResult {
-            server_info: None,
-            capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                definition_provider: Some(OneOf::Left(true)),
-                hover_provider: Some(HoverProviderCapability::Simple(true)),
-                ..ServerCapabilities::default()
-            },
-        })
+    /// Drops whatever was computed with the previous settings, e.g. the usages found with the
+    /// old scopes, and tells the client about the new ones.
+    async fn configuration_changed(&self) {
+        self.invalidate_css_index().await;
+        self.source_usages.invalidate();
+
+        self.register_inlay_hints().await;
+        self.register_css_file_watchers().await;
+        self.republish_diagnostics().await;
     }
 
-    async fn initialized(&self, _: InitializedParams) {
-        self.client
-            .log_message(MessageType::INFO, "server initialized!")
-            .await;
+    /// Tells the client inlay hints are served once they're enabled, if they weren't already
+    /// when it initialized, and the client registers capabilities dynamically.
+    async fn register_inlay_hints(&self) {
+        let enabled = self.config.read().await.inlay_hints.enabled;
+        if !enabled
+            || !self.client_caps().register_inlay_hints
+            || self.serves_inlay_hints.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+
+        let registration = Registration {
+            id: "cnls-inlay-hints".to_string(),
+            method: "textDocument/inlayHint".to_string(),
+            register_options: None,
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            self.serves_inlay_hints.store(false, Ordering::Relaxed);
+            debug!("failed to register inlay hints: {err}");
+        }
     }
 
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        debug!("current source code: {}", params.text_document.uri.path());
+    /// Starts reporting the progress of some work titled `title`, if the client supports it,
+    /// returning the token to report the rest of it with. Only `cancellable` work can be
+    /// cancelled from the progress shown, see [Backend::cancel_progress].
+    async fn begin_progress(
+        &self,
+        title: &str,
+        message: String,
+        cancellable: bool,
+    ) -> Option<ProgressToken> {
+        if !self.client_caps().work_done_progress {
+            return None;
+        }
 
-        let code = params.text_document.text;
+        let token = NumberOrString::String(format!(
+            "cnls-progress-{}",
+            self.next_progress_token.fetch_add(1, Ordering::Relaxed)
+        ));
+        let create = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+        let client = self.client.clone();
+        let created = detached(async move {
+            client
+                .send_request::<request::WorkDoneProgressCreate>(create)
+                .await
+        });
+        if let Err(err) = created.await {
+            debug!("failed to create a progress token: {err}");
+            return None;
+        }
 
-        self.documents.insert(params.text_document.uri, code);
+        let begin = WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(cancellable),
+            message: Some(message),
+            percentage: Some(0),
+        };
+        self.send_progress(&token, WorkDoneProgress::Begin(begin))
+            .await;
+
+        Some(token)
     }
 
-    async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        self.documents.remove(&params.text_document.uri);
+    async fn send_progress(&self, token: &ProgressToken, progress: WorkDoneProgress) {
+        let params = ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(progress),
+        };
+        self.client
+            .send_notification::<notification::Progress>(params)
+            .await;
     }
 
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
-        let uri = params.text_document.uri;
+    /// Asks the client to watch the css files, see [Config::css_watch_globs], once it
+    /// initialized or they changed, if it registers file watchers dynamically.
+    async fn register_css_file_watchers(&self) {
+        if !self.client_caps().register_file_watchers {
+            return;
+        }
 
-        debug!("current source code: {}", uri.path());
+        let globs = self.config.read().await.css_watch_globs();
+        let mut watched = self.watched_css_globs.lock().await;
+        if watched.as_ref() == Some(&globs) {
+            return;
+        }
+
+        if watched.take().is_some() {
+            let unregistration = Unregistration {
+                id: CSS_FILE_WATCHERS_ID.to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+            };
+            if let Err(err) = self
+                .client
+                .unregister_capability(vec![unregistration])
+                .await
+            {
+                debug!("failed to unregister css file watchers: {err}");
+            }
+        }
 
-        let code = mem::take(&mut params.content_changes[0].text);
-        self.documents.insert(uri, code);
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: globs
+                .iter()
+                .map(|glob| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(glob.clone()),
+                    kind: None,
+                })
+                .collect(),
+        };
+        let registration = Registration {
+            id: CSS_FILE_WATCHERS_ID.to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        match self.client.register_capability(vec![registration]).await {
+            Ok(()) => *watched = Some(globs),
+            Err(err) => debug!("failed to register css file watchers: {err}"),
+        }
     }
 
-    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
-        let parsed_scopes_from_config = params.settings["cnls"]["scopes"].as_array().map(|arr| {
-            arr.iter()
-                .filter_map(|v| v.as_str())
-                .map(cnls::scope::Scope::from_str)
-                .collect::<Vec<_>>()
-        });
+    /// Asks the client to watch the `cnls.toml` file, if it registers capabilities dynamically.
+    async fn register_config_file_watchers(&self) {
+        if !self.client_caps().register_file_watchers {
+            return;
+        }
 
-        match parsed_scopes_from_config {
-            Some(results) => {
-                let mut config = self.config.write().await;
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: config_file::CONFIG_FILE_NAMES
+                .iter()
+                .map(|name| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/{name}")),
+                    kind: None,
+                })
+                .collect(),
+        };
+        let registration = Registration {
+            id: CONFIG_FILE_WATCHERS_ID.to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        match self.client.register_capability(vec![registration]).await {
+            Ok(()) => self.config_sources().file_watched = true,
+            Err(err) => debug!("failed to register config file watchers: {err}"),
+        }
+    }
 
-                config.scopes.clear();
+    /// Whether `uri` is the `cnls.toml` file settings are read from, or would be if it existed.
+    fn is_config_file(&self, uri: &Url) -> bool {
+        let path = TextDocument::path(uri);
+        let is_named_so = path
+            .file_name()
+            .is_some_and(|name| config_file::CONFIG_FILE_NAMES.iter().any(|n| name == *n));
 
-                for r in results {
-                    match r {
-                        Ok(scope) => config.scopes.push(scope),
-                        Err(err) => {
-                            self.client
-                                .log_message(MessageType::ERROR, format!("cnls.scopes: {err:#}"))
-                                .await
-                        }
-                    }
-                }
+        is_named_so && path.parent() == self.config_sources().root.as_deref()
+    }
+
+    /// Reloads the `cnls.toml` file when it changed on disk, for clients that don't watch it,
+    /// at most every [Config::css_check_interval].
+    async fn check_config_file(&self) {
+        let interval = self.config.read().await.css_check_interval;
+        let modified = {
+            let mut sources = self.config_sources();
+            if sources.file_watched
+                || sources
+                    .file_checked_at
+                    .is_some_and(|at| at.elapsed() < interval)
+            {
+                return;
             }
-            None => {
-                self.client
-                    .log_message(
-                        MessageType::WARNING,
-                        "cnls.scopes should be an array of strings",
-                    )
-                    .await;
+            sources.file_checked_at = Some(Instant::now());
+
+            let Some(root) = &sources.root else {
+                return;
+            };
+            let modified = config_file::find_in(root).and_then(|path| modified_time(&path));
+            if modified == sources.file_modified {
+                return;
             }
+            sources.file_modified = modified;
+            modified
         };
+
+        debug!(?modified, "the cnls.toml file changed on disk");
+        self.reload_config_file().await;
     }
 
-    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        let uri = params.text_document_position_params.text_document.uri;
-        let current_position = params.text_document_position_params.position;
+    /// Re-analyzes every open source document, and the css files, against a fresh css index,
+    /// e.g. after a stylesheet changed, publishing their diagnostics anew. Clients pulling
+    /// diagnostics are asked to pull them again instead.
+    async fn republish_diagnostics(&self) {
+        if self.client_caps().refresh_diagnostics {
+            let client = self.client.clone();
+            let refreshed = detached(async move { client.workspace_diagnostic_refresh().await });
+            if let Err(err) = refreshed.await {
+                debug!("failed to refresh diagnostics: {err}");
+            }
+        }
 
-        if let Some((css_file, span)) = self
-            .find_class_name_on_cursor_at(&uri, current_position)
-            .await?
-        {
-            let result = std::fs::File::open(&css_file)
-                .context("failed to open css source file")
-                .with_context(|| format!("failed to open css source file: {}", css_file.display()))
-                .and_then(|file| {
-                    let rule_start_pos = span.lo.0 - 1; // swc's BytePos is
-                                                        // 1-based
-                    let byte_read_count = span.hi.0 - span.lo.0;
-                    let mut buf = vec![0; byte_read_count as usize];
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::fs::FileExt;
-                        file.read_exact_at(&mut buf, rule_start_pos.into())
-                            .with_context(|| {
-                                format!("failed to read file in the span: {:?}", span)
-                            })?;
-                    }
+        let Some(css_index) = self.css_index(None).await else {
+            return;
+        };
 
-                    #[cfg(not(unix))]
-                    {
-                        use std::os::windows::fs::FileExt;
-                        file.seek_read(&mut buf, rule_start_pos.into())
-                            .with_context(|| {
-                                format!("failed to read file in the span: {:?}", span)
-                            })?;
-                    }
+        if !self.client_caps().pull_diagnostics {
+            let open_documents = self
+                .documents
+                .iter()
+                .filter(|doc| doc.syntax(&doc.uri).is_some())
+                .map(|doc| doc.uri.clone())
+                .collect::<Vec<_>>();
 
-                    let s = String::from_utf8(buf).context("failed to read utf-8 string")?;
-                    Ok(s)
-                });
+            for uri in open_documents {
+                self.publish_diagnostics(uri, &css_index).await;
+            }
+        }
 
-            let source_rule = match result {
-                Ok(s) => s,
-                Err(err) => {
-                    self.client
-                        .log_message(MessageType::ERROR, format!("{err:#}",))
-                        .await;
+        self.publish_css_diagnostics(&css_index).await;
+    }
 
-                    return Ok(None);
+    /// Publishes the duplicate class definitions of the `css_index` in the css files holding
+    /// them, along with their parse problems when enabled, clearing the diagnostics of the files
+    /// that no longer have any. These are always pushed, since they concern css files whether
+    /// they're open or not.
+    async fn publish_css_diagnostics(&self, css_index: &CssIndex) {
+        let encoding = self.client_caps().position_encoding;
+        let (level, report_parse_errors) = {
+            let config = self.config.read().await;
+            (
+                config.diagnostics.duplicate_class,
+                config.report_css_parse_errors,
+            )
+        };
+        let mut diagnostics = analysis::duplicate_class_diagnostics(css_index, level, encoding);
+        if report_parse_errors {
+            for (uri, problems) in analysis::parse_problem_diagnostics(css_index, encoding) {
+                match diagnostics.iter_mut().find(|(u, _)| *u == uri) {
+                    Some((_, file_diagnostics)) => file_diagnostics.extend(problems),
+                    None => diagnostics.push((uri, problems)),
                 }
-            };
+            }
+        }
 
-            return Ok(Some(Hover {
-                contents: HoverContents::Scalar(MarkedString::LanguageString(LanguageString {
-                    language: "css".to_string(),
-                    value: source_rule,
-                })),
-                range: None,
-            }));
+        let cleared = self
+            .css_files_with_diagnostics
+            .iter()
+            .map(|uri| uri.clone())
+            .filter(|uri| diagnostics.iter().all(|(u, _)| u != uri))
+            .collect::<Vec<_>>();
+
+        for uri in cleared {
+            self.css_files_with_diagnostics.remove(&uri);
+            self.client.publish_diagnostics(uri, vec![], None).await;
         }
 
-        Ok(None)
+        for (uri, file_diagnostics) in diagnostics {
+            self.css_files_with_diagnostics.insert(uri.clone());
+            self.client
+                .publish_diagnostics(uri, file_diagnostics, None)
+                .await;
+        }
     }
 
-    async fn goto_definition(
+    /// Analyzes the open source document at `uri` against the `css_index`, for both pushed and
+    /// pulled diagnostics. Returns the text analyzed alongside, `None` if the document isn't
+    /// an open source document or can't be parsed.
+    async fn source_diagnostics(
         &self,
-        params: GotoDefinitionParams,
-    ) -> Result<Option<GotoDefinitionResponse>> {
-        let uri = params.text_document_position_params.text_document.uri;
-        let current_position = params.text_document_position_params.position;
+        uri: &Url,
+        css_index: &CssIndex,
+    ) -> Option<(String, Vec<Diagnostic>)> {
+        let encoding = self.client_caps().position_encoding;
+        let (syntax, code) = self
+            .documents
+            .get(uri)
+            .and_then(|doc| Some((doc.syntax(uri)?, doc.text.clone())))?;
 
-        if let Some((css_file, span)) = self
-            .find_class_name_on_cursor_at(&uri, current_position)
-            .await?
-        {
-            fn get_location(
-                css_file: std::path::PathBuf,
-                span: swc_common::Span,
-            ) -> anyhow::Result<Location> {
-                let uri = Url::from_file_path(&css_file).map_err(|_| {
-                    anyhow!(
-                        "failed to get uri from css file path: {}",
-                        css_file.display()
-                    )
-                })?;
-
-                let (cssfile, _) = crate::collect::css_source_file_from(css_file)
-                    .context("failed to build a SourceFile from a css file")?;
-
-                let start_ln_num = cssfile.lookup_line(span.lo).ok_or(anyhow!(
-                    "failed to get line number of the span start: {:?}",
-                    span
-                ))?;
-                let end_ln_num = cssfile.lookup_line(span.hi).ok_or(anyhow!(
-                    "failed to get line number of the span end: {:?}",
-                    span
-                ))?;
-                let range = Range::new(
-                    Position {
-                        line: start_ln_num as u32,
-                        character: (span.lo - cssfile.line_begin_pos(span.lo)).0,
-                    },
-                    Position {
-                        line: end_ln_num as u32,
-                        character: (span.hi - cssfile.line_begin_pos(span.hi)).0,
-                    },
-                );
+        let (scopes, token_filter, level) = {
+            let config = self.config.read().await;
+            (
+                config.scopes.clone(),
+                config.token_filter.clone(),
+                config.diagnostics.unknown_class,
+            )
+        };
 
-                Ok(Location::new(uri, range))
+        let path = TextDocument::path(uri);
+        let tokens = match find::collect_class_names(path, syntax, code.clone(), &scopes) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                debug!("{err:#}");
+                return None;
             }
+        };
 
-            let location = match get_location(css_file, span) {
-                Ok(l) => l,
-                Err(err) => {
-                    self.client
-                        .log_message(MessageType::ERROR, format!("{err:#}"))
-                        .await;
+        let resolved = analysis::resolve_tokens(tokens, css_index, &token_filter);
+        let diagnostics = analysis::unknown_class_diagnostics(&resolved, &code, level, encoding);
 
-                    return Ok(None);
-                }
-            };
+        Some((code, diagnostics))
+    }
 
-            return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+    /// Publishes the diagnostics of the open source document at `uri`, unless it was edited in
+    /// the meantime, since that edit publishes its own.
+    async fn publish_diagnostics(&self, uri: Url, css_index: &CssIndex) {
+        let Some((code, diagnostics)) = self.source_diagnostics(&uri, css_index).await else {
+            return;
+        };
+
+        let is_outdated = self.documents.get(&uri).is_some_and(|doc| doc.text != code);
+        if is_outdated {
+            return;
         }
 
-        Ok(None)
+        self.client
+            .publish_diagnostics(uri, diagnostics, None)
+            .await;
     }
 
-    async fn shutdown(&self) -> Result<()> {
-        Ok(())
+    /// Publishes the diagnostics of the open document at `uri` after it was opened or edited:
+    /// those of a source document, unless the client pulls them, or the duplicate class
+    /// definitions for a css one.
+    async fn diagnose_document(&self, uri: Url) {
+        let Some((is_source, is_css)) = self
+            .documents
+            .get(&uri)
+            .map(|doc| (doc.syntax(&uri).is_some(), doc.is_css(&uri)))
+        else {
+            return;
+        };
+
+        let is_pushed = is_source && !self.client_caps().pull_diagnostics;
+        if !is_pushed && !is_css {
+            return;
+        }
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return;
+        };
+
+        if is_source {
+            self.publish_diagnostics(uri, &css_index).await;
+        } else {
+            self.publish_css_diagnostics(&css_index).await;
+        }
     }
-}
 
-pub async fn start() {
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
+    /// Answers `textDocument/diagnostic` with the same diagnostics as those pushed. The result
+    /// id identifies the document version and css index generation they were computed for, so
+    /// that they aren't computed again until either changes.
+    async fn pull_diagnostics(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let uri = params.text_document.uri;
+        let mut timer = self
+            .timer("textDocument/diagnostic", uri.path().to_string())
+            .await;
 
-    let (service, socket) = LspService::new(|client| Backend {
-        client,
-        config: tokio::sync::RwLock::new(Config::default()),
-        documents: DashMap::new(),
-    });
+        let Some(version) = self.documents.get(&uri).map(|doc| doc.version) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
 
-    Server::new(stdin, stdout, socket).serve(service).await;
+        let result_id = format!("{version}-{}", self.css_index.generation());
+
+        if params.previous_result_id.as_ref() == Some(&result_id) {
+            let report = RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id,
+                },
+            };
+            return Ok(DocumentDiagnosticReportResult::Report(
+                DocumentDiagnosticReport::Unchanged(report),
+            ));
+        }
+
+        let css_index = self.css_index(Some(&uri)).await;
+        timer.phase("scan");
+
+        let items = match css_index {
+            Some(css_index) => self
+                .source_diagnostics(&uri, &css_index)
+                .await
+                .map(|(_, diagnostics)| diagnostics)
+                .unwrap_or_default(),
+            None => vec![],
+        };
+
+        let report = RelatedFullDocumentDiagnosticReport {
+            related_documents: None,
+            full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                result_id: Some(result_id),
+                items,
+            },
+        };
+
+        Ok(DocumentDiagnosticReportResult::Report(
+            DocumentDiagnosticReport::Full(report),
+        ))
+    }
+
+    /// Lists the css files to search, in the order their definitions should be preferred: by
+    /// workspace folder, then by their priority within it, the oversized ones ranked low after
+    /// those of every folder. The files of nested folders are listed once. Also lists the
+    /// oversized ones skipped, and counts the entries that couldn't be read on the way, logging
+    /// them.
+    async fn css_files_to_index(
+        &self,
+        roots: &[std::path::PathBuf],
+    ) -> (
+        Vec<(std::path::PathBuf, index::Rank)>,
+        Vec<std::path::PathBuf>,
+        usize,
+    ) {
+        let (index_minified, limits, css) = {
+            let config = self.config.read().await;
+            (
+                config.index_minified,
+                config.css_file_limits,
+                config.css.clone(),
+            )
+        };
+
+        let mut seen = HashSet::new();
+        let mut prioritized = vec![];
+        let mut skipped = vec![];
+        let mut unreadable = 0;
+        for root in roots {
+            let fs::WalkSummary { mut files, errors } = fs::walk_css_files_in_dir(root, &css);
+            unreadable += errors.len();
+            for (path, err) in errors {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("failed to read {}: {err}", self.display_path(&path)),
+                    )
+                    .await
+            }
+            files.retain(|css_file| seen.insert(css_file.clone()));
+
+            let mut in_root = index::prioritize_css_files(root, files, index_minified, limits);
+            skipped.append(&mut in_root.skipped);
+            prioritized.push(in_root);
+        }
+
+        (index::rank_css_files(prioritized), skipped, unreadable)
+    }
+
+    /// Logs the css files `skipped` for looking minified or being oversized, in one notice,
+    /// each once.
+    async fn report_skipped_css_files(&self, skipped: Vec<std::path::PathBuf>) {
+        let newly_skipped = skipped
+            .into_iter()
+            .filter(|file| self.reported_skipped_css_files.insert(file.clone()))
+            .map(|file| self.display_path(&file))
+            .collect::<Vec<_>>();
+
+        if !newly_skipped.is_empty() {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!(
+                        "skipped minified or oversized css files: {}",
+                        newly_skipped.join(", ")
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Scans the workspace and parses its css files, sharing the work with any other request
+    /// that needs it at the same time.
+    async fn source_usages(&self, document: Option<&Url>) -> Option<Arc<usage::UsageLocations>> {
+        let backend = self.clone();
+        let document = document.cloned();
+        self.source_usages
+            .run(|cancelled| async move {
+                backend
+                    .build_source_usages(document.as_ref(), cancelled)
+                    .await
+            })
+            .await
+    }
+
+    /// Scans the source files, stopping once `cancelled` is set, e.g. as they're saved.
+    async fn build_source_usages(
+        &self,
+        document: Option<&Url>,
+        cancelled: Arc<AtomicBool>,
+    ) -> Option<Arc<usage::UsageLocations>> {
+        let encoding = self.client_caps().position_encoding;
+        let roots = self.workspace_roots(document).await?;
+
+        let mut source_files = vec![];
+        if let Err(err) = fs::find_all_source_files_in_dirs(&roots, &mut source_files) {
+            self.client
+                .log_message(MessageType::ERROR, format!("{err:#}"))
+                .await
+        };
+
+        let scopes = self.config.read().await.scopes.clone();
+
+        let (locations, errors) = tokio::task::spawn_blocking(move || {
+            usage::scan_usage_locations(&source_files, &scopes, encoding, &cancelled)
+        })
+        .await
+        .expect("scanning source files shouldn't panic");
+
+        for err in errors {
+            debug!("{err:#}");
+        }
+
+        Some(Arc::new(locations))
+    }
+
+    /// The css index, built for the workspace folders, or the directory of the `document` a
+    /// request is about without any, see [Backend::workspace_roots].
+    async fn css_index(&self, document: Option<&Url>) -> Option<Arc<CssIndex>> {
+        let path = document.map(TextDocument::path);
+        let is_built_elsewhere = match self.built_css_index.read().await.as_ref() {
+            // around a document in another tree, for want of a workspace folder
+            Some(built) => match (&built.document_dir, &path) {
+                (Some(dir), Some(path)) if !path.starts_with(dir) => true,
+                _ => return Some(built.css_index.clone()),
+            },
+            None => false,
+        };
+        if is_built_elsewhere {
+            self.invalidate_css_index().await;
+        }
+
+        let backend = self.clone();
+        let document = document.cloned();
+        self.css_index
+            .run(|cancelled| async move {
+                backend
+                    .build_css_index(document.as_ref(), cancelled)
+                    .await
+            })
+            .await
+    }
+
+    /// Drops the css index, so that the next request needing it walks the workspace anew.
+    async fn invalidate_css_index(&self) {
+        let mut built = self.built_css_index.write().await;
+        *built = None;
+        self.css_index.invalidate();
+    }
+
+    /// Walks the workspace and parses its css files, stopping once `cancelled` is set: when the
+    /// index is invalidated, e.g. to reindex, or the client cancels the progress.
+    async fn build_css_index(
+        &self,
+        document: Option<&Url>,
+        cancelled: Arc<AtomicBool>,
+    ) -> Option<Arc<CssIndex>> {
+        let generation = self.css_index.generation();
+        let roots = self.workspace_roots(document).await?;
+        // without a root given by the client, see [workspace::search_roots]
+        let document_dir = document
+            .and_then(|document| Some(TextDocument::path(document).parent()?.to_path_buf()))
+            .filter(|dir| self.initialize_root.get().is_none() && roots == [dir.clone()]);
+
+        let (css_files, mut skipped, unreadable) = self.css_files_to_index(&roots).await;
+        let (follow_imports, index_minified, limits) = {
+            let config = self.config.read().await;
+            (
+                config.follow_imports,
+                config.index_minified,
+                config.css_file_limits,
+            )
+        };
+
+        let total = css_files.len();
+        let mut progress = ProgressGuard {
+            client: self.client.clone(),
+            token: self
+                .begin_progress("Indexing CSS", format!("0/{total}"), true)
+                .await,
+        };
+        if let Some(token) = &progress.token {
+            *self
+                .indexing_progress
+                .lock()
+                .expect("indexing progress lock was poisoned") =
+                Some((token.clone(), cancelled.clone()));
+        }
+
+        // parsed off the async workers, so that requests waiting on a budget can time out
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let parse_cancelled = cancelled.clone();
+        let parse = tokio::task::spawn_blocking(move || {
+            // as of before parsing, so that changes made meanwhile are found later on
+            let modified = css_files
+                .iter()
+                .filter_map(|(css_file, _)| Some((css_file.clone(), modified_time(css_file)?)))
+                .collect::<HashMap<_, _>>();
+            let progress = |done: usize, total: usize| {
+                if done == total || done.is_multiple_of((total / 10).max(1)) {
+                    let _ = progress_tx.send(done);
+                }
+            };
+            let (mut css_index, skipped, mut errors) = CssIndex::parse_files_with(
+                css_files,
+                index_minified,
+                limits,
+                &progress,
+                &parse_cancelled,
+            );
+
+            let parsed = css_index.css_files().count();
+            errors.extend(css_index.follow_imports(follow_imports, &parse_cancelled));
+            let imported = css_index
+                .css_files()
+                .skip(parsed)
+                .map(|css_file| css_file.to_path_buf())
+                .collect::<HashSet<_>>();
+            let mut modified = modified;
+            modified.extend(
+                imported
+                    .iter()
+                    .filter_map(|css_file| Some((css_file.clone(), modified_time(css_file)?))),
+            );
+            (css_index, skipped, errors, modified, imported)
+        });
+
+        while let Some(done) = progress_rx.recv().await {
+            if let Some(token) = &progress.token {
+                let report = WorkDoneProgressReport {
+                    cancellable: Some(true),
+                    message: Some(format!("{done}/{total}")),
+                    percentage: Some((done * 100 / total.max(1)) as u32),
+                };
+                self.send_progress(token, WorkDoneProgress::Report(report))
+                    .await;
+            }
+        }
+
+        let (css_index, skipped_once_read, errors, modified, imported) =
+            parse.await.expect("parsing css files shouldn't panic");
+        skipped.extend(skipped_once_read);
+        self.report_skipped_css_files(skipped).await;
+
+        let indexed = css_index.css_files().count() - imported.len();
+        let message = if cancelled.load(Ordering::Relaxed) {
+            let message = format!("cancelled, indexed {indexed}/{total} css files");
+            // unless invalidated, for the next request to index them anew
+            if self.css_index.generation() == generation {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!(
+                            "css indexing {message}, run {} to index them all",
+                            protocol::REINDEX_COMMAND
+                        ),
+                    )
+                    .await;
+            }
+            message
+        } else if imported.is_empty() {
+            format!("indexed {indexed}/{total} css files")
+        } else {
+            format!(
+                "indexed {indexed}/{total} css files, and {} they import",
+                imported.len()
+            )
+        };
+        let message = match unreadable {
+            0 => message,
+            _ => format!("{message}, {unreadable} entries couldn't be read"),
+        };
+        if let Some(token) = progress.token.take() {
+            self.indexing_progress
+                .lock()
+                .expect("indexing progress lock was poisoned")
+                .take_if(|(indexing, _)| *indexing == token);
+            let end = WorkDoneProgressEnd {
+                message: Some(message),
+            };
+            self.send_progress(&token, WorkDoneProgress::End(end)).await;
+        }
+
+        for err in errors {
+            self.client
+                .log_message(MessageType::ERROR, format!("{err:#}"))
+                .await
+        }
+
+        let css_index = Arc::new(css_index);
+
+        // unless it was invalidated while being built
+        let mut built = self.built_css_index.write().await;
+        if self.css_index.generation() == generation {
+            *built = Some(BuiltCssIndex {
+                roots,
+                document_dir,
+                css_index: css_index.clone(),
+                modified,
+                imported,
+            });
+        }
+
+        Some(css_index)
+    }
+
+    /// Updates the built css index with the css files changed on disk, parsing only those, and
+    /// publishing diagnostics anew.
+    async fn update_css_index(&self, changes: Vec<FileEvent>) {
+        let css_files = changes
+            .into_iter()
+            .filter_map(|event| Some((event.uri.to_file_path().ok()?, event.typ)))
+            .filter(|(path, _)| Dialect::of(path).is_some())
+            .collect::<Vec<_>>();
+
+        if css_files.is_empty() {
+            return;
+        }
+
+        let Some((roots, imported)) = self
+            .built_css_index
+            .read()
+            .await
+            .as_ref()
+            .map(|built| (built.roots.clone(), built.imported.clone()))
+        else {
+            // the next request builds the index from scratch anyway
+            self.css_index.invalidate();
+            self.republish_diagnostics().await;
+            return;
+        };
+
+        let (index_minified, limits, css) = {
+            let config = self.config.read().await;
+            (
+                config.index_minified,
+                config.css_file_limits,
+                config.css.clone(),
+            )
+        };
+
+        let parsed = tokio::task::spawn_blocking(move || {
+            css_files
+                .into_iter()
+                .map(|(css_file, typ)| {
+                    // the first folder listing it, as when the index was built
+                    let root = roots
+                        .iter()
+                        .find(|root| css_file.starts_with(root))
+                        .cloned();
+                    // kept wherever it is, until the index is built anew
+                    let is_imported = imported.contains(&css_file);
+                    let root = root.or_else(|| roots.first().cloned().filter(|_| is_imported));
+                    let rank = root
+                        .as_ref()
+                        .filter(|_| typ != FileChangeType::DELETED)
+                        .and_then(|root| {
+                            if is_imported {
+                                return Some(index::Rank {
+                                    imported: true,
+                                    ..index::Rank::default()
+                                });
+                            }
+                            if !css.includes(root, &css_file) {
+                                return None;
+                            }
+                            let prioritized = index::prioritize_css_files(
+                                root,
+                                vec![css_file.clone()],
+                                index_minified,
+                                limits,
+                            );
+                            // as when the index was built, minified ones last
+                            index::rank_css_files(vec![prioritized])
+                                .pop()
+                                .map(|(_, rank)| rank)
+                        });
+                    let modified = modified_time(&css_file);
+                    let collector = rank.map(|rank| {
+                        index::parse_css_file(css_file.clone(), rank, index_minified, limits)
+                    });
+                    (css_file, modified, collector)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .expect("parsing css files shouldn't panic");
+
+        let mut skipped = vec![];
+        {
+            let mut built = self.built_css_index.write().await;
+            let Some(built) = built.as_mut() else {
+                return;
+            };
+            let css_index = Arc::make_mut(&mut built.css_index);
+
+            for (css_file, modified, collector) in parsed {
+                match modified {
+                    Some(modified) => built.modified.insert(css_file.clone(), modified),
+                    None => built.modified.remove(&css_file),
+                };
+
+                match collector {
+                    Some(Ok(Some((rank, collector)))) => {
+                        css_index.update(&built.roots, css_file, rank, collector)
+                    }
+                    Some(Ok(None)) => {
+                        css_index.remove(&css_file);
+                        skipped.push(css_file);
+                    }
+                    Some(Err(err)) => {
+                        self.client
+                            .log_message(MessageType::ERROR, format!("{err:#}"))
+                            .await;
+                        css_index.remove(&css_file);
+                    }
+                    None => {
+                        css_index.remove(&css_file);
+                    }
+                }
+            }
+
+            // for the results computed from the index as it was to be outdated
+            self.css_index.invalidate();
+        }
+        self.report_skipped_css_files(skipped).await;
+
+        self.republish_diagnostics().await;
+    }
+
+    /// Updates the css index with the indexed css files modified or deleted since they were
+    /// parsed, at most every `css_check_interval`, for clients that don't watch them.
+    async fn check_css_files(&self) {
+        if self.watched_css_globs.lock().await.is_some() {
+            return;
+        }
+
+        let Some(modified) = self
+            .built_css_index
+            .read()
+            .await
+            .as_ref()
+            .map(|built| built.modified.clone())
+        else {
+            return;
+        };
+
+        let interval = self.config.read().await.css_check_interval;
+        {
+            let mut checked_at = self
+                .css_files_checked_at
+                .lock()
+                .expect("check time lock was poisoned");
+            if checked_at.is_some_and(|at| at.elapsed() < interval) {
+                return;
+            }
+            *checked_at = Some(Instant::now());
+        }
+
+        let changes = tokio::task::spawn_blocking(move || {
+            modified
+                .into_iter()
+                .filter_map(|(css_file, modified)| {
+                    let typ = match modified_time(&css_file) {
+                        Some(now) if now == modified => return None,
+                        Some(_) => FileChangeType::CHANGED,
+                        None => FileChangeType::DELETED,
+                    };
+                    Some(FileEvent::new(Url::from_file_path(css_file).ok()?, typ))
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .expect("checking css files shouldn't panic");
+
+        if !changes.is_empty() {
+            debug!("{} indexed css files changed on disk", changes.len());
+            self.update_css_index(changes).await;
+        }
+    }
+
+    /// The css index, if it's built within the `index_wait_budget`. Otherwise, an index of the
+    /// stylesheets the document at `uri` imports, or that are near it, see
+    /// [fs::find_css_files_near].
+    async fn css_index_within_budget(&self, uri: &Url) -> Option<Arc<CssIndex>> {
+        let budget = self.config.read().await.index_wait_budget;
+
+        if let Ok(css_index) = tokio::time::timeout(budget, self.css_index(Some(uri))).await {
+            return css_index;
+        }
+
+        let path = TextDocument::path(uri);
+        warn!(
+            "degraded lookup: css index still building after {}ms, searching the stylesheets near {}",
+            budget.as_millis(),
+            self.display_path(&path)
+        );
+
+        let imported = self
+            .documents
+            .get(uri)
+            .and_then(|doc| Some((doc.syntax(uri)?, doc.text.clone())))
+            .and_then(|(syntax, code)| find::imported_stylesheets(path.clone(), syntax, code).ok())
+            .unwrap_or_default();
+
+        let dir = path.parent().unwrap_or(&path);
+        let mut css_files = imported
+            .iter()
+            .filter(|src| src.starts_with("./") || src.starts_with("../"))
+            .map(|src| dir.join(src))
+            .filter(|css_file| css_file.is_file())
+            .collect::<Vec<_>>();
+
+        let root = self
+            .workspace
+            .read()
+            .expect("workspace lock was poisoned")
+            .root_of(&path)
+            .map(|root| root.to_path_buf());
+        for css_file in fs::find_css_files_near(&path, root.as_deref()) {
+            if !css_files.contains(&css_file) {
+                css_files.push(css_file);
+            }
+        }
+
+        let (css_index, _) = tokio::task::spawn_blocking(move || CssIndex::parse_files(css_files))
+            .await
+            .expect("parsing css files shouldn't panic");
+
+        Some(Arc::new(css_index))
+    }
+
+    /// Where the classes of the document at `uri` are looked up: the css files it imports,
+    /// along with those they `@import` as far as they're indexed, e.g. through the entry css
+    /// of a component, and the package it's in.
+    async fn definition_scope(&self, uri: &Url, css_index: &CssIndex) -> DefinitionScope {
+        let path = TextDocument::path(uri);
+        let imported = self
+            .documents
+            .get(uri)
+            .and_then(|doc| Some((doc.syntax(uri)?, doc.text.clone())))
+            .and_then(|(syntax, code)| find::imported_stylesheets(path.clone(), syntax, code).ok())
+            .unwrap_or_default();
+
+        let (options, order) = {
+            let config = self.config.read().await;
+            (config.definition, config.resolution.order)
+        };
+        DefinitionScope {
+            imported: css_index.import_closure(
+                imported
+                    .iter()
+                    .filter_map(|src| fs::resolve_import(&path, src))
+                    .collect(),
+            ),
+            package: fs::package_root(&path).map(|root| root.to_path_buf()),
+            roots: self
+                .built_css_index
+                .read()
+                .await
+                .as_ref()
+                .map(|built| built.roots.clone())
+                .unwrap_or_default(),
+            options,
+            order,
+            document: path,
+        }
+    }
+
+    /// Every rule defining the classes on the cursor, by its css file and the span of the first
+    /// selector of the class in it, in the order of [definitions_of_token] for each class.
+    async fn find_class_name_on_cursor_at(
+        &self,
+        uri: &Url,
+        position: tower_lsp::lsp_types::Position,
+        timer: &mut RequestTimer<'_>,
+    ) -> Result<Vec<(std::path::PathBuf, swc_common::Span)>> {
+        let tokens = self.class_names_on_cursor_at(uri, position).await?;
+        if tokens.is_empty() {
+            return Ok(vec![]);
+        }
+        timer.phase("parse");
+
+        let Some(css_index) = self.css_index_within_budget(uri).await else {
+            return Ok(vec![]);
+        };
+        timer.phase("scan");
+
+        let scope = self.definition_scope(uri, &css_index).await;
+        let mut rules = vec![];
+        let mut definitions = vec![];
+        let definitions_of_tokens = tokens
+            .iter()
+            .flat_map(|token| definitions_of_token(&css_index, token, &scope));
+        for (css_file, class) in definitions_of_tokens {
+            // e.g. `.btn, .btn:hover` share their rule
+            if !rules.contains(&(css_file, class.span)) {
+                rules.push((css_file, class.span));
+                definitions.push((css_file.to_path_buf(), class.selector_span));
+            }
+        }
+        timer.phase("lookup");
+
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!(
+                    "found {} class rules {:?}",
+                    definitions.len(),
+                    tokens.iter().map(|token| &token.value).collect::<Vec<_>>()
+                ),
+            )
+            .await;
+
+        Ok(definitions)
+    }
+
+    /// Finds the class names on the cursor, with their byte ranges in the document, see
+    /// [SrcCodeMeta::get_classnames_on_cursor], or the one in the `@apply` or `composes` class
+    /// list on the cursor in a css one, without its variant prefix.
+    async fn class_names_on_cursor_at(
+        &self,
+        uri: &Url,
+        position: tower_lsp::lsp_types::Position,
+    ) -> Result<Vec<ClassNameToken>> {
+        let encoding = self.client_caps().position_encoding;
+        let Some(doc) = self.documents.get(uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+        debug!(
+            "looking for the class name on cursor in version {}",
+            doc.version
+        );
+        let config = self.config.read().await;
+        let scopes = &config.scopes;
+        let path = TextDocument::path(uri);
+
+        if doc.is_css(uri) {
+            return Ok(text::byte_offset_of(&doc.text, position, encoding)
+                .and_then(|cursor| complete::apply_context_at(&doc.text, cursor))
+                .filter(|ctx| !ctx.typed.is_empty())
+                .map(|ctx| {
+                    let range = ctx.completed_range(&doc.text);
+                    ClassNameToken {
+                        value: doc.text[range.clone()].to_string(),
+                        range,
+                        css_module: None,
+                    }
+                })
+                .into_iter()
+                .collect());
+        }
+
+        let Some(syntax) = doc.syntax(uri) else {
+            return Ok(config
+                .fallback_patterns
+                .pattern_for(&path)
+                .and_then(|pattern| {
+                    let cursor = text::byte_offset_of(&doc.text, position, encoding)?;
+                    fallback::find_class_name_at(&doc.text, pattern, cursor)
+                })
+                .into_iter()
+                .collect());
+        };
+
+        let src = match SrcCodeMeta::build(path, syntax, doc.text, position, encoding) {
+            Ok(s) => s,
+            Err(err) => {
+                error!("{err:#}");
+                return Ok(vec![]);
+            }
+        };
+
+        match src.get_classnames_on_cursor(scopes) {
+            Ok(classnames_on_cursor) => Ok(classnames_on_cursor),
+            Err(err) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("{err:#}"))
+                    .await;
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// Finds the class name on the cursor in a source document, or the class selector on the
+    /// cursor in a css one.
+    async fn class_name_or_selector_at(
+        &self,
+        uri: &Url,
+        position: tower_lsp::lsp_types::Position,
+    ) -> Result<Option<String>> {
+        let encoding = self.client_caps().position_encoding;
+        let Some(doc) = self.documents.get(uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+
+        if doc.is_css(uri) {
+            return Ok(text::byte_offset_of(&doc.text, position, encoding)
+                .and_then(|cursor| collect::class_selector_at(&doc.text, cursor)));
+        }
+
+        // a variable holding several class names isn't one class
+        let mut tokens = self.class_names_on_cursor_at(uri, position).await?;
+        Ok(match tokens.len() {
+            1 => tokens.pop().map(|token| token.value),
+            _ => None,
+        })
+    }
+}
+
+impl Backend {
+    async fn configuration(&self) -> Result<EffectiveConfiguration> {
+        Ok(self.config.read().await.effective())
+    }
+
+    async fn version(&self) -> Result<protocol::VersionInfo> {
+        Ok(protocol::VersionInfo::current())
+    }
+
+    async fn cancel_progress(&self, params: WorkDoneProgressCancelParams) {
+        let indexing = self
+            .indexing_progress
+            .lock()
+            .expect("indexing progress lock was poisoned");
+        match indexing.as_ref() {
+            Some((token, cancelled)) if *token == params.token => {
+                cancelled.store(true, Ordering::Relaxed)
+            }
+            _ => debug!("no progress to cancel for {:?}", params.token),
+        }
+    }
+
+    async fn document_status(&self, params: DocumentStatusParams) -> Result<DocumentStatus> {
+        let uri = params.text_document.uri;
+        let mut timer = self
+            .timer(protocol::DOCUMENT_STATUS_METHOD, uri.path().to_string())
+            .await;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+
+        let Some(syntax) = doc.syntax(&uri) else {
+            return Ok(DocumentStatus::default());
+        };
+
+        let path = TextDocument::path(&uri);
+        let scopes = self.config.read().await.scopes.clone();
+
+        // as with diagnostics, a document that doesn't parse, e.g. mid-edit, has no classes
+        let tokens = match find::collect_class_names(path, syntax, doc.text, &scopes) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                debug!("{err:#}");
+                return Ok(DocumentStatus::default());
+            }
+        };
+        timer.phase("parse");
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return Ok(DocumentStatus::default());
+        };
+        timer.phase("scan");
+
+        let token_filter = self.config.read().await.token_filter.clone();
+        let resolved = analysis::resolve_tokens(tokens, &css_index, &token_filter);
+        timer.phase("lookup");
+
+        Ok(analysis::document_status(&resolved))
+    }
+
+    async fn resolve_range(&self, params: ResolveRangeParams) -> Result<Vec<ResolvedClassToken>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+        let mut timer = self
+            .timer(
+                protocol::RESOLVE_RANGE_METHOD,
+                format!("{}:{:?}", uri.path(), params.range),
+            )
+            .await;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+
+        let Some(syntax) = doc.syntax(&uri) else {
+            return Ok(vec![]);
+        };
+        let code = doc.text;
+
+        let (Some(start), Some(end)) = (
+            text::byte_offset_of(&code, params.range.start, encoding),
+            text::byte_offset_of(&code, params.range.end, encoding),
+        ) else {
+            return Err(Error::invalid_params("range is out of the document"));
+        };
+
+        let path = TextDocument::path(&uri);
+        let scopes = self.config.read().await.scopes.clone();
+
+        let tokens = match find::collect_class_names(path, syntax, code.clone(), &scopes) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("{err:#}"))
+                    .await;
+                return Err(Error::internal_error());
+            }
+        };
+        timer.phase("parse");
+
+        let css_index = self.css_index(Some(&uri)).await.unwrap_or_default();
+        timer.phase("scan");
+        let mut locator = analysis::DefinitionLocator::new(encoding);
+        let token_filter = self.config.read().await.token_filter.clone();
+
+        let tokens = tokens
+            .into_iter()
+            .filter(|token| token.range.start < end && start < token.range.end)
+            .collect();
+
+        let resolved = analysis::resolve_tokens(tokens, &css_index, &token_filter)
+            .into_iter()
+            .map(|r| ResolvedClassToken {
+                range: text::range_of(&code, r.token.range, encoding),
+                definitions: css_index
+                    .definitions_of(&r.token.value)
+                    .filter_map(|(css_file, class)| locator.locate(css_file, class.span))
+                    .collect(),
+                text: r.token.value,
+            })
+            .collect();
+        timer.phase("lookup");
+
+        Ok(resolved)
+    }
+
+    /// Finds the uses of the class `value` in every source file of the workspace, reading the
+    /// open ones from their unsaved buffers.
+    async fn class_name_references(&self, value: &str, document: &Url) -> Vec<Location> {
+        let encoding = self.client_caps().position_encoding;
+        let Some(roots) = self.workspace_roots(Some(document)).await else {
+            return vec![];
+        };
+
+        let mut source_files = vec![];
+        if let Err(err) = fs::find_all_source_files_in_dirs(&roots, &mut source_files) {
+            self.client
+                .log_message(MessageType::ERROR, format!("{err:#}"))
+                .await
+        };
+
+        let buffers = source_files
+            .into_iter()
+            .filter_map(|file| {
+                let uri = Url::from_file_path(&file).ok()?;
+                let (syntax, code) = match self.documents.get(&uri) {
+                    Some(doc) => (doc.syntax(&uri)?, Some(doc.text.clone())),
+                    None => (find::syntax_of_file(&file).ok()?, None),
+                };
+                Some((file, uri, syntax, code))
+            })
+            .collect::<Vec<_>>();
+
+        let scopes = self.config.read().await.scopes.clone();
+        let value = value.to_string();
+
+        let search = tokio::task::spawn_blocking(move || {
+            let mut locations = vec![];
+
+            for (file, uri, syntax, code) in buffers {
+                let Some(code) = code.or_else(|| std::fs::read_to_string(&file).ok()) else {
+                    continue;
+                };
+
+                match analysis::class_name_references(file, syntax, code.clone(), &scopes, &value) {
+                    Ok(ranges) => locations.extend(ranges.into_iter().map(|range| {
+                        Location::new(uri.clone(), text::range_of(&code, range, encoding))
+                    })),
+                    Err(err) => debug!("{err:#}"),
+                }
+            }
+
+            locations
+        });
+
+        search.await.unwrap_or_else(|err| {
+            error!("references search failed: {err}");
+            vec![]
+        })
+    }
+
+    /// Finds the class selectors of `value` in every indexed css file, reading the open ones
+    /// from their unsaved buffers.
+    async fn class_selector_locations(&self, value: &str, document: &Url) -> Vec<Location> {
+        let encoding = self.client_caps().position_encoding;
+        let Some(css_index) = self.css_index(Some(document)).await else {
+            return vec![];
+        };
+
+        css_index
+            .css_files()
+            .filter_map(|css_file| {
+                let uri = Url::from_file_path(css_file).ok()?;
+                let code = match self.documents.get(&uri) {
+                    Some(doc) => doc.text.clone(),
+                    None => std::fs::read_to_string(css_file).ok()?,
+                };
+
+                let locations = collect::class_selector_ranges(&code, value)
+                    .into_iter()
+                    .map(|range| Location::new(uri.clone(), text::range_of(&code, range, encoding)))
+                    .collect::<Vec<_>>();
+
+                Some(locations)
+            })
+            .flatten()
+            .collect()
+    }
+
+    async fn usage_report(&self) -> Result<UsageReport> {
+        let Some(roots) = self.workspace_roots(None).await else {
+            return Err(Error::invalid_params(NO_ROOTS_MESSAGE));
+        };
+
+        let Some(css_index) = self.css_index(None).await else {
+            return Ok(UsageReport::default());
+        };
+
+        let mut source_files = vec![];
+        if let Err(err) = fs::find_all_source_files_in_dirs(&roots, &mut source_files) {
+            self.client
+                .log_message(MessageType::ERROR, format!("{err:#}"))
+                .await
+        };
+
+        let scopes = self.config.read().await.scopes.clone();
+
+        // Dropped along with this future when the request is cancelled, stopping the scan.
+        let cancel = CancelOnDrop::default();
+        let cancelled = cancel.0.clone();
+
+        // cancelled as a request rather than from the progress shown
+        let total = source_files.len();
+        let mut progress = ProgressGuard {
+            client: self.client.clone(),
+            token: self
+                .begin_progress("Usage report", format!("0/{total}"), false)
+                .await,
+        };
+
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let scan = tokio::task::spawn_blocking(move || {
+            let progress = |done: usize, total: usize| {
+                if done == total || done.is_multiple_of((total / 10).max(1)) {
+                    let _ = progress_tx.send(done);
+                }
+            };
+            usage::scan_usages(&source_files, &scopes, &progress, &cancelled)
+        });
+
+        while let Some(done) = progress_rx.recv().await {
+            if let Some(token) = &progress.token {
+                let report = WorkDoneProgressReport {
+                    cancellable: Some(false),
+                    message: Some(format!("{done}/{total}")),
+                    percentage: Some((done * 100 / total.max(1)) as u32),
+                };
+                self.send_progress(token, WorkDoneProgress::Report(report))
+                    .await;
+            }
+        }
+
+        let (counts, errors) = scan.await.map_err(|err| {
+            error!("usage scan failed: {err}");
+            Error::internal_error()
+        })?;
+        drop(cancel);
+
+        if let Some(token) = progress.token.take() {
+            let end = WorkDoneProgressEnd {
+                message: Some(format!("scanned {total} source files")),
+            };
+            self.send_progress(&token, WorkDoneProgress::End(end)).await;
+        }
+
+        for err in errors {
+            self.client
+                .log_message(MessageType::ERROR, format!("{err:#}"))
+                .await
+        }
+
+        let workspace = self.workspace.read().expect("workspace lock was poisoned");
+        Ok(usage::usage_report(&counts, &css_index, &workspace))
+    }
+
+    /// Outlines the scoped strings of a source document, grouped by what their scope starts at.
+    async fn class_string_symbols(
+        &self,
+        uri: &Url,
+        doc: &TextDocument,
+    ) -> Option<DocumentSymbolResponse> {
+        let encoding = self.client_caps().position_encoding;
+        let syntax = doc.syntax(uri)?;
+        let scopes = self.config.read().await.scopes.clone();
+        let code = &doc.text;
+
+        let groups =
+            match find::class_string_groups(TextDocument::path(uri), syntax, code.clone(), &scopes)
+            {
+                Ok(groups) => groups,
+                Err(err) => {
+                    debug!("{err:#}");
+                    return None;
+                }
+            };
+
+        #[allow(deprecated)]
+        let symbols = groups
+            .into_iter()
+            .map(|group| DocumentSymbol {
+                name: group.owner,
+                detail: None,
+                kind: SymbolKind::OBJECT,
+                tags: None,
+                deprecated: None,
+                range: text::range_of(code, group.range.clone(), encoding),
+                selection_range: text::range_of(code, group.range, encoding),
+                children: Some(
+                    group
+                        .strings
+                        .into_iter()
+                        .map(|string| DocumentSymbol {
+                            name: code[string.clone()].to_string(),
+                            detail: None,
+                            kind: SymbolKind::STRING,
+                            tags: None,
+                            deprecated: None,
+                            range: text::range_of(code, string.clone(), encoding),
+                            selection_range: text::range_of(code, string, encoding),
+                            children: None,
+                        })
+                        .collect(),
+                ),
+            })
+            .collect();
+
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    async fn index_stats(&self) -> IndexStats {
+        let (css_files, class_names, css_parse_problems) = match self.css_index(None).await {
+            Some(css_index) => (
+                css_index.css_files().count(),
+                css_index.class_names().count(),
+                css_index.parse_problems().count(),
+            ),
+            None => (0, 0, 0),
+        };
+
+        IndexStats {
+            css_files,
+            class_names,
+            css_parse_problems,
+            requests: self.request_stats.summary(),
+        }
+    }
+}
+
+/// The client command showing a list of locations, run from the code lenses of the css rules.
+const SHOW_REFERENCES_COMMAND: &str = "editor.action.showReferences";
+
+/// How many css files are offered to create a missing class rule in, nearest first.
+const MAX_CREATE_RULE_TARGETS: usize = 5;
+
+/// Awaits a request to the client on a task of its own, so that it's answered even once the
+/// request that sent it is cancelled: tower-lsp panics on responses nobody awaits anymore.
+async fn detached<T: Send + 'static>(request: impl Future<Output = T> + Send + 'static) -> T {
+    tokio::spawn(request)
+        .await
+        .expect("requests to the client shouldn't panic")
+}
+
+fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+/// Where the classes of a document are looked up, see [Backend::definition_scope].
+struct DefinitionScope {
+    imported: HashSet<std::path::PathBuf>,
+    /// The folder of the nearest `package.json` above the document.
+    package: Option<std::path::PathBuf>,
+    /// The workspace folders the css files were found in, for their [index::PathPriority].
+    roots: Vec<std::path::PathBuf>,
+    options: config::DefinitionOptions,
+    order: config::ResolutionOrder,
+    document: std::path::PathBuf,
+}
+
+/// Every rule defining the class of the `token`, only in the stylesheet of the css module it's
+/// accessed on if any, where a camelCase key falls back to the kebab-case class, e.g.
+/// `styles.primaryDark` to `.primary-dark`. Otherwise, those in the css files the document
+/// imports come alone, unless there are none and the others aren't left out as
+/// `importedOnly`. Of the others, those in its package come first, or alone as
+/// `packageScoped`. Either way, those closest to the document come first among those ranking
+/// alike in the index, e.g. not before the source for a minified bundle or build output next to
+/// it, unless ordered alphabetically.
+fn definitions_of_token<'a>(
+    css_index: &'a CssIndex,
+    token: &'a ClassNameToken,
+    scope: &DefinitionScope,
+) -> Vec<(&'a std::path::Path, &'a ClassName)> {
+    let Some(css_module) = &token.css_module else {
+        let mut definitions = css_index.definitions_of(&token.value).collect::<Vec<_>>();
+        if scope.order == config::ResolutionOrder::Proximity {
+            let tier = |css_file: &std::path::Path| {
+                let root = scope.roots.iter().find(|root| css_file.starts_with(root));
+                (
+                    css_index.rank_of(css_file).unwrap_or_default(),
+                    index::PathPriority::of(
+                        root.map_or(std::path::Path::new(""), |root| root),
+                        css_file,
+                    ),
+                )
+            };
+            let mut tiered = definitions
+                .into_iter()
+                .map(|(css_file, class)| (tier(css_file), css_file, class))
+                .collect::<Vec<_>>();
+            // stable, keeping the rules of a css file in order
+            tiered.sort_by(|(a_tier, a, _), (b_tier, b, _)| {
+                a_tier
+                    .cmp(b_tier)
+                    .then_with(|| index::compare_proximity(&scope.document, a, b))
+            });
+            definitions = tiered
+                .into_iter()
+                .map(|(_, css_file, class)| (css_file, class))
+                .collect();
+        }
+
+        // documents importing no stylesheet, e.g. templates, rely on global ones
+        if !scope.imported.is_empty() {
+            let (in_imported, others): (Vec<_>, Vec<_>) = definitions
+                .into_iter()
+                .partition(|(css_file, _)| scope.imported.contains(*css_file));
+            // what the document imports counts wherever it is, e.g. in another package
+            if !in_imported.is_empty() || scope.options.imported_only {
+                return in_imported;
+            }
+            definitions = others;
+        }
+
+        let Some(package) = &scope.package else {
+            return definitions;
+        };
+        let (mut in_package, others): (Vec<_>, Vec<_>) = definitions
+            .into_iter()
+            .partition(|(css_file, _)| css_file.starts_with(package));
+        if !scope.options.package_scoped {
+            in_package.extend(others);
+        }
+        return in_package;
+    };
+
+    let in_module = |value: &str| {
+        css_index
+            .class_names()
+            .filter(|(css_file, class)| *css_file == css_module.as_path() && &*class.value == value)
+            .collect::<Vec<_>>()
+    };
+    let definitions = in_module(&token.value);
+    if !definitions.is_empty() {
+        return definitions;
+    }
+
+    let mut kebab_case = String::new();
+    for c in token.value.chars() {
+        if c.is_ascii_uppercase() {
+            kebab_case.push('-');
+        }
+        kebab_case.push(c.to_ascii_lowercase());
+    }
+    in_module(&kebab_case)
+}
+
+/// A rule composed by the rule of a hovered class, through `composes` in a css module.
+enum Composition {
+    Rule {
+        class: String,
+        css_file: std::path::PathBuf,
+        /// Starting at 1.
+        line: u32,
+        rule: String,
+    },
+    Unresolved(String),
+    /// The classes composing each other back to the first one, e.g. `a`, `b` and `a`.
+    Cycle(Vec<String>),
+}
+
+/// Adds the rules the `rule` composes, in the stylesheet of `composing` or the one they're
+/// imported `from`, to `composed`, depth first in the order they're composed, each once. The
+/// classes being composed, by css file, are pushed on `composing` while their own rules are, to
+/// report cycles rather than follow them.
+fn compose_rules(
+    css_index: &CssIndex,
+    rule: &str,
+    dialect: Dialect,
+    composing: &mut Vec<(std::path::PathBuf, String)>,
+    composed: &mut Vec<Composition>,
+) {
+    let Some((composer_file, _)) = composing.last().cloned() else {
+        return;
+    };
+
+    for composes in crate::css::composes(rule, dialect) {
+        let stylesheet = match composes.from.as_deref() {
+            None => Some(Some(composer_file.clone())),
+            Some("global") => Some(None),
+            Some(from) => fs::resolve_import(&composer_file, from).map(Some),
+        };
+
+        for name in composes.classes {
+            let definition = stylesheet.as_ref().and_then(|stylesheet| {
+                css_index.class_names().find(|(css_file, class)| {
+                    class.is_standalone
+                        && &*class.value == name
+                        && stylesheet
+                            .as_deref()
+                            .is_none_or(|stylesheet| *css_file == stylesheet)
+                })
+            });
+            let Some((css_file, class)) = definition else {
+                composed.push(Composition::Unresolved(name));
+                continue;
+            };
+
+            let key = (css_file.to_path_buf(), name);
+            if let Some(at) = composing.iter().position(|composer| *composer == key) {
+                let mut classes = composing[at..]
+                    .iter()
+                    .map(|(_, class)| class.clone())
+                    .collect::<Vec<_>>();
+                classes.push(key.1);
+                composed.push(Composition::Cycle(classes));
+                continue;
+            }
+
+            let is_shown = composed.iter().any(|shown| {
+                matches!(shown, Composition::Rule { class, css_file, .. }
+                    if css_file == &key.0 && class == &key.1)
+            });
+            if is_shown {
+                continue;
+            }
+
+            let read = std::fs::read_to_string(css_file).ok().and_then(|source| {
+                let rule =
+                    extract_rule_text(&class.definition, &SourceText::Retained(&source)).ok()?;
+                let line = source[..class.definition.rule.start].matches('\n').count() as u32 + 1;
+                Some((rule, line))
+            });
+            let Some((rule, line)) = read else {
+                composed.push(Composition::Unresolved(key.1));
+                continue;
+            };
+
+            let dialect = Dialect::of(css_file).unwrap_or(Dialect::Css);
+            composed.push(Composition::Rule {
+                class: key.1.clone(),
+                css_file: key.0.clone(),
+                line,
+                rule: rule.clone(),
+            });
+            composing.push(key);
+            compose_rules(css_index, &rule, dialect, composing, composed);
+            composing.pop();
+        }
+    }
+}
+
+/// The declarations of the rule defining the class `name` that `@apply` pulls in, preferably
+/// the one it makes up the whole selector of, unless it isn't indexed or its css file changed
+/// since. Variant prefixes are left out, e.g. `hover:` in `hover:bg-blue-600`, since the index
+/// has the bare class names.
+fn applied_declarations(css_index: &CssIndex, name: &str) -> Option<Vec<String>> {
+    let name = name.rsplit(':').next().unwrap_or(name);
+    let (css_file, class) = css_index
+        .definitions_of(name)
+        .find(|(_, class)| class.is_standalone && class.at_rules.is_empty())
+        .or_else(|| css_index.find_class_name_by_value(name))?;
+    let rule = extract_rule_text(&class.definition, &SourceText::File(css_file)).ok()?;
+    let dialect = Dialect::of(css_file).unwrap_or(Dialect::Css);
+    Some(crate::css::declarations(&rule, dialect))
+}
+
+/// Where a request points to in a document, for the logs.
+fn position_context(uri: &Url, position: Position) -> String {
+    format!("{}:{}:{}", uri.path(), position.line, position.character)
+}
+
+#[derive(Default)]
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Ends the progress reported with `token`, if it's dropped before, e.g. along with a request
+/// that's cancelled, so that the client doesn't show it forever.
+struct ProgressGuard {
+    client: Client,
+    token: Option<ProgressToken>,
+}
+
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        let Some(token) = self.token.take() else {
+            return;
+        };
+
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let end = WorkDoneProgressEnd {
+                message: Some("cancelled".to_string()),
+            };
+            let params = ProgressParams {
+                token,
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(end)),
+            };
+            client
+                .send_notification::<notification::Progress>(params)
+                .await;
+        });
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        {
+            let mut config = self.config.write().await;
+            let mut errors = vec![];
+
+            #[allow(deprecated)]
+            let initialize_root = params
+                .root_uri
+                .as_ref()
+                .and_then(|uri| uri.to_file_path().ok())
+                .or_else(|| params.root_path.as_ref().map(std::path::PathBuf::from));
+            let root = params
+                .workspace_folders
+                .as_ref()
+                .and_then(|folders| folders.first())
+                .and_then(|folder| folder.uri.to_file_path().ok())
+                .or_else(|| initialize_root.clone());
+            if let Some(initialize_root) = initialize_root {
+                let _ = self.initialize_root.set(initialize_root);
+            }
+            if let Some(path) = root.as_deref().and_then(config_file::find_in) {
+                info!("reading settings from {}", path.display());
+                errors.extend(config.update_from_file(&path));
+                // the environment still overrides the file, its errors were logged on start
+                config.update_from_env();
+                self.config_sources().file_modified = modified_time(&path);
+            }
+            {
+                let mut sources = self.config_sources();
+                sources.root = root;
+                sources.initialization_options = params.initialization_options.clone();
+            }
+
+            if let Some(options) = &params.initialization_options {
+                errors.extend(
+                    config
+                        .update_from_initialization_options(options)
+                        .into_iter()
+                        .map(|err| err.context("initializationOptions")),
+                );
+            }
+
+            // shown once initialized, since the client can't take messages before
+            *self
+                .initialization_errors
+                .lock()
+                .expect("initialization errors lock was poisoned") = errors;
+        }
+
+        let caps = ClientCaps::new(&params.capabilities);
+        debug!(?caps, "client capabilities");
+        let _ = self.client_caps.set(caps.clone());
+
+        let serves_inlay_hints = caps.inlay_hints && self.config.read().await.inlay_hints.enabled;
+        self.serves_inlay_hints
+            .store(serves_inlay_hints, Ordering::Relaxed);
+
+        Ok(InitializeResult {
+            server_info: Some(ServerInfo {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
+            capabilities: ServerCapabilities {
+                position_encoding: Some(caps.position_encoding.kind()),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                            include_text: Some(false),
+                        })),
+                        ..Default::default()
+                    },
+                )),
+                definition_provider: Some(OneOf::Left(true)),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                references_provider: Some(OneOf::Left(true)),
+                inlay_hint_provider: serves_inlay_hints.then_some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                semantic_tokens_provider: caps.semantic_tokens.then(|| {
+                    SemanticTokensOptions {
+                        legend: SemanticTokensLegend {
+                            token_types: analysis::SEMANTIC_TOKEN_TYPES.to_vec(),
+                            token_modifiers: vec![],
+                        },
+                        full: Some(SemanticTokensFullOptions::Bool(true)),
+                        ..Default::default()
+                    }
+                    .into()
+                }),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::SOURCE,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                diagnostic_provider: caps.pull_diagnostics.then(|| {
+                    DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                        identifier: Some("cnls".to_string()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: false,
+                        work_done_progress_options: Default::default(),
+                    })
+                }),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                completion_provider: Some(CompletionOptions {
+                    resolve_provider: Some(true),
+                    ..Default::default()
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        protocol::USAGE_REPORT_COMMAND.to_string(),
+                        protocol::SHOW_INDEX_STATS_COMMAND.to_string(),
+                        protocol::REINDEX_COMMAND.to_string(),
+                    ],
+                    ..Default::default()
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
+                ..ServerCapabilities::default()
+            },
+        })
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        self.pull_diagnostics(params).await
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        let version = protocol::VersionInfo::current();
+        let scopes = self
+            .config
+            .read()
+            .await
+            .scopes
+            .iter()
+            .map(|scope| scope.to_string())
+            .collect::<Vec<_>>();
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!(
+                    "cnls {} ({}) initialized, with scopes: {}",
+                    version.version,
+                    version.git_sha.as_deref().unwrap_or("unknown commit"),
+                    scopes.join("; ")
+                ),
+            )
+            .await;
+
+        let errors = std::mem::take(
+            &mut *self
+                .initialization_errors
+                .lock()
+                .expect("initialization errors lock was poisoned"),
+        );
+        for err in errors {
+            self.client
+                .show_message(
+                    MessageType::ERROR,
+                    format!("invalid cnls settings: {err:#}"),
+                )
+                .await;
+        }
+
+        self.register_css_file_watchers().await;
+        self.register_config_file_watchers().await;
+
+        // for clients that don't push their settings
+        self.pull_and_apply_configuration().await;
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri;
+        debug!("current source code: {}", uri.path());
+
+        let doc = TextDocument {
+            uri: uri.clone(),
+            text: params.text_document.text,
+            language_id: Some(params.text_document.language_id),
+            version: params.text_document.version,
+        };
+
+        let is_handled = doc.is_css(&uri)
+            || doc.syntax(&uri).is_some()
+            || self
+                .config
+                .read()
+                .await
+                .fallback_patterns
+                .pattern_for(&TextDocument::path(&uri))
+                .is_some();
+
+        if !is_handled {
+            debug!(
+                "ignoring {}, cnls can't handle its languageId {:?}",
+                uri.path(),
+                doc.language_id
+            );
+        }
+
+        if self.documents.insert(&uri, doc).is_some() {
+            debug!("{} was already open, replacing its text", uri.path());
+        }
+        self.diagnose_document(uri).await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        if self.documents.remove(&uri).is_none() {
+            debug!("{} was closed without being open", uri.path());
+            return;
+        }
+        self.client.publish_diagnostics(uri, vec![], None).await;
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+
+        debug!("current source code: {}", uri.path());
+
+        if params.content_changes.is_empty() {
+            debug!("ignoring a change of {} without content", uri.path());
+            return;
+        }
+
+        let version = params.text_document.version;
+        match self.documents.get_mut(&uri) {
+            Some(mut doc) => {
+                // a change delayed behind a newer one would roll the text back
+                if version <= doc.version {
+                    debug!(
+                        "dropping stale change of {} at version {version}, which is at {}",
+                        uri.path(),
+                        doc.version
+                    );
+                    return;
+                }
+
+                doc.uri = uri.clone();
+                for change in params.content_changes {
+                    text::apply_change(&mut doc.text, change, encoding);
+                }
+                doc.version = version;
+            }
+            None => {
+                // only the changes from the last one giving the whole text on can be applied
+                let Some(whole) = params
+                    .content_changes
+                    .iter()
+                    .rposition(|change| change.range.is_none())
+                else {
+                    debug!(
+                        "ignoring ranged changes of {}, which was never open",
+                        uri.path()
+                    );
+                    return;
+                };
+
+                debug!("{} was changed without being open", uri.path());
+                let mut code = String::new();
+                for change in params.content_changes.into_iter().skip(whole) {
+                    text::apply_change(&mut code, change, encoding);
+                }
+                self.documents.insert(
+                    &uri,
+                    TextDocument {
+                        uri: uri.clone(),
+                        text: code,
+                        language_id: None,
+                        version,
+                    },
+                );
+            }
+        }
+
+        // duplicate class definitions are found in the css files as saved
+        let is_css = self.documents.get(&uri).is_some_and(|doc| doc.is_css(&uri));
+        if !is_css && !self.config.read().await.diagnostics.on_save_only {
+            self.diagnose_document(uri).await;
+        }
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri;
+
+        let is_css = self
+            .documents
+            .get(&uri)
+            .map(|doc| doc.is_css(&uri))
+            .unwrap_or_else(|| Dialect::of(&TextDocument::path(&uri)).is_some());
+
+        if is_css {
+            // re-parsed right away, for hover in other documents to show what was saved
+            self.update_css_index(vec![FileEvent::new(uri, FileChangeType::CHANGED)])
+                .await;
+        } else {
+            self.source_usages.invalidate();
+            self.diagnose_document(uri).await;
+        }
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let paths = |folders: Vec<WorkspaceFolder>| {
+            folders
+                .iter()
+                .map(|folder| TextDocument::path(&folder.uri))
+                .collect::<Vec<_>>()
+        };
+        let added = paths(params.event.added);
+        let removed = paths(params.event.removed);
+        debug!(?added, ?removed, "workspace folders changed");
+
+        self.source_usages.invalidate();
+
+        let mut changes = {
+            let mut built = self.built_css_index.write().await;
+            let Some(built) = built.as_mut() else {
+                drop(built);
+                // for an index being built to be built anew, with the folders as they are now
+                self.css_index.invalidate();
+                self.republish_diagnostics().await;
+                return;
+            };
+
+            built.roots.retain(|root| !removed.contains(root));
+            let removed_css_files = built
+                .css_index
+                .css_files()
+                .filter(|css_file| removed.iter().any(|root| css_file.starts_with(root)))
+                .filter(|css_file| !built.roots.iter().any(|root| css_file.starts_with(root)))
+                .filter_map(|css_file| Url::from_file_path(css_file).ok())
+                .map(|uri| FileEvent::new(uri, FileChangeType::DELETED))
+                .collect::<Vec<_>>();
+            built.roots.extend(added.iter().cloned());
+
+            removed_css_files
+        };
+
+        let (css_files, skipped, _) = self.css_files_to_index(&added).await;
+        self.report_skipped_css_files(skipped).await;
+        changes.extend(
+            css_files
+                .into_iter()
+                .filter_map(|(css_file, _)| Url::from_file_path(css_file).ok())
+                .map(|uri| FileEvent::new(uri, FileChangeType::CREATED)),
+        );
+        self.update_css_index(changes).await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let (config_file_changes, changes): (Vec<_>, Vec<_>) = params
+            .changes
+            .into_iter()
+            .partition(|event| self.is_config_file(&event.uri));
+        if !config_file_changes.is_empty() {
+            self.reload_config_file().await;
+        }
+
+        let changes_source_files = changes
+            .iter()
+            .any(|event| Dialect::of(&TextDocument::path(&event.uri)).is_none());
+        if changes_source_files {
+            self.source_usages.invalidate();
+        }
+
+        self.update_css_index(changes).await;
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        if let Some(section) = config::settings_section(&params.settings) {
+            self.apply_configuration(section).await;
+            return;
+        }
+
+        if !self.pull_and_apply_configuration().await {
+            debug!("received empty settings and could not pull the cnls configuration");
+        }
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document_position_params.text_document.uri;
+        let current_position = params.text_document_position_params.position;
+        let mut timer = self
+            .timer(
+                "textDocument/hover",
+                position_context(&uri, current_position),
+            )
+            .await;
+
+        self.check_config_file().await;
+        let tokens = self
+            .class_names_on_cursor_at(&uri, current_position)
+            .await?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        timer.phase("parse");
+
+        self.check_css_files().await;
+        let Some(css_index) = self.css_index_within_budget(&uri).await else {
+            return Ok(None);
+        };
+        timer.phase("scan");
+
+        let scope = self.definition_scope(&uri, &css_index).await;
+        let mut rules: Vec<(&std::path::Path, &ClassName)> = vec![];
+        let definitions_of_tokens = tokens
+            .iter()
+            .flat_map(|token| definitions_of_token(&css_index, token, &scope));
+        for (css_file, class) in definitions_of_tokens {
+            // e.g. `.btn, .btn:hover` share their rule
+            let is_shown = rules.iter().any(|(shown_file, shown)| {
+                *shown_file == css_file && shown.definition.rule == class.definition.rule
+            });
+            if !is_shown {
+                rules.push((css_file, class));
+            }
+        }
+        timer.phase("lookup");
+
+        let max_rules = self.config.read().await.hover.max_rules;
+        let mut doc = HoverDoc::default();
+        for (css_file, class) in rules.iter().take(max_rules) {
+            // read once for the rule, its line and its comment
+            let read = std::fs::read_to_string(css_file)
+                .map_err(RuleTextError::Io)
+                .and_then(|text| {
+                    let rule = extract_rule_text(&class.definition, &SourceText::Retained(&text))?;
+                    Ok((rule, text))
+                });
+            let (rule, source) = match read {
+                Ok(read) => read,
+                Err(err) => {
+                    self.client
+                        .log_message(MessageType::ERROR, format!("{err:#}",))
+                        .await;
+                    continue;
+                }
+            };
+
+            let rule_start = class.definition.rule.start;
+            let header = format!(
+                "{}:{}",
+                self.display_path(css_file),
+                text::position_of(&source, rule_start, encoding).line + 1
+            );
+            doc = doc.header(header);
+            if let Some(comment) = crate::css::preceding_comment(&source, rule_start) {
+                doc = doc.note(comment);
+            }
+            let dialect = Dialect::of(css_file).unwrap_or(Dialect::Css);
+            let source_rule = match dialect {
+                Dialect::Css => crate::css::pretty_print(&rule),
+                // as written, pretty printing would make it css
+                _ => crate::css::dedent(&rule),
+            };
+            // nested rules are shown in the context of their ancestors
+            let source_rule = match dialect {
+                Dialect::Sass => crate::css::indent_in_blocks(&source_rule, &class.ancestors),
+                _ => crate::css::wrap_in_blocks(&source_rule, &class.ancestors),
+            };
+            doc = doc.code(dialect.language_id(), source_rule);
+
+            if !class.siblings.is_empty() {
+                let siblings = class
+                    .siblings
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>();
+                doc = doc.note(format!("also requires: {}", siblings.join(", ")));
+            }
+
+            let variables = crate::css::referenced_custom_properties(&rule)
+                .into_iter()
+                .map(|name| match css_index.find_custom_property(name) {
+                    Some((_, property)) => format!("- `{name}: {}`", &*property.value),
+                    None => format!("- `{name}` is unresolved"),
+                })
+                .collect::<Vec<_>>();
+            if !variables.is_empty() {
+                doc = doc.note(format!("Variables:\n{}", variables.join("\n")));
+            }
+
+            let mut expansion = vec![];
+            let mut unresolved = vec![];
+            for name in crate::css::applied_class_names(&rule, dialect) {
+                match applied_declarations(&css_index, &name) {
+                    Some(declarations) => expansion.extend(declarations),
+                    None => unresolved.push(format!("`{name}`")),
+                }
+            }
+            if !expansion.is_empty() {
+                let declarations = expansion
+                    .iter()
+                    .map(|declaration| format!("{declaration};"))
+                    .collect::<Vec<_>>();
+                doc = doc
+                    .note("Expands to:")
+                    .code(dialect.language_id(), declarations.join("\n"));
+            }
+            if !unresolved.is_empty() {
+                doc = doc.note(format!("Unresolved: {}", unresolved.join(", ")));
+            }
+
+            let mut composed = vec![];
+            let mut composing = vec![(css_file.to_path_buf(), class.value.to_string())];
+            compose_rules(&css_index, &rule, dialect, &mut composing, &mut composed);
+            for composition in composed {
+                match composition {
+                    Composition::Rule {
+                        class,
+                        css_file,
+                        line,
+                        rule,
+                    } => {
+                        let dialect = Dialect::of(&css_file).unwrap_or(Dialect::Css);
+                        let rule = match dialect {
+                            Dialect::Css => crate::css::pretty_print(&rule),
+                            _ => crate::css::dedent(&rule),
+                        };
+                        doc = doc
+                            .note(format!(
+                                "Composes `{class}`, from {}:{line}",
+                                self.display_path(&css_file)
+                            ))
+                            .code(dialect.language_id(), rule);
+                    }
+                    Composition::Unresolved(class) => {
+                        doc = doc.note(format!("Composes `{class}`, which is unresolved"));
+                    }
+                    Composition::Cycle(classes) => {
+                        doc = doc.note(format!(
+                            "Composes `{}` in a cycle, left out",
+                            classes.join("` → `")
+                        ));
+                    }
+                }
+            }
+        }
+        timer.phase("read");
+
+        if doc.is_empty() {
+            return Ok(None);
+        }
+
+        match rules.len().saturating_sub(max_rules) {
+            0 => {}
+            1 => doc = doc.note("and 1 more rule"),
+            hidden => doc = doc.note(format!("and {hidden} more rules")),
+        }
+
+        let markup_kind = self.client_caps().hover_markup_kind.clone();
+        let contents = HoverContents::Markup(doc.render(markup_kind));
+
+        // a variable on the cursor resolves to class names written elsewhere
+        let range = self.documents.get(&uri).and_then(|doc| {
+            let cursor = text::byte_offset_of(&doc.text, current_position, encoding)?;
+            let token = tokens.iter().find(|token| token.range.contains(&cursor))?;
+            Some(text::range_of(&doc.text, token.range.clone(), encoding))
+        });
+
+        Ok(Some(Hover { contents, range }))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document_position_params.text_document.uri;
+        let current_position = params.text_document_position_params.position;
+        let mut timer = self
+            .timer(
+                "textDocument/definition",
+                position_context(&uri, current_position),
+            )
+            .await;
+
+        let definitions = self
+            .find_class_name_on_cursor_at(&uri, current_position, &mut timer)
+            .await?;
+
+        let mut locator = analysis::DefinitionLocator::new(encoding);
+        let mut locations: Vec<Location> = vec![];
+        for (css_file, span) in definitions {
+            match locator.locate(&css_file, span) {
+                Some(location) if locations.contains(&location) => {}
+                Some(location) => locations.push(location),
+                None => {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("failed to locate a rule in {}", css_file.display()),
+                        )
+                        .await
+                }
+            }
+        }
+        timer.phase("read");
+
+        match <[Location; 1]>::try_from(locations) {
+            Ok([location]) => Ok(Some(GotoDefinitionResponse::Scalar(location))),
+            Err(locations) if locations.is_empty() => Ok(None),
+            Err(locations) => Ok(Some(GotoDefinitionResponse::Array(locations))),
+        }
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let mut timer = self
+            .timer("textDocument/references", position_context(&uri, position))
+            .await;
+
+        let Some(class_name) = self.class_name_or_selector_at(&uri, position).await? else {
+            return Ok(None);
+        };
+        timer.phase("parse");
+
+        let mut locations = self.class_name_references(&class_name, &uri).await;
+        timer.phase("scan");
+
+        if params.context.include_declaration {
+            if let Some(css_index) = self.css_index(Some(&uri)).await {
+                timer.phase("index");
+                let mut locator = analysis::DefinitionLocator::new(encoding);
+                locations.extend(
+                    css_index
+                        .definitions_of(&class_name)
+                        .filter_map(|(css_file, class)| locator.locate(css_file, class.span)),
+                );
+                timer.phase("lookup");
+            }
+        }
+
+        Ok(Some(locations))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+        let (scopes, options) = {
+            let config = self.config.read().await;
+            (config.scopes.clone(), config.document_links.clone())
+        };
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+        let Some(syntax) = doc.syntax(&uri) else {
+            return Ok(None);
+        };
+
+        let tokens = match find::collect_class_names(
+            TextDocument::path(&uri),
+            syntax,
+            doc.text.clone(),
+            &scopes,
+        ) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                debug!("{err:#}");
+                return Ok(None);
+            }
+        };
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return Ok(None);
+        };
+
+        let links = analysis::document_links(
+            &doc.text,
+            &tokens,
+            &css_index,
+            &options,
+            |path| self.display_path(path),
+            encoding,
+        );
+
+        Ok(Some(links))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+        let scopes = self.config.read().await.scopes.clone();
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+        let Some(syntax) = doc.syntax(&uri) else {
+            return Ok(None);
+        };
+
+        let tokens = match find::collect_class_names(
+            TextDocument::path(&uri),
+            syntax,
+            doc.text.clone(),
+            &scopes,
+        ) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                debug!("{err:#}");
+                return Ok(None);
+            }
+        };
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return Ok(None);
+        };
+
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: analysis::semantic_tokens(&doc.text, &tokens, &css_index, encoding),
+        })))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+        let (enabled, scopes) = {
+            let config = self.config.read().await;
+            (config.inlay_hints.enabled, config.scopes.clone())
+        };
+        if !enabled {
+            return Ok(None);
+        }
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+        let Some(syntax) = doc.syntax(&uri) else {
+            return Ok(None);
+        };
+
+        let path = TextDocument::path(&uri);
+        let found = find::scoped_class_lists(path.clone(), syntax, doc.text.clone(), &scopes)
+            .and_then(|lists| {
+                let tokens = find::collect_class_names(path, syntax, doc.text.clone(), &scopes)?;
+                Ok((lists, tokens))
+            });
+        let (lists, tokens) = match found {
+            Ok(found) => found,
+            Err(err) => {
+                debug!("{err:#}");
+                return Ok(None);
+            }
+        };
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return Ok(None);
+        };
+
+        let hints = analysis::string_definition_files(&doc.text, &lists, &tokens, &css_index)
+            .into_iter()
+            .map(|(end, files)| (text::position_of(&doc.text, end, encoding), files))
+            .filter(|(position, _)| {
+                params.range.start <= *position && *position <= params.range.end
+            })
+            .map(|(position, files)| {
+                let label = match files.as_slice() {
+                    [css_file] => css_file
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    files => format!("{} files", files.len()),
+                };
+                let tooltip = files
+                    .iter()
+                    .map(|css_file| self.display_path(css_file))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                InlayHint {
+                    position,
+                    label: InlayHintLabel::String(format!("» {label}")),
+                    kind: None,
+                    text_edits: None,
+                    tooltip: Some(InlayHintTooltip::String(tooltip)),
+                    padding_left: Some(true),
+                    padding_right: None,
+                    data: None,
+                }
+            })
+            .collect();
+
+        Ok(Some(hints))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+        if !doc.is_css(&uri) {
+            return Ok(None);
+        }
+
+        let lenses = actions::rule_class_lenses(&doc.text)
+            .into_iter()
+            .map(|(class_name, range, is_alone)| CodeLens {
+                range: text::range_of(&doc.text, range.clone(), encoding),
+                command: None,
+                data: Some(serde_json::json!({
+                    "uri": uri,
+                    "position": text::position_of(&doc.text, range.start, encoding),
+                    "className": class_name,
+                    "isAlone": is_alone,
+                })),
+            })
+            .collect();
+
+        Ok(Some(lenses))
+    }
+
+    /// Counts the uses of the class of a lens, deferred to here since it takes a scan of every
+    /// source file of the workspace, done once until one is saved.
+    async fn code_lens_resolve(&self, mut lens: CodeLens) -> Result<CodeLens> {
+        let Some(data) = lens.data.take() else {
+            return Ok(lens);
+        };
+        let (Some(class_name), Some(uri), Some(position)) = (
+            data["className"].as_str(),
+            data.get("uri").cloned(),
+            data.get("position").cloned(),
+        ) else {
+            return Err(Error::invalid_params("code lens data is missing"));
+        };
+
+        let locations = self
+            .source_usages(serde_json::from_value::<Url>(uri.clone()).ok().as_ref())
+            .await
+            .and_then(|usages| usages.get(class_name).cloned())
+            .unwrap_or_default();
+
+        let count = match locations.len() {
+            1 => "1 reference".to_string(),
+            n => format!("{n} references"),
+        };
+        let title = match data["isAlone"].as_bool() {
+            Some(false) => format!(".{class_name}: {count}"),
+            _ => count,
+        };
+
+        lens.command = Some(Command {
+            title,
+            command: SHOW_REFERENCES_COMMAND.to_string(),
+            arguments: Some(vec![uri, position, serde_json::json!(locations)]),
+        });
+
+        Ok(lens)
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+        let mut timer = self
+            .timer(
+                "textDocument/codeAction",
+                position_context(&uri, params.range.start),
+            )
+            .await;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+        let code = doc.text.clone();
+
+        let mut code_actions = vec![];
+        let wants = |kind: &CodeActionKind| {
+            params
+                .context
+                .only
+                .as_ref()
+                .is_none_or(|only| only.iter().any(|o| kind.as_str().starts_with(o.as_str())))
+        };
+        if let Some(syntax) = doc.syntax(&uri).filter(|_| wants(&CodeActionKind::SOURCE)) {
+            let (scopes, order) = {
+                let config = self.config.read().await;
+                (config.scopes.clone(), config.sort.order.clone())
+            };
+            let cursor =
+                text::byte_offset_of(&code, params.range.start, encoding).unwrap_or_default();
+
+            let list =
+                find::scoped_class_lists(TextDocument::path(&uri), syntax, code.clone(), &scopes)
+                    .map_err(|err| debug!("failed to find class lists: {err:#}"))
+                    .ok()
+                    .and_then(|lists| {
+                        lists
+                            .into_iter()
+                            .find(|list| list.start <= cursor && cursor <= list.end)
+                    });
+
+            if let Some(edit) =
+                list.and_then(|list| actions::sort_classes_edit(&code, list, &order, encoding))
+            {
+                code_actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Sort class names".to_string(),
+                    kind: Some(CodeActionKind::SOURCE),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(std::collections::HashMap::from([(uri.clone(), vec![edit])])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+            }
+        }
+
+        let mut unknown_classes: Vec<(&Diagnostic, String)> = vec![];
+        for diagnostic in &params.context.diagnostics {
+            if let Some(class_name) = actions::unknown_class_of(diagnostic, &code, encoding) {
+                if unknown_classes.iter().all(|(_, c)| *c != class_name) {
+                    unknown_classes.push((diagnostic, class_name));
+                }
+            }
+        }
+
+        if unknown_classes.is_empty() || !wants(&CodeActionKind::QUICKFIX) {
+            return Ok((!code_actions.is_empty()).then_some(code_actions));
+        }
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return Ok(Some(code_actions));
+        };
+        timer.phase("scan");
+
+        let document = TextDocument::path(&uri);
+        let mut css_files = {
+            let workspace = self.workspace.read().expect("workspace lock was poisoned");
+            css_index
+                .css_files()
+                .filter(|css_file| {
+                    let root = workspace.root_of(css_file).unwrap_or(css_file);
+                    index::PathPriority::of(root, css_file) == index::PathPriority::FirstParty
+                })
+                .map(|css_file| css_file.to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        css_files.sort_by_key(|css_file| {
+            std::cmp::Reverse(actions::shared_components(css_file, &document))
+        });
+        css_files.truncate(MAX_CREATE_RULE_TARGETS);
+
+        let mut suggested = vec![];
+        for (diagnostic, class_name) in &unknown_classes {
+            let suggestions =
+                actions::suggest_class_names(class_name, css_index.class_name_values());
+            for (i, suggestion) in suggestions.iter().enumerate() {
+                code_actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Change to {suggestion}"),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![(*diagnostic).clone()]),
+                    edit: Some(actions::replace_class_edit(
+                        uri.clone(),
+                        diagnostic,
+                        &code,
+                        suggestion,
+                        encoding,
+                    )),
+                    is_preferred: Some(i == 0),
+                    ..Default::default()
+                }));
+            }
+            if !suggestions.is_empty() {
+                suggested.push(class_name);
+            }
+        }
+
+        let mut targets_offered = 0;
+        for css_file in css_files {
+            let Ok(css_uri) = Url::from_file_path(&css_file) else {
+                continue;
+            };
+
+            let css_code = match self.documents.get(&css_uri) {
+                Some(doc) => doc.text.clone(),
+                None => match std::fs::read_to_string(&css_file) {
+                    Ok(css_code) => css_code,
+                    Err(err) => {
+                        debug!("failed to read {}: {err}", css_file.display());
+                        continue;
+                    }
+                },
+            };
+
+            for (diagnostic, class_name) in &unknown_classes {
+                let is_preferred = targets_offered == 0 && !suggested.contains(&class_name);
+                code_actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Create .{class_name} in {}", self.display_path(&css_file)),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![(*diagnostic).clone()]),
+                    edit: Some(actions::create_rule_edit(
+                        css_uri.clone(),
+                        &css_code,
+                        class_name,
+                        encoding,
+                    )),
+                    is_preferred: Some(is_preferred),
+                    ..Default::default()
+                }));
+            }
+            targets_offered += 1;
+        }
+
+        for (diagnostic, _) in &unknown_classes {
+            let Some(edit) = actions::remove_class_edit(uri.clone(), diagnostic, &code, encoding)
+            else {
+                continue;
+            };
+            let written = text::byte_offset_of(&code, diagnostic.range.start, encoding)
+                .zip(text::byte_offset_of(&code, diagnostic.range.end, encoding))
+                .and_then(|(start, end)| code.get(start..end))
+                .unwrap_or_default();
+
+            code_actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Remove '{written}'"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![(*diagnostic).clone()]),
+                edit: Some(edit),
+                is_preferred: Some(false),
+                ..Default::default()
+            }));
+        }
+        timer.phase("lookup");
+
+        Ok(Some(code_actions))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let _timer = self
+            .timer(
+                "textDocument/documentHighlight",
+                position_context(&uri, position),
+            )
+            .await;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+
+        let (Some(syntax), Some(cursor)) = (
+            doc.syntax(&uri),
+            text::byte_offset_of(&doc.text, position, encoding),
+        ) else {
+            return Ok(None);
+        };
+
+        let scopes = self.config.read().await.scopes.clone();
+        let path = TextDocument::path(&uri);
+
+        let ranges = match analysis::class_name_highlights(
+            path,
+            syntax,
+            doc.text.clone(),
+            &scopes,
+            cursor,
+        ) {
+            Ok(ranges) => ranges,
+            Err(err) => {
+                debug!("{err:#}");
+                return Ok(None);
+            }
+        };
+
+        let highlights = ranges
+            .into_iter()
+            .map(|range| DocumentHighlight {
+                range: text::range_of(&doc.text, range, encoding),
+                kind: Some(DocumentHighlightKind::TEXT),
+            })
+            .collect();
+
+        Ok(Some(highlights))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let encoding = self.client_caps().position_encoding;
+        let mut timer = self
+            .timer("workspace/symbol", format!("{:?}", params.query))
+            .await;
+
+        let Some(css_index) = self.css_index(None).await else {
+            return Ok(None);
+        };
+        timer.phase("scan");
+
+        let mut locator = analysis::DefinitionLocator::new(encoding);
+
+        let symbols = css_index
+            .search(&params.query)
+            .filter_map(|(css_file, class)| {
+                #[allow(deprecated)]
+                Some(SymbolInformation {
+                    name: class.value.to_string(),
+                    kind: SymbolKind::CLASS,
+                    tags: None,
+                    deprecated: None,
+                    location: locator.locate(css_file, class.span)?,
+                    container_name: Some(self.display_path(css_file)),
+                })
+            })
+            .collect();
+        timer.phase("lookup");
+
+        Ok(Some(symbols))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+        let _timer = self
+            .timer("textDocument/documentSymbol", uri.path().to_string())
+            .await;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+
+        if !doc.is_css(&uri) {
+            return Ok(self.class_string_symbols(&uri, &doc).await);
+        }
+
+        #[allow(deprecated)]
+        fn to_document_symbol(
+            code: &str,
+            symbol: collect::CssSymbol,
+            encoding: PositionEncoding,
+        ) -> DocumentSymbol {
+            DocumentSymbol {
+                name: symbol.name,
+                detail: None,
+                kind: if symbol.is_at_rule {
+                    SymbolKind::NAMESPACE
+                } else {
+                    SymbolKind::CLASS
+                },
+                tags: None,
+                deprecated: None,
+                range: text::range_of(code, symbol.range, encoding),
+                selection_range: text::range_of(code, symbol.selection_range, encoding),
+                children: (!symbol.children.is_empty()).then(|| {
+                    symbol
+                        .children
+                        .into_iter()
+                        .map(|child| to_document_symbol(code, child, encoding))
+                        .collect()
+                }),
+            }
+        }
+
+        let symbols = collect::css_outline(&doc.text)
+            .into_iter()
+            .map(|symbol| to_document_symbol(&doc.text, symbol, encoding))
+            .collect();
+
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document.uri;
+        let position = params.position;
+        let mut timer = self
+            .timer(
+                "textDocument/prepareRename",
+                position_context(&uri, position),
+            )
+            .await;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Err(Error::invalid_params(format!("unknown document: {uri}")));
+        };
+        let code = doc.text.clone();
+
+        let token = if doc.is_css(&uri) {
+            text::byte_offset_of(&code, position, encoding).and_then(|cursor| {
+                let value = collect::class_selector_at(&code, cursor)?;
+                let range = collect::class_selector_ranges(&code, &value)
+                    .into_iter()
+                    .find(|range| range.start <= cursor && cursor <= range.end)?;
+                Some((value, range))
+            })
+        } else if let Some(syntax) = doc.syntax(&uri) {
+            let scopes = self.config.read().await.scopes.clone();
+            let path = TextDocument::path(&uri);
+            match SrcCodeMeta::build(path, syntax, doc.text, position, encoding)
+                .and_then(|src| src.get_class_name_token_on_cursor(&scopes))
+            {
+                Ok(token) => token.map(|token| (token.value, token.range)),
+                Err(err) => {
+                    debug!("{err:#}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        timer.phase("parse");
+
+        let Some((value, range)) = token else {
+            return Ok(None);
+        };
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return Ok(None);
+        };
+        timer.phase("scan");
+
+        let is_defined = css_index.definitions_of(&value).next().is_some();
+        timer.phase("lookup");
+
+        Ok(
+            is_defined
+                .then(|| PrepareRenameResponse::Range(text::range_of(&code, range, encoding))),
+        )
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let new_name = params.new_name;
+        let mut timer = self
+            .timer("textDocument/rename", position_context(&uri, position))
+            .await;
+
+        if new_name.is_empty() || new_name.contains(char::is_whitespace) {
+            return Err(Error::invalid_params(format!(
+                "invalid class name: {new_name:?}"
+            )));
+        }
+
+        let Some(class_name) = self.class_name_or_selector_at(&uri, position).await? else {
+            return Ok(None);
+        };
+        timer.phase("parse");
+
+        let mut changes: std::collections::HashMap<Url, Vec<TextEdit>> = Default::default();
+
+        let escaped_new_name = collect::escape_class_name(&new_name);
+        for location in self.class_selector_locations(&class_name, &uri).await {
+            changes
+                .entry(location.uri)
+                .or_default()
+                .push(TextEdit::new(location.range, escaped_new_name.clone()));
+        }
+        timer.phase("css");
+
+        for location in self.class_name_references(&class_name, &uri).await {
+            changes
+                .entry(location.uri)
+                .or_default()
+                .push(TextEdit::new(location.range, new_name.clone()));
+        }
+        timer.phase("scan");
+
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let encoding = self.client_caps().position_encoding;
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let mut timer = self
+            .timer("textDocument/completion", position_context(&uri, position))
+            .await;
+
+        let Some(doc) = self.documents.get(&uri).map(|doc| doc.clone()) else {
+            return Ok(None);
+        };
+        let code = &doc.text;
+
+        let Some(cursor) = text::byte_offset_of(code, position, encoding) else {
+            return Ok(None);
+        };
+
+        let ctx = if doc.is_css(&uri) {
+            complete::apply_context_at(code, cursor)
+        } else if let Some(syntax) = doc.syntax(&uri) {
+            let scopes = self.config.read().await.scopes.clone();
+            let path = TextDocument::path(&uri);
+            match complete::class_string_context_at(path, syntax, code, &scopes, cursor) {
+                Ok(ctx) => ctx,
+                Err(err) => {
+                    debug!("{err:#}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let Some(ctx) = ctx else {
+            return Ok(None);
+        };
+        timer.phase("parse");
+
+        let Some(css_index) = self.css_index(Some(&uri)).await else {
+            return Ok(None);
+        };
+        timer.phase("scan");
+
+        let completed = ctx.completed_range(code);
+        let typed = &code[completed.start..cursor.max(completed.start)];
+        let range = text::range_of(code, completed, encoding);
+
+        let workspace = self.workspace.read().expect("workspace lock was poisoned");
+        let items =
+            complete::class_name_completions(&css_index, range, typed, &ctx.listed, &workspace);
+        drop(workspace);
+        timer.phase("lookup");
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    async fn completion_resolve(&self, mut item: CompletionItem) -> Result<CompletionItem> {
+        let Some(data) = item
+            .data
+            .take()
+            .and_then(|data| serde_json::from_value::<complete::CompletionData>(data).ok())
+        else {
+            return Ok(item);
+        };
+
+        match complete::rule_text(&item.label, &data) {
+            Ok(rule) => {
+                let doc = HoverDoc::default().code("css", rule);
+                item.documentation = Some(Documentation::MarkupContent(
+                    doc.render(MarkupKind::Markdown),
+                ));
+            }
+            Err(err) => {
+                self.client
+                    .log_message(MessageType::ERROR, format!("{err:#}"))
+                    .await
+            }
+        }
+
+        Ok(item)
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        let value = match params.command.as_str() {
+            protocol::USAGE_REPORT_COMMAND => serde_json::to_value(self.usage_report().await?),
+            protocol::SHOW_INDEX_STATS_COMMAND => serde_json::to_value(self.index_stats().await),
+            protocol::REINDEX_COMMAND => {
+                self.invalidate_css_index().await;
+                self.source_usages.invalidate();
+                self.republish_diagnostics().await;
+                Ok(serde_json::Value::Null)
+            }
+            _ => {
+                return Err(Error::invalid_params(format!(
+                    "unknown command: {}",
+                    params.command
+                )))
+            }
+        };
+
+        value.map(Some).map_err(|_| Error::internal_error())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub async fn start() {
+    let mut config = Config::default();
+
+    for err in config.update_from_env() {
+        error!("{err:#}");
+    }
+
+    info!(
+        scopes = ?config.scopes,
+        "starting with scopes from, in increasing precedence: defaults, cnls.toml, ${}, client settings",
+        config::SCOPES_ENV_VAR
+    );
+
+    let stdin = tokio::io::stdin();
+    let stdout = tokio::io::stdout();
+
+    let (service, socket) = service(config);
+
+    Server::new(stdin, stdout, socket).serve(service).await;
+}
+
+/// Builds the language server, to be served over any transport, e.g. in-memory for tests.
+pub fn service(config: Config) -> (LspService<Backend>, ClientSocket) {
+    LspService::build(|client| backend(client, config))
+        .custom_method(protocol::DOCUMENT_STATUS_METHOD, Backend::document_status)
+        .custom_method(protocol::RESOLVE_RANGE_METHOD, Backend::resolve_range)
+        .custom_method(protocol::CONFIGURATION_METHOD, Backend::configuration)
+        .custom_method(protocol::VERSION_METHOD, Backend::version)
+        .custom_method("window/workDoneProgress/cancel", Backend::cancel_progress)
+        .finish()
+}
+
+/// The server talking to the `client`, starting with the `config` read from the environment.
+fn backend(client: Client, config: Config) -> Backend {
+    Backend(Arc::new(BackendState {
+        client,
+        config: tokio::sync::RwLock::new(config.clone()),
+        base_config: config,
+        config_sources: Default::default(),
+        documents: DocumentStore::default(),
+        client_caps: OnceLock::new(),
+        initialize_root: OnceLock::new(),
+        next_progress_token: AtomicU32::new(0),
+        indexing_progress: Default::default(),
+        watched_css_globs: Default::default(),
+        css_files_checked_at: Default::default(),
+        serves_inlay_hints: AtomicBool::new(false),
+        initialization_errors: Default::default(),
+        reported_skipped_css_files: DashSet::new(),
+        reported_bad_roots: DashSet::new(),
+        reported_empty_folders: AtomicBool::new(false),
+        reported_no_roots: AtomicBool::new(false),
+        css_files_with_diagnostics: DashSet::new(),
+        workspace: Default::default(),
+        css_index: SingleFlight::default(),
+        built_css_index: Default::default(),
+        source_usages: SingleFlight::default(),
+        request_stats: RequestStats::default(),
+    }))
 }
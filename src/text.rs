@@ -0,0 +1,317 @@
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range, TextDocumentContentChangeEvent};
+
+/// What the characters of LSP positions count, as negotiated with the client.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum PositionEncoding {
+    /// Bytes, like the offsets of swc spans.
+    Utf8,
+    /// UTF-16 code units, which every client supports.
+    #[default]
+    Utf16,
+    /// Unicode scalar values.
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// The encoding to count in, out of those the client `offered`: UTF-8 when it's offered,
+    /// sparing any conversion, otherwise the first one known, and UTF-16 by default.
+    pub fn negotiate(offered: &[PositionEncodingKind]) -> Self {
+        if offered.contains(&PositionEncodingKind::UTF8) {
+            return Self::Utf8;
+        }
+
+        offered
+            .iter()
+            .find_map(|kind| match kind.as_str() {
+                "utf-16" => Some(Self::Utf16),
+                "utf-32" => Some(Self::Utf32),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+            Self::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    fn len_of(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Resolves an LSP position, with its character counted in the `encoding`, to a byte offset
+/// into `code`.
+pub fn byte_offset_of(code: &str, position: Position, encoding: PositionEncoding) -> Option<usize> {
+    let line_start = match position.line {
+        0 => 0,
+        line => code
+            .match_indices('\n')
+            .nth(line as usize - 1)
+            .map(|(i, _)| i + 1)?,
+    };
+
+    let line = code[line_start..].split('\n').next().unwrap_or_default();
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut column = 0;
+
+    for (offset, c) in line.char_indices() {
+        if column >= position.character as usize {
+            return Some(line_start + offset);
+        }
+        column += encoding.len_of(c);
+    }
+
+    (column >= position.character as usize).then_some(line_start + line.len())
+}
+
+/// Like [byte_offset_of], but a character past the end of its line resolves to the end of it.
+/// `None` only for lines past the end of `code`.
+pub fn line_clamped_byte_offset_of(
+    code: &str,
+    position: Position,
+    encoding: PositionEncoding,
+) -> Option<usize> {
+    byte_offset_of(code, Position::new(position.line, 0), encoding)?;
+    Some(clamped_byte_offset_of(code, position, encoding))
+}
+
+/// Resolves a byte offset into `code` to an LSP position, with its character counted in the
+/// `encoding`.
+pub fn position_of(code: &str, offset: usize, encoding: PositionEncoding) -> Position {
+    let before = &code[..offset.min(code.len())];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let character = before[line_start..]
+        .chars()
+        .map(|c| encoding.len_of(c))
+        .sum::<usize>();
+
+    Position::new(line as u32, character as u32)
+}
+
+/// Like [byte_offset_of], but positions past the end of their line resolve to the end of it, and
+/// those past the last line to the end of `code`, as the LSP spec asks for.
+fn clamped_byte_offset_of(code: &str, position: Position, encoding: PositionEncoding) -> usize {
+    if let Some(offset) = byte_offset_of(code, position, encoding) {
+        return offset;
+    }
+
+    let end = byte_offset_of(
+        code,
+        Position::new(position.line.saturating_add(1), 0),
+        encoding,
+    )
+    .map(|next_line| next_line - 1)
+    .unwrap_or(code.len());
+    match code[..end].ends_with('\r') {
+        true => end - 1,
+        false => end,
+    }
+}
+
+/// Applies a change sent by the client to the `code` of a document: a replacement of the text
+/// in its range, or of the whole text when it has none.
+pub fn apply_change(
+    code: &mut String,
+    change: TextDocumentContentChangeEvent,
+    encoding: PositionEncoding,
+) {
+    let Some(range) = change.range else {
+        *code = change.text;
+        return;
+    };
+
+    let start = clamped_byte_offset_of(code, range.start, encoding);
+    let end = clamped_byte_offset_of(code, range.end, encoding).max(start);
+    code.replace_range(start..end, &change.text);
+}
+
+/// Resolves a range of byte offsets into `code` to an LSP range, the ends of which can be on
+/// any line, regardless of where the enclosing string starts.
+pub fn range_of(code: &str, range: std::ops::Range<usize>, encoding: PositionEncoding) -> Range {
+    Range::new(
+        position_of(code, range.start, encoding),
+        position_of(code, range.end, encoding),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{
+        Position, PositionEncodingKind, Range, TextDocumentContentChangeEvent,
+    };
+
+    use super::PositionEncoding::{self, Utf16, Utf32, Utf8};
+    use super::{apply_change, byte_offset_of, line_clamped_byte_offset_of, position_of, range_of};
+
+    fn change(
+        range: Option<((u32, u32), (u32, u32))>,
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: range.map(|(start, end)| {
+                Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+            }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_round_trips_positions() {
+        let code = ".a {\n  @apply px-4;\n}\n";
+
+        for (offset, position) in [
+            (0, Position::new(0, 0)),
+            (4, Position::new(0, 4)),
+            (5, Position::new(1, 0)),
+            (14, Position::new(1, 9)),
+            (21, Position::new(2, 1)),
+            (22, Position::new(3, 0)),
+        ] {
+            assert_eq!(position_of(code, offset, Utf16), position);
+            assert_eq!(byte_offset_of(code, position, Utf16), Some(offset));
+        }
+
+        assert_eq!(byte_offset_of(code, Position::new(4, 0), Utf16), None);
+
+        let clamped = |line, character| {
+            line_clamped_byte_offset_of(code, Position::new(line, character), Utf16)
+        };
+        assert_eq!(clamped(1, 9), Some(14));
+        assert_eq!(clamped(1, 1000), Some(19));
+        assert_eq!(clamped(3, 1000), Some(22));
+        assert_eq!(clamped(4, 0), None);
+        assert_eq!(clamped(u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    fn it_resolves_ranges_across_lines() {
+        let code = "<div className={`\n  flex\n  rounded-lg\n`} />";
+        let start = code.find("rounded-lg").unwrap();
+
+        assert_eq!(
+            range_of(code, start..start + "rounded-lg".len(), Utf16),
+            Range::new(Position::new(2, 2), Position::new(2, 12))
+        );
+
+        let start = code.find("flex").unwrap();
+        assert_eq!(
+            range_of(code, start..start + "flex\n  rounded".len(), Utf16),
+            Range::new(Position::new(1, 2), Position::new(2, 9))
+        );
+    }
+
+    #[test]
+    fn it_counts_characters_in_each_encoding() {
+        // é is 2 bytes, 👋 is 4 bytes or 2 utf-16 code units, 日本 is 3 bytes each
+        let code = "<div title=\"héllo 👋 日本\" className=\"flex\" />";
+        let offset = code.find("flex").unwrap();
+
+        for (encoding, character) in [(Utf8, 43), (Utf16, 36), (Utf32, 35)] {
+            let position = Position::new(0, character);
+            assert_eq!(position_of(code, offset, encoding), position);
+            assert_eq!(byte_offset_of(code, position, encoding), Some(offset));
+            assert_eq!(byte_offset_of(code, Position::new(0, 100), encoding), None);
+        }
+
+        // inside the emoji, resolving to the end of it
+        let after_emoji = code.find(" 日本").unwrap();
+        assert_eq!(
+            byte_offset_of(code, Position::new(0, 19), Utf16),
+            Some(after_emoji)
+        );
+        assert_eq!(
+            byte_offset_of(code, Position::new(0, 20), Utf8),
+            Some(after_emoji)
+        );
+    }
+
+    #[test]
+    fn it_prefers_utf8_out_of_the_offered_encodings() {
+        let negotiate = |offered: &[&'static str]| {
+            let offered = offered
+                .iter()
+                .map(|kind| PositionEncodingKind::new(kind))
+                .collect::<Vec<_>>();
+            PositionEncoding::negotiate(&offered)
+        };
+
+        assert_eq!(negotiate(&["utf-16", "utf-8"]), Utf8);
+        assert_eq!(negotiate(&["utf-32", "utf-16"]), Utf32);
+        assert_eq!(negotiate(&["utf-7", "utf-16"]), Utf16);
+        assert_eq!(negotiate(&[]), Utf16);
+        assert_eq!(Utf8.kind(), PositionEncodingKind::UTF8);
+    }
+
+    #[test]
+    fn it_keeps_crlf_line_endings_out_of_lines() {
+        let code = ".a {\r\n  @apply px-4;\r\n}\r\n";
+
+        for (offset, position) in [
+            (4, Position::new(0, 4)),
+            (6, Position::new(1, 0)),
+            (15, Position::new(1, 9)),
+            (20, Position::new(1, 14)),
+            (25, Position::new(3, 0)),
+        ] {
+            assert_eq!(position_of(code, offset, Utf16), position);
+            assert_eq!(byte_offset_of(code, position, Utf16), Some(offset));
+        }
+        assert_eq!(byte_offset_of(code, Position::new(0, 5), Utf16), None);
+
+        // past the end of a line, before its line break
+        let mut code = code.to_string();
+        apply_change(&mut code, change(Some(((0, 9), (0, 9))), " "), Utf16);
+        apply_change(&mut code, change(Some(((1, 16), (1, 20))), ";"), Utf16);
+        assert_eq!(code, ".a { \r\n  @apply px-4;;\r\n}\r\n");
+    }
+
+    #[test]
+    fn it_applies_changes_in_sequence() {
+        let mut code = "<div className=\"p-2\">\n  👋 <b className=\"x\" />\n</div>".to_string();
+
+        for c in [
+            change(Some(((0, 19), (0, 19))), " flex"),
+            // after the emoji, which counts as two utf-16 code units
+            change(Some(((1, 5), (1, 5))), "hi "),
+            change(Some(((1, 22), (1, 23))), "bold"),
+            change(Some(((0, 0), (0, 5))), "<main "),
+        ] {
+            apply_change(&mut code, c, Utf16);
+        }
+
+        assert_eq!(
+            code,
+            "<main className=\"p-2 flex\">\n  👋 hi <b className=\"bold\" />\n</div>"
+        );
+
+        // after the emoji again, which counts as four bytes
+        apply_change(&mut code, change(Some(((1, 7), (1, 9))), "yo"), Utf8);
+        assert_eq!(
+            code,
+            "<main className=\"p-2 flex\">\n  👋 yo <b className=\"bold\" />\n</div>"
+        );
+
+        apply_change(&mut code, change(Some(((0, 100), (9, 0))), ""), Utf16);
+        apply_change(
+            &mut code,
+            change(Some(((u32::MAX, 0), (u32::MAX, u32::MAX))), "!"),
+            Utf16,
+        );
+        assert_eq!(code, "<main className=\"p-2 flex\">!");
+        apply_change(&mut code, change(Some(((0, 27), (0, 28))), ""), Utf16);
+        assert_eq!(code, "<main className=\"p-2 flex\">");
+
+        apply_change(&mut code, change(None, "<p />"), Utf16);
+        assert_eq!(code, "<p />");
+    }
+}
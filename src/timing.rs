@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::protocol::RequestTimings;
+use tracing::{debug, warn};
+
+/// How many of the latest durations of a method its percentiles are computed from, for the
+/// stats not to grow for as long as the server runs.
+const RECENT_DURATIONS: usize = 1024;
+
+#[derive(Default)]
+struct MethodDurations {
+    /// The requests served since startup.
+    count: usize,
+    /// The durations of the latest [RECENT_DURATIONS] requests, oldest first.
+    recent: VecDeque<Duration>,
+}
+
+/// Durations of the requests served since startup, by method.
+#[derive(Default)]
+pub struct RequestStats {
+    durations: Mutex<HashMap<&'static str, MethodDurations>>,
+}
+
+impl RequestStats {
+    fn record(&self, method: &'static str, duration: Duration) {
+        let mut durations = self
+            .durations
+            .lock()
+            .expect("request stats lock was poisoned");
+        let durations = durations.entry(method).or_default();
+
+        durations.count += 1;
+        if durations.recent.len() == RECENT_DURATIONS {
+            durations.recent.pop_front();
+        }
+        durations.recent.push_back(duration);
+    }
+
+    pub fn summary(&self) -> BTreeMap<String, RequestTimings> {
+        let durations = self
+            .durations
+            .lock()
+            .expect("request stats lock was poisoned");
+
+        durations
+            .iter()
+            .map(|(method, durations)| {
+                let mut sorted = Vec::from(durations.recent.clone());
+                sorted.sort();
+                // Nearest rank.
+                let percentile =
+                    |p: usize| sorted[(sorted.len() * p).div_ceil(100) - 1].as_millis();
+
+                let timings = RequestTimings {
+                    count: durations.count,
+                    p50_ms: percentile(50),
+                    p95_ms: percentile(95),
+                };
+                (method.to_string(), timings)
+            })
+            .collect()
+    }
+}
+
+/// Times a request by phase (parse, scan, lookup...), recording the total into the stats and
+/// logging a "slow request" line when it exceeds the threshold, once finished or dropped.
+pub struct RequestTimer<'a> {
+    method: &'static str,
+    /// What the request is about, e.g. the document and position.
+    context: String,
+    stats: &'a RequestStats,
+    threshold: Duration,
+    started: Instant,
+    last_mark: Instant,
+    phases: Vec<(&'static str, Duration)>,
+    finished: bool,
+}
+
+impl<'a> RequestTimer<'a> {
+    pub fn start(
+        method: &'static str,
+        context: String,
+        stats: &'a RequestStats,
+        threshold: Duration,
+    ) -> Self {
+        let now = Instant::now();
+
+        Self {
+            method,
+            context,
+            stats,
+            threshold,
+            started: now,
+            last_mark: now,
+            phases: vec![],
+            finished: false,
+        }
+    }
+
+    /// Ends the current phase, named `name`, and starts the next one.
+    pub fn phase(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now - self.last_mark));
+        self.last_mark = now;
+    }
+
+    /// Records the request, returning the "slow request" line if it was logged.
+    pub fn finish(&mut self) -> Option<String> {
+        if std::mem::replace(&mut self.finished, true) {
+            return None;
+        }
+
+        let total = self.started.elapsed();
+        self.stats.record(self.method, total);
+
+        let phases = self
+            .phases
+            .iter()
+            .map(|(name, duration)| format!("{name} {}ms", duration.as_millis()))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if total < self.threshold {
+            debug!(method = self.method, total_ms = total.as_millis(), %phases, "request done");
+            return None;
+        }
+
+        let line = format!(
+            "slow request: {} {} took {}ms ({phases})",
+            self.method,
+            self.context,
+            total.as_millis()
+        );
+
+        warn!(
+            method = self.method,
+            context = %self.context,
+            total_ms = total.as_millis(),
+            %phases,
+            "{line}"
+        );
+
+        Some(line)
+    }
+}
+
+impl Drop for RequestTimer<'_> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{RequestStats, RequestTimer, RECENT_DURATIONS};
+
+    #[test]
+    fn it_reports_slow_requests() {
+        let stats = RequestStats::default();
+
+        let mut timer = RequestTimer::start(
+            "textDocument/hover",
+            "/app/src/App.tsx:3:14".to_owned(),
+            &stats,
+            Duration::from_millis(20),
+        );
+        timer.phase("parse");
+        std::thread::sleep(Duration::from_millis(30));
+        timer.phase("scan");
+
+        let line = timer.finish().unwrap();
+        assert!(line.starts_with("slow request: textDocument/hover /app/src/App.tsx:3:14 took"));
+        // however long the parse phase took on a busy machine
+        let scan_ms = line
+            .split_once("(parse ")
+            .and_then(|(_, phases)| phases.split_once("ms, scan "))
+            .and_then(|(_, scan)| scan.strip_suffix("ms)"))
+            .unwrap();
+        assert!(scan_ms.parse::<u128>().unwrap() >= 30);
+        drop(timer);
+
+        let timer = RequestTimer::start(
+            "textDocument/hover",
+            String::new(),
+            &stats,
+            Duration::from_secs(10),
+        );
+        drop(timer);
+
+        let summary = stats.summary();
+        assert_eq!(summary["textDocument/hover"].count, 2);
+        assert!(summary["textDocument/hover"].p95_ms >= 30);
+    }
+
+    #[test]
+    fn it_keeps_only_the_latest_durations() {
+        let stats = RequestStats::default();
+        stats.record("textDocument/hover", Duration::from_millis(100));
+        for _ in 0..RECENT_DURATIONS {
+            stats.record("textDocument/hover", Duration::from_millis(1));
+        }
+
+        let summary = stats.summary();
+        assert_eq!(summary["textDocument/hover"].count, RECENT_DURATIONS + 1);
+        assert_eq!(summary["textDocument/hover"].p95_ms, 1);
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::protocol::{ClassUsage, UsageReport, UsageStatus};
+use crate::scope::Scope;
+use anyhow::Context;
+use tower_lsp::lsp_types::{Location, Url};
+
+use crate::config::Config;
+use crate::find;
+use crate::index::{self, CssIndex};
+use crate::text::{self, PositionEncoding};
+use crate::workspace::WorkspaceContext;
+
+/// Counts of every class name used in the scanned source files.
+pub type UsageCounts = HashMap<String, usize>;
+
+/// Where each class name is used in the scanned source files.
+pub type UsageLocations = HashMap<String, Vec<Location>>;
+
+/// Scans the `source_files` for class names in parallel, calling `progress` with the number of
+/// files done out of the total after each one. Stops early, with partial counts, once
+/// `cancelled` is set.
+pub fn scan_usages(
+    source_files: &[PathBuf],
+    scopes: &[Scope],
+    progress: &(dyn Fn(usize, usize) + Sync),
+    cancelled: &AtomicBool,
+) -> (UsageCounts, Vec<anyhow::Error>) {
+    scan_files(
+        source_files,
+        scopes,
+        progress,
+        cancelled,
+        |counts: &mut UsageCounts, _, _, tokens| {
+            for token in tokens {
+                *counts.entry(token.value).or_default() += 1;
+            }
+        },
+        |counts, other| {
+            for (class, count) in other {
+                *counts.entry(class).or_default() += count;
+            }
+        },
+    )
+}
+
+/// Like [scan_usages], keeping where each class name is used rather than how often.
+pub fn scan_usage_locations(
+    source_files: &[PathBuf],
+    scopes: &[Scope],
+    encoding: PositionEncoding,
+    cancelled: &AtomicBool,
+) -> (UsageLocations, Vec<anyhow::Error>) {
+    scan_files(
+        source_files,
+        scopes,
+        &|_, _| {},
+        cancelled,
+        |locations: &mut UsageLocations, file, code, tokens| {
+            let Ok(uri) = Url::from_file_path(file) else {
+                return;
+            };
+            for token in tokens {
+                let range = text::range_of(code, token.range, encoding);
+                locations
+                    .entry(token.value)
+                    .or_default()
+                    .push(Location::new(uri.clone(), range));
+            }
+        },
+        |locations, other| {
+            for (class, other) in other {
+                locations.entry(class).or_default().extend(other);
+            }
+        },
+    )
+}
+
+/// Scans the `source_files` with a worker per core, each adding the class names of its files
+/// to its own accumulator with `add`, then merged together with `merge`.
+fn scan_files<A: Default + Send>(
+    source_files: &[PathBuf],
+    scopes: &[Scope],
+    progress: &(dyn Fn(usize, usize) + Sync),
+    cancelled: &AtomicBool,
+    add: impl Fn(&mut A, &Path, &str, Vec<find::ClassNameToken>) + Sync,
+    merge: impl Fn(&mut A, A) + Sync,
+) -> (A, Vec<anyhow::Error>) {
+    let total = source_files.len();
+    let done = AtomicUsize::new(0);
+    let next = AtomicUsize::new(0);
+    let merged = Mutex::new(A::default());
+    let errors = Mutex::new(vec![]);
+
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(total.max(1));
+
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| {
+                let mut local = A::default();
+
+                while !cancelled.load(Ordering::Relaxed) {
+                    let Some(file) = source_files.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+
+                    match scan_file(file, scopes) {
+                        Ok((code, tokens)) => add(&mut local, file, &code, tokens),
+                        Err(err) => errors
+                            .lock()
+                            .expect("usage errors lock was poisoned")
+                            .push(err),
+                    }
+
+                    progress(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+                }
+
+                merge(
+                    &mut merged.lock().expect("usage counts lock was poisoned"),
+                    local,
+                );
+            });
+        }
+    });
+
+    (
+        merged.into_inner().expect("usage counts lock was poisoned"),
+        errors.into_inner().expect("usage errors lock was poisoned"),
+    )
+}
+
+fn scan_file(file: &Path, scopes: &[Scope]) -> anyhow::Result<(String, Vec<find::ClassNameToken>)> {
+    let code = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+
+    let tokens = find::collect_class_names(
+        file.to_path_buf(),
+        find::syntax_of_file(file)?,
+        code.clone(),
+        scopes,
+    )?;
+
+    Ok((code, tokens))
+}
+
+/// Joins the usage `counts` against the definitions in the `css_index`, sorted from most to
+/// least used, then by class name.
+pub fn usage_report(
+    counts: &UsageCounts,
+    css_index: &CssIndex,
+    workspace: &WorkspaceContext,
+) -> UsageReport {
+    let mut defined_in: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+    for (css_file, class) in css_index.class_names() {
+        let files = defined_in.entry(&class.value).or_default();
+        let css_file = workspace.display_path(css_file);
+        if !files.contains(&css_file) {
+            files.push(css_file);
+        }
+    }
+
+    let mut classes = counts
+        .iter()
+        .map(|(class, count)| {
+            let files = defined_in.remove(class.as_str()).unwrap_or_default();
+            ClassUsage {
+                class: class.clone(),
+                count: *count,
+                status: if files.is_empty() {
+                    UsageStatus::UsedButUndefined
+                } else {
+                    UsageStatus::Used
+                },
+                defined_in: files,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    classes.extend(defined_in.into_iter().map(|(class, files)| ClassUsage {
+        class: class.to_string(),
+        count: 0,
+        defined_in: files,
+        status: UsageStatus::DefinedButUnused,
+    }));
+
+    classes.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.class.cmp(&b.class)));
+
+    UsageReport { classes }
+}
+
+/// Renders the `report` as a plain text table.
+pub fn render_table(report: &UsageReport) -> String {
+    let class_width = report
+        .classes
+        .iter()
+        .map(|usage| usage.class.len())
+        .chain(["CLASS".len()])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:>7}  {:<class_width$}  {:<18}  DEFINED IN\n",
+        "COUNT", "CLASS", "STATUS"
+    );
+
+    for usage in &report.classes {
+        let status = match usage.status {
+            UsageStatus::Used => "",
+            UsageStatus::UsedButUndefined => "used but undefined",
+            UsageStatus::DefinedButUnused => "defined but unused",
+        };
+
+        table.push_str(
+            format!(
+                "{:>7}  {:<class_width$}  {:<18}  {}",
+                usage.count,
+                usage.class,
+                status,
+                usage.defined_in.join(", ")
+            )
+            .trim_end(),
+        );
+        table.push('\n');
+    }
+
+    table
+}
+
+/// Runs the usage report over the workspace at `root` from the command line, printing it to
+/// stdout as JSON or as a table, with progress on stderr.
+pub fn run_cli(root: &Path, config: &Config, json: bool) -> anyhow::Result<()> {
+    crate::fs::check_workspace_root(root)?;
+
+    let mut css_files = vec![];
+    crate::fs::find_all_css_files_in_dir(root, &config.css, &mut css_files)?;
+    let prioritized = index::prioritize_css_files(
+        root,
+        css_files,
+        config.index_minified,
+        config.css_file_limits,
+    );
+    let (css_index, _, errors) = CssIndex::parse_files_with(
+        index::rank_css_files(vec![prioritized]),
+        config.index_minified,
+        config.css_file_limits,
+        &|_, _| {},
+        &AtomicBool::new(false),
+    );
+    for err in errors {
+        eprintln!("{err:#}");
+    }
+
+    let mut source_files = vec![];
+    crate::fs::find_all_source_files_in_dir(root, &mut source_files)?;
+
+    let progress = |done: usize, total: usize| eprint!("\rscanned {done}/{total} files");
+    let (counts, errors) = scan_usages(
+        &source_files,
+        &config.scopes,
+        &progress,
+        &AtomicBool::new(false),
+    );
+    eprintln!();
+    for err in errors {
+        eprintln!("{err:#}");
+    }
+
+    let workspace = WorkspaceContext::new(vec![root.to_path_buf()]);
+    let report = usage_report(&counts, &css_index, &workspace);
+
+    let mut stdout = std::io::stdout().lock();
+    if json {
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&report)?)?;
+    } else {
+        write!(stdout, "{}", render_table(&report))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::UsageStatus;
+
+    use super::{render_table, usage_report, UsageCounts};
+    use crate::index::CssIndex;
+    use crate::workspace::WorkspaceContext;
+
+    #[test]
+    fn it_joins_counts_with_definitions() {
+        let dir = std::env::temp_dir().join(format!("cnls-usage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.css"), dir.join("b.css"));
+        std::fs::write(&a, ".flex {} .btn {} .unused {}").unwrap();
+        std::fs::write(&b, ".btn {}").unwrap();
+
+        let (css_index, errors) = CssIndex::parse_files(vec![a, b]);
+        assert!(errors.is_empty());
+
+        let counts = UsageCounts::from([
+            ("flex".to_string(), 3),
+            ("btn".to_string(), 3),
+            ("missing".to_string(), 1),
+        ]);
+
+        let report = usage_report(
+            &counts,
+            &css_index,
+            &WorkspaceContext::new(vec![dir.clone()]),
+        );
+        let rows = report
+            .classes
+            .iter()
+            .map(|u| (u.class.as_str(), u.count, u.defined_in.len(), u.status))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            rows,
+            vec![
+                ("btn", 3, 2, UsageStatus::Used),
+                ("flex", 3, 1, UsageStatus::Used),
+                ("missing", 1, 0, UsageStatus::UsedButUndefined),
+                ("unused", 0, 1, UsageStatus::DefinedButUnused),
+            ]
+        );
+
+        let table = render_table(&report);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("  COUNT  CLASS"));
+        assert_eq!(lines[3].trim(), "1  missing  used but undefined");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::{Path, PathBuf};
+
+/// The workspace roots, for showing paths to the user relative to the root containing them.
+#[derive(Debug, Default, Clone)]
+pub struct WorkspaceContext {
+    roots: Vec<PathBuf>,
+}
+
+impl WorkspaceContext {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self { roots }
+    }
+
+    /// The innermost root containing the `path`.
+    pub fn root_of(&self, path: &Path) -> Option<&Path> {
+        let path = normalize(path);
+
+        self.roots
+            .iter()
+            .filter(|root| strip_root(&path, &normalize(root)).is_some())
+            .max_by_key(|root| root.as_os_str().len())
+            .map(|root| root.as_path())
+    }
+
+    /// Renders the `path` relative to the innermost root containing it, prefixed by the name of
+    /// that root when there are several. Paths outside every root are shortened instead, see
+    /// [shorten]. Separators are always shown as `/`.
+    pub fn display_path(&self, path: &Path) -> String {
+        let path = normalize(path);
+
+        let containing_root = self
+            .roots
+            .iter()
+            .map(|root| normalize(root))
+            .filter_map(|root| {
+                let relative = strip_root(&path, &root)?.to_string();
+                Some((root, relative))
+            })
+            .max_by_key(|(root, _)| root.len());
+
+        let Some((root, relative)) = containing_root else {
+            return shorten(&path);
+        };
+
+        if self.roots.len() == 1 {
+            return relative;
+        }
+
+        match root.rsplit('/').next().filter(|name| !name.is_empty()) {
+            Some(root_name) if relative.is_empty() => root_name.to_string(),
+            Some(root_name) => format!("{root_name}/{relative}"),
+            None => relative,
+        }
+    }
+}
+
+/// The folders to search for css and source files, by order of preference: the workspace
+/// folders of the client, unless there are none, the root it gave on initialize, and the
+/// directory of a document. `None` when there's none of them.
+pub fn search_roots(
+    folders: Option<Vec<PathBuf>>,
+    root: Option<&Path>,
+    document_dir: Option<&Path>,
+) -> Option<Vec<PathBuf>> {
+    folders
+        .filter(|folders| !folders.is_empty())
+        .or_else(|| root.map(|root| vec![root.to_path_buf()]))
+        .or_else(|| document_dir.map(|dir| vec![dir.to_path_buf()]))
+}
+
+/// The `path` as a string with `/` separators and no trailing one.
+fn normalize(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+
+    match path.trim_end_matches('/') {
+        "" => path,
+        trimmed => trimmed.to_string(),
+    }
+}
+
+fn is_windows_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// The part of the normalized `path` after the normalized `root`, if it's in the root. Drive
+/// letters and the rest of windows paths are compared ignoring case.
+fn strip_root<'a>(path: &'a str, root: &str) -> Option<&'a str> {
+    let head = path.get(..root.len())?;
+
+    let same_root = if is_windows_path(root) {
+        head.eq_ignore_ascii_case(root)
+    } else {
+        head == root
+    };
+
+    if !same_root {
+        return None;
+    }
+
+    let rest = &path[root.len()..];
+    if rest.is_empty() {
+        return Some(rest);
+    }
+
+    if root.ends_with('/') {
+        return Some(rest);
+    }
+
+    rest.strip_prefix('/')
+}
+
+/// Shortens a normalized path outside of the workspace, replacing the home directory by `~`
+/// and eliding all but the last three components of longer paths.
+fn shorten(path: &str) -> String {
+    let home = std::env::var("HOME")
+        .ok()
+        .map(|home| normalize(Path::new(&home)));
+
+    if let Some(rest) = home
+        .as_deref()
+        .filter(|home| *home != "/")
+        .and_then(|home| strip_root(path, home))
+    {
+        return format!("~/{rest}");
+    }
+
+    let components = path
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>();
+    if components.len() <= 4 {
+        return path.to_string();
+    }
+
+    format!("…/{}", components[components.len() - 3..].join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{search_roots, WorkspaceContext};
+
+    fn workspace(roots: &[&str]) -> WorkspaceContext {
+        WorkspaceContext::new(roots.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn it_renders_paths_relative_to_the_root() {
+        let ws = workspace(&["/work/app"]);
+        assert_eq!(
+            ws.display_path(Path::new("/work/app/src/styles.css")),
+            "src/styles.css"
+        );
+        assert_eq!(
+            ws.display_path(Path::new("/work/application/a.css")),
+            "/work/application/a.css"
+        );
+    }
+
+    #[test]
+    fn it_picks_the_containing_root_among_many() {
+        let ws = workspace(&["/work/app", "/work/ui", "/work/app/packages/theme"]);
+        assert_eq!(
+            ws.display_path(Path::new("/work/ui/button.css")),
+            "ui/button.css"
+        );
+        assert_eq!(
+            ws.display_path(Path::new("/work/app/packages/theme/index.css")),
+            "theme/index.css"
+        );
+        assert_eq!(ws.display_path(Path::new("/work/app/")), "app");
+        assert_eq!(
+            ws.root_of(Path::new("/work/app/packages/theme/a.css")),
+            Some(Path::new("/work/app/packages/theme"))
+        );
+        assert_eq!(ws.root_of(Path::new("/work/other/a.css")), None);
+    }
+
+    #[test]
+    fn it_shortens_paths_outside_every_root() {
+        let ws = workspace(&["/work/app"]);
+        assert_eq!(
+            ws.display_path(Path::new("/usr/lib/node_modules/tailwindcss/dist/base.css")),
+            "…/tailwindcss/dist/base.css"
+        );
+        assert_eq!(ws.display_path(Path::new("/tmp/a.css")), "/tmp/a.css");
+    }
+
+    #[test]
+    fn it_normalizes_windows_paths() {
+        let ws = workspace(&[r"C:\Users\dev\app"]);
+        assert_eq!(
+            ws.display_path(Path::new(r"c:\Users\dev\app\src\styles.css")),
+            "src/styles.css"
+        );
+        assert_eq!(
+            ws.display_path(Path::new(r"D:\vendor\a.css")),
+            "D:/vendor/a.css"
+        );
+    }
+
+    #[test]
+    fn it_falls_back_on_the_root_then_the_document_directory() {
+        let folders = || Some(vec![PathBuf::from("/work/app"), PathBuf::from("/work/lib")]);
+        let root = Some(Path::new("/work"));
+        let document_dir = Some(Path::new("/tmp/scratch"));
+
+        assert_eq!(
+            search_roots(folders(), root, document_dir),
+            Some(vec![PathBuf::from("/work/app"), PathBuf::from("/work/lib")])
+        );
+        assert_eq!(
+            search_roots(None, root, document_dir),
+            Some(vec![PathBuf::from("/work")])
+        );
+        assert_eq!(
+            search_roots(Some(vec![]), root, document_dir),
+            Some(vec![PathBuf::from("/work")])
+        );
+        assert_eq!(
+            search_roots(Some(vec![]), None, document_dir),
+            Some(vec![PathBuf::from("/tmp/scratch")])
+        );
+        assert_eq!(search_roots(Some(vec![]), None, None), None);
+    }
+}
@@ -0,0 +1,422 @@
+//! Drives the language server with jsonrpc messages over an in-memory transport, answering its
+//! requests to the client like an editor would.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, WriteHalf};
+use tokio::sync::{mpsc, Semaphore};
+use tower_lsp::lsp_types::{Position, Url};
+use tower_lsp::Server;
+
+/// A temporary workspace, removed when dropped.
+pub struct Workspace {
+    pub root: PathBuf,
+}
+
+impl Workspace {
+    /// Creates a workspace named after the test, holding the `files` (relative path, content).
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let root = std::env::temp_dir().join(format!("cnls-lsp-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        for (path, content) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        Self { root }
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    pub fn uri(&self, path: &str) -> Url {
+        Url::from_file_path(self.path(path)).unwrap()
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+type Writer = Arc<tokio::sync::Mutex<WriteHalf<DuplexStream>>>;
+
+pub struct TestServer {
+    writer: Writer,
+    next_id: i64,
+    responses: mpsc::UnboundedReceiver<Value>,
+    /// Responses read while waiting for another one, by id.
+    pending: HashMap<i64, Value>,
+    notifications: mpsc::UnboundedReceiver<Value>,
+    /// What the client answers to `workspace/configuration` requests, for the `cnls` section.
+    pub settings: Arc<Mutex<Value>>,
+    /// What the client answers to `workspace/workspaceFolders` requests, the root by default.
+    pub folders: Arc<Mutex<Vec<PathBuf>>>,
+    /// The requests of the server the client holds off answering, by method, see
+    /// [TestServer::hold].
+    held: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+/// The `folders` as `WorkspaceFolder`s, named after their last component.
+fn workspace_folders(folders: &[PathBuf]) -> Value {
+    folders
+        .iter()
+        .map(|folder| {
+            let name = folder.file_name().unwrap().to_string_lossy();
+            json!({ "uri": Url::from_file_path(folder).unwrap(), "name": name })
+        })
+        .collect()
+}
+
+impl TestServer {
+    /// Starts a server with the default configuration, without initializing it.
+    pub fn start(root: &Path) -> Self {
+        Self::start_with(root, cnls::config::Config::default())
+    }
+
+    /// Starts a server with the `config` it would have read from the environment, without
+    /// initializing it.
+    pub fn start_with(root: &Path, config: cnls::config::Config) -> Self {
+        let (service, socket) = cnls::server::service(config);
+
+        let (client_end, server_end) = tokio::io::duplex(1 << 20);
+        let (server_read, server_write) = tokio::io::split(server_end);
+        tokio::spawn(Server::new(server_read, server_write, socket).serve(service));
+
+        let (client_read, client_write) = tokio::io::split(client_end);
+        let writer: Writer = Arc::new(tokio::sync::Mutex::new(client_write));
+        let settings = Arc::new(Mutex::new(Value::Null));
+        let folders = Arc::new(Mutex::new(vec![root.to_path_buf()]));
+        let held = Arc::new(Mutex::new(HashMap::<String, Arc<Semaphore>>::new()));
+
+        let (responses_tx, responses) = mpsc::unbounded_channel();
+        let (notifications_tx, notifications) = mpsc::unbounded_channel();
+
+        let client_writer = writer.clone();
+        let client_settings = settings.clone();
+        let client_folders = folders.clone();
+        let client_held = held.clone();
+
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(client_read);
+
+            while let Some(message) = read_message(&mut reader).await {
+                match (message.get("id"), message.get("method")) {
+                    (Some(id), Some(method)) => {
+                        let result = match method.as_str().unwrap() {
+                            "workspace/workspaceFolders" => {
+                                workspace_folders(&client_folders.lock().unwrap())
+                            }
+                            "workspace/configuration" => {
+                                json!([client_settings.lock().unwrap().clone()])
+                            }
+                            _ => Value::Null,
+                        };
+
+                        let response = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                        let gate = client_held
+                            .lock()
+                            .unwrap()
+                            .get(method.as_str().unwrap())
+                            .cloned();
+                        match gate {
+                            Some(gate) => {
+                                let writer = client_writer.clone();
+                                tokio::spawn(async move {
+                                    // closed once released
+                                    let _ = gate.acquire().await;
+                                    write_message(&writer, &response).await;
+                                });
+                            }
+                            None => write_message(&client_writer, &response).await,
+                        }
+                        let _ = notifications_tx.send(message);
+                    }
+                    (Some(_), None) => {
+                        let _ = responses_tx.send(message);
+                    }
+                    (None, _) => {
+                        let _ = notifications_tx.send(message);
+                    }
+                }
+            }
+        });
+
+        Self {
+            writer,
+            next_id: 0,
+            responses,
+            pending: HashMap::new(),
+            notifications,
+            settings,
+            folders,
+            held,
+        }
+    }
+
+    /// Holds off answering the requests of `method` the server sends from now on, until
+    /// [TestServer::release], e.g. to keep the work awaiting them going. They're still
+    /// received as notifications meanwhile.
+    pub fn hold(&self, method: &str) {
+        let gate = Arc::new(Semaphore::new(0));
+        self.held.lock().unwrap().insert(method.to_string(), gate);
+    }
+
+    /// Answers the requests of `method` held so far, and those that follow right away.
+    pub fn release(&self, method: &str) {
+        if let Some(gate) = self.held.lock().unwrap().remove(method) {
+            gate.close();
+        }
+    }
+
+    /// Starts a server and initializes it for the workspace at `root`.
+    pub async fn initialized(root: &Path) -> Self {
+        let mut server = Self::start(root);
+        server.initialize(root).await;
+        server
+    }
+
+    /// Initializes the server for the workspace at `root`, declaring the capabilities of a
+    /// typical editor that has the server push diagnostics.
+    pub async fn initialize(&mut self, root: &Path) -> Value {
+        self.initialize_with(
+            root,
+            json!({
+                "workspace": { "configuration": true, "workspaceFolders": true },
+                "textDocument": {
+                    "inlayHint": {},
+                    "semanticTokens": {
+                        "requests": { "full": true },
+                        "tokenTypes": ["class"],
+                        "tokenModifiers": [],
+                        "formats": ["relative"]
+                    }
+                }
+            }),
+        )
+        .await
+    }
+
+    /// Initializes the server for the workspace at `root`, declaring the client `capabilities`.
+    pub async fn initialize_with(&mut self, root: &Path, capabilities: Value) -> Value {
+        self.initialize_with_options(root, capabilities, Value::Null)
+            .await
+    }
+
+    /// Like [TestServer::initialize_with], passing the server its `initializationOptions`.
+    pub async fn initialize_with_options(
+        &mut self,
+        root: &Path,
+        capabilities: Value,
+        options: Value,
+    ) -> Value {
+        let root_uri = Url::from_file_path(root).unwrap();
+        let folders = workspace_folders(&self.folders.lock().unwrap());
+
+        let result = self
+            .request(
+                "initialize",
+                json!({
+                    "processId": null,
+                    "rootUri": root_uri,
+                    "capabilities": capabilities,
+                    "initializationOptions": options,
+                    "workspaceFolders": folders
+                }),
+            )
+            .await;
+
+        self.notify("initialized", json!({})).await;
+
+        result
+    }
+
+    /// Sends a request and waits for its result, panicking if it fails.
+    pub async fn request(&mut self, method: &str, params: Value) -> Value {
+        let response = self.raw_request(method, params).await;
+
+        if let Some(error) = response.get("error") {
+            panic!("{method} failed: {error}");
+        }
+
+        response["result"].clone()
+    }
+
+    /// Sends a request and waits for the whole response, error included.
+    pub async fn raw_request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.send_request(method, params).await;
+        self.response(id).await
+    }
+
+    /// Sends a request without waiting for its response, returning its id.
+    pub async fn send_request(&mut self, method: &str, params: Value) -> i64 {
+        self.next_id += 1;
+        let id = self.next_id;
+
+        let mut request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        // requests without parameters leave the field out
+        if params.is_null() {
+            request.as_object_mut().unwrap().remove("params");
+        }
+        write_message(&self.writer, &request).await;
+
+        id
+    }
+
+    /// Waits for the whole response to the request `id`, error included.
+    pub async fn response(&mut self, id: i64) -> Value {
+        loop {
+            if let Some(response) = self.pending.remove(&id) {
+                return response;
+            }
+
+            let response = tokio::time::timeout(Duration::from_secs(10), self.responses.recv())
+                .await
+                .unwrap_or_else(|_| panic!("timed out waiting for a response to request {id}"))
+                .expect("server should still be running");
+
+            self.pending
+                .insert(response["id"].as_i64().unwrap(), response);
+        }
+    }
+
+    pub async fn notify(&mut self, method: &str, params: Value) {
+        let notification = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&self.writer, &notification).await;
+    }
+
+    /// Waits for the next notification of `method` from the server, or request once answered or
+    /// held, skipping the others.
+    pub async fn notification(&mut self, method: &str) -> Value {
+        loop {
+            let notification =
+                tokio::time::timeout(Duration::from_secs(10), self.notifications.recv())
+                    .await
+                    .unwrap_or_else(|_| panic!("timed out waiting for {method}"))
+                    .expect("server should still be running");
+
+            if notification["method"] == method {
+                return notification["params"].clone();
+            }
+        }
+    }
+
+    pub async fn did_open(&mut self, uri: &Url, language_id: &str, text: &str) {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": language_id, "version": 1, "text": text }
+            }),
+        )
+        .await;
+    }
+
+    /// Replaces the whole text of the document at `uri`.
+    pub async fn did_change(&mut self, uri: &Url, version: i32, text: &str) {
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": version },
+                "contentChanges": [{ "text": text }]
+            }),
+        )
+        .await;
+    }
+
+    pub async fn did_save(&mut self, uri: &Url) {
+        self.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+    }
+
+    /// Tells the server files changed on disk, each by its uri and `FileChangeType`: 1 when
+    /// created, 2 when changed, 3 when deleted.
+    pub async fn did_change_watched_files(&mut self, changes: &[(&Url, u8)]) {
+        let changes = changes
+            .iter()
+            .map(|(uri, typ)| json!({ "uri": uri, "type": typ }))
+            .collect::<Vec<_>>();
+        self.notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": changes }),
+        )
+        .await;
+    }
+
+    pub async fn did_change_configuration(&mut self, settings: Value) {
+        self.notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": settings }),
+        )
+        .await;
+    }
+
+    pub async fn hover(&mut self, uri: &Url, position: Position) -> Value {
+        self.request("textDocument/hover", position_params(uri, position))
+            .await
+    }
+
+    pub async fn definition(&mut self, uri: &Url, position: Position) -> Value {
+        self.request("textDocument/definition", position_params(uri, position))
+            .await
+    }
+}
+
+pub fn position_params(uri: &Url, position: Position) -> Value {
+    json!({ "textDocument": { "uri": uri }, "position": position })
+}
+
+/// The position of the first occurrence of `needle` in `text`, plus `offset` characters.
+pub fn position_of(text: &str, needle: &str, offset: u32) -> Position {
+    let index = text.find(needle).expect("needle should be in the text");
+    let line = text[..index].matches('\n').count() as u32;
+    let line_start = text[..index].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Position::new(line, (index - line_start) as u32 + offset)
+}
+
+async fn write_message(writer: &Writer, message: &Value) {
+    let body = message.to_string();
+    let mut writer = writer.lock().await;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n{body}", body.len()).as_bytes())
+        .await
+        .unwrap();
+    writer.flush().await.unwrap();
+}
+
+async fn read_message<R: tokio::io::AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Option<Value> {
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(length) = line.strip_prefix("Content-Length: ") {
+            content_length = length.parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; content_length?];
+    reader.read_exact(&mut body).await.ok()?;
+
+    serde_json::from_slice(&body).ok()
+}
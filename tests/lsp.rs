@@ -0,0 +1,2420 @@
+mod common;
+
+use common::{position_of, TestServer, Workspace};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use tower_lsp::lsp_types::{Position, Url};
+
+const APP: &str = r#"const badge = cva("badge");
+
+export const App = () => (
+  <div className="btn card">
+    <span className="card" />
+  </div>
+);
+"#;
+
+const STYLES: &str = ".btn {
+  padding: 0;
+}
+
+.badge {
+  color: red;
+}
+";
+
+fn workspace(name: &str) -> Workspace {
+    Workspace::new(name, &[("src/App.tsx", APP), ("src/styles.css", STYLES)])
+}
+
+#[tokio::test]
+async fn it_advertises_capabilities() {
+    let ws = workspace("capabilities");
+    let mut server = TestServer::start(&ws.root);
+
+    let result = server.initialize(&ws.root).await;
+    let capabilities = &result["capabilities"];
+
+    assert_eq!(capabilities["hoverProvider"], json!(true));
+    assert_eq!(capabilities["definitionProvider"], json!(true));
+    assert_eq!(capabilities["positionEncoding"], json!("utf-16"));
+    assert!(capabilities["inlayHintProvider"].is_null());
+    assert_eq!(
+        capabilities["semanticTokensProvider"]["legend"]["tokenTypes"],
+        json!(["class"])
+    );
+    assert_eq!(
+        capabilities["renameProvider"]["prepareProvider"],
+        json!(true)
+    );
+}
+
+#[tokio::test]
+async fn it_advertises_only_what_a_minimal_client_supports() {
+    let ws = workspace("minimal-capabilities");
+    let config = cnls::config::Config {
+        inlay_hints: cnls::config::InlayHintOptions { enabled: true },
+        ..Default::default()
+    };
+    let mut server = TestServer::start_with(&ws.root, config);
+
+    let result = server.initialize_with(&ws.root, json!({})).await;
+    let capabilities = &result["capabilities"];
+
+    assert_eq!(capabilities["hoverProvider"], json!(true));
+    assert!(capabilities["semanticTokensProvider"].is_null());
+    assert!(capabilities["inlayHintProvider"].is_null());
+    assert!(capabilities["diagnosticProvider"].is_null());
+
+    // diagnostics are pushed instead
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn it_counts_characters_in_the_negotiated_encoding() {
+    let app = "export const App = () => <div title=\"🎉 新\" className=\"btn\" />;\n";
+    let styles = "/* 🎉 新 */ .btn { padding: 0; }\n";
+    let ws = Workspace::new(
+        "position-encoding",
+        &[("src/App.tsx", app), ("src/styles.css", styles)],
+    );
+    let app_uri = ws.uri("src/App.tsx");
+    let btn = app.find("btn").unwrap();
+    let selector = styles.find(".btn").unwrap();
+
+    for (encoding, count) in [
+        ("utf-8", str::len as fn(&str) -> usize),
+        ("utf-32", |s: &str| s.chars().count()),
+    ] {
+        let mut server = TestServer::start(&ws.root);
+        let result = server
+            .initialize_with(
+                &ws.root,
+                json!({ "general": { "positionEncodings": [encoding, "utf-16"] } }),
+            )
+            .await;
+        assert_eq!(result["capabilities"]["positionEncoding"], json!(encoding));
+
+        server.did_open(&app_uri, "typescriptreact", app).await;
+        let position = Position::new(0, count(&app[..btn]) as u32 + 1);
+        let hover = server.hover(&app_uri, position).await;
+        assert!(hover["contents"].is_object(), "{encoding}: {hover}");
+
+        let definition = server.definition(&app_uri, position).await;
+        let start = count(&styles[..selector]);
+        assert_eq!(
+            definition["range"],
+            json!({
+                "start": { "line": 0, "character": start },
+                "end": { "line": 0, "character": start + 4 }
+            }),
+            "{encoding}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn it_tells_its_version() {
+    let ws = workspace("version");
+    let mut server = TestServer::start(&ws.root);
+
+    let result = server.initialize(&ws.root).await;
+    assert_eq!(
+        result["serverInfo"],
+        json!({ "name": "cnls", "version": env!("CARGO_PKG_VERSION") })
+    );
+
+    let message = server.notification("window/logMessage").await;
+    let message = message["message"].as_str().unwrap();
+    assert!(message.starts_with(&format!("cnls {} (", env!("CARGO_PKG_VERSION"))));
+    assert!(message.ends_with("initialized, with scopes: att:className,class; fn:createElement"));
+
+    let version = server.request("cnls/version", json!(null)).await;
+    assert_eq!(version["name"], "cnls");
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(version.get("gitSha").is_some());
+}
+
+#[tokio::test]
+async fn it_counts_no_class_names_in_a_document_that_doesnt_parse() {
+    let ws = workspace("document-status-unparsed");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server
+        .did_open(&app, "typescriptreact", "const a = <div className=\"btn\"")
+        .await;
+
+    let status = server
+        .request(
+            "cnls/documentStatus",
+            json!({ "textDocument": { "uri": app } }),
+        )
+        .await;
+    assert_eq!(
+        status,
+        json!({ "total": 0, "resolved": 0, "unresolved": 0, "duplicated": 0, "unresolvedNames": [] })
+    );
+}
+
+#[tokio::test]
+async fn it_hovers_class_names() {
+    let ws = workspace("hover");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert_eq!(hover["contents"]["kind"], "markdown");
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.css:1**\n\n```css\n.btn {\n  padding: 0;\n}\n```"
+    );
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 3, "character": 18 }, "end": { "line": 3, "character": 21 } })
+    );
+
+    let hover = server.hover(&app, position_of(APP, "card", 1)).await;
+    assert!(hover.is_null());
+}
+
+#[tokio::test]
+async fn it_hovers_class_names_after_multibyte_characters() {
+    let app = "const label = \"héllo ✨\"; const a = <div className=\"p-2 btn\" />;\n";
+    let ws = Workspace::new(
+        "hover-multibyte",
+        &[("src/App.tsx", app), ("src/styles.css", STYLES)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app_uri = ws.uri("src/App.tsx");
+    server.did_open(&app_uri, "typescriptreact", app).await;
+
+    // "héllo ✨" is 10 bytes, but 7 UTF-16 code units
+    let btn = app[..app.find("btn").unwrap()].encode_utf16().count() as u32;
+    assert_eq!(btn as usize, app.find("btn").unwrap() - 3);
+
+    let hover = server.hover(&app_uri, Position::new(0, btn + 1)).await;
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 0, "character": btn }, "end": { "line": 0, "character": btn + 3 } })
+    );
+}
+
+#[tokio::test]
+async fn it_answers_positions_out_of_the_document() {
+    let app = format!("{APP}const wave = <p className=\"👋 btn\" />;");
+    let ws = Workspace::new(
+        "out-of-range",
+        &[("src/App.tsx", &app), ("src/styles.css", STYLES)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app_uri = ws.uri("src/App.tsx");
+    server.did_open(&app_uri, "typescriptreact", &app).await;
+
+    let emoji = position_of(&app, "👋", 1);
+    for position in [
+        Position::new(1000, 0),
+        Position::new(3, 10_000),
+        Position::new(u32::MAX, u32::MAX),
+        emoji,
+    ] {
+        for method in [
+            "textDocument/hover",
+            "textDocument/definition",
+            "textDocument/references",
+            "textDocument/documentHighlight",
+            "textDocument/prepareRename",
+            "textDocument/completion",
+        ] {
+            let mut params = common::position_params(&app_uri, position);
+            params["context"] = json!({ "includeDeclaration": true });
+            let response = server.raw_request(method, params).await;
+            assert!(
+                response["result"].is_null() || response["result"] == json!([]),
+                "{method} at {position:?}: {response}"
+            );
+        }
+    }
+
+    // deleting past the end of the document
+    server
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": app_uri, "version": 2 },
+                "contentChanges": [{
+                    "range": {
+                        "start": { "line": 8, "character": 0 },
+                        "end": { "line": 4000, "character": 12 }
+                    },
+                    "text": ""
+                }]
+            }),
+        )
+        .await;
+
+    let hover = server.hover(&app_uri, position_of(&app, "btn", 1)).await;
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 3, "character": 18 }, "end": { "line": 3, "character": 21 } })
+    );
+}
+
+#[tokio::test]
+async fn it_handles_crlf_line_endings() {
+    let app = APP.replace('\n', "\r\n");
+    let styles = STYLES.replace('\n', "\r\n");
+    let ws = Workspace::new(
+        "crlf",
+        &[("src/App.tsx", &app), ("src/styles.css", &styles)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app_uri = ws.uri("src/App.tsx");
+    server.did_open(&app_uri, "typescriptreact", &app).await;
+
+    let hover = server.hover(&app_uri, position_of(&app, "btn", 1)).await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.css:1**\n\n```css\n.btn {\n  padding: 0;\n}\n```"
+    );
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 3, "character": 18 }, "end": { "line": 3, "character": 21 } })
+    );
+
+    let definition = server
+        .definition(&app_uri, position_of(&app, "btn", 1))
+        .await;
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 4 } })
+    );
+
+    let styles_uri = ws.uri("src/styles.css");
+    server.did_open(&styles_uri, "css", &styles).await;
+    let symbols = server
+        .request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": styles_uri } }),
+        )
+        .await;
+    assert_eq!(symbols[1]["name"], "badge");
+    assert_eq!(
+        symbols[1]["range"],
+        json!({ "start": { "line": 4, "character": 0 }, "end": { "line": 6, "character": 1 } })
+    );
+    assert_eq!(
+        symbols[1]["selectionRange"],
+        json!({ "start": { "line": 4, "character": 0 }, "end": { "line": 4, "character": 6 } })
+    );
+}
+
+#[tokio::test]
+async fn it_hovers_in_plain_text_for_clients_without_markdown() {
+    let ws = workspace("hover-plaintext");
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with(
+            &ws.root,
+            json!({ "textDocument": { "hover": { "contentFormat": ["plaintext"] } } }),
+        )
+        .await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert_eq!(hover["contents"]["kind"], "plaintext");
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .starts_with("src/styles.css:1\n\n    .btn {"));
+}
+
+#[tokio::test]
+async fn it_hovers_classes_nested_in_css_with_their_ancestors() {
+    let app = "export const App = () => <div className=\"card active\" />;\n";
+    let styles = ".card {\n  padding: 1rem;\n\n  &.active .icon {\n    opacity: 1;\n  }\n}\n";
+    let ws = Workspace::new(
+        "css-nesting",
+        &[("src/App.tsx", app), ("src/styles.css", styles)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app_uri = ws.uri("src/App.tsx");
+    server.did_open(&app_uri, "typescriptreact", app).await;
+
+    let hover = server.hover(&app_uri, position_of(app, "active", 1)).await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.css:4**\n\n```css\n.card {\n  &.active .icon {\n    opacity: 1;\n  }\n}\n```\n\nalso requires: .card"
+    );
+}
+
+#[tokio::test]
+async fn it_hovers_classes_nested_in_scss() {
+    let app = "export const App = () => <div className=\"card-title icon\" />;\n";
+    let styles = "$gap: 4px;\n\n.card {\n  padding: $gap;\n\n  &-title {\n    // bold\n    font-weight: bold;\n  }\n}\n";
+    let ws = Workspace::new("scss", &[("src/App.tsx", app), ("src/styles.scss", styles)]);
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app_uri = ws.uri("src/App.tsx");
+    server.did_open(&app_uri, "typescriptreact", app).await;
+
+    let hover = server
+        .hover(&app_uri, position_of(app, "card-title", 1))
+        .await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.scss:6**\n\n```scss\n.card {\n  &-title {\n    // bold\n    font-weight: bold;\n  }\n}\n```"
+    );
+
+    let definition = server
+        .definition(&app_uri, position_of(app, "card-title", 1))
+        .await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/styles.scss")));
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 5, "character": 2 }, "end": { "line": 5, "character": 9 } })
+    );
+
+    // icon is defined nowhere
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    let diagnostics = published["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["message"], "unknown class name \"icon\"");
+}
+
+#[tokio::test]
+async fn it_hovers_classes_nested_in_indented_sass() {
+    let app = "export const App = () => <div className=\"card-title\" />;\n";
+    let styles =
+        ".card\n  padding: 4px\n\n  &-title\n    font-weight: bold\n\n  .icon\n    width: 1em\n";
+    let ws = Workspace::new("sass", &[("src/App.tsx", app), ("src/styles.sass", styles)]);
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app_uri = ws.uri("src/App.tsx");
+    server.did_open(&app_uri, "typescriptreact", app).await;
+
+    let hover = server
+        .hover(&app_uri, position_of(app, "card-title", 1))
+        .await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.sass:4**\n\n```sass\n.card\n  &-title\n    font-weight: bold\n```"
+    );
+
+    let definition = server
+        .definition(&app_uri, position_of(app, "card-title", 1))
+        .await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/styles.sass")));
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 3, "character": 2 }, "end": { "line": 3, "character": 9 } })
+    );
+}
+
+#[tokio::test]
+async fn it_hovers_classes_nested_in_less() {
+    let app = "export const App = () => <div className=\"card-title bordered\" />;\n";
+    let styles = "@gap: 4px;\n\n.bordered(@width: 1px) {\n  border: @width solid;\n}\n\n.card {\n  .bordered();\n\n  &-title {\n    padding: @gap;\n  }\n}\n";
+    let ws = Workspace::new("less", &[("src/App.tsx", app), ("src/styles.less", styles)]);
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app_uri = ws.uri("src/App.tsx");
+    server.did_open(&app_uri, "typescriptreact", app).await;
+
+    let hover = server
+        .hover(&app_uri, position_of(app, "card-title", 1))
+        .await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.less:10**\n\n```less\n.card {\n  &-title {\n    padding: @gap;\n  }\n}\n```"
+    );
+
+    // neither the mixin nor its call define a class
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    let diagnostics = published["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["message"], "unknown class name \"bordered\"");
+}
+
+#[tokio::test]
+async fn it_goes_to_definitions() {
+    let ws = workspace("definition");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let definition = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/styles.css")));
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 4 } })
+    );
+}
+
+#[tokio::test]
+async fn it_goes_to_the_classes_css_files_apply_and_compose() {
+    const COMPONENTS: &str = ".btn {\n  @apply px-4 hover:rounded;\n}\n\n\
+                              .card {\n  composes: rounded from \"./utilities.css\";\n}\n";
+    let ws = Workspace::new(
+        "definition-apply",
+        &[
+            (
+                "src/utilities.css",
+                ".rounded {\n  border-radius: 0.25rem;\n}\n",
+            ),
+            ("src/components.scss", COMPONENTS),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let components = ws.uri("src/components.scss");
+    server.did_open(&components, "scss", COMPONENTS).await;
+
+    let rounded = json!({
+        "uri": ws.uri("src/utilities.css"),
+        "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 8 } }
+    });
+    let applied = position_of(COMPONENTS, "hover:rounded", 8);
+    assert_eq!(server.definition(&components, applied).await, rounded);
+    let composed = position_of(COMPONENTS, "composes: rounded", 11);
+    assert_eq!(server.definition(&components, composed).await, rounded);
+
+    let hover = server.hover(&components, applied).await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/utilities.css:1**\n\n```css\n.rounded {\n  border-radius: 0.25rem;\n}\n```"
+    );
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 1, "character": 20 }, "end": { "line": 1, "character": 27 } })
+    );
+
+    // neither the at-rule nor the classes of other rules
+    let hover = server
+        .hover(&components, position_of(COMPONENTS, "apply", 1))
+        .await;
+    assert!(hover.is_null());
+}
+
+#[tokio::test]
+async fn it_looks_up_css_module_classes_in_their_own_stylesheet() {
+    const BUTTON: &str = r#"import styles from "./Button.module.css";
+
+export const Button = () => (
+  <button className={styles.primary}>
+    <span className={styles.primaryDark} />
+  </button>
+);
+"#;
+    let ws = Workspace::new(
+        "css-modules",
+        &[
+            ("src/Button.tsx", BUTTON),
+            (
+                "src/Button.module.css",
+                ".primary {\n  color: blue;\n}\n\n.primary-dark {\n  color: navy;\n}\n",
+            ),
+            ("src/Card.module.css", ".primary {\n  color: red;\n}\n"),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let button = ws.uri("src/Button.tsx");
+    server.did_open(&button, "typescriptreact", BUTTON).await;
+
+    let primary = position_of(BUTTON, "styles.primary}", 7);
+    assert_eq!(
+        server.definition(&button, primary).await,
+        json!({
+            "uri": ws.uri("src/Button.module.css"),
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 8 } }
+        })
+    );
+    let hover = server.hover(&button, primary).await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/Button.module.css:1**\n\n```css\n.primary {\n  color: blue;\n}\n```"
+    );
+
+    // camelCase keys fall back to the kebab-case classes
+    let primary_dark = position_of(BUTTON, "styles.primaryDark", 7);
+    assert_eq!(
+        server.definition(&button, primary_dark).await,
+        json!({
+            "uri": ws.uri("src/Button.module.css"),
+            "range": { "start": { "line": 4, "character": 0 }, "end": { "line": 4, "character": 13 } }
+        })
+    );
+}
+
+#[tokio::test]
+async fn it_hovers_the_rules_a_css_module_class_composes() {
+    const BUTTON: &str = r#"import styles from "./Button.module.css";
+
+export const Button = () => <button className={styles.button} />;
+"#;
+    let ws = Workspace::new(
+        "css-modules-composes",
+        &[
+            ("src/Button.tsx", BUTTON),
+            (
+                "src/Button.module.css",
+                ".button {\n  composes: base;\n  composes: shadow from \"./shared.css\";\n  \
+                 color: blue;\n}\n\n.base {\n  padding: 0;\n}\n",
+            ),
+            (
+                "src/shared.css",
+                ".shadow {\n  composes: elevated;\n  box-shadow: none;\n}\n\n\
+                 .elevated {\n  composes: shadow;\n}\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let button = ws.uri("src/Button.tsx");
+    server.did_open(&button, "typescriptreact", BUTTON).await;
+
+    let hover = server
+        .hover(&button, position_of(BUTTON, "styles.button", 7))
+        .await;
+    let value = hover["contents"]["value"].as_str().unwrap();
+    assert!(value.starts_with("**src/Button.module.css:1**"), "{value}");
+    assert!(
+        value.ends_with(
+            "Composes `base`, from src/Button.module.css:7\n\n```css\n.base {\n  padding: 0;\n}\n```\n\n\
+             Composes `shadow`, from src/shared.css:1\n\n\
+             ```css\n.shadow {\n  composes: elevated;\n  box-shadow: none;\n}\n```\n\n\
+             Composes `elevated`, from src/shared.css:6\n\n```css\n.elevated {\n  composes: shadow;\n}\n```\n\n\
+             Composes `shadow` → `elevated` → `shadow` in a cycle, left out"
+        ),
+        "{value}"
+    );
+}
+
+#[tokio::test]
+async fn it_prefers_the_css_files_a_document_imports() {
+    const COMPONENT: &str = r#"import "./entry.css";
+
+export const App = () => <div className="btn card" />;
+"#;
+    let ws = Workspace::new(
+        "definition-imported",
+        &[
+            ("src/App.tsx", COMPONENT),
+            ("src/entry.css", "@import \"./b/button.css\";\n"),
+            ("src/a/button.css", ".btn {\n  color: red;\n}\n"),
+            ("src/b/button.css", ".btn {\n  color: blue;\n}\n"),
+            ("src/z.css", ".card {\n  margin: 0;\n}\n"),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", COMPONENT).await;
+
+    // through the `@import` of the imported css file
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "btn", 0))
+        .await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/b/button.css")));
+
+    // none of them defines it
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "card", 0))
+        .await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/z.css")));
+
+    server
+        .did_change_configuration(json!({ "cnls": { "definition": { "importedOnly": true } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "card", 0))
+        .await;
+    assert!(definition.is_null());
+    let hover = server.hover(&app, position_of(COMPONENT, "card", 0)).await;
+    assert!(hover.is_null());
+}
+
+#[tokio::test]
+async fn it_indexes_the_css_files_the_globs_include_anew() {
+    const COMPONENT: &str = "export const App = () => <div className=\"btn legacy\" />;\n";
+    let ws = Workspace::new(
+        "css-globs",
+        &[
+            ("src/App.tsx", COMPONENT),
+            ("src/button.css", ".btn {\n  color: red;\n}\n"),
+            ("legacy/old.css", ".legacy {\n  color: blue;\n}\n"),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", COMPONENT).await;
+
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "legacy", 0))
+        .await;
+    assert_eq!(definition["uri"], json!(ws.uri("legacy/old.css")));
+
+    server
+        .did_change_configuration(json!({ "cnls": { "css": { "include": ["src/**"] } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "legacy", 0))
+        .await;
+    assert!(definition.is_null());
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "btn", 0))
+        .await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/button.css")));
+}
+
+#[tokio::test]
+async fn it_ranks_the_css_files_of_the_package_of_a_document_first() {
+    const COMPONENT: &str = "export const App = () => <div className=\"btn\" />;\n";
+    let ws = Workspace::new(
+        "definition-packages",
+        &[
+            ("package.json", "{}"),
+            ("packages/app-a/package.json", "{}"),
+            (
+                "packages/app-a/src/button.css",
+                ".btn {\n  color: red;\n}\n",
+            ),
+            ("packages/app-b/package.json", "{}"),
+            ("packages/app-b/src/App.tsx", COMPONENT),
+            (
+                "packages/app-b/src/button.css",
+                ".btn {\n  color: blue;\n}\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("packages/app-b/src/App.tsx");
+    server.did_open(&app, "typescriptreact", COMPONENT).await;
+
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "btn", 0))
+        .await;
+    let uris = definition
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|location| location["uri"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        uris,
+        [
+            json!(ws.uri("packages/app-b/src/button.css")),
+            json!(ws.uri("packages/app-a/src/button.css"))
+        ]
+    );
+
+    server
+        .did_change_configuration(json!({ "cnls": { "definition": { "packageScoped": true } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let definition = server
+        .definition(&app, position_of(COMPONENT, "btn", 0))
+        .await;
+    assert_eq!(
+        definition["uri"],
+        json!(ws.uri("packages/app-b/src/button.css"))
+    );
+}
+
+#[tokio::test]
+async fn it_ranks_definitions_by_proximity_to_the_document() {
+    const CARD: &str = "export const Card = () => <div className=\"btn\" />;\n";
+    let ws = Workspace::new(
+        "definition-proximity",
+        &[
+            ("src/a/button.css", ".btn {\n  color: red;\n}\n"),
+            ("src/components/card/Card.tsx", CARD),
+            (
+                "src/components/card/button.css",
+                ".btn {\n  color: blue;\n}\n",
+            ),
+            ("src/components/z.css", ".btn {\n  color: green;\n}\n"),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let card = ws.uri("src/components/card/Card.tsx");
+    server.did_open(&card, "typescriptreact", CARD).await;
+
+    let uris = |definition: serde_json::Value| {
+        definition
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| location["uri"].clone())
+            .collect::<Vec<_>>()
+    };
+
+    let definition = server.definition(&card, position_of(CARD, "btn", 0)).await;
+    assert_eq!(
+        uris(definition),
+        [
+            json!(ws.uri("src/components/card/button.css")),
+            json!(ws.uri("src/components/z.css")),
+            json!(ws.uri("src/a/button.css"))
+        ]
+    );
+    let hover = server.hover(&card, position_of(CARD, "btn", 0)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .starts_with("**src/components/card/button.css:1**"));
+
+    server
+        .did_change_configuration(json!({ "cnls": { "resolution": { "order": "alphabetical" } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let definition = server.definition(&card, position_of(CARD, "btn", 0)).await;
+    assert_eq!(
+        uris(definition),
+        [
+            json!(ws.uri("src/a/button.css")),
+            json!(ws.uri("src/components/card/button.css")),
+            json!(ws.uri("src/components/z.css"))
+        ]
+    );
+}
+
+#[tokio::test]
+async fn it_skips_the_css_files_found_minified_once_read() {
+    let ws = Workspace::new(
+        "skipped-minified",
+        &[
+            ("src/App.tsx", APP),
+            ("src/styles.css", STYLES),
+            ("src/bundle.css", ".btn{color:blue}.card{padding:0}\n"),
+        ],
+    );
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with_options(
+            &ws.root,
+            json!({ "workspace": { "configuration": true, "workspaceFolders": true } }),
+            json!({ "maxLineLength": 24 }),
+        )
+        .await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let definition = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/styles.css")));
+    loop {
+        let message = server.notification("window/logMessage").await;
+        let message = message["message"].as_str().unwrap();
+        if message.starts_with("skipped") {
+            assert_eq!(
+                message,
+                "skipped minified or oversized css files: src/bundle.css"
+            );
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn it_ranks_definitions_by_proximity_only_among_files_ranking_alike() {
+    const CARD: &str = "export const Card = () => <div className=\"btn\" />;\n";
+    let ws = Workspace::new(
+        "definition-proximity-tiers",
+        &[
+            ("src/styles/button.css", ".btn {\n  color: red;\n}\n"),
+            ("src/components/card/Card.tsx", CARD),
+            (
+                "src/components/card/button.min.css",
+                ".btn{color:blue}.card{padding:0}\n",
+            ),
+            (
+                "src/components/card/dist/button.css",
+                ".btn {\n  color: green;\n}\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with_options(
+            &ws.root,
+            json!({ "workspace": { "configuration": true, "workspaceFolders": true } }),
+            json!({
+                "indexMinified": "lowPriority",
+                "maxLineLength": 24,
+                "css": { "exclude": ["!dist"] }
+            }),
+        )
+        .await;
+    let card = ws.uri("src/components/card/Card.tsx");
+    server.did_open(&card, "typescriptreact", CARD).await;
+
+    // the source first, then the build output, then the minified bundle, closest as they are
+    let definition = server.definition(&card, position_of(CARD, "btn", 0)).await;
+    assert_eq!(
+        definition
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| location["uri"].clone())
+            .collect::<Vec<_>>(),
+        [
+            json!(ws.uri("src/styles/button.css")),
+            json!(ws.uri("src/components/card/dist/button.css")),
+            json!(ws.uri("src/components/card/button.min.css"))
+        ]
+    );
+}
+
+#[tokio::test]
+async fn it_goes_to_definitions_after_multibyte_characters() {
+    let ws = Workspace::new(
+        "definition-multibyte",
+        &[
+            ("src/App.tsx", APP),
+            ("src/styles.css", "/* café ☕🎉 */ .btn { padding: 0; }\n"),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    // `.btn` starts on byte 20, after 15 UTF-16 code units
+    let definition = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 0, "character": 15 }, "end": { "line": 0, "character": 19 } })
+    );
+}
+
+#[tokio::test]
+async fn it_goes_to_every_definition_of_a_class() {
+    let ws = Workspace::new(
+        "definitions",
+        &[
+            ("src/App.tsx", APP),
+            ("src/styles.css", STYLES),
+            (
+                "src/wide.css",
+                "@media (min-width: 768px) {\n  .btn, .btn:hover {\n    padding: 4px;\n  }\n}\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let definitions = server.definition(&app, position_of(APP, "btn", 0)).await;
+    let definitions = definitions.as_array().unwrap();
+    assert_eq!(definitions.len(), 2);
+    assert_eq!(definitions[0]["uri"], json!(ws.uri("src/styles.css")));
+    assert_eq!(definitions[1]["uri"], json!(ws.uri("src/wide.css")));
+    assert_eq!(
+        definitions[1]["range"],
+        json!({ "start": { "line": 1, "character": 2 }, "end": { "line": 1, "character": 6 } })
+    );
+}
+
+#[tokio::test]
+async fn it_goes_to_the_definitions_of_every_class_a_variable_holds() {
+    let ws = workspace("variable-definitions");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    let code = "const cls = \"btn badge\";\nconst a = <div className={cls} />;\n";
+    server.did_open(&app, "typescriptreact", code).await;
+
+    let definitions = server.definition(&app, position_of(code, "{cls", 1)).await;
+    let definitions = definitions.as_array().unwrap();
+    assert_eq!(definitions.len(), 2);
+    assert_eq!(
+        definitions[0]["range"]["start"],
+        json!({ "line": 0, "character": 0 })
+    );
+    assert_eq!(
+        definitions[1]["range"]["start"],
+        json!({ "line": 4, "character": 0 })
+    );
+}
+
+#[tokio::test]
+async fn it_applies_configuration_changes() {
+    let ws = workspace("configuration");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let badge = position_of(APP, "\"badge", 3);
+    assert!(server.hover(&app, badge).await.is_null());
+
+    server
+        .did_change_configuration(json!({ "cnls": { "scopes": ["att:className", "fn:cva"] } }))
+        .await;
+    // open documents are re-analyzed once the configuration is applied
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let hover = server.hover(&app, badge).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".badge {"));
+
+    *server.settings.lock().unwrap() = json!({ "scopes": ["att:className"] });
+    server.did_change_configuration(json!(null)).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    assert!(server.hover(&app, badge).await.is_null());
+}
+
+#[tokio::test]
+async fn it_publishes_unknown_class_diagnostics() {
+    let ws = workspace("diagnostics");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["uri"], json!(app));
+    let diagnostics = published["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0]["message"], r#"unknown class name "card""#);
+    assert_eq!(diagnostics[0]["severity"], 2);
+    let start = position_of(APP, "card", 0);
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({ "start": start, "end": { "line": start.line, "character": start.character + 4 } })
+    );
+
+    let fixed = APP.replace("card", "btn");
+    server.did_change(&app, 2, &fixed).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"], json!([]));
+
+    server
+        .did_change_configuration(json!({ "cnls": { "diagnostics": { "unknownClass": "error" } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+    server.did_change(&app, 3, APP).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"][0]["severity"], 1);
+
+    server
+        .did_change_configuration(json!({ "cnls": { "diagnostics": { "unknownClass": "off" } } }))
+        .await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"], json!([]));
+}
+
+#[tokio::test]
+async fn it_reports_classes_defined_in_several_css_files() {
+    let legacy = ".btn {\n  color: red;\n}\n";
+    let ws = Workspace::new(
+        "duplicates",
+        &[("src/styles.css", STYLES), ("src/legacy.css", legacy)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let styles = ws.uri("src/styles.css");
+    server.did_open(&styles, "css", STYLES).await;
+
+    let mut published = vec![];
+    for _ in 0..2 {
+        published.push(server.notification("textDocument/publishDiagnostics").await);
+    }
+    published.sort_by_key(|p| p["uri"].as_str().unwrap().to_string());
+
+    assert_eq!(published[0]["uri"], json!(ws.uri("src/legacy.css")));
+    assert_eq!(published[1]["uri"], json!(styles));
+
+    let diagnostic = &published[1]["diagnostics"][0];
+    assert_eq!(
+        diagnostic["message"],
+        r#"class "btn" is defined more than once"#
+    );
+    assert_eq!(
+        diagnostic["relatedInformation"][0]["location"]["uri"],
+        json!(ws.uri("src/legacy.css"))
+    );
+}
+
+#[tokio::test]
+async fn it_hovers_from_nearby_stylesheets_while_indexing() {
+    let vendor = (0..40)
+        .map(|i| format!(".vendor-{i} {{\n  margin: {i}px;\n}}\n"))
+        .collect::<String>();
+    let vendor_files = (0..500)
+        .map(|i| (format!("vendor/{i}/index.css"), vendor.clone()))
+        .collect::<Vec<_>>();
+
+    let mut files = vec![
+        ("src/App.tsx", APP),
+        ("src/styles.css", STYLES),
+        ("lib/card.css", ".card {\n  margin: 0;\n}\n"),
+    ];
+    files.extend(vendor_files.iter().map(|(p, c)| (p.as_str(), c.as_str())));
+    let ws = Workspace::new("budget", &files);
+
+    let config = cnls::config::Config {
+        index_wait_budget: Duration::ZERO,
+        ..Default::default()
+    };
+    let mut server = TestServer::start_with(&ws.root, config);
+    // pulling diagnostics, only the hovers wait for the index
+    server
+        .initialize_with(
+            &ws.root,
+            json!({
+                "workspace": { "workspaceFolders": true },
+                "textDocument": { "diagnostic": {} }
+            }),
+        )
+        .await;
+
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let started = Instant::now();
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.css:1**\n\n```css\n.btn {\n  padding: 0;\n}\n```"
+    );
+
+    // only the stylesheets near the document are searched until the index is built
+    let hover = server.hover(&app, position_of(APP, "card", 1)).await;
+    assert!(hover.is_null());
+
+    // which goes on once the hovers stop waiting for it
+    let started = Instant::now();
+    let hover = loop {
+        let hover = server.hover(&app, position_of(APP, "card", 1)).await;
+        if !hover.is_null() {
+            break hover;
+        }
+        assert!(started.elapsed() < Duration::from_secs(30));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert_eq!(
+        hover["contents"]["value"],
+        "**lib/card.css:1**\n\n```css\n.card {\n  margin: 0;\n}\n```"
+    );
+}
+
+#[tokio::test]
+async fn it_answers_a_hover_right_after_initialize_within_the_budget() {
+    let vendor = (0..40)
+        .map(|i| format!(".vendor-{i} {{\n  margin: {i}px;\n}}\n"))
+        .collect::<String>();
+    let vendor_files = (0..2000)
+        .map(|i| (format!("vendor/{i}/index.css"), vendor.clone()))
+        .collect::<Vec<_>>();
+
+    let mut files = vec![("src/App.tsx", APP), ("src/styles.css", STYLES)];
+    files.extend(vendor_files.iter().map(|(p, c)| (p.as_str(), c.as_str())));
+    let ws = Workspace::new("budget-after-initialize", &files);
+
+    let budget = Duration::from_millis(500);
+    let config = cnls::config::Config {
+        index_wait_budget: budget,
+        ..Default::default()
+    };
+    let mut server = TestServer::start_with(&ws.root, config);
+    let started = Instant::now();
+    server.initialize(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    // whether the index is built by then or not
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert!(started.elapsed() < budget + Duration::from_secs(1));
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.css:1**\n\n```css\n.btn {\n  padding: 0;\n}\n```"
+    );
+}
+
+#[tokio::test]
+async fn it_answers_pulled_diagnostics() {
+    let ws = workspace("pull-diagnostics");
+    let mut server = TestServer::start(&ws.root);
+    let capabilities = json!({
+        "workspace": { "configuration": true, "workspaceFolders": true },
+        "textDocument": { "diagnostic": { "dynamicRegistration": false } }
+    });
+    let result = server.initialize_with(&ws.root, capabilities).await;
+    assert_eq!(
+        result["capabilities"]["diagnosticProvider"]["identifier"],
+        "cnls"
+    );
+
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let pull = |previous_result_id: Option<&str>| json!({ "textDocument": { "uri": app }, "previousResultId": previous_result_id });
+
+    let report = server.request("textDocument/diagnostic", pull(None)).await;
+    assert_eq!(report["kind"], "full");
+    assert_eq!(report["items"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        report["items"][0]["message"],
+        r#"unknown class name "card""#
+    );
+    let result_id = report["resultId"].as_str().unwrap().to_string();
+
+    let report = server
+        .request("textDocument/diagnostic", pull(Some(&result_id)))
+        .await;
+    assert_eq!(
+        report,
+        json!({ "kind": "unchanged", "resultId": result_id })
+    );
+
+    server
+        .did_change(&app, 2, &APP.replace("card", "btn"))
+        .await;
+    let report = server
+        .request("textDocument/diagnostic", pull(Some(&result_id)))
+        .await;
+    assert_eq!(report["kind"], "full");
+    assert_eq!(report["items"], json!([]));
+    assert_ne!(report["resultId"], json!(result_id));
+}
+
+#[tokio::test]
+async fn it_offers_to_create_missing_classes() {
+    let ws = workspace("create-rule");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    let diagnostic = published["diagnostics"][0].clone();
+
+    let actions = server
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": app },
+                "range": diagnostic["range"],
+                "context": { "diagnostics": [diagnostic] }
+            }),
+        )
+        .await;
+
+    let action = &actions[0];
+    assert_eq!(action["title"], "Create .card in src/styles.css");
+    assert_eq!(action["kind"], "quickfix");
+    assert_eq!(action["isPreferred"], true);
+
+    let styles = ws.uri("src/styles.css").to_string();
+    assert_eq!(
+        action["edit"]["changes"][&styles],
+        json!([{
+            "range": { "start": { "line": 7, "character": 0 }, "end": { "line": 7, "character": 0 } },
+            "newText": "\n.card {\n\n}\n"
+        }])
+    );
+}
+
+#[tokio::test]
+async fn it_reports_css_parse_errors_until_fixed() {
+    let broken = ".card {\n  margin: 0;\n}\n\n.1bad {\n}\n";
+    let ws = Workspace::new("parse-errors", &[("src/broken.css", broken)]);
+    let config = cnls::config::Config {
+        report_css_parse_errors: true,
+        ..Default::default()
+    };
+    let mut server = TestServer::start_with(&ws.root, config);
+    server.initialize(&ws.root).await;
+
+    let css = ws.uri("src/broken.css");
+    server.did_open(&css, "css", broken).await;
+
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["uri"], json!(css));
+    let diagnostic = &published["diagnostics"][0];
+    assert_eq!(diagnostic["code"], "css-parse-error");
+    assert_eq!(diagnostic["severity"], 3);
+    assert_eq!(
+        diagnostic["range"],
+        json!({ "start": { "line": 4, "character": 0 }, "end": { "line": 4, "character": 5 } })
+    );
+
+    std::fs::write(ws.path("src/broken.css"), ".card {\n  margin: 0;\n}\n").unwrap();
+    server
+        .notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": css } }),
+        )
+        .await;
+
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["uri"], json!(css));
+    assert_eq!(published["diagnostics"], json!([]));
+}
+
+#[tokio::test]
+async fn it_suggests_close_class_names() {
+    let app = r#"export const App = () => <div className="hover:bdge" />;"#;
+    let ws = Workspace::new(
+        "did-you-mean",
+        &[("src/App.tsx", app), ("src/styles.css", STYLES)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let uri = ws.uri("src/App.tsx");
+    server.did_open(&uri, "typescriptreact", app).await;
+
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    let diagnostic = published["diagnostics"][0].clone();
+
+    let actions = server
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": diagnostic["range"],
+                "context": { "diagnostics": [diagnostic] }
+            }),
+        )
+        .await;
+
+    let action = &actions[0];
+    assert_eq!(action["title"], "Change to badge");
+    assert_eq!(action["isPreferred"], true);
+    assert_eq!(
+        action["edit"]["changes"][uri.as_str()],
+        json!([{ "range": diagnostic["range"], "newText": "hover:badge" }])
+    );
+
+    assert_eq!(actions[1]["title"], "Create .bdge in src/styles.css");
+    assert_eq!(actions[1]["isPreferred"], false);
+}
+
+#[tokio::test]
+async fn it_offers_to_remove_unknown_classes() {
+    let app = "export const App = () => (\n  <div className={`\n    a\\u0020b\n    flx\n    btn\n  `} />\n);\n";
+    let ws = Workspace::new(
+        "remove-class",
+        &[("src/App.tsx", app), ("src/styles.css", STYLES)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let uri = ws.uri("src/App.tsx");
+    server.did_open(&uri, "typescriptreact", app).await;
+
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    let diagnostic = published["diagnostics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|d| d["data"]["className"] == "flx")
+        .unwrap()
+        .clone();
+
+    let actions = server
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": diagnostic["range"],
+                "context": { "diagnostics": [diagnostic] }
+            }),
+        )
+        .await;
+
+    let remove = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|action| action["title"] == "Remove 'flx'")
+        .unwrap();
+    assert_eq!(
+        remove["edit"]["changes"][uri.as_str()],
+        json!([{
+            "range": { "start": { "line": 3, "character": 4 }, "end": { "line": 4, "character": 4 } },
+            "newText": ""
+        }])
+    );
+}
+
+#[tokio::test]
+async fn it_tolerates_out_of_order_document_notifications() {
+    let ws = workspace("lifecycle");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    let btn = position_of(APP, "btn", 1);
+
+    // closing a document that was never opened is a no-op
+    server
+        .notify(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": app } }),
+        )
+        .await;
+
+    // a change without content is ignored
+    server
+        .notify(
+            "textDocument/didChange",
+            json!({ "textDocument": { "uri": app, "version": 1 }, "contentChanges": [] }),
+        )
+        .await;
+    let response = server
+        .raw_request("textDocument/hover", common::position_params(&app, btn))
+        .await;
+    assert_eq!(response["error"]["code"], -32602);
+
+    // opening twice replaces the text
+    server.did_open(&app, "typescriptreact", "").await;
+    server.did_open(&app, "typescriptreact", APP).await;
+    assert!(!server.hover(&app, btn).await.is_null());
+
+    // a change after closing opens the document again
+    server
+        .notify(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": app } }),
+        )
+        .await;
+    server.did_change(&app, 3, APP).await;
+    assert!(!server.hover(&app, btn).await.is_null());
+}
+
+#[tokio::test]
+async fn it_finds_documents_whichever_way_their_uri_is_encoded() {
+    let ws = workspace("uri-encoding");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    let encoded = tower_lsp::lsp_types::Url::parse(&app.as_str().replace("App", "%41pp")).unwrap();
+    assert_ne!(encoded, app);
+
+    server.did_open(&encoded, "typescriptreact", APP).await;
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".btn {"));
+}
+
+#[tokio::test]
+async fn it_sorts_the_class_names_of_a_string() {
+    let app = "export const App = () => (\n  <div className=\"md:p-4 p-2  btn sm:p-1\" />\n);\n";
+    let ws = Workspace::new(
+        "sort-classes",
+        &[("src/App.tsx", app), ("src/styles.css", STYLES)],
+    );
+    let config = cnls::config::Config {
+        sort: cnls::config::SortOptions {
+            order: vec!["sm:".to_string(), "md:".to_string()],
+        },
+        ..Default::default()
+    };
+    let mut server = TestServer::start_with(&ws.root, config);
+    server.initialize(&ws.root).await;
+    let uri = ws.uri("src/App.tsx");
+    server.did_open(&uri, "typescriptreact", app).await;
+
+    let position = position_of(app, "p-2", 0);
+    let actions = server
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": { "start": position, "end": position },
+                "context": { "diagnostics": [], "only": ["source"] }
+            }),
+        )
+        .await;
+
+    assert_eq!(actions.as_array().unwrap().len(), 1);
+    assert_eq!(actions[0]["title"], "Sort class names");
+    assert_eq!(actions[0]["kind"], "source");
+    assert_eq!(
+        actions[0]["edit"]["changes"][uri.as_str()],
+        json!([{
+            "range": { "start": { "line": 1, "character": 18 }, "end": { "line": 1, "character": 40 } },
+            "newText": "btn p-2 sm:p-1 md:p-4"
+        }])
+    );
+}
+
+#[tokio::test]
+async fn it_answers_the_effective_configuration() {
+    let ws = workspace("effective-configuration");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    server
+        .did_change_configuration(json!({ "cnls": { "diagnostics": { "unknownClass": "error" } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let effective = server.request("cnls/configuration", json!(null)).await;
+    assert_eq!(
+        effective["config"]["diagnostics"],
+        json!({ "unknownClass": "error", "duplicateClass": "warn", "onSaveOnly": false })
+    );
+    assert_eq!(effective["provenance"]["diagnostics"], "client");
+    assert_eq!(effective["provenance"]["indexWaitBudget"], "default");
+}
+
+#[tokio::test]
+async fn it_counts_the_uses_of_css_rules_in_code_lenses() {
+    let ws = workspace("code-lens");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let styles = ws.uri("src/styles.css");
+    server.did_open(&styles, "css", STYLES).await;
+
+    let lenses = server
+        .request(
+            "textDocument/codeLens",
+            json!({ "textDocument": { "uri": styles } }),
+        )
+        .await;
+    let lenses = lenses.as_array().unwrap();
+    assert_eq!(lenses.len(), 2);
+    assert_eq!(
+        lenses[0]["range"],
+        json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 4 } })
+    );
+
+    let btn = server.request("codeLens/resolve", lenses[0].clone()).await;
+    assert_eq!(btn["command"]["title"], "1 reference");
+    assert_eq!(btn["command"]["command"], "editor.action.showReferences");
+    let locations = &btn["command"]["arguments"][2];
+    assert_eq!(locations[0]["uri"], json!(ws.uri("src/App.tsx")));
+
+    // badge is only used in cva(), out of the default scopes
+    let badge = server.request("codeLens/resolve", lenses[1].clone()).await;
+    assert_eq!(badge["command"]["title"], "0 references");
+}
+
+#[tokio::test]
+async fn it_hints_the_css_files_of_class_strings_once_enabled() {
+    let ws = workspace("inlay-hints");
+    let config = cnls::config::Config {
+        inlay_hints: cnls::config::InlayHintOptions { enabled: true },
+        ..Default::default()
+    };
+    let mut server = TestServer::start_with(&ws.root, config);
+    let init = server.initialize(&ws.root).await;
+    assert_eq!(init["capabilities"]["inlayHintProvider"], true);
+
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let hints = server
+        .request(
+            "textDocument/inlayHint",
+            json!({
+                "textDocument": { "uri": app },
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 7, "character": 0 } }
+            }),
+        )
+        .await;
+
+    // "card" is defined nowhere, so its string gets no hint
+    assert_eq!(hints.as_array().unwrap().len(), 1);
+    assert_eq!(hints[0]["label"], "» styles.css");
+    assert_eq!(hints[0]["position"], json!({ "line": 3, "character": 27 }));
+    assert_eq!(hints[0]["paddingLeft"], true);
+}
+
+#[tokio::test]
+async fn it_colors_known_class_names_across_lines() {
+    let app = "export const App = () => (\n  <div className={`btn nope\n    badge btn`} />\n);\n";
+    let ws = Workspace::new(
+        "semantic-tokens",
+        &[("src/App.tsx", app), ("src/styles.css", STYLES)],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let uri = ws.uri("src/App.tsx");
+    server.did_open(&uri, "typescriptreact", app).await;
+
+    let tokens = server
+        .request(
+            "textDocument/semanticTokens/full",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+
+    // btn at 1:19, badge at 2:4 and btn at 2:10, leaving out the unknown nope
+    assert_eq!(
+        tokens["data"],
+        json!([1, 19, 3, 0, 0, 1, 4, 5, 0, 0, 0, 6, 3, 0, 0])
+    );
+}
+
+#[tokio::test]
+async fn it_links_class_names_to_their_rules() {
+    let ws = workspace("document-links");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let links = server
+        .request(
+            "textDocument/documentLink",
+            json!({ "textDocument": { "uri": app } }),
+        )
+        .await;
+
+    // card is defined nowhere, so it isn't linked
+    let links = links.as_array().unwrap();
+    assert_eq!(links.len(), 1);
+    assert_eq!(
+        links[0]["range"],
+        json!({ "start": { "line": 3, "character": 18 }, "end": { "line": 3, "character": 21 } })
+    );
+    assert_eq!(
+        links[0]["target"],
+        format!("{}#L1", ws.uri("src/styles.css"))
+    );
+    assert_eq!(links[0]["tooltip"], "src/styles.css");
+
+    server
+        .did_change_configuration(json!({ "cnls": { "documentLinks": { "fragment": "" } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let links = server
+        .request(
+            "textDocument/documentLink",
+            json!({ "textDocument": { "uri": app } }),
+        )
+        .await;
+    assert_eq!(links[0]["target"], json!(ws.uri("src/styles.css")));
+}
+
+#[tokio::test]
+async fn it_hovers_every_rule_defining_a_class() {
+    let ws = Workspace::new(
+        "hover-every-rule",
+        &[
+            ("src/App.tsx", APP),
+            ("src/styles.css", STYLES),
+            (
+                "src/wide.css",
+                "@media (min-width: 768px) {\n  .btn, .btn:hover {\n    padding: 4px;\n  }\n}\n",
+            ),
+            (
+                "src/z.css",
+                "/**\n * The main call to action.\n */\n.btn.primary {\n  color: blue;\n}\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert_eq!(
+        hover["contents"]["value"],
+        "**src/styles.css:1**\n\n```css\n.btn {\n  padding: 0;\n}\n```\n\n---\n\n\
+         **src/wide.css:2**\n\n```css\n@media (min-width: 768px) {\n  .btn,\n  .btn:hover {\n    padding: 4px;\n  }\n}\n```\n\n---\n\n\
+         **src/z.css:4**\n\nThe main call to action.\n\n```css\n.btn.primary {\n  color: blue;\n}\n```\n\nalso requires: .primary"
+    );
+
+    server
+        .did_change_configuration(json!({ "cnls": { "hover": { "maxRules": 2 } } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    let value = hover["contents"]["value"].as_str().unwrap();
+    assert!(value.contains("**src/wide.css:2**"));
+    assert!(!value.contains("src/z.css"));
+    assert!(value.ends_with("```\n\nand 1 more rule"));
+}
+
+#[tokio::test]
+async fn it_hovers_the_custom_properties_of_a_rule() {
+    let ws = Workspace::new(
+        "hover-variables",
+        &[
+            ("src/App.tsx", APP),
+            ("src/theme.css", ":root {\n  --brand: #3b82f6;\n}\n"),
+            (
+                "src/styles.css",
+                ".btn {\n  color: var(--brand);\n  margin: var(--gap);\n}\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .ends_with("```\n\nVariables:\n- `--brand: #3b82f6`\n- `--gap` is unresolved"));
+}
+
+#[tokio::test]
+async fn it_hovers_what_a_rule_applies() {
+    let ws = Workspace::new(
+        "hover-apply",
+        &[
+            ("src/App.tsx", APP),
+            (
+                "src/utilities.css",
+                ".px-4 {\n  padding-left: 1rem;\n  padding-right: 1rem;\n}\n\n\
+                 .rounded { border-radius: 0.25rem; }\n",
+            ),
+            (
+                "src/styles.css",
+                ".btn {\n  @apply px-4 rounded shadow-xl;\n}\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert!(hover["contents"]["value"].as_str().unwrap().ends_with(
+        "```\n\nExpands to:\n\n```css\npadding-left: 1rem;\npadding-right: 1rem;\n\
+         border-radius: 0.25rem;\n```\n\nUnresolved: `shadow-xl`"
+    ));
+}
+
+#[tokio::test]
+async fn it_applies_incremental_changes() {
+    let ws = workspace("incremental-sync");
+    let mut server = TestServer::start(&ws.root);
+    let init = server.initialize(&ws.root).await;
+    assert_eq!(init["capabilities"]["textDocumentSync"]["change"], 2);
+
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    // `btn card` becomes `badge card`, then `badge`, and the span's `card` becomes `btn`
+    server
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": app, "version": 2 },
+                "contentChanges": [
+                    { "range": { "start": { "line": 3, "character": 18 }, "end": { "line": 3, "character": 21 } }, "text": "badge" },
+                    { "range": { "start": { "line": 3, "character": 23 }, "end": { "line": 3, "character": 28 } }, "text": "" },
+                    { "range": { "start": { "line": 4, "character": 21 }, "end": { "line": 4, "character": 25 } }, "text": "btn" }
+                ]
+            }),
+        )
+        .await;
+    let diagnostics = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(diagnostics["diagnostics"], json!([]));
+
+    let hover = server
+        .hover(&app, tower_lsp::lsp_types::Position::new(3, 20))
+        .await;
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 3, "character": 18 }, "end": { "line": 3, "character": 23 } })
+    );
+}
+
+#[tokio::test]
+async fn it_applies_every_content_change() {
+    let ws = workspace("content-changes");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    // a change without content leaves the document as it is
+    server
+        .notify(
+            "textDocument/didChange",
+            json!({ "textDocument": { "uri": app, "version": 2 }, "contentChanges": [] }),
+        )
+        .await;
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".btn {"));
+
+    let fixed = APP.replace("card", "btn");
+    server.did_change(&app, 3, &fixed).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"], json!([]));
+
+    // the whole text, then two ranged changes on top of it
+    server
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": app, "version": 4 },
+                "contentChanges": [
+                    { "text": APP },
+                    { "range": { "start": { "line": 3, "character": 18 }, "end": { "line": 3, "character": 21 } }, "text": "nope" },
+                    { "range": { "start": { "line": 4, "character": 21 }, "end": { "line": 4, "character": 25 } }, "text": "badge" }
+                ]
+            }),
+        )
+        .await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    let messages = published["diagnostics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|diagnostic| diagnostic["message"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            r#"unknown class name "nope""#,
+            r#"unknown class name "card""#
+        ]
+    );
+}
+
+#[tokio::test]
+async fn it_drops_changes_older_than_the_document() {
+    let ws = workspace("stale-changes");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let fixed = APP.replace("card", "btn");
+    server.did_change(&app, 3, &fixed).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    // delivered after the change it precedes
+    server.did_change(&app, 2, APP).await;
+    // `btn` in the text as fixed, `card` in the stale one
+    let hover = server.hover(&app, position_of(APP, "card", 1)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".btn {"));
+
+    server.did_change(&app, 4, APP).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn it_updates_the_index_as_css_files_change_on_disk() {
+    let ws = workspace("watched-files");
+    let mut server = TestServer::initialized(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"].as_array().unwrap().len(), 2);
+
+    let card = ws.uri("src/card.css");
+    std::fs::write(ws.path("src/card.css"), ".card {\n  margin: 0;\n}\n").unwrap();
+    server.did_change_watched_files(&[(&card, 1)]).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"], json!([]));
+
+    let styles = ws.uri("src/styles.css");
+    std::fs::write(
+        ws.path("src/styles.css"),
+        "/* moved */\n\n.btn {\n  padding: 2px;\n}\n",
+    )
+    .unwrap();
+    server.did_change_watched_files(&[(&styles, 2)]).await;
+    server.notification("textDocument/publishDiagnostics").await;
+    let hover = server.hover(&app, position_of(APP, "btn", 1)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("**src/styles.css:3**"));
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("padding: 2px;"));
+
+    std::fs::remove_file(ws.path("src/card.css")).unwrap();
+    server.did_change_watched_files(&[(&card, 3)]).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn it_registers_css_file_watchers_with_clients_that_allow_it() {
+    let ws = workspace("file-watchers");
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with(
+            &ws.root,
+            json!({ "workspace": { "didChangeWatchedFiles": { "dynamicRegistration": true } } }),
+        )
+        .await;
+
+    let registration = server.notification("client/registerCapability").await;
+    let registration = &registration["registrations"][0];
+    assert_eq!(registration["method"], "workspace/didChangeWatchedFiles");
+    assert_eq!(
+        registration["registerOptions"]["watchers"],
+        json!([{ "globPattern": "**/*.{css,scss,sass,less}" }])
+    );
+}
+
+#[tokio::test]
+async fn it_checks_css_files_for_changes_without_file_watchers() {
+    let ws = workspace("modified-files");
+    let mut server = TestServer::start_with(
+        &ws.root,
+        cnls::config::Config {
+            css_check_interval: Duration::ZERO,
+            ..Default::default()
+        },
+    );
+    server.initialize(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let btn = position_of(APP, "btn", 1);
+    let hover = server.hover(&app, btn).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("padding: 0;"));
+
+    std::fs::write(ws.path("src/styles.css"), ".btn {\n  padding: 8px;\n}\n").unwrap();
+    let hover = server.hover(&app, btn).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("padding: 8px;"));
+}
+
+#[tokio::test]
+async fn it_diagnoses_documents_once_saved_when_asked_to() {
+    let ws = workspace("on-save-only");
+    let mut config = cnls::config::Config::default();
+    config.diagnostics.on_save_only = true;
+    let mut server = TestServer::start_with(&ws.root, config);
+    server.initialize(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"].as_array().unwrap().len(), 2);
+
+    server
+        .did_change(&app, 2, &APP.replace("card", "btn"))
+        .await;
+    let saved = APP.replacen("card", "btn", 1);
+    server.did_change(&app, 3, &saved).await;
+    server.did_save(&app).await;
+    // only the text as saved was diagnosed
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"].as_array().unwrap().len(), 1);
+
+    std::fs::write(ws.path("src/styles.css"), ".btn {}\n\n.card {}\n").unwrap();
+    server.did_save(&ws.uri("src/styles.css")).await;
+    let published = server.notification("textDocument/publishDiagnostics").await;
+    assert_eq!(published["diagnostics"], json!([]));
+
+    let hover = server.hover(&app, position_of(&saved, "card", 1)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("**src/styles.css:3**"));
+}
+
+#[tokio::test]
+async fn it_reports_the_progress_of_indexing() {
+    let ws = workspace("indexing-progress");
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with(
+            &ws.root,
+            json!({
+                "workspace": { "workspaceFolders": true },
+                "window": { "workDoneProgress": true }
+            }),
+        )
+        .await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let create = server.notification("window/workDoneProgress/create").await;
+    let mut progress = vec![];
+    loop {
+        let params = server.notification("$/progress").await;
+        assert_eq!(params["token"], create["token"]);
+        progress.push(params["value"].clone());
+        if params["value"]["kind"] == "end" {
+            break;
+        }
+    }
+
+    assert_eq!(progress[0]["kind"], "begin");
+    assert_eq!(progress[0]["title"], "Indexing CSS");
+    assert_eq!(progress[0]["message"], "0/1");
+    assert_eq!(progress[1]["kind"], "report");
+    assert_eq!(progress[1]["message"], "1/1");
+    assert_eq!(progress[2]["message"], "indexed 1/1 css files");
+}
+
+#[tokio::test]
+async fn it_reports_the_progress_of_the_usage_report() {
+    let ws = workspace("usage-report-progress");
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with(
+            &ws.root,
+            json!({
+                "workspace": { "workspaceFolders": true },
+                "window": { "workDoneProgress": true }
+            }),
+        )
+        .await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    // the progress of indexing first
+    server.notification("window/workDoneProgress/create").await;
+
+    let report = server
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": "cnls.usageReport", "arguments": [] }),
+        )
+        .await;
+    assert!(report.is_object());
+
+    let create = server.notification("window/workDoneProgress/create").await;
+    let mut progress = vec![];
+    loop {
+        let params = server.notification("$/progress").await;
+        if params["token"] != create["token"] {
+            continue;
+        }
+        progress.push(params["value"].clone());
+        if params["value"]["kind"] == "end" {
+            break;
+        }
+    }
+
+    assert_eq!(progress[0]["kind"], "begin");
+    assert_eq!(progress[0]["title"], "Usage report");
+    assert_eq!(progress[0]["cancellable"], false);
+    assert_eq!(progress[1]["kind"], "report");
+    assert_eq!(progress[1]["message"], "1/1");
+    assert_eq!(progress[2]["message"], "scanned 1 source files");
+}
+
+#[tokio::test]
+async fn it_stays_responsive_after_a_hover_is_cancelled_mid_index() {
+    let ws = workspace("cancel-hover");
+    // waiting on the index as long as it takes
+    let config = cnls::config::Config {
+        index_wait_budget: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let mut server = TestServer::start_with(&ws.root, config);
+    // pulling diagnostics, opening the document doesn't index the css files
+    server
+        .initialize_with(
+            &ws.root,
+            json!({
+                "workspace": { "workspaceFolders": true },
+                "textDocument": { "diagnostic": {} },
+                "window": { "workDoneProgress": true }
+            }),
+        )
+        .await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    // indexing stays underway until the client creates its progress
+    server.hold("window/workDoneProgress/create");
+    let btn = position_of(APP, "btn", 1);
+    let id = server
+        .send_request("textDocument/hover", common::position_params(&app, btn))
+        .await;
+    server.notification("window/workDoneProgress/create").await;
+    server.notify("$/cancelRequest", json!({ "id": id })).await;
+    let response = server.response(id).await;
+    assert_eq!(response["error"]["code"], -32800);
+    server.release("window/workDoneProgress/create");
+
+    let hover = server.hover(&app, btn).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".btn {"));
+
+    // the index the cancelled hover started was built to the end, not anew
+    let end = loop {
+        let progress = server.notification("$/progress").await;
+        if progress["value"]["kind"] == "end" {
+            break progress;
+        }
+    };
+    assert!(end["value"]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("indexed"));
+}
+
+#[tokio::test]
+async fn it_reads_scopes_from_initialization_options() {
+    let ws = workspace("initialization-options");
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with_options(
+            &ws.root,
+            json!({ "workspace": { "configuration": true, "workspaceFolders": true } }),
+            json!({ "scopes": ["att:className", "fn:cva", "nope"] }),
+        )
+        .await;
+
+    let message = server.notification("window/showMessage").await;
+    assert_eq!(message["type"], 1);
+    assert!(message["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("invalid cnls settings: initializationOptions"));
+
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    server.notification("textDocument/publishDiagnostics").await;
+
+    let badge = position_of(APP, "\"badge", 3);
+    let hover = server.hover(&app, badge).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".badge {"));
+
+    server
+        .did_change_configuration(json!({ "cnls": { "scopes": ["att:className"] } }))
+        .await;
+    server.notification("textDocument/publishDiagnostics").await;
+    assert!(server.hover(&app, badge).await.is_null());
+}
+
+#[tokio::test]
+async fn it_reads_settings_from_a_config_file() {
+    let ws = Workspace::new(
+        "config-file",
+        &[
+            ("src/App.tsx", APP),
+            ("src/styles.css", STYLES),
+            (
+                "cnls.toml",
+                "scopes = [\"att:className\", \"fn:cva\"]\n\n[diagnostics]\nunknownClass = \"error\"\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::start(&ws.root);
+    *server.settings.lock().unwrap() = json!({ "diagnostics": { "unknownClass": "off" } });
+    server.initialize(&ws.root).await;
+    server.notification("workspace/configuration").await;
+
+    // the client settings override the file key by key, once the response is handled
+    let started = Instant::now();
+    let effective = loop {
+        let effective = server.request("cnls/configuration", json!(null)).await;
+        if effective["provenance"]["diagnostics"] == "client" {
+            break effective;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(effective["provenance"]["scopes"], "file");
+    assert_eq!(effective["config"]["diagnostics"]["unknownClass"], "off");
+
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let hover = server.hover(&app, position_of(APP, "\"badge", 3)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".badge {"));
+}
+
+#[tokio::test]
+async fn it_shows_where_a_config_file_is_invalid() {
+    let ws = Workspace::new(
+        "invalid-config-file",
+        &[
+            ("src/App.tsx", APP),
+            ("src/styles.css", STYLES),
+            (
+                ".cnls.toml",
+                "scopes = [\n  \"att:className\"\n  \"fn:cva\"\n]\n",
+            ),
+        ],
+    );
+    let mut server = TestServer::start(&ws.root);
+    server.initialize(&ws.root).await;
+
+    let message = server.notification("window/showMessage").await;
+    assert_eq!(message["type"], 1);
+    let message = message["message"].as_str().unwrap();
+    assert!(message.contains(".cnls.toml:3: "));
+}
+
+#[tokio::test]
+async fn it_reloads_the_config_file_as_it_changes() {
+    let ws = Workspace::new(
+        "reloaded-config-file",
+        &[
+            ("src/App.tsx", APP),
+            ("src/styles.css", STYLES),
+            ("cnls.toml", "scopes = [\"att:className\"]\n"),
+        ],
+    );
+    let mut server = TestServer::start(&ws.root);
+    server
+        .initialize_with(
+            &ws.root,
+            json!({ "workspace": { "didChangeWatchedFiles": { "dynamicRegistration": true } } }),
+        )
+        .await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let badge = position_of(APP, "\"badge", 3);
+    assert!(server.hover(&app, badge).await.is_null());
+
+    let config_file = ws.uri("cnls.toml");
+    std::fs::write(
+        ws.path("cnls.toml"),
+        "scopes = [\"att:className\", \"fn:cva\"]\n",
+    )
+    .unwrap();
+    server.did_change_watched_files(&[(&config_file, 2)]).await;
+    loop {
+        let message = server.notification("window/logMessage").await;
+        if let Some(message) = message["message"].as_str() {
+            if message.starts_with("reloaded") {
+                assert!(message.ends_with("cnls.toml, changing: scopes"));
+                break;
+            }
+        }
+    }
+    let hover = server.hover(&app, badge).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".badge {"));
+
+    std::fs::write(ws.path("cnls.toml"), "scopes = [\"att:className\"\n").unwrap();
+    server.did_change_watched_files(&[(&config_file, 2)]).await;
+    let message = server.notification("window/showMessage").await;
+    assert_eq!(message["type"], 1);
+    let message = message["message"].as_str().unwrap();
+    assert!(message.starts_with("invalid cnls settings, keeping the previous ones"));
+    assert!(message.contains("cnls.toml:2: "));
+    assert!(!server.hover(&app, badge).await.is_null());
+}
+
+#[tokio::test]
+async fn it_checks_the_config_file_for_changes_without_file_watchers() {
+    let ws = workspace("modified-config-file");
+    let mut server = TestServer::start_with(
+        &ws.root,
+        cnls::config::Config {
+            css_check_interval: Duration::ZERO,
+            ..Default::default()
+        },
+    );
+    server.initialize(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let badge = position_of(APP, "\"badge", 3);
+    assert!(server.hover(&app, badge).await.is_null());
+
+    std::fs::write(ws.path(".cnls.toml"), "scopes = [\"fn:cva\"]\n").unwrap();
+    let hover = server.hover(&app, badge).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".badge {"));
+}
+
+#[tokio::test]
+async fn it_searches_every_workspace_folder() {
+    let app_ws = workspace("multi-root-app");
+    let lib_ws = Workspace::new(
+        "multi-root-lib",
+        &[("src/card.css", ".card {\n  margin: 0;\n}\n")],
+    );
+    let mut server = TestServer::start(&app_ws.root);
+    // the second folder nests in the first, its files being found once
+    *server.folders.lock().unwrap() =
+        vec![app_ws.root.clone(), lib_ws.root.clone(), app_ws.path("src")];
+    server.initialize(&app_ws.root).await;
+    let app = app_ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let definition = server.definition(&app, position_of(APP, "card", 0)).await;
+    assert_eq!(definition["uri"], json!(lib_ws.uri("src/card.css")));
+
+    let definition = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(definition["uri"], json!(app_ws.uri("src/styles.css")));
+}
+
+#[tokio::test]
+async fn it_indexes_workspace_folders_as_they_come_and_go() {
+    let app_ws = workspace("added-folders-app");
+    let lib_ws = Workspace::new(
+        "added-folders-lib",
+        &[("src/card.css", ".card {\n  margin: 0;\n}\n")],
+    );
+    let mut server = TestServer::start(&app_ws.root);
+    let result = server.initialize(&app_ws.root).await;
+    assert_eq!(
+        result["capabilities"]["workspace"]["workspaceFolders"],
+        json!({ "supported": true, "changeNotifications": true })
+    );
+    let app = app_ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let card = position_of(APP, "card", 0);
+    assert!(server.definition(&app, card).await.is_null());
+
+    let lib = json!([{ "uri": Url::from_file_path(&lib_ws.root).unwrap(), "name": "lib" }]);
+    server.folders.lock().unwrap().push(lib_ws.root.clone());
+    server
+        .notify(
+            "workspace/didChangeWorkspaceFolders",
+            json!({ "event": { "added": lib, "removed": [] } }),
+        )
+        .await;
+    let started = Instant::now();
+    while server.definition(&app, card).await.is_null() {
+        assert!(started.elapsed() < Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let definition = server.definition(&app, card).await;
+    assert_eq!(definition["uri"], json!(lib_ws.uri("src/card.css")));
+
+    server.folders.lock().unwrap().pop();
+    server
+        .notify(
+            "workspace/didChangeWorkspaceFolders",
+            json!({ "event": { "added": [], "removed": lib } }),
+        )
+        .await;
+    let started = Instant::now();
+    while !server.definition(&app, card).await.is_null() {
+        assert!(started.elapsed() < Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let btn = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(btn["uri"], json!(app_ws.uri("src/styles.css")));
+}
+
+#[tokio::test]
+async fn it_searches_the_root_uri_without_workspace_folders() {
+    let ws = workspace("root-uri");
+    let mut server = TestServer::start(&ws.root);
+    server.initialize_with(&ws.root, json!({})).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let definition = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/styles.css")));
+}
+
+#[tokio::test]
+async fn it_searches_the_root_uri_when_the_workspace_folders_are_empty() {
+    let ws = workspace("empty-folders");
+    let mut server = TestServer::start(&ws.root);
+    server.folders.lock().unwrap().clear();
+    server.initialize(&ws.root).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let definition = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/styles.css")));
+    loop {
+        let message = server.notification("window/logMessage").await;
+        if message["type"] == 2 {
+            assert!(message["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("the client gave no workspace folders"));
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn it_searches_the_directory_of_the_document_without_any_root() {
+    let ws = workspace("document-dir");
+    let mut server = TestServer::start(&ws.root);
+    server
+        .request(
+            "initialize",
+            json!({ "processId": null, "rootUri": null, "capabilities": {} }),
+        )
+        .await;
+    server.notify("initialized", json!({})).await;
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+
+    let definition = server.definition(&app, position_of(APP, "btn", 0)).await;
+    assert_eq!(definition["uri"], json!(ws.uri("src/styles.css")));
+}
+
+#[tokio::test]
+async fn it_searches_the_directory_of_each_document_without_any_root() {
+    let first_ws = workspace("document-dir-first");
+    let second_ws = workspace("document-dir-second");
+    let mut server = TestServer::start(&first_ws.root);
+    server
+        .request(
+            "initialize",
+            json!({ "processId": null, "rootUri": null, "capabilities": {} }),
+        )
+        .await;
+    server.notify("initialized", json!({})).await;
+    let first_app = first_ws.uri("src/App.tsx");
+    server.did_open(&first_app, "typescriptreact", APP).await;
+    let definition = server
+        .definition(&first_app, position_of(APP, "btn", 0))
+        .await;
+    assert_eq!(definition["uri"], json!(first_ws.uri("src/styles.css")));
+
+    let second_app = second_ws.uri("src/App.tsx");
+    server.did_open(&second_app, "typescriptreact", APP).await;
+    let definition = server
+        .definition(&second_app, position_of(APP, "btn", 0))
+        .await;
+    assert_eq!(definition["uri"], json!(second_ws.uri("src/styles.css")));
+}
+
+#[tokio::test]
+async fn it_pulls_the_configuration_once_initialized() {
+    let ws = workspace("pulled-configuration");
+    let mut server = TestServer::start(&ws.root);
+    *server.settings.lock().unwrap() = json!({ "scopes": ["att:className", "fn:cva"] });
+    server.initialize(&ws.root).await;
+    server.notification("workspace/configuration").await;
+
+    // applied once the response is handled
+    let started = Instant::now();
+    loop {
+        let effective = server.request("cnls/configuration", json!(null)).await;
+        if effective["provenance"]["scopes"] == "client" {
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let app = ws.uri("src/App.tsx");
+    server.did_open(&app, "typescriptreact", APP).await;
+    let hover = server.hover(&app, position_of(APP, "\"badge", 3)).await;
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains(".badge {"));
+}